@@ -0,0 +1,82 @@
+//! Compares the size and time of caching item-heavy results as plain
+//! JSON, gzip and zstd, through Workflow::memoize.
+//!
+//! Run with `cargo bench --bench cache_compression --features
+//! gzip,zstd`. Writes include serializing and compressing; reads include
+//! decompressing and deserializing. Like the other benches, this is a
+//! plain timing loop, so treat the numbers as rough comparisons on the
+//! same machine.
+//!
+use std::fs::{read_dir, remove_dir_all};
+use std::time::{Duration, Instant};
+
+use alfrusco::test_support::workflow_in;
+use alfrusco::{Compression, URLItem};
+
+const SIZES: [usize; 2] = [1_000, 20_000];
+const ROUNDS: u32 = 10;
+const TTL: Duration = Duration::from_secs(3600);
+
+fn url_items(count: usize) -> Vec<URLItem> {
+    (0..count)
+        .map(|i| {
+            URLItem::new(
+                format!("Bookmark number {}", i),
+                format!("https://example.com/bookmarks/{}?ref=alfred", i),
+            )
+            .short_title(format!("Bookmark {}", i))
+        })
+        .collect()
+}
+
+fn best_of(mut f: impl FnMut()) -> Duration {
+    f();
+    let mut best = Duration::MAX;
+    for _ in 0..ROUNDS {
+        let started = Instant::now();
+        f();
+        best = best.min(started.elapsed());
+    }
+    best
+}
+
+fn main() {
+    let dir = tempfile::tempdir().unwrap();
+    println!("{:<24} {:>12} {:>12} {:>12}", "", "size", "write", "read");
+    for count in SIZES {
+        let items = url_items(count);
+        for compression in [None, Some(Compression::Gzip), Some(Compression::Zstd)] {
+            let name = match compression {
+                None => "json",
+                Some(Compression::Gzip) => "gzip",
+                Some(Compression::Zstd) => "zstd",
+            };
+            let mut workflow = workflow_in(dir.path());
+            workflow.set_cache_compression(compression);
+            let write = best_of(|| {
+                let _ = remove_dir_all(workflow.memoize_dir());
+                workflow
+                    .memoize("items", TTL, || Ok(items.clone()))
+                    .unwrap();
+            });
+            let read = best_of(|| {
+                let cached: Vec<URLItem> = workflow
+                    .memoize("items", TTL, || panic!("should be cached"))
+                    .unwrap();
+                assert_eq!(cached.len(), count);
+            });
+            let size: u64 = read_dir(workflow.memoize_dir())
+                .unwrap()
+                .map(|entry| entry.unwrap().metadata().unwrap().len())
+                .sum();
+
+            println!(
+                "{:<24} {:>10} B {:>12.1?} {:>12.1?}",
+                format!("{}/{}", count, name),
+                size,
+                write,
+                read
+            );
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! Compares the two ways of checking whether a background job's process
+//! is still running: enumerating every process with System::new_all, as
+//! job checks used to, and refreshing only the one pid, as they do now.
+//!
+//! Run with `cargo bench --bench process_lookup`. Like the serialization
+//! bench, this is a plain timing loop, so treat the numbers as rough
+//! comparisons on the same machine. The gap grows with the number of
+//! running processes.
+//!
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+
+const ROUNDS: u32 = 20;
+
+fn bench(name: &str, mut f: impl FnMut()) -> Duration {
+    f();
+    let mut best = Duration::MAX;
+    for _ in 0..ROUNDS {
+        let started = Instant::now();
+        f();
+        best = best.min(started.elapsed());
+    }
+    println!("{:<32} {:>10.1?}", name, best);
+    best
+}
+
+fn main() {
+    let pid = Pid::from_u32(std::process::id());
+
+    let all = bench("process_lookup/new_all", || {
+        let mut system = System::new_all();
+        system.refresh_processes(ProcessesToUpdate::All, true);
+        black_box(system.process(pid).map(|p| p.start_time()));
+    });
+
+    let targeted = bench("process_lookup/targeted", || {
+        let mut system = System::new();
+        system.refresh_processes_specifics(
+            ProcessesToUpdate::Some(&[pid]),
+            true,
+            ProcessRefreshKind::nothing(),
+        );
+        black_box(system.process(pid).map(|p| p.start_time()));
+    });
+
+    println!(
+        "targeted lookup is {:.0}x faster",
+        all.as_secs_f64() / targeted.as_secs_f64()
+    );
+}
@@ -0,0 +1,84 @@
+//! Measures the cost of building and serializing item-heavy responses.
+//!
+//! Run with `cargo bench --bench serialization`. This uses a plain timing
+//! loop rather than a benchmarking framework, so treat the numbers as
+//! rough comparisons between revisions on the same machine.
+//!
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use alfrusco::{CopyFormats, Item, Response, URLItem};
+
+const ITEMS: usize = 10_000;
+const ROUNDS: u32 = 20;
+
+fn url_items() -> Vec<URLItem> {
+    (0..ITEMS)
+        .map(|i| {
+            URLItem::new(
+                format!("Bookmark number {}", i),
+                format!("https://example.com/bookmarks/{}?ref=alfred", i),
+            )
+            .short_title(format!("Bookmark {}", i))
+        })
+        .collect()
+}
+
+fn plain_items() -> Vec<Item> {
+    (0..ITEMS)
+        .map(|i| {
+            Item::new(format!("Item {}", i))
+                .subtitle("A typical subtitle")
+                .arg(i.to_string())
+                .var("SOURCE", "bench")
+                .var("INDEX", i.to_string())
+        })
+        .collect()
+}
+
+fn bench(name: &str, mut f: impl FnMut()) {
+    // Warm up allocator and caches
+    f();
+    let mut best = Duration::MAX;
+    for _ in 0..ROUNDS {
+        let started = Instant::now();
+        f();
+        best = best.min(started.elapsed());
+    }
+    println!(
+        "{:<32} {:>10.1?} total {:>8} ns/item",
+        name,
+        best,
+        best.as_nanos() / ITEMS as u128
+    );
+}
+
+fn main() {
+    let formats = CopyFormats::default();
+
+    bench("url_items/convert", || {
+        let items: Vec<Item> = url_items()
+            .into_iter()
+            .map(|url_item| url_item.into_item_with(&formats))
+            .collect();
+        black_box(items);
+    });
+
+    let converted: Vec<Item> = url_items()
+        .into_iter()
+        .map(|url_item| url_item.into_item_with(&formats))
+        .collect();
+    let response = Response::new_with_items(converted);
+    bench("url_items/serialize", || {
+        response.write(std::io::sink()).unwrap();
+    });
+
+    bench("items/build", || {
+        black_box(plain_items());
+    });
+
+    let response = Response::new_with_items(plain_items());
+    bench("items/serialize", || {
+        response.write(std::io::sink()).unwrap();
+    });
+}
@@ -1,4 +1,4 @@
-use alfrusco::{config, AsyncRunnable, Item, Workflow, WorkflowError};
+use alfrusco::{config, AsyncRunnable, Item, Workflow};
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 
@@ -11,7 +11,6 @@ struct RandomUserWorkflow {
 
 #[tokio::main]
 pub async fn main() {
-    env_logger::init();
     let command = RandomUserWorkflow::parse();
     alfrusco::execute_async(&config::AlfredEnvProvider, command, &mut std::io::stdout()).await;
 }
@@ -70,43 +69,15 @@ pub struct RandomUserName {
     pub last: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum RandomUserError {
-    Reqwest(reqwest::Error),
-    Json(serde_json::Error),
+    #[error("Reqwest error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
 }
 
-impl From<reqwest::Error> for RandomUserError {
-    fn from(e: reqwest::Error) -> Self {
-        Self::Reqwest(e)
-    }
-}
-
-impl From<serde_json::Error> for RandomUserError {
-    fn from(e: serde_json::Error) -> Self {
-        Self::Json(e)
-    }
-}
-
-impl WorkflowError for RandomUserError {}
-
-impl std::fmt::Display for RandomUserError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            RandomUserError::Reqwest(e) => write!(f, "Reqwest error: {}", e),
-            RandomUserError::Json(e) => write!(f, "JSON error: {}", e),
-        }
-    }
-}
-
-impl std::error::Error for RandomUserError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        match self {
-            RandomUserError::Reqwest(e) => Some(e),
-            RandomUserError::Json(e) => Some(e),
-        }
-    }
-}
+alfrusco::workflow_error!(RandomUserError);
 
 #[cfg(test)]
 mod tests {
@@ -120,7 +91,7 @@ mod tests {
         };
 
         let mut buffer = Vec::new();
-        let dir = tempfile::tempdir().unwrap().into_path();
+        let dir = tempfile::tempdir().unwrap().keep();
         alfrusco::execute_async(&config::TestingProvider(dir), command, &mut buffer).await;
         let output = String::from_utf8(buffer).unwrap();
         assert!(output.contains("\"title\":\"Mr Fletcher Hall\""));
@@ -11,7 +11,6 @@ struct SleepCommand {
 }
 
 pub fn main() {
-    env_logger::init();
     let command = SleepCommand::parse();
     alfrusco::execute(&config::AlfredEnvProvider, command, &mut std::io::stdout());
 }
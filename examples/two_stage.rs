@@ -0,0 +1,95 @@
+use alfrusco::{config, ActionRunnable, Item, Output, Runnable, Workflow};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// The variables each item carries from the Script Filter to the Run
+/// Script action connected to it, which runs this binary again.
+#[derive(Serialize, Deserialize)]
+struct Link {
+    title: String,
+    url: String,
+    visits: u32,
+}
+
+#[derive(Parser)]
+struct LinkFilter {
+    query: Vec<String>,
+}
+
+struct OpenLink;
+
+pub fn main() {
+    alfrusco::dispatch(
+        &config::AlfredEnvProvider,
+        LinkFilter::parse(),
+        OpenLink,
+        &mut std::io::stdout(),
+    );
+}
+
+impl Runnable for LinkFilter {
+    type Error = alfrusco::Error;
+    fn run(self, wf: &mut Workflow) -> Result<(), Self::Error> {
+        wf.set_filter_keyword(self.query.join(" "));
+        let links = [
+            ("Rust", "https://www.rust-lang.org", 12),
+            ("Alfred", "https://www.alfredapp.com", 3),
+        ];
+        for (title, url, visits) in links {
+            let link = Link {
+                title: title.to_string(),
+                url: url.to_string(),
+                visits,
+            };
+            wf.append_item(Item::new(title).subtitle(url).action_vars(&link));
+        }
+        Ok(())
+    }
+}
+
+impl ActionRunnable for OpenLink {
+    type Vars = Link;
+    type Error = alfrusco::Error;
+    fn run_action(self, link: Link, wf: &mut Workflow) -> Result<(), Self::Error> {
+        // The arg goes on to e.g. an Open URL object; the variable can be
+        // used as {var:message} in a Post Notification
+        wf.output(Output::new().arg(&link.url).var(
+            "message",
+            format!("Opening {} (visit {})", link.title, link.visits + 1),
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alfrusco::test_support::WorkflowCommand;
+    use alfrusco::{ACTION_STAGE_VAR, ACTION_VARS_VAR};
+
+    #[test]
+    fn test_two_stages() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = WorkflowCommand::example("two_stage", dir.path())
+            .arg("rust")
+            .run()
+            .unwrap()
+            .assert_success();
+        let items = output.items();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].get_title(), "Rust");
+
+        // Alfred passes the chosen item's variables to the action stage
+        let mut action = WorkflowCommand::example("two_stage", dir.path());
+        for name in [ACTION_STAGE_VAR, ACTION_VARS_VAR, "title"] {
+            action = action.env(name, items[0].get_var(name).unwrap());
+        }
+        let output = action.run().unwrap();
+        assert!(output.status.success(), "{}", output.stderr);
+        let json: serde_json::Value = serde_json::from_str(&output.stdout).unwrap();
+        assert_eq!(json["alfredworkflow"]["arg"], "https://www.rust-lang.org");
+        assert_eq!(
+            json["alfredworkflow"]["variables"]["message"],
+            "Opening Rust (visit 13)"
+        );
+    }
+}
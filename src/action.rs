@@ -0,0 +1,430 @@
+//! Two-stage workflows: a Script Filter and the action run on the item the
+//! user chooses, handled by the same binary.
+
+use log::{error, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::{ConfigProvider, EnvMap};
+use crate::{
+    finalize_workflow, run_runnable, setup_workflow, Error, Hook, Item, Key, Modifier, Runnable,
+    RunnableError, Workflow,
+};
+
+/// The variable marking a run as the action stage. Item::action_vars sets
+/// it to "action".
+pub const ACTION_STAGE_VAR: &str = "ALFRUSCO_STAGE";
+
+/// The variable carrying the JSON encoded action variables.
+pub const ACTION_VARS_VAR: &str = "ALFRUSCO_ACTION_VARS";
+
+/// The variable naming the modifier keys held when the item was actioned,
+/// e.g. "cmd" or "cmd+shift". Modifier::action_vars sets it, and
+/// Item::action_vars sets it empty. It can also be set on a modifier's
+/// connection in Alfred. See ActionContext.
+pub const ACTION_MODIFIER_VAR: &str = "ALFRUSCO_MODIFIER";
+
+const ACTION_STAGE: &str = "action";
+
+/// ActionContext describes how the item being actioned was chosen: which
+/// modifier keys were held, as recorded in ACTION_MODIFIER_VAR.
+///
+/// ```
+/// use alfrusco::{ActionContext, Key};
+/// use alfrusco::config::EnvMap;
+///
+/// let env: EnvMap = [("ALFRUSCO_MODIFIER", "cmd+shift")].into_iter().collect();
+/// let context = ActionContext::from_env(&env);
+/// assert!(context.held(Key::Cmd));
+/// assert!(context.matches(&[Key::Shift, Key::Cmd]));
+/// assert!(!context.is_plain());
+/// ```
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ActionContext {
+    modifiers: Vec<Key>,
+}
+
+impl ActionContext {
+    /// Reads the held modifier keys from `env`. Unknown key names are
+    /// logged and skipped.
+    pub fn from_env(env: &EnvMap) -> Self {
+        let modifiers = env
+            .get(ACTION_MODIFIER_VAR)
+            .unwrap_or_default()
+            .split('+')
+            .filter(|name| !name.trim().is_empty())
+            .filter_map(|name| {
+                name.parse()
+                    .map_err(|e| warn!("ignoring {}: {}", ACTION_MODIFIER_VAR, e))
+                    .ok()
+            })
+            .collect();
+        ActionContext { modifiers }
+    }
+
+    /// The modifier keys that were held, in the order they were named.
+    pub fn modifiers(&self) -> &[Key] {
+        &self.modifiers
+    }
+
+    /// Whether `key` was held, alone or with other keys.
+    pub fn held(&self, key: Key) -> bool {
+        self.modifiers.contains(&key)
+    }
+
+    /// Whether exactly the keys in `keys` were held, in any order.
+    pub fn matches(&self, keys: &[Key]) -> bool {
+        keys.len() == self.modifiers.len() && keys.iter().all(|key| self.held(*key))
+    }
+
+    /// Whether the item was actioned without any modifier keys.
+    pub fn is_plain(&self) -> bool {
+        self.modifiers.is_empty()
+    }
+}
+
+impl Workflow {
+    /// Returns the modifier keys held when the item was actioned, for
+    /// action stage code. See ActionContext.
+    pub fn action_context(&self) -> ActionContext {
+        ActionContext::from_env(&self.config.env)
+    }
+}
+
+/// ActionRunnable is the action stage of a two-stage workflow, run with
+/// the variables of the item the user chose.
+///
+/// The Script Filter stage attaches typed variables to its items with
+/// Item::action_vars. Alfred passes them to the Run Script action
+/// connected to the Script Filter, which invokes the binary again;
+/// dispatch sees ACTION_STAGE_VAR and runs the ActionRunnable with the
+/// variables decoded back into their type.
+///
+/// ```
+/// use alfrusco::{ActionRunnable, Error, Item, Output, Runnable, Workflow};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Repo {
+///     name: String,
+///     stars: u32,
+/// }
+///
+/// struct Search;
+///
+/// impl Runnable for Search {
+///     type Error = Error;
+///
+///     fn run(self, workflow: &mut Workflow) -> Result<(), Error> {
+///         let repo = Repo { name: "adlio/alfrusco".to_string(), stars: 42 };
+///         workflow.append_item(Item::new(&repo.name).action_vars(&repo));
+///         Ok(())
+///     }
+/// }
+///
+/// struct Star;
+///
+/// impl ActionRunnable for Star {
+///     type Vars = Repo;
+///     type Error = Error;
+///
+///     fn run_action(self, repo: Repo, workflow: &mut Workflow) -> Result<(), Error> {
+///         workflow.output(Output::new().arg(format!("Starred {} ({})", repo.name, repo.stars + 1)));
+///         Ok(())
+///     }
+/// }
+///
+/// // In main:
+/// // alfrusco::dispatch(&alfrusco::config::AlfredEnvProvider, Search, Star, &mut std::io::stdout());
+/// ```
+///
+pub trait ActionRunnable {
+    /// The variables the Script Filter stage attached to its items.
+    type Vars: DeserializeOwned;
+    type Error: RunnableError;
+
+    /// Lifecycle hooks to register before the action runs.
+    fn hooks(&self) -> Vec<Box<dyn Hook>> {
+        Vec::new()
+    }
+
+    /// Performs the action. Set an Output on the workflow to pass an arg
+    /// or variables on to the objects after the Run Script action.
+    fn run_action(
+        self,
+        vars: Self::Vars,
+        workflow: &mut Workflow,
+    ) -> std::result::Result<(), Self::Error>;
+}
+
+/// Runs `filter` as a Script Filter, or `action` when the run is the
+/// action stage for an item carrying action_vars.
+///
+/// A failing action can't show an error item, since Alfred isn't showing
+/// results any more, so the error is logged and posted as a notification
+/// instead.
+pub fn dispatch<F: Runnable, A: ActionRunnable>(
+    provider: &dyn ConfigProvider,
+    filter: F,
+    action: A,
+    writer: &mut dyn std::io::Write,
+) {
+    if let Some(workflow) = setup_workflow(provider, writer) {
+        run_stage(workflow, filter, action, writer)
+    }
+}
+
+fn run_stage<F: Runnable, A: ActionRunnable>(
+    mut workflow: Workflow,
+    filter: F,
+    action: A,
+    writer: &mut dyn std::io::Write,
+) {
+    if !is_action_stage(&workflow) {
+        run_runnable(&mut workflow, filter);
+        return finalize_workflow(workflow, writer);
+    }
+
+    workflow.add_boxed_hooks(action.hooks());
+    workflow.fire_start();
+    match action_vars::<A::Vars>(&workflow) {
+        Ok(vars) => {
+            if let Err(e) = action.run_action(vars, &mut workflow) {
+                report_action_error(&mut workflow, &e);
+            }
+        }
+        Err(e) => report_action_error(&mut workflow, &e),
+    }
+    // Clear the stage so Script Filters further downstream run as filters
+    let output = workflow.output.take().unwrap_or_default();
+    workflow.output(output.var(ACTION_STAGE_VAR, ""));
+    finalize_workflow(workflow, writer);
+}
+
+fn is_action_stage(workflow: &Workflow) -> bool {
+    workflow.config.env.get(ACTION_STAGE_VAR) == Some(ACTION_STAGE)
+}
+
+/// Decodes the action variables from the environment.
+fn action_vars<T: DeserializeOwned>(workflow: &Workflow) -> crate::Result<T> {
+    let json = workflow
+        .config
+        .env
+        .get(ACTION_VARS_VAR)
+        .ok_or_else(|| Error::MissingEnvVar(ACTION_VARS_VAR.to_string()))?;
+    serde_json::from_str(json)
+        .map_err(|e| Error::Workflow(format!("invalid {}: {}", ACTION_VARS_VAR, e)))
+}
+
+/// Reports an error from an action run, which has no Script Filter to show
+/// an error item in, as a notification.
+pub(crate) fn report_action_error(workflow: &mut Workflow, e: &dyn RunnableError) {
+    workflow.fire_error(e.as_error());
+    error!("action failed: {}", e.as_error());
+    let item = crate::i18n::with_strings(&workflow.strings, || e.to_error_item());
+    let message = item.get_subtitle().unwrap_or_default().to_string();
+    if let Err(e) = workflow.notify(item.get_title(), &message) {
+        warn!("unable to post the action's error: {}", e);
+    }
+}
+
+/// Returns the variables carrying `vars` to the action stage: the stage
+/// marker, the JSON encoding, and each top-level field as its own
+/// variable (strings as is, other values as JSON) for use as {var:name}
+/// in other workflow objects.
+fn encode_action_vars<T: Serialize>(vars: &T) -> Vec<(String, String)> {
+    let value = match serde_json::to_value(vars) {
+        Ok(value) => value,
+        Err(e) => {
+            warn!("unable to encode action variables: {}", e);
+            return Vec::new();
+        }
+    };
+    let mut encoded = vec![
+        (ACTION_STAGE_VAR.to_string(), ACTION_STAGE.to_string()),
+        (ACTION_VARS_VAR.to_string(), value.to_string()),
+    ];
+    if let Value::Object(fields) = value {
+        for (name, field) in fields {
+            let field = match field {
+                Value::String(text) => text,
+                other => other.to_string(),
+            };
+            encoded.push((name, field));
+        }
+    }
+    encoded
+}
+
+impl Item {
+    /// Attaches `vars` for the action stage of a two-stage workflow (see
+    /// ActionRunnable), and makes the item valid. Vars must serialize
+    /// to a JSON object, such as a struct with named fields.
+    pub fn action_vars<T: Serialize>(self, vars: &T) -> Self {
+        encode_action_vars(vars)
+            .into_iter()
+            .fold(self.valid(true), |item, (name, value)| {
+                item.var(name, value)
+            })
+            .var(ACTION_MODIFIER_VAR, "")
+    }
+}
+
+impl Modifier {
+    /// Attaches `vars` for the action stage when the item is actioned
+    /// with this modifier held, recording the keys in ACTION_MODIFIER_VAR.
+    /// See Item::action_vars.
+    pub fn action_vars<T: Serialize>(self, vars: &T) -> Self {
+        let keys = self.keys.clone();
+        encode_action_vars(vars)
+            .into_iter()
+            .fold(self.valid(true), |modifier, (name, value)| {
+                modifier.var(name, value)
+            })
+            .var(ACTION_MODIFIER_VAR, keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::test_support::workflow_in;
+    use crate::{MockSystemActions, Output, SystemCall};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Repo {
+        name: String,
+        stars: u32,
+        archived: bool,
+    }
+
+    fn repo() -> Repo {
+        Repo {
+            name: "adlio/alfrusco".to_string(),
+            stars: 42,
+            archived: false,
+        }
+    }
+
+    struct Search;
+
+    impl Runnable for Search {
+        type Error = Error;
+
+        fn run(self, workflow: &mut Workflow) -> Result<(), Error> {
+            workflow.append_item(Item::new("alfrusco").action_vars(&repo()));
+            Ok(())
+        }
+    }
+
+    struct Star(Rc<RefCell<Option<Repo>>>);
+
+    impl ActionRunnable for Star {
+        type Vars = Repo;
+        type Error = Error;
+
+        fn run_action(self, repo: Repo, workflow: &mut Workflow) -> Result<(), Error> {
+            workflow.output(Output::new().arg(format!("Starred {}", repo.name)));
+            *self.0.borrow_mut() = Some(repo);
+            Ok(())
+        }
+    }
+
+    fn run(env: &[(&str, &str)]) -> (String, Option<Repo>, Vec<SystemCall>) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        let mock = MockSystemActions::new();
+        workflow.set_system_actions(mock.clone());
+        for (name, value) in env {
+            workflow.config.env.set(*name, *value);
+        }
+        let starred = Rc::default();
+        let mut buffer = Vec::new();
+        run_stage(workflow, Search, Star(Rc::clone(&starred)), &mut buffer);
+        let starred = starred.borrow().clone();
+        (String::from_utf8(buffer).unwrap(), starred, mock.calls())
+    }
+
+    #[test]
+    fn test_action_vars() {
+        let item = Item::new("alfrusco").action_vars(&repo());
+        assert_eq!(item.get_var(ACTION_STAGE_VAR), Some("action"));
+        assert_eq!(item.get_var("name"), Some("adlio/alfrusco"));
+        assert_eq!(item.get_var("stars"), Some("42"));
+        assert_eq!(item.get_var("archived"), Some("false"));
+        let json = item.get_var(ACTION_VARS_VAR).unwrap();
+        assert_eq!(serde_json::from_str::<Repo>(json).unwrap(), repo());
+        assert_eq!(item.valid, Some(true));
+
+        assert_eq!(item.get_var(ACTION_MODIFIER_VAR), Some(""));
+
+        let modifier = Modifier::new_combo(&[Key::Cmd, Key::Shift]).action_vars(&repo());
+        let json = serde_json::to_value(&modifier).unwrap();
+        assert_eq!(json["variables"]["stars"], "42");
+        assert_eq!(json["variables"]["ALFRUSCO_MODIFIER"], "cmd+shift");
+    }
+
+    #[test]
+    fn test_action_context() {
+        let context = |value: &str| {
+            let env: EnvMap = [(ACTION_MODIFIER_VAR, value)].into_iter().collect();
+            ActionContext::from_env(&env)
+        };
+        assert!(context("").is_plain());
+        assert!(ActionContext::from_env(&EnvMap::new()).is_plain());
+
+        let cmd_alt = context("cmd+alt");
+        assert_eq!(cmd_alt.modifiers(), [Key::Cmd, Key::Alt]);
+        assert!(cmd_alt.held(Key::Alt));
+        assert!(!cmd_alt.held(Key::Shift));
+        assert!(cmd_alt.matches(&[Key::Alt, Key::Cmd]));
+        assert!(!cmd_alt.matches(&[Key::Cmd]));
+
+        // Unknown keys are skipped
+        assert_eq!(context("hyper+ctrl").modifiers(), [Key::Ctrl]);
+    }
+
+    #[test]
+    fn test_dispatch_filter_stage() {
+        let (output, starred, _) = run(&[]);
+        let response: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(response["items"][0]["title"], "alfrusco");
+        assert_eq!(
+            response["items"][0]["variables"]["ALFRUSCO_STAGE"],
+            "action"
+        );
+        assert!(starred.is_none());
+    }
+
+    #[test]
+    fn test_dispatch_action_stage() {
+        let vars = serde_json::to_string(&repo()).unwrap();
+        let (output, starred, calls) =
+            run(&[(ACTION_STAGE_VAR, "action"), (ACTION_VARS_VAR, &vars)]);
+        assert!(calls.is_empty());
+        assert_eq!(starred, Some(repo()));
+        let output: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(output["alfredworkflow"]["arg"], "Starred adlio/alfrusco");
+        assert_eq!(output["alfredworkflow"]["variables"]["ALFRUSCO_STAGE"], "");
+    }
+
+    #[test]
+    fn test_dispatch_invalid_vars() {
+        let (output, starred, calls) =
+            run(&[(ACTION_STAGE_VAR, "action"), (ACTION_VARS_VAR, "{}")]);
+        assert!(starred.is_none());
+        // The error is posted as a notification
+        assert!(matches!(
+            &calls[..],
+            [SystemCall::RunOsascript(script)] if script.contains("missing field")
+        ));
+        assert!(output.starts_with(r#"{"alfredworkflow":"#));
+    }
+}
@@ -0,0 +1,80 @@
+use crate::workflow::Workflow;
+
+impl Workflow {
+    /// Returns `query` prefixed with the Script Filter's keyword, suitable
+    /// for Item::autocomplete when Tab should keep the user inside this
+    /// Script Filter. Returns `query` unchanged when the workflow has no
+    /// keyword (e.g. it was started by a hotkey).
+    pub fn keyword_autocomplete(&self, query: &str) -> String {
+        match self.config.workflow_keyword.as_deref() {
+            Some(keyword) if !keyword.is_empty() => {
+                let prefix = format!("{} ", keyword);
+                if query.starts_with(&prefix) {
+                    query.to_string()
+                } else {
+                    format!("{}{}", prefix, query)
+                }
+            }
+            _ => query.to_string(),
+        }
+    }
+
+    /// Prefixes the keyword to the autocomplete of items built with
+    /// Item::autocomplete_query. Called automatically when the workflow
+    /// finishes.
+    pub(crate) fn apply_keyword_autocomplete(&mut self) {
+        let mut items = std::mem::take(&mut self.response.items);
+        for item in &mut items {
+            if !item.autocomplete_keyword {
+                continue;
+            }
+            if let Some(query) = &item.autocomplete {
+                item.autocomplete = Some(self.keyword_autocomplete(query));
+            }
+        }
+        self.response.items = items;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use crate::{Item, Workflow};
+
+    fn test_workflow(keyword: Option<&str>) -> (Workflow, TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = crate::test_support::workflow_in(dir.path());
+        workflow.config.workflow_keyword = keyword.map(str::to_string);
+        (workflow, dir)
+    }
+
+    #[test]
+    fn test_keyword_autocomplete() {
+        let (workflow, _dir) = test_workflow(Some("gh"));
+        assert_eq!(workflow.keyword_autocomplete("rust"), "gh rust");
+        assert_eq!(workflow.keyword_autocomplete("gh rust"), "gh rust");
+        assert_eq!(workflow.keyword_autocomplete(""), "gh ");
+
+        let (workflow, _dir) = test_workflow(None);
+        assert_eq!(workflow.keyword_autocomplete("rust"), "rust");
+    }
+
+    #[test]
+    fn test_autocomplete_query_items() {
+        let (mut workflow, _dir) = test_workflow(Some("gh"));
+        workflow.items(vec![
+            Item::new("Query").autocomplete_query("rust-lang/rust"),
+            Item::new("Plain").autocomplete("rust-lang/rust"),
+            Item::new("Overridden")
+                .autocomplete_query("ignored")
+                .autocomplete("kept"),
+        ]);
+        workflow.apply_keyword_autocomplete();
+
+        let items = &workflow.response.items;
+        assert_eq!(items[0].autocomplete.as_deref(), Some("gh rust-lang/rust"));
+        assert_eq!(items[1].autocomplete.as_deref(), Some("rust-lang/rust"));
+        assert_eq!(items[2].autocomplete.as_deref(), Some("kept"));
+    }
+}
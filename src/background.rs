@@ -1,9 +1,34 @@
 use std::path::PathBuf;
-use std::process::Command;
 use std::time::Duration;
 
-use crate::background_job::BackgroundJob;
+use humantime::format_duration;
+use log::{debug, error};
+
+use crate::background_job::{
+    BackgroundJob, BackgroundJobStatus, IntoCommand, JobInfo, JobOptions, JobReport, JobState,
+    JobStateSnapshot,
+};
 use crate::workflow::Workflow;
+use crate::{
+    InternalCommand, Item, Key, Modifier, Result, RunPolicy, ICON_ALERT_CAUTION_BADGE, ICON_CLOCK,
+};
+
+/// The internal query listing the workflow's background jobs.
+pub(crate) const JOBS_QUERY: &str = "workflow:jobs";
+
+/// The internal commands run by the workflow:jobs modifiers, and the
+/// argument naming their job.
+const JOB_REFRESH_COMMAND: &str = "job_refresh";
+const JOB_CANCEL_COMMAND: &str = "job_cancel";
+const JOB_ARG: &str = "job";
+
+/// The response variable set to "1" while any background job is pending,
+/// so downstream objects and reruns can branch on it.
+pub const JOBS_PENDING_VAR: &str = "ALFRUSCO_JOBS_PENDING";
+
+/// The response variable listing the keys of the pending jobs, separated
+/// by commas.
+pub const PENDING_JOBS_VAR: &str = "ALFRUSCO_PENDING_JOBS";
 
 impl Workflow {
     /// Ensure that a particular command is run at least as often as the
@@ -11,17 +36,587 @@ impl Workflow {
     /// the response items if the job is stale to inform the user that
     /// work is being done in the background to update results.
     ///
-    pub fn run_in_background(&mut self, job_key: &str, max_age: Duration, cmd: Command) {
-        let mut job = BackgroundJob::new(self, job_key, max_age, cmd);
-        let job_item = job.run();
-        if let Some(item) = job_item {
-            self.response.rerun(Duration::from_secs(1));
+    /// `cmd` may be a Command or a closure returning one. A closure is
+    /// only called when the job actually needs to run, so expensive
+    /// argument lists aren't built while the job is fresh.
+    ///
+    /// Returns the JobReport for the job, e.g. to show a different message
+    /// while the job's first run is in progress, or None if it couldn't be
+    /// checked or started (the error is shown as an item).
+    ///
+    pub fn run_in_background(
+        &mut self,
+        job_key: &str,
+        max_age: Duration,
+        cmd: impl IntoCommand,
+    ) -> Option<JobReport> {
+        self.run_in_background_with_options(job_key, max_age, cmd, JobOptions::default())
+    }
+
+    /// Like run_in_background, but with JobOptions controlling how the
+    /// command is spawned (e.g. its nice level, PATH or where its output
+    /// goes).
+    pub fn run_in_background_with_options(
+        &mut self,
+        job_key: &str,
+        max_age: Duration,
+        cmd: impl IntoCommand,
+        options: JobOptions,
+    ) -> Option<JobReport> {
+        self.run_in_background_with_policy(job_key, RunPolicy::Every(max_age), cmd, options)
+    }
+
+    /// Like run_in_background_with_options, with a RunPolicy deciding when
+    /// the job is due instead of a max age: once ever (for migrations), or
+    /// on a cron schedule (e.g. a daily sync).
+    ///
+    /// ```no_run
+    /// # use alfrusco::{JobOptions, RunPolicy, Workflow};
+    /// # use std::process::Command;
+    /// # fn example(workflow: &mut Workflow) -> alfrusco::Result<()> {
+    /// workflow.run_in_background_with_policy(
+    ///     "daily_sync",
+    ///     RunPolicy::cron("0 6 * * *")?,
+    ///     || Command::new("./sync"),
+    ///     JobOptions::default(),
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    pub fn run_in_background_with_policy(
+        &mut self,
+        job_key: &str,
+        policy: RunPolicy,
+        cmd: impl IntoCommand,
+        options: JobOptions,
+    ) -> Option<JobReport> {
+        let jobs_dir = self.jobs_dir();
+        let snapshot = self
+            .job_snapshot
+            .get_or_insert_with(|| JobStateSnapshot::read(&jobs_dir));
+        let report = BackgroundJob::new(&jobs_dir, job_key, policy).run_if_needed(
+            snapshot.get_mut(job_key),
+            cmd,
+            &options,
+        );
+        if report.as_ref().is_ok_and(JobReport::is_running) {
+            self.pending_jobs.push(job_key.to_string());
+            self.response
+                .var(JOBS_PENDING_VAR, "1")
+                .var(PENDING_JOBS_VAR, self.pending_jobs.join(","));
+        }
+        if let Some(item) = self.job_status_item(job_key, &report) {
+            // A failed job isn't retried until it is due, so there's
+            // nothing to wait for
+            let failed = report
+                .as_ref()
+                .is_ok_and(|r| matches!(r.status, BackgroundJobStatus::Failed(..)));
+            if !failed {
+                self.response.rerun(Duration::from_secs(1));
+            }
+            self.job_status_items.push(item.clone());
             self.response.prepend_items(vec![item]);
         }
+        report.ok()
+    }
+
+    /// Builds the item informing the user about a stale or failed job.
+    /// Fresh jobs get no item.
+    fn job_status_item(&self, job_key: &str, report: &Result<JobReport>) -> Option<Item> {
+        use BackgroundJobStatus::*;
+
+        let title = self.t_with("job.title", &[("job", job_key)]);
+        let report = match report {
+            Ok(report) => report,
+            Err(e) => {
+                error!("Error starting job '{}': {}", job_key, e);
+                let subtitle = self.t_with("job.error", &[("error", &e.to_string())]);
+                return Some(Item::new(title).subtitle(subtitle));
+            }
+        };
+        match report.status {
+            Fresh(staleness) => {
+                debug!(
+                    "Job '{}' is fresh, last run {}",
+                    job_key,
+                    format_duration(staleness)
+                );
+                None
+            }
+            Stale(Some(staleness), duration) => {
+                debug!(
+                    "Job '{}' is stale. Last run {} ago, running for {}",
+                    job_key,
+                    format_duration(staleness),
+                    format_duration(duration),
+                );
+                // Truncate to milliseconds
+                let staleness = Duration::from_millis(staleness.as_millis() as u64);
+                let duration = Duration::from_millis(duration.as_millis() as u64);
+                let subtitle = match report.failed_exit_code() {
+                    Some(code) => self.t_with(
+                        "job.retrying",
+                        &[
+                            ("code", &code.to_string()),
+                            ("duration", &format_duration(duration).to_string()),
+                        ],
+                    ),
+                    None => self.t_with(
+                        "job.stale",
+                        &[
+                            ("staleness", &format_duration(staleness).to_string()),
+                            ("duration", &format_duration(duration).to_string()),
+                        ],
+                    ),
+                };
+                Some(
+                    Item::new(title)
+                        .subtitle(subtitle)
+                        .icon(ICON_CLOCK.into())
+                        .valid(false),
+                )
+            }
+            Stale(None, duration) => {
+                debug!(
+                    "Job '{}' has never run before, running for {}",
+                    job_key,
+                    format_duration(duration)
+                );
+                let subtitle = self.t_with(
+                    "job.never_run",
+                    &[("duration", &format_duration(duration).to_string())],
+                );
+                Some(
+                    Item::new(title)
+                        .subtitle(subtitle)
+                        .icon(ICON_CLOCK.into())
+                        .valid(false),
+                )
+            }
+            Failed(staleness, code) => {
+                debug!(
+                    "Job '{}' failed with exit status {} {} ago",
+                    job_key,
+                    code,
+                    format_duration(staleness)
+                );
+                let staleness = Duration::from_secs(staleness.as_secs());
+                let subtitle = self.t_with(
+                    "job.failed",
+                    &[
+                        ("code", &code.to_string()),
+                        ("staleness", &format_duration(staleness).to_string()),
+                    ],
+                );
+                Some(
+                    Item::new(title)
+                        .subtitle(subtitle)
+                        .icon(ICON_ALERT_CAUTION_BADGE.into())
+                        .valid(false),
+                )
+            }
+        }
+    }
+
+    /// Returns true if any job started with run_in_background during this
+    /// execution is stale and still running.
+    pub fn any_jobs_pending(&self) -> bool {
+        !self.pending_jobs.is_empty()
+    }
+
+    /// The keys of the jobs that are stale and still running.
+    pub fn pending_jobs(&self) -> &[String] {
+        &self.pending_jobs
+    }
+
+    /// Returns what is known about every background job that has been
+    /// started, sorted by job key. This reads the jobs directory afresh, so
+    /// it reflects jobs that finished since this execution started.
+    pub fn jobs(&self) -> Vec<JobInfo> {
+        let jobs_dir = self.jobs_dir();
+        JobStateSnapshot::read(&jobs_dir)
+            .names()
+            .iter()
+            .filter_map(|name| JobInfo::read(&jobs_dir, name))
+            .collect()
+    }
+
+    /// Returns what is known about the background job `job_key`, or None
+    /// if it has never been started.
+    pub fn job(&self, job_key: &str) -> Option<JobInfo> {
+        JobInfo::read(&self.jobs_dir(), job_key)
+    }
+
+    /// Makes the background job `job_key` run the next time it is checked
+    /// by run_in_background, however recently it last ran.
+    pub fn refresh_job(&mut self, job_key: &str) -> Result<()> {
+        self.with_job(job_key, |job, state| job.invalidate(state))
+    }
+
+    /// Stops the current run of the background job `job_key` with SIGTERM.
+    /// The job isn't started again until it is due. Returns true if the job
+    /// was running.
+    pub fn cancel_job(&mut self, job_key: &str) -> Result<bool> {
+        self.with_job(job_key, |job, state| job.cancel(state))
+    }
+
+    fn with_job<T>(
+        &mut self,
+        job_key: &str,
+        f: impl FnOnce(&BackgroundJob, &mut JobState) -> Result<T>,
+    ) -> Result<T> {
+        let jobs_dir = self.jobs_dir();
+        let snapshot = self
+            .job_snapshot
+            .get_or_insert_with(|| JobStateSnapshot::read(&jobs_dir));
+        // The policy only matters when deciding whether to run the job
+        let job = BackgroundJob::new(&jobs_dir, job_key, RunPolicy::Once);
+        f(&job, snapshot.get_mut(job_key))
+    }
+
+    /// Runs the refresh or cancel command from a workflow:jobs item's
+    /// modifier, posting a notification with the outcome. Returns true if
+    /// there was one.
+    pub(crate) fn handle_job_command(&mut self) -> bool {
+        let Some(command) = InternalCommand::from_env(&self.config.env) else {
+            return false;
+        };
+        let job = command.get(JOB_ARG).unwrap_or_default().to_string();
+        let result = match command.command() {
+            JOB_REFRESH_COMMAND => self.refresh_job(&job).map(|_| "jobs.refreshed"),
+            JOB_CANCEL_COMMAND => self.cancel_job(&job).map(|stopped| {
+                if stopped {
+                    "jobs.cancelled"
+                } else {
+                    "jobs.not_running"
+                }
+            }),
+            _ => return false,
+        };
+        let message = match result {
+            Ok(key) => self.strings.format(key, &[("job", &job)]),
+            Err(e) => {
+                error!("unable to update job '{}': {}", job, e);
+                self.strings
+                    .format("jobs.failed", &[("job", &job), ("error", &e.to_string())])
+            }
+        };
+        let name = self.config.workflow_name.clone();
+        if let Err(e) = self.notify(&name, &message) {
+            error!("unable to post the job notification: {}", e);
+        }
+        true
+    }
+
+    /// Replaces the response items with one item per background job,
+    /// showing when it last ran and the command it runs. Holding cmd
+    /// refreshes the job, alt cancels it while it runs and ctrl opens its
+    /// output.
+    pub(crate) fn show_jobs(&mut self) {
+        debug!("listing background jobs");
+        let jobs = self.jobs();
+        let items = if jobs.is_empty() {
+            vec![Item::new(self.strings.get("jobs.empty")).valid(false)]
+        } else {
+            jobs.iter().map(|job| self.job_item(job)).collect()
+        };
+        self.response.items = items;
+    }
+
+    fn job_item(&self, job: &JobInfo) -> Item {
+        let seconds = |d: Duration| format_duration(Duration::from_secs(d.as_secs())).to_string();
+        let status = match (job.running_for, job.last_run) {
+            (Some(running_for), _) => self
+                .strings
+                .format("jobs.running", &[("duration", &seconds(running_for))]),
+            (None, Some(last_run)) => {
+                let staleness = seconds(last_run.elapsed().unwrap_or_default());
+                match job.last_exit_code.filter(|code| *code != 0) {
+                    Some(code) => self.strings.format(
+                        "jobs.failed_run",
+                        &[("staleness", &staleness), ("code", &code.to_string())],
+                    ),
+                    None => self
+                        .strings
+                        .format("jobs.last_run", &[("staleness", &staleness)]),
+                }
+            }
+            (None, None) => self.strings.get("jobs.never_run").to_string(),
+        };
+        let mut item = Item::new(&job.name).valid(false);
+        let subtitle = match &job.command {
+            Some(command) => {
+                let command_line = command.command_line();
+                item = item.copy_text(&command_line);
+                let command = self.strings.format(
+                    "jobs.command",
+                    &[("policy", &command.policy), ("command", &command_line)],
+                );
+                format!("{} · {}", status, command)
+            }
+            None => status,
+        };
+        let job_command = |command| InternalCommand::new(command).arg(JOB_ARG, &job.name);
+        item = item.modifier(
+            Modifier::new(Key::Cmd)
+                .subtitle(self.strings.get("jobs.refresh"))
+                .internal_command(job_command(JOB_REFRESH_COMMAND)),
+        );
+        if job.running_for.is_some() {
+            item = item.icon(ICON_CLOCK.into()).modifier(
+                Modifier::new(Key::Alt)
+                    .subtitle(self.strings.get("jobs.cancel"))
+                    .internal_command(job_command(JOB_CANCEL_COMMAND)),
+            );
+        }
+        if let Some(log_file) = &job.log_file {
+            item = item.modifier(
+                Modifier::new(Key::Ctrl)
+                    .subtitle(self.strings.get("jobs.open_log"))
+                    .internal_command(
+                        InternalCommand::new("open_path")
+                            .arg("open_path", log_file.to_string_lossy()),
+                    ),
+            );
+        }
+        item.subtitle(subtitle)
     }
 
     /// Returns the path to the cache subdirectory where jobs data is held
     pub fn jobs_dir(&self) -> PathBuf {
-        self.config.workflow_cache.join("jobs")
+        self.reserved_cache_dir("jobs")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+
+    #[test]
+    fn test_pending_jobs_set_response_variables() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = crate::test_support::workflow_in(dir.path());
+        assert!(!workflow.any_jobs_pending());
+
+        let mut cmd = Command::new("sleep");
+        cmd.arg("1");
+        workflow.run_in_background("refresh", Duration::from_secs(60), cmd);
+
+        assert!(workflow.any_jobs_pending());
+        assert_eq!(workflow.pending_jobs(), ["refresh"]);
+        assert_eq!(workflow.response.variables[JOBS_PENDING_VAR], "1");
+        assert_eq!(workflow.response.variables[PENDING_JOBS_VAR], "refresh");
+    }
+
+    #[test]
+    fn test_command_closure_not_called_when_fresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = crate::test_support::workflow_in(dir.path());
+        let job_dir = workflow.jobs_dir().join("refresh");
+        std::fs::create_dir_all(&job_dir).unwrap();
+        std::fs::write(job_dir.join("job.last_run"), "").unwrap();
+
+        workflow.run_in_background("refresh", Duration::from_secs(60), || -> Command {
+            panic!("command built for a fresh job")
+        });
+
+        assert!(!workflow.any_jobs_pending());
+        assert!(workflow.response.items.is_empty());
+    }
+
+    #[test]
+    fn test_run_once_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = crate::test_support::workflow_in(dir.path());
+        let job_dir = workflow.jobs_dir().join("migrate");
+        std::fs::create_dir_all(&job_dir).unwrap();
+        std::fs::write(job_dir.join("job.last_run"), "").unwrap();
+        let old = std::time::SystemTime::now() - Duration::from_secs(365 * 24 * 60 * 60);
+        std::fs::File::options()
+            .write(true)
+            .open(job_dir.join("job.last_run"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+
+        workflow.run_in_background_with_policy(
+            "migrate",
+            RunPolicy::Once,
+            || -> Command { panic!("migration ran twice") },
+            JobOptions::default(),
+        );
+        assert!(!workflow.any_jobs_pending());
+    }
+
+    #[test]
+    fn test_jobs_listing() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = crate::test_support::workflow_in(dir.path());
+        workflow.set_filter_keyword(JOBS_QUERY.to_string());
+        workflow.handle_internal_query();
+        assert_eq!(
+            workflow.response.items[0].get_title(),
+            "No background jobs have run"
+        );
+
+        let mut cmd = Command::new("sleep");
+        cmd.args(["5", "a b"]);
+        workflow.run_in_background("refresh", Duration::from_secs(60), cmd);
+        assert_eq!(workflow.job("missing"), None);
+        let jobs = workflow.jobs();
+        assert_eq!(jobs.len(), 1);
+        assert!(jobs[0].running_for.is_some());
+        let command = jobs[0].command.as_ref().unwrap();
+        assert_eq!(command.argv, vec!["sleep", "5", "a b"]);
+        assert_eq!(command.policy, "every 1m");
+
+        workflow.handle_internal_query();
+        let item = &workflow.response.items[0];
+        assert_eq!(item.get_title(), "refresh");
+        let subtitle = item.subtitle.as_deref().unwrap();
+        assert!(subtitle.starts_with("Running for"), "{}", subtitle);
+        assert!(
+            subtitle.ends_with(" · every 1m: sleep 5 'a b'"),
+            "{}",
+            subtitle
+        );
+        workflow.cancel_job("refresh").unwrap();
+    }
+
+    #[test]
+    fn test_jobs_listing_modifiers() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = crate::test_support::workflow_in(dir.path());
+        let mut cmd = Command::new("sleep");
+        cmd.arg("30");
+        workflow.run_in_background("refresh", Duration::from_secs(60), cmd);
+        workflow.set_filter_keyword(JOBS_QUERY.to_string());
+        workflow.handle_internal_query();
+
+        let item = &workflow.response.items[0];
+        let var = |key: &str| {
+            item.modifiers
+                .get(key)
+                .and_then(|m| m.variables.as_ref())
+                .and_then(|vars| vars.get(crate::INTERNAL_VAR).cloned())
+        };
+        assert_eq!(
+            var("cmd").as_deref(),
+            Some(r#"{"command":"job_refresh","job":"refresh"}"#)
+        );
+        assert_eq!(
+            var("alt").as_deref(),
+            Some(r#"{"command":"job_cancel","job":"refresh"}"#)
+        );
+        let log_file = workflow.jobs_dir().join("refresh/job.log");
+        let open_log =
+            InternalCommand::new("open_path").arg("open_path", log_file.to_string_lossy());
+        assert_eq!(var("ctrl"), Some(open_log.to_json()));
+        workflow.cancel_job("refresh").unwrap();
+    }
+
+    #[test]
+    fn test_handle_job_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = crate::test_support::workflow_in(dir.path());
+        let mock = crate::MockSystemActions::new();
+        workflow.set_system_actions(mock.clone());
+        assert!(!workflow.handle_job_command());
+
+        let mut cmd = Command::new("sleep");
+        cmd.arg("30");
+        workflow.run_in_background("refresh", Duration::from_secs(60), cmd);
+        let run = |workflow: &mut Workflow, command: &str| {
+            let command = InternalCommand::new(command).arg(JOB_ARG, "refresh");
+            workflow
+                .config
+                .env
+                .set(crate::INTERNAL_VAR, command.to_json());
+            assert!(workflow.handle_job_command());
+        };
+
+        run(&mut workflow, JOB_CANCEL_COMMAND);
+        let job = workflow.job("refresh").unwrap();
+        assert_eq!(job.running_for, None);
+        assert!(job.last_run.is_some());
+
+        run(&mut workflow, JOB_CANCEL_COMMAND);
+        run(&mut workflow, JOB_REFRESH_COMMAND);
+        assert_eq!(workflow.job("refresh").unwrap().last_run, None);
+
+        let messages: Vec<String> = mock
+            .calls()
+            .into_iter()
+            .map(|call| match call {
+                crate::SystemCall::RunOsascript(script) => script,
+                other => panic!("unexpected call {:?}", other),
+            })
+            .collect();
+        assert!(messages[0].contains("Stopped 'refresh'"));
+        assert!(messages[1].contains("'refresh' wasn't running"));
+        assert!(messages[2].contains("'refresh' will run the next time it is checked"));
+    }
+
+    #[test]
+    fn test_failed_job_item() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = crate::test_support::workflow_in(dir.path());
+        let job_dir = workflow.jobs_dir().join("refresh");
+        std::fs::create_dir_all(&job_dir).unwrap();
+        std::fs::write(job_dir.join("job.last_run"), "").unwrap();
+        std::fs::write(job_dir.join("job.last_exit"), "3").unwrap();
+        std::fs::write(job_dir.join("job.failures"), "1").unwrap();
+
+        let report = workflow
+            .run_in_background("refresh", Duration::from_secs(60), || -> Command {
+                panic!("command built for a job that isn't due")
+            })
+            .unwrap();
+        assert_eq!(report.failed_exit_code(), Some(3));
+        assert!(!workflow.any_jobs_pending());
+        assert_eq!(workflow.response.rerun, None);
+        let subtitle = workflow.response.items[0].subtitle.clone().unwrap();
+        assert_eq!(subtitle, "Last refresh failed (exit 3) 0s ago");
+
+        assert_eq!(workflow.job("refresh").unwrap().last_exit_code, Some(3));
+        workflow.set_filter_keyword(JOBS_QUERY.to_string());
+        workflow.handle_internal_query();
+        let subtitle = workflow.response.items[0].subtitle.clone().unwrap();
+        assert_eq!(subtitle, "Last run 0s ago failed (exit 3)");
+    }
+
+    #[test]
+    fn test_first_run_item() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = crate::test_support::workflow_in(dir.path());
+        let report = workflow
+            .run_in_background("setup", Duration::from_secs(60), Command::new("true"))
+            .unwrap();
+        assert!(report.is_first_run());
+        let subtitle = workflow.response.items[0].subtitle.clone().unwrap();
+        assert!(
+            subtitle.starts_with("First run in progress"),
+            "{}",
+            subtitle
+        );
+    }
+
+    #[test]
+    fn test_command_closure_called_when_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = crate::test_support::workflow_in(dir.path());
+        let mut built = false;
+
+        workflow.run_in_background("refresh", Duration::from_secs(60), || {
+            built = true;
+            Command::new("true")
+        });
+
+        assert!(built);
+        assert!(workflow.any_jobs_pending());
+        assert_eq!(workflow.response.items.len(), 1);
     }
 }
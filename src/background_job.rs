@@ -1,248 +1,1442 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{OsStr, OsString};
 use std::fs::{self, create_dir_all, read_to_string, write, File, FileTimes};
-use std::path::PathBuf;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use chrono::{DateTime, Utc};
-use humantime::format_duration;
-use log::{debug, error};
-use sysinfo::System;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, Signal, System};
 
-use crate::workflow::Workflow;
-use crate::{Item, Result, ICON_CLOCK};
+use crate::logging::{rotate, MAX_LOG_FILE_BYTES};
+use crate::notify::BACKGROUND_VAR;
+use crate::{Result, RunPolicy};
 
 pub type RunDuration = Duration;
 pub type Staleness = Duration;
 
-pub(crate) struct BackgroundJob<'a> {
-    /// The unique identifier/name for this background job
-    id: &'a str,
+/// QosClass selects the macOS quality-of-service clamp applied to a
+/// background job via `taskpolicy -c`. Lower classes get less CPU and I/O
+/// priority and are more likely to run on efficiency cores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QosClass {
+    Utility,
+    Background,
+    Maintenance,
+}
+
+impl std::fmt::Display for QosClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QosClass::Utility => write!(f, "utility"),
+            QosClass::Background => write!(f, "background"),
+            QosClass::Maintenance => write!(f, "maintenance"),
+        }
+    }
+}
+
+/// JobOptions holds optional settings controlling how a background job's
+/// command is spawned.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JobOptions {
+    pub(crate) nice: Option<i32>,
+    pub(crate) qos: Option<QosClass>,
+    pub(crate) path_prepend: Vec<PathBuf>,
+    pub(crate) path: Option<OsString>,
+    pub(crate) failure_backoff: Option<FailureBackoff>,
+    pub(crate) stdout: Option<JobOutput>,
+    pub(crate) stderr: Option<JobOutput>,
+    pub(crate) max_log_bytes: Option<u64>,
+}
+
+/// JobOutput is where a background job's stdout or stderr goes. Output
+/// written to a file is appended to it, and the file is moved aside to
+/// `<file>.old` when a run starts once it is bigger than the options'
+/// max_log_bytes. The size is only checked then, so a single run can
+/// write past it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobOutput {
+    /// The job's own log file, job.log in its job directory, which is
+    /// what `workflow:jobs` opens. Where stdout goes by default.
+    Log,
+
+    /// The given file
+    File(PathBuf),
+
+    /// The workflow's own stdout or stderr. Where stderr goes by default,
+    /// so a job's errors show up in Alfred's debugger.
+    Inherit,
+
+    /// Nowhere
+    Discard,
+}
+
+/// FailureBackoff throttles the retries of a job whose runs fail (exit with
+/// a non-zero status), so a broken API isn't hit again on every keystroke.
+/// After `n` consecutive failures the job is retried `initial * 2^(n-1)`
+/// after the failed run started, up to `max`. A successful run resets it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FailureBackoff {
+    initial: Duration,
+    max: Duration,
+}
+
+impl FailureBackoff {
+    /// Doubles the delay after each consecutive failure, from `initial`
+    /// up to `max`.
+    pub fn exponential(initial: Duration, max: Duration) -> Self {
+        FailureBackoff {
+            initial,
+            max: max.max(initial),
+        }
+    }
+
+    /// Retries failed runs after the same delay every time.
+    pub fn fixed(delay: Duration) -> Self {
+        Self::exponential(delay, delay)
+    }
+
+    /// Returns how long after a failed run the job is retried, given the
+    /// number of consecutive failures. Zero if there were none.
+    pub fn delay(&self, failures: u32) -> Duration {
+        if failures == 0 {
+            return Duration::ZERO;
+        }
+        let factor = 2u32.saturating_pow(failures - 1);
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+/// Directories where Homebrew and other package managers install
+/// binaries, which aren't on the PATH Alfred gives workflows.
+pub const COMMON_PATHS: &[&str] = &["/opt/homebrew/bin", "/usr/local/bin"];
+
+impl JobOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the command with the given niceness (-20 to 19; higher values
+    /// are lower priority) via `nice -n`.
+    pub fn nice(mut self, nice: i32) -> Self {
+        self.nice = Some(nice.clamp(-20, 19));
+        self
+    }
+
+    /// Runs the command clamped to the given QoS class via `taskpolicy`.
+    /// This only has an effect on macOS.
+    pub fn qos(mut self, qos: QosClass) -> Self {
+        self.qos = Some(qos);
+        self
+    }
+
+    /// Retries the job on `backoff` when its runs fail, instead of on its
+    /// RunPolicy. Without a backoff, a failed run counts as a run like any
+    /// other.
+    pub fn failure_backoff(mut self, backoff: FailureBackoff) -> Self {
+        self.failure_backoff = Some(backoff);
+        self
+    }
+
+    /// Sends the command's stdout to `output` instead of the job's log file.
+    pub fn stdout(mut self, output: JobOutput) -> Self {
+        self.stdout = Some(output);
+        self
+    }
+
+    /// Sends the command's stderr to `output` instead of the workflow's
+    /// stderr.
+    pub fn stderr(mut self, output: JobOutput) -> Self {
+        self.stderr = Some(output);
+        self
+    }
+
+    /// Caps the size of the files the command's output is written to
+    /// (1MiB by default). A bigger file is moved aside to `<file>.old`,
+    /// replacing the previous one, when the job next runs. The size is
+    /// only checked when a run starts, not while the job writes, so a
+    /// run's output is never split and one noisy run can exceed the cap.
+    pub fn max_log_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_log_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Puts `dir` at the front of the command's PATH. Directories added
+    /// first are searched first.
+    pub fn prepend_path(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.path_prepend.push(dir.into());
+        self
+    }
+
+    /// Puts COMMON_PATHS (/opt/homebrew/bin and /usr/local/bin) at the
+    /// front of the command's PATH, so brew-installed tools can be found.
+    pub fn common_paths(self) -> Self {
+        COMMON_PATHS
+            .iter()
+            .fold(self, |options, dir| options.prepend_path(dir))
+    }
+
+    /// Runs the command with `path` as its PATH instead of the one
+    /// inherited from Alfred. Directories from prepend_path and
+    /// common_paths still go in front of it.
+    pub fn path(mut self, path: impl Into<OsString>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Returns the PATH the command should run with, or None if the options
+    /// leave it alone. The base PATH is the one set by `path`, else the
+    /// command's own, else this process's. Repeated directories are kept
+    /// only where they first appear.
+    pub(crate) fn resolve_path(&self, command: &Command) -> Option<OsString> {
+        if self.path_prepend.is_empty() && self.path.is_none() {
+            return None;
+        }
+        let base = self
+            .path
+            .clone()
+            .or_else(|| {
+                command
+                    .get_envs()
+                    .find(|(key, _)| *key == "PATH")
+                    .and_then(|(_, value)| value.map(OsString::from))
+            })
+            // The job inherits this process's environment, not a config's
+            .or_else(|| std::env::var_os("PATH"))
+            .unwrap_or_default();
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        for dir in self
+            .path_prepend
+            .iter()
+            .cloned()
+            .chain(std::env::split_paths(&base))
+        {
+            if !dir.as_os_str().is_empty() && !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+        std::env::join_paths(dirs).ok()
+    }
+}
+
+/// Returns a Command that runs `command` under `nice` and/or `taskpolicy`
+/// as requested by the options. The program, arguments, environment and
+/// working directory of the original command are preserved. If no options
+/// apply, the command is returned unchanged.
+pub(crate) fn wrap_command(command: Command, options: &JobOptions) -> Command {
+    let mut prefix: Vec<String> = Vec::new();
+    if let Some(nice) = options.nice {
+        prefix.extend(["nice".to_string(), "-n".to_string(), nice.to_string()]);
+    }
+    if let Some(qos) = options.qos {
+        if cfg!(target_os = "macos") {
+            prefix.extend(["taskpolicy".to_string(), "-c".to_string(), qos.to_string()]);
+        }
+    }
+    if prefix.is_empty() {
+        return command;
+    }
+    with_prefix(&command, &prefix)
+}
+
+/// The script run by `sh` around every job, recording the job's exit
+/// status in the file given as `$0` once it finishes. On SIGTERM it passes
+/// the signal on to its process group, so the job and anything it started
+/// stop with it rather than running on detached.
+const MONITOR_SCRIPT: &str =
+    r#"trap 'trap - TERM; kill -TERM -$$' TERM; "$@" & wait $!; echo $? > "$0""#;
+
+/// Returns a Command that runs `command` under a monitoring `sh`, which
+/// writes its exit status to `exit_file` when it finishes. The job's pid
+/// file holds the monitor's pid, which is also the id of the process group
+/// the job is spawned in.
+pub(crate) fn monitor_command(command: &Command, exit_file: &Path) -> Command {
+    let prefix = [
+        OsStr::new("/bin/sh"),
+        OsStr::new("-c"),
+        OsStr::new(MONITOR_SCRIPT),
+        exit_file.as_os_str(),
+    ];
+    with_prefix(command, &prefix)
+}
+
+/// Returns a Command running `prefix` followed by the program and arguments
+/// of `command`, with its environment and working directory.
+fn with_prefix(command: &Command, prefix: &[impl AsRef<OsStr>]) -> Command {
+    let mut wrapped = Command::new(&prefix[0]);
+    wrapped.args(&prefix[1..]);
+    wrapped.arg(command.get_program());
+    wrapped.args(command.get_args());
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => wrapped.env(key, value),
+            None => wrapped.env_remove(key),
+        };
+    }
+    if let Some(dir) = command.get_current_dir() {
+        wrapped.current_dir(dir);
+    }
+    wrapped
+}
 
-    /// The maximum time allowed since the job was last run
-    /// before it is considered stale and we re-run it.
-    max_age: Duration,
+/// IntoCommand is implemented by std::process::Command and by closures
+/// returning one, so callers can defer building a job's command until it
+/// actually needs to be spawned.
+pub trait IntoCommand {
+    fn into_command(self) -> Command;
+}
 
-    /// The command to run to update the data for this job
-    command: Command,
+impl IntoCommand for Command {
+    fn into_command(self) -> Command {
+        self
+    }
+}
 
-    /// The workflow this job is associated with
-    workflow: &'a Workflow,
+impl<F> IntoCommand for F
+where
+    F: FnOnce() -> Command,
+{
+    fn into_command(self) -> Command {
+        self()
+    }
+}
+
+/// JobState is what is known about one job from the files in its
+/// directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct JobState {
+    /// When the job last started a run that has since finished
+    pub(crate) last_run: Option<SystemTime>,
+
+    /// The pid of the job's latest run, until it is found to have exited
+    pub(crate) pid: Option<u32>,
+
+    /// When the pid was recorded, i.e. when that run started
+    pub(crate) started: Option<SystemTime>,
+
+    /// The exit status of the last run that recorded one
+    pub(crate) exit_code: Option<i32>,
+
+    /// How many finished runs in a row have failed
+    pub(crate) failures: u32,
+}
+
+impl JobState {
+    /// Reads the state of the job whose files are in `dir`, or None if
+    /// there is no such directory.
+    pub(crate) fn read(dir: &Path) -> Option<JobState> {
+        let files = fs::read_dir(dir).ok()?;
+        let mut state = JobState::default();
+        for file in files.flatten() {
+            let modified = file.metadata().and_then(|m| m.modified()).ok();
+            match file.file_name().to_str() {
+                Some(LAST_RUN_FILE) => state.last_run = modified,
+                Some(PID_FILE) => {
+                    state.started = modified;
+                    state.pid = read_number(&file.path());
+                }
+                Some(LAST_EXIT_FILE) => state.exit_code = read_number(&file.path()),
+                Some(FAILURES_FILE) => {
+                    state.failures = read_number(&file.path()).unwrap_or_default()
+                }
+                _ => {}
+            }
+        }
+        Some(state)
+    }
+}
+
+impl JobState {
+    /// Returns how long the job's current run has been going, or None if it
+    /// isn't running. See job_process.
+    pub(crate) fn running_for(&self) -> Option<Duration> {
+        let mut system = System::new();
+        job_process(&mut system, self.pid?, self.started?).map(|p| since(process_start(p)))
+    }
+}
+
+/// Reads a number written to a file by a job, if there is a valid one.
+fn read_number<T: std::str::FromStr>(path: &Path) -> Option<T> {
+    read_to_string(path)
+        .ok()
+        .and_then(|text| text.trim().parse().ok())
+}
+
+/// JobCommand is the command a background job was last started with,
+/// recorded in the job's job.json, for debugging jobs that don't seem to
+/// refresh anything. Only the environment variables set on the command
+/// are recorded, not the ones it inherited from Alfred, and the values of
+/// ones whose names look secret (containing TOKEN, SECRET, PASSWORD, KEY
+/// and so on) are replaced with "<redacted>".
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobCommand {
+    /// The program and its arguments, including any `nice` or `taskpolicy`
+    /// wrapper from JobOptions
+    pub argv: Vec<String>,
+
+    /// The variables set on the command, or None for ones removed from it.
+    /// Secret-looking values are redacted.
+    #[serde(default)]
+    pub env: BTreeMap<String, Option<String>>,
+
+    /// The directory the command was run in, if it was set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_dir: Option<PathBuf>,
+
+    /// When the job is due to run again, e.g. "every 5m"
+    #[serde(default)]
+    pub policy: String,
+
+    /// The file stdout was written to, if it went to one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<PathBuf>,
+
+    /// The file stderr was written to, if it went to one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<PathBuf>,
+}
+
+/// Recorded in place of the values of secret-looking variables.
+const REDACTED: &str = "<redacted>";
+
+/// Name fragments of variables whose values JobCommand doesn't record.
+const SECRET_NAME_PARTS: &[&str] = &[
+    "TOKEN",
+    "SECRET",
+    "PASSWORD",
+    "PASSWD",
+    "KEY",
+    "AUTH",
+    "CREDENTIAL",
+    "COOKIE",
+];
+
+fn looks_secret(name: &str) -> bool {
+    let name = name.to_uppercase();
+    SECRET_NAME_PARTS.iter().any(|part| name.contains(part))
+}
+
+impl JobCommand {
+    pub(crate) fn new(command: &Command, policy: &RunPolicy) -> Self {
+        let lossy = |s: &std::ffi::OsStr| s.to_string_lossy().into_owned();
+        JobCommand {
+            argv: std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(lossy)
+                .collect(),
+            env: command
+                .get_envs()
+                .map(|(key, value)| {
+                    let key = lossy(key);
+                    let value = match value {
+                        Some(_) if looks_secret(&key) => Some(REDACTED.to_string()),
+                        value => value.map(lossy),
+                    };
+                    (key, value)
+                })
+                .collect(),
+            current_dir: command.get_current_dir().map(Path::to_path_buf),
+            policy: policy.to_string(),
+            ..JobCommand::default()
+        }
+    }
+
+    /// Returns argv as a shell command line, quoting the arguments that
+    /// need it, e.g. `curl -o 'my file.json' https://example.com`.
+    pub fn command_line(&self) -> String {
+        self.argv
+            .iter()
+            .map(|arg| {
+                let plain = !arg.is_empty()
+                    && arg
+                        .chars()
+                        .all(|c| c.is_alphanumeric() || "-_./:=,@%+".contains(c));
+                if plain {
+                    arg.clone()
+                } else {
+                    format!("'{}'", arg.replace('\'', "'\\''"))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Reads the job.json in the job directory `dir`, if there is one.
+    pub(crate) fn read(dir: &Path) -> Option<JobCommand> {
+        let path = dir.join(JOB_FILE);
+        let bytes = fs::read(&path).ok()?;
+        serde_json::from_slice(&bytes)
+            .map_err(|e| warn!("ignoring unreadable {}: {}", path.display(), e))
+            .ok()
+    }
+}
+
+/// JobInfo describes a background job from the files in its directory:
+/// when it last ran, whether it is running now and what command it runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobInfo {
+    /// The job key passed to run_in_background
+    pub name: String,
+
+    /// When the job last started a run that has since finished
+    pub last_run: Option<SystemTime>,
+
+    /// How long the job's current run has been going, if it is running
+    pub running_for: Option<Duration>,
+
+    /// The exit status of the last run that recorded one
+    pub last_exit_code: Option<i32>,
+
+    /// The command the job was last started with, if it was recorded
+    pub command: Option<JobCommand>,
+
+    /// The file the job's output (its stdout, or else its stderr) is
+    /// appended to, if it has one. It holds the output of earlier runs
+    /// too, back to when it was last rotated (see JobOptions::max_log_bytes)
+    pub log_file: Option<PathBuf>,
+}
+
+impl JobInfo {
+    pub(crate) fn read(jobs_dir: &Path, name: &str) -> Option<JobInfo> {
+        let dir = jobs_dir.join(name);
+        let state = JobState::read(&dir)?;
+        let command = JobCommand::read(&dir);
+        let log_file = match &command {
+            Some(command) => command.stdout.clone().or_else(|| command.stderr.clone()),
+            None => Some(dir.join(LOG_FILE)),
+        };
+        Some(JobInfo {
+            name: name.to_string(),
+            last_run: state.last_run,
+            running_for: state.running_for(),
+            last_exit_code: state.exit_code,
+            command,
+            log_file: log_file.filter(|log| log.is_file()),
+        })
+    }
+}
+
+/// JobStateSnapshot reads the state of every job in the jobs directory in
+/// one pass, so a run that checks several jobs doesn't stat each job's
+/// files separately. It is taken on the first job check of an execution
+/// and kept up to date as jobs are cleaned up and started.
+#[derive(Debug, Default)]
+pub(crate) struct JobStateSnapshot {
+    jobs: HashMap<String, JobState>,
+}
+
+impl JobStateSnapshot {
+    pub(crate) fn read(jobs_dir: &Path) -> Self {
+        let mut jobs = HashMap::new();
+        let Ok(entries) = fs::read_dir(jobs_dir) else {
+            return JobStateSnapshot { jobs };
+        };
+        for entry in entries.flatten() {
+            if let Some(state) = JobState::read(&entry.path()) {
+                jobs.insert(entry.file_name().to_string_lossy().into_owned(), state);
+            }
+        }
+        JobStateSnapshot { jobs }
+    }
+
+    /// The names of the jobs in the snapshot, sorted.
+    pub(crate) fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.jobs.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub(crate) fn get_mut(&mut self, name: &str) -> &mut JobState {
+        self.jobs.entry(name.to_string()).or_default()
+    }
+}
+
+const PID_FILE: &str = "job.pid";
+const LAST_RUN_FILE: &str = "job.last_run";
+const JOB_FILE: &str = "job.json";
+const LOG_FILE: &str = "job.log";
+const EXIT_FILE: &str = "job.exit";
+const LAST_EXIT_FILE: &str = "job.last_exit";
+const FAILURES_FILE: &str = "job.failures";
+
+pub(crate) struct BackgroundJob {
+    /// The unique identifier/name for this background job
+    id: String,
+
+    /// The directory holding this job's pid and last run files
+    dir: PathBuf,
+
+    /// When the job is considered stale and re-run
+    policy: RunPolicy,
 }
 
 /// BackgroundJobStatus reflects the current state of a requested background
 /// task. The task can either be fresh or stale, and if stale, it can either
 /// be in the process of running, or known to have failed.
 ///
-#[derive(Debug)]
+/// A job running for the first time is `Stale(None, _)`. A job whose last
+/// run failed is `Failed` until it is due to run again.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BackgroundJobStatus {
     Fresh(Staleness),
     Stale(Option<Staleness>, RunDuration),
+    /// The last run, this long ago, exited with the given non-zero status
+    Failed(Staleness, i32),
 }
 
-impl<'a> BackgroundJob<'a> {
-    pub fn new(
-        workflow: &'a Workflow,
-        name: &'a str,
-        max_age: Duration,
-        command: Command,
-    ) -> BackgroundJob<'a> {
-        let mut command = command;
+/// JobReport is the outcome of checking a background job, returned by
+/// run_in_background: its status, and how its recent runs went. It tells a
+/// job doing its first-time setup from one whose last refresh failed, so
+/// workflows can show each its own message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobReport {
+    pub status: BackgroundJobStatus,
 
-        // Ensure that the spawned command gets its own STDOUT, while
-        // STDERR is inherited from the parent process.
-        command.stdout(std::process::Stdio::piped());
-        command.stderr(std::process::Stdio::inherit());
-        BackgroundJob {
-            workflow,
-            id: name,
-            max_age,
-            command,
+    /// The exit status of the last run that recorded one
+    pub last_exit_code: Option<i32>,
+
+    /// How many finished runs in a row have failed
+    pub failures: u32,
+}
+
+impl JobReport {
+    pub(crate) fn new(status: BackgroundJobStatus, state: &JobState) -> Self {
+        JobReport {
+            status,
+            last_exit_code: state.exit_code,
+            failures: state.failures,
         }
     }
 
-    pub fn run(&mut self) -> Option<Item> {
-        use BackgroundJobStatus::*;
+    /// True if the job is running and has never finished a run before.
+    pub fn is_first_run(&self) -> bool {
+        matches!(self.status, BackgroundJobStatus::Stale(None, _))
+    }
 
-        let status = self.run_if_needed();
-        match status {
-            Ok(status) => match status {
-                Fresh(staleness) => {
-                    debug!(
-                        "Job '{}' is fresh, last run {}",
-                        self.id,
-                        format_duration(staleness)
-                    );
-                    None
-                }
-                Stale(staleness, duration) => match staleness {
-                    Some(staleness) => {
-                        debug!(
-                            "Job '{}' is stale. Last run {} ago, running for {}",
-                            self.id,
-                            format_duration(staleness),
-                            format_duration(duration),
-                        );
-                        // Truncate to milliseconds
-                        let staleness = Duration::from_millis(staleness.as_millis() as u64);
-                        let duration = Duration::from_millis(duration.as_millis() as u64);
-                        let stale_item = Item::new(format!("Background Job '{}'", self.id))
-                            .subtitle(format!(
-                                "Job is stale by {}, running for {}",
-                                format_duration(staleness),
-                                format_duration(duration)
-                            ))
-                            .icon(ICON_CLOCK.into())
-                            .valid(false);
-                        Some(stale_item)
-                    }
-                    None => {
-                        debug!(
-                            "Job '{}' has never run before, running for {}",
-                            self.id,
-                            format_duration(duration)
-                        );
-                        let stale_item = Item::new(format!("Background Job '{}'", self.id))
-                            .subtitle(format!(
-                                "Job is stale, running for {}",
-                                format_duration(duration)
-                            ))
-                            .icon(ICON_CLOCK.into())
-                            .valid(false);
-                        Some(stale_item)
-                    }
-                },
-            },
-            Err(e) => {
-                error!("Error starting job '{}': {}", self.id, e);
-                let error_item = Item::new(format!("Background Job '{}'", self.id))
-                    .subtitle(format!("Error starting job: {}", e));
-                Some(error_item)
-            }
-        }
+    /// True if the job is running.
+    pub fn is_running(&self) -> bool {
+        matches!(self.status, BackgroundJobStatus::Stale(..))
     }
 
-    /// Runs the provided command in the background if the job is stale.
-    pub fn run_if_needed(&mut self) -> Result<BackgroundJobStatus> {
-        // Ensure this job's operating directory exists
-        create_dir_all(self.job_dir())?;
-        let staleness = self.get_staleness();
+    /// The exit status of the last run, if it failed. While a job is
+    /// retried after failing, this is the failed run's status.
+    pub fn failed_exit_code(&self) -> Option<i32> {
+        self.last_exit_code.filter(|code| *code != 0)
+    }
+}
 
-        // Fresh
-        if let Some(staleness) = staleness {
-            if staleness < self.max_age {
-                return Ok(BackgroundJobStatus::Fresh(staleness));
-            }
+impl BackgroundJob {
+    pub fn new(jobs_dir: &Path, name: &str, policy: RunPolicy) -> BackgroundJob {
+        BackgroundJob {
+            id: name.to_string(),
+            dir: jobs_dir.join(name),
+            policy,
         }
+    }
+
+    /// Runs the command in the background if the job is stale, judging by
+    /// `state` (from a JobStateSnapshot) and updating it to match. The
+    /// command is only built when it is about to be spawned.
+    pub fn run_if_needed(
+        &mut self,
+        state: &mut JobState,
+        command: impl IntoCommand,
+        options: &JobOptions,
+    ) -> Result<JobReport> {
+        let staleness = state.last_run.map(since);
 
-        let run_duration = self.get_running_duration();
+        // Fresh, or failed and waiting to be retried
+        if !self.is_due(state, options) {
+            return Ok(self.not_due(state));
+        }
 
         // Stale, but already running
-        if let Some(duration) = run_duration {
-            return Ok(BackgroundJobStatus::Stale(
-                staleness,
-                duration as RunDuration,
-            ));
+        if let Some(duration) = state.running_for() {
+            let status = BackgroundJobStatus::Stale(staleness, duration as RunDuration);
+            return Ok(JobReport::new(status, state));
         }
 
-        self.cleanup()?;
+        self.cleanup(state)?;
+
+        // The run that just finished may have failed, and be retried later
+        if state.failures > 0 && !self.is_due(state, options) {
+            debug!(
+                "Job '{}' failed {} times in a row, backing off",
+                self.id, state.failures
+            );
+            return Ok(self.not_due(state));
+        }
 
         // Stale and not running, let's start it
-        match self.command.spawn() {
+        debug!("Starting job '{}'", self.id);
+        create_dir_all(&self.dir)?;
+        remove_if_exists(&self.exit_file())?;
+        let mut command = command.into_command();
+        if let Some(path) = options.resolve_path(&command) {
+            debug!("job '{}' PATH: {}", self.id, path.to_string_lossy());
+            command.env("PATH", path);
+        }
+        let mut command = wrap_command(command, options);
+
+        command.env(BACKGROUND_VAR, &self.id);
+        // Ensure that the spawned command gets its own STDOUT, written to
+        // the job's log file by default, while STDERR is inherited from the
+        // parent process.
+        let stdout = options.stdout.as_ref().unwrap_or(&JobOutput::Log);
+        let stderr = options.stderr.as_ref().unwrap_or(&JobOutput::Inherit);
+        let max_log_bytes = options.max_log_bytes.unwrap_or(MAX_LOG_FILE_BYTES);
+        let recorded = JobCommand {
+            stdout: self.output_file(stdout),
+            stderr: self.output_file(stderr),
+            ..JobCommand::new(&command, &self.policy)
+        };
+        write(self.job_file(), serde_json::to_vec_pretty(&recorded)?)?;
+        let mut command = monitor_command(&command, &self.exit_file());
+        command.stdout(self.open_output(stdout, max_log_bytes)?);
+        command.stderr(self.open_output(stderr, max_log_bytes)?);
+        own_process_group(&mut command);
+        match command.spawn() {
             Ok(child) => {
                 let pid = child.id();
-                self.save_pid(pid)?;
-                Ok(BackgroundJobStatus::Stale(
-                    staleness,
-                    RunDuration::from_secs(0),
-                ))
+                write(self.pid_file(), pid.to_string())?;
+                state.pid = Some(pid);
+                state.started = Some(SystemTime::now());
+                let status = BackgroundJobStatus::Stale(staleness, RunDuration::from_secs(0));
+                Ok(JobReport::new(status, state))
             }
             Err(e) => Err(e.into()),
         }
     }
 
-    fn job_dir(&self) -> PathBuf {
-        self.workflow.jobs_dir().join(self.id)
+    /// The report for a job that isn't due to run: Failed if its last run
+    /// failed, otherwise Fresh.
+    fn not_due(&self, state: &JobState) -> JobReport {
+        let staleness = state.last_run.map(since).unwrap_or_default();
+        let status = match state.exit_code {
+            Some(code) if code != 0 => BackgroundJobStatus::Failed(staleness, code),
+            _ => BackgroundJobStatus::Fresh(staleness),
+        };
+        JobReport::new(status, state)
+    }
+
+    /// Stops the job's current run, if it has one, by sending SIGTERM to its
+    /// process group, which includes any processes the job started. The
+    /// run is recorded as the job's last run, so the job isn't
+    /// restarted until it is due again. Returns true if a run was stopped.
+    pub fn cancel(&self, state: &mut JobState) -> Result<bool> {
+        let stopped = match (state.pid, state.started) {
+            (Some(pid), Some(started)) => terminate_process(pid, started),
+            _ => false,
+        };
+        if stopped {
+            debug!("Cancelled job '{}'", self.id);
+        }
+        self.cleanup(state)?;
+        Ok(stopped)
+    }
+
+    /// Forgets when the job last ran and any failures, so it runs the next
+    /// time it is checked whatever its RunPolicy or FailureBackoff.
+    pub fn invalidate(&self, state: &mut JobState) -> Result<()> {
+        remove_if_exists(&self.last_run_file())?;
+        remove_if_exists(&self.failures_file())?;
+        state.last_run = None;
+        state.failures = 0;
+        Ok(())
+    }
+
+    /// Returns true if the job should run now: on its FailureBackoff after
+    /// failed runs, if it has one, otherwise on its RunPolicy.
+    fn is_due(&self, state: &JobState, options: &JobOptions) -> bool {
+        match (options.failure_backoff, state.last_run) {
+            (Some(backoff), Some(last_run)) if state.failures > 0 => {
+                since(last_run) >= backoff.delay(state.failures)
+            }
+            _ => self.policy.is_due(state.last_run, SystemTime::now()),
+        }
     }
 
     fn pid_file(&self) -> PathBuf {
-        self.job_dir().join("job.pid")
+        self.dir.join(PID_FILE)
     }
 
-    fn last_run_file(&self) -> PathBuf {
-        self.job_dir().join("job.last_run")
+    fn exit_file(&self) -> PathBuf {
+        self.dir.join(EXIT_FILE)
     }
 
-    fn get_pid(&self) -> Result<u32> {
-        let pid = read_to_string(self.pid_file())?;
-        pid.trim().parse::<u32>().map_err(|e| e.into())
+    fn last_exit_file(&self) -> PathBuf {
+        self.dir.join(LAST_EXIT_FILE)
     }
 
-    fn save_pid(&self, pid: u32) -> Result<()> {
-        write(self.pid_file(), pid.to_string())?;
-        Ok(())
+    fn failures_file(&self) -> PathBuf {
+        self.dir.join(FAILURES_FILE)
     }
 
-    fn delete_pid_file(&self) -> Result<()> {
-        // Check if the file exists before trying to remove it
-        if !self.pid_file().exists() {
-            return Ok(());
+    fn log_file(&self) -> PathBuf {
+        self.dir.join(LOG_FILE)
+    }
+
+    /// The file `output` is written to, if it goes to one.
+    fn output_file(&self, output: &JobOutput) -> Option<PathBuf> {
+        match output {
+            JobOutput::Log => Some(self.log_file()),
+            JobOutput::File(path) => Some(path.clone()),
+            JobOutput::Inherit | JobOutput::Discard => None,
         }
-        fs::remove_file(self.pid_file())?;
-        Ok(())
+    }
+
+    /// Opens `output` for a run of the job, rotating its file first if it
+    /// has grown past `max_log_bytes`.
+    fn open_output(&self, output: &JobOutput, max_log_bytes: u64) -> Result<Stdio> {
+        if let Some(path) = self.output_file(output) {
+            if let Some(parent) = path.parent() {
+                create_dir_all(parent)?;
+            }
+            rotate(&path, max_log_bytes);
+            let file = File::options().create(true).append(true).open(path)?;
+            return Ok(file.into());
+        }
+        Ok(match output {
+            JobOutput::Discard => Stdio::null(),
+            _ => Stdio::inherit(),
+        })
+    }
+
+    fn last_run_file(&self) -> PathBuf {
+        self.dir.join(LAST_RUN_FILE)
+    }
+
+    /// The file recording the command the job was last started with,
+    /// including the PATH resolved from its JobOptions.
+    fn job_file(&self) -> PathBuf {
+        self.dir.join(JOB_FILE)
     }
 
     /// Called when we detect the process identified by the pid file is no
     /// longer running. We update the last_run_file to reflect the time the
-    /// process started, and remove the pid file.
+    /// process started, record the run's exit status and count it as a
+    /// failure if it was non-zero (a run with no recorded status, e.g. one
+    /// that was cancelled, changes neither), and remove the pid file.
     ///
-    fn cleanup(&self) -> Result<()> {
-        match fs::metadata(self.pid_file()) {
-            Ok(metadata) => {
-                let last_run_systime = metadata.modified().unwrap();
-                let last_run_date = DateTime::<Utc>::from(last_run_systime);
-                write(self.last_run_file(), last_run_date.to_rfc3339())?;
-                let dest = File::options().write(true).open(self.last_run_file())?;
-                let times = FileTimes::new()
-                    .set_accessed(last_run_systime)
-                    .set_modified(last_run_systime);
-                dest.set_times(times)?;
-                self.delete_pid_file()?;
-                Ok(())
+    fn cleanup(&self, state: &mut JobState) -> Result<()> {
+        let Some(started) = state.started else {
+            return Ok(());
+        };
+        let last_run_date = DateTime::<Utc>::from(started);
+        write(self.last_run_file(), last_run_date.to_rfc3339())?;
+        let dest = File::options().write(true).open(self.last_run_file())?;
+        let times = FileTimes::new().set_accessed(started).set_modified(started);
+        dest.set_times(times)?;
+        let exit_code: Option<i32> = read_number(&self.exit_file());
+        if let Some(code) = exit_code {
+            write(self.last_exit_file(), code.to_string())?;
+            remove_if_exists(&self.exit_file())?;
+            state.exit_code = Some(code);
+        }
+        match exit_code {
+            Some(0) => {
+                remove_if_exists(&self.failures_file())?;
+                state.failures = 0;
             }
-            Err(_) => Ok(()),
+            Some(code) => {
+                state.failures += 1;
+                debug!(
+                    "Job '{}' exited with {}, {} failures in a row",
+                    self.id, code, state.failures
+                );
+                write(self.failures_file(), state.failures.to_string())?;
+            }
+            None => {}
         }
+        remove_if_exists(&self.pid_file())?;
+        state.last_run = Some(started);
+        state.pid = None;
+        state.started = None;
+        Ok(())
     }
+}
 
-    /// If the specified job is running, this returns the duration since it
-    /// started. Otherwise, it returns None.
-    ///
-    fn get_running_duration(&self) -> Option<Duration> {
-        let mut system = System::new_all();
-        system.refresh_processes(sysinfo::ProcessesToUpdate::All, true);
-
-        let pid = self.get_pid();
-        match pid {
-            Ok(pid) => system.process(sysinfo::Pid::from(pid as usize)).map(|p| {
-                let start_time = UNIX_EPOCH + Duration::from_secs(p.start_time());
-                SystemTime::now()
-                    .duration_since(start_time)
-                    .unwrap_or_default()
-            }),
-            Err(_) => None,
-        }
-    }
-
-    /// If the specified job has successfully started before, this returns the duration
-    /// since that event occurred. Otherwise, it returns None. We use the file timestamp
-    /// on an empty file to determine the last completion time.
-    fn get_staleness(&self) -> Option<Staleness> {
-        match fs::metadata(self.last_run_file()) {
-            Ok(metadata) => {
-                let last_run = metadata.modified().unwrap();
-                let duration = SystemTime::now().duration_since(last_run).unwrap();
-                Some(duration)
+/// Removes the file at `path`, if there is one.
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the time elapsed since `time`, or zero if it is in the future.
+fn since(time: SystemTime) -> Duration {
+    SystemTime::now().duration_since(time).unwrap_or_default()
+}
+
+/// Returns true if the process with the given pid is still the one that
+/// started at `started`; see job_process. Only that one process is
+/// refreshed: enumerating every process on the system (System::new_all)
+/// costs tens of milliseconds, which Alfred users feel on every keystroke.
+pub(crate) fn is_process_running(pid: u32, started: SystemTime) -> bool {
+    let mut system = System::new();
+    job_process(&mut system, pid, started).is_some()
+}
+
+/// Starts the command in its own process group, so terminate_process can
+/// stop it along with any subprocesses it starts.
+pub(crate) fn own_process_group(command: &mut Command) {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(command, 0);
+    #[cfg(not(unix))]
+    let _ = command;
+}
+
+/// How far a job's process may have started from when its pid file was
+/// written. Process start times only have a resolution of a second.
+const START_TOLERANCE: Duration = Duration::from_secs(3);
+
+/// Looks up the process of a job run that started at `started`, when its
+/// pid file was written. None if there is no process with the pid, or if
+/// the process holding it started at some other time: a pid file left
+/// behind by a crash or reboot can name an unrelated process that reused
+/// the pid, which must not be mistaken for the job, let alone signalled.
+fn job_process(system: &mut System, pid: u32, started: SystemTime) -> Option<&sysinfo::Process> {
+    refresh_process(system, pid).filter(|process| {
+        let start = process_start(process);
+        let difference = start
+            .duration_since(started)
+            .or_else(|_| started.duration_since(start))
+            .unwrap_or_default();
+        difference <= START_TOLERANCE
+    })
+}
+
+fn process_start(process: &sysinfo::Process) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(process.start_time())
+}
+
+/// Sends SIGTERM to the process group led by the job process with the
+/// given pid, falling back to the process alone for jobs that weren't
+/// started in their own group (or killing it, where signals aren't
+/// supported). Returns false if there is no such process; see job_process.
+pub(crate) fn terminate_process(pid: u32, started: SystemTime) -> bool {
+    let mut system = System::new();
+    let Some(process) = job_process(&mut system, pid, started) else {
+        return false;
+    };
+    terminate_group(pid)
+        || process
+            .kill_with(Signal::Term)
+            .unwrap_or_else(|| process.kill())
+}
+
+/// Sends SIGTERM to the process group with the given id. Returns false if
+/// there is no such group.
+#[cfg(unix)]
+fn terminate_group(pgid: u32) -> bool {
+    let Ok(pgid) = libc::pid_t::try_from(pgid) else {
+        return false;
+    };
+    // SAFETY: killpg only sends a signal, and takes no pointers
+    unsafe { libc::killpg(pgid, libc::SIGTERM) == 0 }
+}
+
+#[cfg(not(unix))]
+fn terminate_group(_pgid: u32) -> bool {
+    false
+}
+
+/// Looks up the process with the given pid, refreshing only that one.
+fn refresh_process(system: &mut System, pid: u32) -> Option<&sysinfo::Process> {
+    let pid = Pid::from_u32(pid);
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::nothing(),
+    );
+    system.process(pid)
+}
+
+/// True if the process exists and isn't a zombie waiting to be reaped.
+#[cfg(test)]
+pub(crate) fn is_alive(pid: u32) -> bool {
+    let mut system = System::new();
+    refresh_process(&mut system, pid).is_some_and(|p| p.status() != sysinfo::ProcessStatus::Zombie)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::*;
+
+    fn args(command: &Command) -> Vec<&OsStr> {
+        command.get_args().collect()
+    }
+
+    #[test]
+    fn test_snapshot_reads_job_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let refresh = dir.path().join("refresh");
+        create_dir_all(&refresh).unwrap();
+        write(refresh.join(LAST_RUN_FILE), "").unwrap();
+        write(refresh.join(PID_FILE), "42\n").unwrap();
+        create_dir_all(dir.path().join("empty")).unwrap();
+
+        let mut snapshot = JobStateSnapshot::read(dir.path());
+        let state = snapshot.get_mut("refresh");
+        assert!(state.last_run.is_some());
+        assert!(state.started.is_some());
+        assert_eq!(state.pid, Some(42));
+        assert_eq!(*snapshot.get_mut("empty"), JobState::default());
+        assert_eq!(*snapshot.get_mut("missing"), JobState::default());
+
+        let snapshot = JobStateSnapshot::read(&dir.path().join("no_jobs_yet"));
+        assert!(snapshot.jobs.is_empty());
+    }
+
+    #[test]
+    fn test_cleanup_records_last_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let job = BackgroundJob::new(dir.path(), "refresh", RunPolicy::Once);
+        create_dir_all(&job.dir).unwrap();
+        write(job.pid_file(), "42").unwrap();
+        let started = SystemTime::now() - Duration::from_secs(120);
+        let mut state = JobState {
+            pid: Some(42),
+            started: Some(started),
+            ..JobState::default()
+        };
+
+        job.cleanup(&mut state).unwrap();
+        assert!(!job.pid_file().exists());
+        assert_eq!(state.last_run, Some(started));
+        assert_eq!(state.pid, None);
+
+        let snapshot_state = JobStateSnapshot::read(dir.path())
+            .get_mut("refresh")
+            .clone();
+        assert_eq!(snapshot_state, state);
+    }
+
+    #[test]
+    fn test_cancel_and_invalidate() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut job = BackgroundJob::new(dir.path(), "refresh", RunPolicy::Once);
+        let mut state = JobState::default();
+        let mut command = Command::new("sleep");
+        command.arg("30");
+        job.run_if_needed(&mut state, command, &JobOptions::new())
+            .unwrap();
+        assert!(job.log_file().exists());
+        assert!(state.pid.is_some());
+
+        assert!(job.cancel(&mut state).unwrap());
+        assert_eq!(state.pid, None);
+        assert!(state.last_run.is_some());
+        assert!(!job.pid_file().exists());
+        assert!(!job.cancel(&mut state).unwrap());
+
+        job.invalidate(&mut state).unwrap();
+        assert_eq!(state.last_run, None);
+        assert!(!job.last_run_file().exists());
+        assert_eq!(
+            JobStateSnapshot::read(dir.path())
+                .get_mut("refresh")
+                .clone(),
+            state
+        );
+    }
+
+    /// Waits for the job's current run to write its exit status.
+    fn wait_for_exit(job: &BackgroundJob) {
+        for _ in 0..100 {
+            if job.exit_file().exists() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        panic!("job '{}' didn't finish", job.id);
+    }
+
+    #[test]
+    fn test_failure_backoff_delay() {
+        let backoff = FailureBackoff::exponential(Duration::from_secs(10), Duration::from_secs(60));
+        let delays: Vec<u64> = (0..6).map(|n| backoff.delay(n).as_secs()).collect();
+        assert_eq!(delays, vec![0, 10, 20, 40, 60, 60]);
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(60));
+        assert_eq!(
+            FailureBackoff::fixed(Duration::from_secs(5)).delay(3),
+            Duration::from_secs(5)
+        );
+    }
+
+    #[test]
+    fn test_failed_runs_back_off() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut job = BackgroundJob::new(dir.path(), "refresh", RunPolicy::Every(Duration::ZERO));
+        let options = JobOptions::new().failure_backoff(FailureBackoff::exponential(
+            Duration::from_secs(3600),
+            Duration::from_secs(86400),
+        ));
+        let mut state = JobState::default();
+        let mut command = Command::new("sh");
+        command.args(["-c", "exit 3"]);
+        job.run_if_needed(&mut state, command, &options).unwrap();
+        wait_for_exit(&job);
+        job.cleanup(&mut state).unwrap();
+        assert_eq!(state.exit_code, Some(3));
+        assert_eq!(state.failures, 1);
+        assert_eq!(
+            JobStateSnapshot::read(dir.path())
+                .get_mut("refresh")
+                .clone(),
+            state
+        );
+
+        let report = job
+            .run_if_needed(
+                &mut state,
+                || -> Command { panic!("retried during the backoff") },
+                &options,
+            )
+            .unwrap();
+        assert!(matches!(report.status, BackgroundJobStatus::Failed(_, 3)));
+        assert_eq!(report.failed_exit_code(), Some(3));
+        assert_eq!(report.failures, 1);
+        assert!(!report.is_running());
+
+        // Without a backoff the job runs on its policy, and succeeds
+        let report = job
+            .run_if_needed(&mut state, Command::new("true"), &JobOptions::new())
+            .unwrap();
+        assert!(report.is_running());
+        assert!(!report.is_first_run());
+        assert_eq!(report.failures, 1);
+        wait_for_exit(&job);
+        job.cleanup(&mut state).unwrap();
+        assert_eq!(state.exit_code, Some(0));
+        assert_eq!(state.failures, 0);
+        assert!(!job.failures_file().exists());
+        assert!(!job.exit_file().exists());
+        assert_eq!(read_to_string(job.last_exit_file()).unwrap(), "0");
+    }
+
+    #[test]
+    fn test_first_run_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut job = BackgroundJob::new(dir.path(), "setup", RunPolicy::Once);
+        let mut state = JobState::default();
+        let mut command = Command::new("sleep");
+        command.arg("1");
+        let report = job
+            .run_if_needed(&mut state, command, &JobOptions::new())
+            .unwrap();
+        assert!(report.is_first_run());
+        assert_eq!(report.failed_exit_code(), None);
+    }
+
+    #[test]
+    fn test_invalidate_resets_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let job = BackgroundJob::new(dir.path(), "refresh", RunPolicy::Once);
+        create_dir_all(&job.dir).unwrap();
+        write(job.failures_file(), "4").unwrap();
+        let mut state = JobState::read(&job.dir).unwrap();
+        assert_eq!(state.failures, 4);
+        job.invalidate(&mut state).unwrap();
+        assert_eq!(state.failures, 0);
+        assert!(!job.failures_file().exists());
+    }
+
+    /// Starts a job whose command starts a `sleep` of its own, returning
+    /// the job, its state and the sleep's pid.
+    fn start_sleeping_job(dir: &Path) -> (BackgroundJob, JobState, u32) {
+        let mut job = BackgroundJob::new(dir, "sleepy", RunPolicy::Once);
+        let mut state = JobState::default();
+        let child_pid_file = dir.join("child.pid");
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(format!(
+            "sleep 30 & echo $! > '{}'; wait",
+            child_pid_file.display()
+        ));
+        job.run_if_needed(&mut state, command, &JobOptions::new())
+            .unwrap();
+        for _ in 0..100 {
+            if let Some(child) = read_number(&child_pid_file) {
+                assert!(is_alive(child));
+                return (job, state, child);
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        panic!("the job didn't start its child");
+    }
+
+    fn assert_stops(pid: u32) {
+        for _ in 0..100 {
+            if !is_alive(pid) {
+                return;
             }
-            Err(_) => None,
+            std::thread::sleep(Duration::from_millis(50));
         }
+        panic!("process {} is still running", pid);
+    }
+
+    #[test]
+    fn test_cancel_stops_the_process_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let (job, mut state, child) = start_sleeping_job(dir.path());
+        let monitor = state.pid.unwrap();
+        assert!(job.cancel(&mut state).unwrap());
+        assert_stops(child);
+        assert_stops(monitor);
+        assert!(!job.exit_file().exists());
+    }
+
+    #[test]
+    fn test_monitor_passes_on_sigterm() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_job, state, child) = start_sleeping_job(dir.path());
+        let mut system = System::new();
+        let monitor = refresh_process(&mut system, state.pid.unwrap()).unwrap();
+        assert_eq!(monitor.kill_with(Signal::Term), Some(true));
+        assert_stops(child);
+    }
+
+    #[test]
+    fn test_job_output_destinations() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut job = BackgroundJob::new(dir.path(), "refresh", RunPolicy::Every(Duration::ZERO));
+        let mut state = JobState::default();
+        let command = || {
+            let mut command = Command::new("sh");
+            command.args(["-c", "echo out; echo err >&2"]);
+            command
+        };
+
+        let out = dir.path().join("logs/out.txt");
+        let options = JobOptions::new()
+            .stdout(JobOutput::File(out.clone()))
+            .stderr(JobOutput::Discard);
+        job.run_if_needed(&mut state, command, &options).unwrap();
+        wait_for_exit(&job);
+        job.cleanup(&mut state).unwrap();
+        assert_eq!(read_to_string(&out).unwrap(), "out\n");
+        assert!(!job.log_file().exists());
+        let recorded = JobCommand::read(&job.dir).unwrap();
+        assert_eq!(recorded.stdout, Some(out));
+        assert_eq!(recorded.stderr, None);
+
+        let options = JobOptions::new().stderr(JobOutput::Log);
+        for _ in 0..2 {
+            job.run_if_needed(&mut state, command, &options).unwrap();
+            wait_for_exit(&job);
+            job.cleanup(&mut state).unwrap();
+        }
+        assert_eq!(
+            read_to_string(job.log_file()).unwrap(),
+            "out\nerr\nout\nerr\n"
+        );
+        let info = JobInfo::read(dir.path(), "refresh").unwrap();
+        assert_eq!(info.log_file, Some(job.log_file()));
+
+        let options = options.max_log_bytes(10);
+        job.run_if_needed(&mut state, command, &options).unwrap();
+        wait_for_exit(&job);
+        assert_eq!(read_to_string(job.log_file()).unwrap(), "out\nerr\n");
+        assert_eq!(
+            read_to_string(job.dir.join("job.log.old")).unwrap(),
+            "out\nerr\nout\nerr\n"
+        );
+    }
+
+    #[test]
+    fn test_cancel_ignores_reused_pids() {
+        let dir = tempfile::tempdir().unwrap();
+        let job = BackgroundJob::new(dir.path(), "refresh", RunPolicy::Once);
+        create_dir_all(&job.dir).unwrap();
+        write(job.pid_file(), "").unwrap();
+
+        // A process that took the pid of a job run that started long ago
+        let mut other = Command::new("sleep").arg("30").spawn().unwrap();
+        let mut state = JobState {
+            pid: Some(other.id()),
+            started: Some(SystemTime::now() - Duration::from_secs(3600)),
+            ..JobState::default()
+        };
+        assert_eq!(state.running_for(), None);
+        assert!(!job.cancel(&mut state).unwrap());
+        assert!(!job.pid_file().exists());
+        assert_eq!(state.pid, None);
+        assert!(is_alive(other.id()));
+
+        other.kill().unwrap();
+        other.wait().unwrap();
+    }
+
+    #[test]
+    fn test_is_process_running() {
+        let mut child = Command::new("/bin/sleep").arg("5").spawn().unwrap();
+        let started = SystemTime::now();
+        assert!(is_process_running(child.id(), started));
+        // A pid reused by a process started at some other time
+        let earlier = started - Duration::from_secs(60 * 60);
+        assert!(!is_process_running(child.id(), earlier));
+        assert!(!is_process_running(u32::MAX - 1, started));
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn test_wrap_command_without_options() {
+        let mut command = Command::new("/bin/sleep");
+        command.arg("5");
+        let wrapped = wrap_command(command, &JobOptions::new());
+        assert_eq!(wrapped.get_program(), "/bin/sleep");
+        assert_eq!(args(&wrapped), vec!["5"]);
+    }
+
+    #[test]
+    fn test_wrap_command_with_nice() {
+        let mut command = Command::new("/bin/sleep");
+        command.arg("5").env("FOO", "bar").current_dir("/tmp");
+        let wrapped = wrap_command(command, &JobOptions::new().nice(10));
+
+        assert_eq!(wrapped.get_program(), "nice");
+        assert_eq!(args(&wrapped), vec!["-n", "10", "/bin/sleep", "5"]);
+        let envs: Vec<_> = wrapped.get_envs().collect();
+        assert_eq!(envs, vec![(OsStr::new("FOO"), Some(OsStr::new("bar")))]);
+        assert_eq!(
+            wrapped.get_current_dir(),
+            Some(std::path::Path::new("/tmp"))
+        );
+    }
+
+    #[test]
+    fn test_resolve_path() {
+        let mut command = Command::new("jq");
+        assert_eq!(JobOptions::new().resolve_path(&command), None);
+
+        command.env("PATH", "/usr/bin:/bin:/usr/local/bin");
+        let options = JobOptions::new()
+            .prepend_path("/Users/me/bin")
+            .common_paths();
+        assert_eq!(
+            options.resolve_path(&command),
+            Some("/Users/me/bin:/opt/homebrew/bin:/usr/local/bin:/usr/bin:/bin".into())
+        );
+
+        let options = options.path("/usr/bin::/opt/homebrew/bin");
+        assert_eq!(
+            options.resolve_path(&command),
+            Some("/Users/me/bin:/opt/homebrew/bin:/usr/local/bin:/usr/bin".into())
+        );
+    }
+
+    #[test]
+    fn test_run_if_needed_records_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut job = BackgroundJob::new(dir.path(), "refresh", RunPolicy::Once);
+        let mut state = JobState::default();
+        let options = JobOptions::new()
+            .path("/usr/bin:/bin")
+            .prepend_path("/opt/tools");
+        job.run_if_needed(&mut state, Command::new("true"), &options)
+            .unwrap();
+        let recorded = JobCommand::read(&job.dir).unwrap();
+        assert_eq!(recorded.argv, vec!["true"]);
+        assert_eq!(recorded.policy, "once");
+        assert_eq!(
+            recorded.env["PATH"].as_deref(),
+            Some("/opt/tools:/usr/bin:/bin")
+        );
+        assert_eq!(recorded.env[BACKGROUND_VAR].as_deref(), Some("refresh"));
+
+        state.last_run = None;
+        state.pid = None;
+        job.run_if_needed(&mut state, Command::new("true"), &JobOptions::new())
+            .unwrap();
+        let recorded = JobCommand::read(&job.dir).unwrap();
+        assert!(!recorded.env.contains_key("PATH"));
+    }
+
+    #[test]
+    fn test_job_command_line() {
+        let mut command = Command::new("curl");
+        command
+            .args([
+                "-o",
+                "my file.json",
+                "it's",
+                "",
+                "https://example.com/a?b=1",
+            ])
+            .env("TOKEN", "abc")
+            .env("github_api_key", "def")
+            .env("LANG", "en_US.UTF-8")
+            .env_remove("DEBUG");
+        let recorded = JobCommand::new(&command, &RunPolicy::Once);
+        assert_eq!(
+            recorded.command_line(),
+            r#"curl -o 'my file.json' 'it'\''s' '' 'https://example.com/a?b=1'"#
+        );
+        assert_eq!(recorded.env["TOKEN"].as_deref(), Some("<redacted>"));
+        assert_eq!(
+            recorded.env["github_api_key"].as_deref(),
+            Some("<redacted>")
+        );
+        assert_eq!(recorded.env["LANG"].as_deref(), Some("en_US.UTF-8"));
+        assert_eq!(recorded.env["DEBUG"], None);
+    }
+
+    #[test]
+    fn test_job_info() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(JobInfo::read(dir.path(), "refresh"), None);
+
+        let job_dir = dir.path().join("refresh");
+        create_dir_all(&job_dir).unwrap();
+        write(job_dir.join(LAST_RUN_FILE), "").unwrap();
+        write(job_dir.join(JOB_FILE), r#"{"argv": ["./refresh"]}"#).unwrap();
+        let info = JobInfo::read(dir.path(), "refresh").unwrap();
+        assert_eq!(info.name, "refresh");
+        assert!(info.last_run.is_some());
+        assert_eq!(info.running_for, None);
+        assert_eq!(info.command.unwrap().argv, vec!["./refresh"]);
+
+        write(job_dir.join(JOB_FILE), "not json").unwrap();
+        assert_eq!(JobInfo::read(dir.path(), "refresh").unwrap().command, None);
+    }
+
+    #[test]
+    fn test_nice_is_clamped() {
+        assert_eq!(JobOptions::new().nice(40).nice, Some(19));
+        assert_eq!(JobOptions::new().nice(-40).nice, Some(-20));
+    }
+
+    #[test]
+    fn test_wrap_command_with_qos() {
+        let command = Command::new("/bin/sleep");
+        let wrapped = wrap_command(
+            command,
+            &JobOptions::new().nice(5).qos(QosClass::Background),
+        );
+        if cfg!(target_os = "macos") {
+            assert_eq!(
+                args(&wrapped),
+                vec!["-n", "5", "taskpolicy", "-c", "background", "/bin/sleep"]
+            );
+        } else {
+            assert_eq!(args(&wrapped), vec!["-n", "5", "/bin/sleep"]);
+        }
+    }
+
+    #[test]
+    fn test_wrapped_command_runs() {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("exit 3");
+        let status = wrap_command(command, &JobOptions::new().nice(10))
+            .status()
+            .unwrap();
+        assert_eq!(status.code(), Some(3));
     }
 }
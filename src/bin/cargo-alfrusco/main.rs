@@ -0,0 +1,229 @@
+//! `cargo alfrusco`: scaffolding for new alfrusco workflows.
+//!
+//! Install with `cargo install alfrusco --features cli`, then run
+//! `cargo alfrusco new my-workflow` to create a project with a Runnable,
+//! a generated info.plist, a placeholder icon and a GitHub Actions
+//! release workflow.
+//!
+use std::fs::{create_dir_all, write};
+use std::path::{Path, PathBuf};
+
+use alfrusco::export::{InfoPlist, RunScript, ScriptFilter};
+use alfrusco::{Error, Result};
+use clap::{Args, Parser, Subcommand};
+
+const CARGO_TOML: &str = include_str!("templates/Cargo.toml.tmpl");
+const MAIN_RS: &str = include_str!("templates/main.rs.tmpl");
+const GITIGNORE: &str = include_str!("templates/gitignore.tmpl");
+const MAKEFILE: &str = include_str!("templates/Makefile.tmpl");
+const RELEASE_YML: &str = include_str!("templates/release.yml.tmpl");
+const ICON_PNG: &[u8] = include_bytes!("templates/icon.png");
+
+/// Cargo runs `cargo-alfrusco alfrusco <args>` for `cargo alfrusco <args>`.
+#[derive(Parser)]
+#[command(name = "cargo", bin_name = "cargo")]
+enum Cargo {
+    Alfrusco(Alfrusco),
+}
+
+#[derive(Args)]
+#[command(version, about = "Tools for alfrusco workflows")]
+struct Alfrusco {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Creates a new workflow project
+    New(New),
+}
+
+#[derive(Args)]
+struct New {
+    /// The crate and binary name, e.g. "github-search"
+    name: String,
+
+    /// The directory to create. Defaults to ./<name>
+    #[arg(long)]
+    path: Option<PathBuf>,
+
+    /// The workflow's bundle id. Defaults to com.example.<name>
+    #[arg(long)]
+    bundle_id: Option<String>,
+
+    /// The keyword that runs the Script Filter. Defaults to the name
+    #[arg(long)]
+    keyword: Option<String>,
+
+    /// The author shown in Alfred
+    #[arg(long, default_value = "")]
+    author: String,
+}
+
+fn main() {
+    let Cargo::Alfrusco(alfrusco) = Cargo::parse();
+    let result = match alfrusco.command {
+        Command::New(new) => scaffold(&new),
+    };
+    match result {
+        Ok(dir) => {
+            println!("Created {}", dir.display());
+            println!();
+            println!("Next steps:");
+            println!("  cd {} && make workflow", dir.display());
+            println!("  Link the workflow/ directory into Alfred's workflows folder");
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Creates the project, returning its directory.
+fn scaffold(new: &New) -> Result<PathBuf> {
+    validate_name(&new.name)?;
+    let dir = new.path.clone().unwrap_or_else(|| PathBuf::from(&new.name));
+    if dir.exists() {
+        return Err(Error::Workflow(format!("{} already exists", dir.display())));
+    }
+
+    let files: Vec<(&str, Vec<u8>)> = vec![
+        ("Cargo.toml", render(CARGO_TOML, &new.name).into_bytes()),
+        ("src/main.rs", render(MAIN_RS, &new.name).into_bytes()),
+        (".gitignore", render(GITIGNORE, &new.name).into_bytes()),
+        ("Makefile", render(MAKEFILE, &new.name).into_bytes()),
+        (
+            ".github/workflows/release.yml",
+            render(RELEASE_YML, &new.name).into_bytes(),
+        ),
+        ("workflow/info.plist", info_plist(new).to_xml().into_bytes()),
+        ("workflow/icon.png", ICON_PNG.to_vec()),
+    ];
+    for (path, contents) in files {
+        write_file(&dir.join(path), &contents)?;
+    }
+    Ok(dir)
+}
+
+/// Checks that `name` works as both a crate name and a file name.
+fn validate_name(name: &str) -> Result<()> {
+    let valid = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && name.starts_with(|c: char| c.is_ascii_alphabetic());
+    if !valid {
+        return Err(Error::Workflow(format!(
+            "invalid name '{}': use letters, digits, '-' and '_', starting with a letter",
+            name
+        )));
+    }
+    Ok(())
+}
+
+fn render(template: &str, name: &str) -> String {
+    template
+        .replace("{{name}}", name)
+        .replace("{{type_name}}", &type_name(name))
+        .replace("{{alfrusco_version}}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Returns the Runnable's type name: "github-search" becomes
+/// "GithubSearchWorkflow".
+fn type_name(name: &str) -> String {
+    let mut type_name: String = name
+        .split(['-', '_'])
+        .filter(|word| !word.is_empty())
+        .map(|word| word[..1].to_uppercase() + &word[1..])
+        .collect();
+    type_name.push_str("Workflow");
+    type_name
+}
+
+/// A Script Filter running the binary, connected to a Run Script running
+/// it again for alfrusco's internal commands (such as copy_on_action).
+fn info_plist(new: &New) -> InfoPlist {
+    let mut plist = InfoPlist::new(
+        new.bundle_id
+            .clone()
+            .unwrap_or_else(|| format!("com.example.{}", new.name)),
+        &new.name,
+    )
+    .created_by(&new.author)
+    .version("0.1.0");
+    let command = format!("./{} \"$1\"", new.name);
+    let filter = plist.add(
+        ScriptFilter::new(new.keyword.as_deref().unwrap_or(&new.name), &command).title(&new.name),
+    );
+    let action = plist.add(RunScript::new(&command));
+    plist.connect(filter, action);
+    plist
+}
+
+fn write_file(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        create_dir_all(dir)?;
+    }
+    write(path, contents)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read_to_string;
+
+    use super::*;
+
+    fn new(name: &str, path: &Path) -> New {
+        New {
+            name: name.to_string(),
+            path: Some(path.to_path_buf()),
+            bundle_id: None,
+            keyword: Some("gh".to_string()),
+            author: "Ada".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_scaffold() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("project");
+        assert_eq!(scaffold(&new("github-search", &dir)).unwrap(), dir);
+
+        let cargo_toml = read_to_string(dir.join("Cargo.toml")).unwrap();
+        assert!(cargo_toml.contains("name = \"github-search\""));
+        assert!(cargo_toml.contains(&format!("alfrusco = \"{}\"", env!("CARGO_PKG_VERSION"))));
+        let main_rs = read_to_string(dir.join("src/main.rs")).unwrap();
+        assert!(main_rs.contains("impl Runnable for GithubSearchWorkflow {"));
+        assert!(!main_rs.contains("{{"));
+        let release = read_to_string(dir.join(".github/workflows/release.yml")).unwrap();
+        assert!(release.contains("workflow/github-search \\"));
+        assert!(release.contains("GH_TOKEN: ${{ github.token }}"));
+        let plist = read_to_string(dir.join("workflow/info.plist")).unwrap();
+        assert!(plist.contains("<string>com.example.github-search</string>"));
+        assert!(plist.contains("<key>keyword</key>\n\t\t\t\t<string>gh</string>"));
+        assert!(plist.contains("<string>./github-search \"$1\"</string>"));
+        assert!(std::fs::read(dir.join("workflow/icon.png"))
+            .unwrap()
+            .starts_with(b"\x89PNG"));
+
+        // Existing directories are left alone
+        assert!(scaffold(&new("github-search", &dir)).is_err());
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("github-search").is_ok());
+        assert!(validate_name("wf_2").is_ok());
+        for name in ["", "2fa", "my workflow", "../escape", "-x"] {
+            assert!(validate_name(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_type_name() {
+        assert_eq!(type_name("github-search"), "GithubSearchWorkflow");
+        assert_eq!(type_name("wf_2"), "Wf2Workflow");
+    }
+}
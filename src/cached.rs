@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::item::SearchWeight;
+use crate::workflow::Workflow;
+use crate::{Hook, Item, Runnable};
+
+/// Cached wraps a Runnable, saving the items it produces to the cache
+/// directory and serving them on later executions while they are younger
+/// than the TTL, without calling the runnable at all.
+///
+/// The key identifies the cached output, so it should include everything
+/// the output depends on, typically the query. Along with the items, the
+/// filter keyword and response variables the runnable set are restored.
+/// Failed runs aren't cached, and neither are runs that ask Alfred to
+/// rerun (e.g. while a background job refreshes the data), since their
+/// results are about to change. Items describing background jobs are
+/// never cached either.
+///
+/// This caches on alfrusco's side, across queries and Alfred sessions.
+/// Response::cache additionally lets Alfred reuse results without
+/// starting the workflow at all.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use alfrusco::{Cached, Error, Item, Runnable, Workflow};
+///
+/// struct Search {
+///     query: String,
+/// }
+///
+/// impl Runnable for Search {
+///     type Error = Error;
+///
+///     fn run(self, workflow: &mut Workflow) -> Result<(), Error> {
+///         // ... an expensive search ...
+///         workflow.append_item(Item::new(format!("Results for {}", self.query)));
+///         Ok(())
+///     }
+/// }
+///
+/// let query = "rust".to_string();
+/// let runnable = Cached::new(
+///     format!("search:{}", query),
+///     Duration::from_secs(300),
+///     Search { query },
+/// );
+/// ```
+///
+pub struct Cached<R> {
+    key: String,
+    ttl: Duration,
+    runnable: R,
+}
+
+impl<R> Cached<R> {
+    pub fn new(key: impl Into<String>, ttl: Duration, runnable: R) -> Self {
+        Cached {
+            key: key.into(),
+            ttl,
+            runnable,
+        }
+    }
+}
+
+/// CachedRun is what Cached saves of a run's output.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedRun {
+    items: Vec<CachedItem>,
+    filter_keyword: Option<String>,
+    variables: HashMap<String, String>,
+}
+
+/// CachedItem holds an Item along with the settings that aren't part of
+/// its Script Filter JSON but still affect how it is filtered and
+/// written.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedItem {
+    item: Item,
+    sticky: Option<u8>,
+    search_weight: f32,
+    autocomplete_keyword: bool,
+    bufferable: bool,
+}
+
+impl From<Item> for CachedItem {
+    fn from(item: Item) -> Self {
+        CachedItem {
+            sticky: item.sticky,
+            search_weight: item.search_weight.0,
+            autocomplete_keyword: item.autocomplete_keyword,
+            bufferable: item.bufferable,
+            item,
+        }
+    }
+}
+
+impl From<CachedItem> for Item {
+    fn from(cached: CachedItem) -> Self {
+        let mut item = cached.item;
+        item.sticky = cached.sticky;
+        item.search_weight = SearchWeight(cached.search_weight);
+        item.autocomplete_keyword = cached.autocomplete_keyword;
+        item.bufferable = cached.bufferable;
+        item
+    }
+}
+
+impl<R: Runnable> Runnable for Cached<R> {
+    type Error = R::Error;
+
+    fn hooks(&self) -> Vec<Box<dyn Hook>> {
+        self.runnable.hooks()
+    }
+
+    fn run(self, workflow: &mut Workflow) -> Result<(), R::Error> {
+        let dir = workflow.cached_runs_dir();
+        if let Some(cached) = workflow.read_cache_file::<CachedRun>(&dir, &self.key, self.ttl) {
+            debug!(
+                "cached '{}': serving {} items",
+                self.key,
+                cached.items.len()
+            );
+            workflow.append_items(cached.items.into_iter().map(Item::from).collect());
+            if let Some(keyword) = cached.filter_keyword {
+                workflow.set_filter_keyword(keyword);
+            }
+            for (key, value) in cached.variables {
+                workflow.response.var(key, value);
+            }
+            return Ok(());
+        }
+
+        // Run with the response's items set aside, so the runnable's own
+        // items are known however it changes the list
+        let existing = std::mem::take(&mut workflow.response.items);
+        let variables = workflow.response.variables.clone();
+        let result = self.runnable.run(workflow);
+        let produced = std::mem::replace(&mut workflow.response.items, existing);
+        let (job_status, produced): (Vec<Item>, Vec<Item>) = produced
+            .into_iter()
+            .partition(|item| workflow.job_status_items.contains(item));
+        workflow.response.prepend_items(job_status);
+        workflow.response.append_items(produced.clone());
+        result?;
+        if workflow.response.rerun.is_some() {
+            debug!("cached '{}': not caching a run that reruns", self.key);
+            return Ok(());
+        }
+
+        let cached = CachedRun {
+            items: produced.into_iter().map(CachedItem::from).collect(),
+            filter_keyword: workflow
+                .sort_and_filter_results
+                .then(|| workflow.keyword.clone())
+                .flatten(),
+            variables: workflow
+                .response
+                .variables
+                .iter()
+                .filter(|(key, value)| variables.get(*key) != Some(value))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        };
+        if let Err(e) = workflow.write_cache_file(&dir, &self.key, &cached) {
+            warn!("cached '{}': unable to cache items: {}", self.key, e);
+        }
+        Ok(())
+    }
+}
+
+impl Workflow {
+    /// Returns the path to the cache subdirectory where Cached saves the
+    /// output of runnables
+    pub fn cached_runs_dir(&self) -> PathBuf {
+        self.reserved_cache_dir("runs")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::test_support::workflow_in;
+    use crate::Error;
+
+    struct Counting {
+        calls: Rc<Cell<usize>>,
+        rerun: bool,
+    }
+
+    impl Runnable for Counting {
+        type Error = Error;
+
+        fn run(self, workflow: &mut Workflow) -> Result<(), Error> {
+            self.calls.set(self.calls.get() + 1);
+            workflow.append_item(
+                Item::new("Sticky")
+                    .sticky_with_priority(3)
+                    .autocomplete_query("sticky"),
+            );
+            workflow.append_item(Item::new("Result").arg("result"));
+            workflow.set_filter_keyword("res".to_string());
+            workflow.response.var("SOURCE", "search");
+            if self.rerun {
+                workflow.response.rerun(Duration::from_secs(1));
+            }
+            Ok(())
+        }
+    }
+
+    fn cached(calls: &Rc<Cell<usize>>, rerun: bool) -> Cached<Counting> {
+        let runnable = Counting {
+            calls: calls.clone(),
+            rerun,
+        };
+        Cached::new("search:res", Duration::from_secs(60), runnable)
+    }
+
+    #[test]
+    fn test_serves_cached_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let calls = Rc::new(Cell::new(0));
+
+        let mut first = workflow_in(dir.path());
+        first.response.var("ALREADY_SET", "1");
+        cached(&calls, false).run(&mut first).unwrap();
+
+        let mut second = workflow_in(dir.path());
+        cached(&calls, false).run(&mut second).unwrap();
+        assert_eq!(calls.get(), 1);
+        assert_eq!(second.response.items, first.response.items);
+        assert_eq!(second.response.items[0].sticky, Some(3));
+        assert!(second.response.items[0].autocomplete_keyword);
+        assert_eq!(second.keyword.as_deref(), Some("res"));
+        assert_eq!(second.response.variables["SOURCE"], "search");
+        assert!(!second.response.variables.contains_key("ALREADY_SET"));
+
+        // Keys of any length give short file names
+        let stem = crate::memoize::key_file_stem(&"search:".repeat(100));
+        assert_eq!(stem.len(), 64);
+    }
+
+    #[test]
+    fn test_caches_only_the_runnables_items() {
+        struct Replacing;
+
+        impl Runnable for Replacing {
+            type Error = Error;
+
+            fn run(self, workflow: &mut Workflow) -> Result<(), Error> {
+                workflow.response.items(vec![Item::new("Replaced")]);
+                Ok(())
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut first = workflow_in(dir.path());
+        first.append_item(Item::new("Existing"));
+        Cached::new("replace", Duration::from_secs(60), Replacing)
+            .run(&mut first)
+            .unwrap();
+        let titles: Vec<_> = first.response.items.iter().map(|i| &i.title).collect();
+        assert_eq!(titles, ["Existing", "Replaced"]);
+
+        let mut second = workflow_in(dir.path());
+        Cached::new("replace", Duration::from_secs(60), Replacing)
+            .run(&mut second)
+            .unwrap();
+        assert_eq!(second.response.items, [Item::new("Replaced")]);
+    }
+
+    #[test]
+    fn test_does_not_cache_job_status_items() {
+        struct FailedJob;
+
+        impl Runnable for FailedJob {
+            type Error = Error;
+
+            fn run(self, workflow: &mut Workflow) -> Result<(), Error> {
+                workflow.run_in_background(
+                    "refresh",
+                    Duration::from_secs(60),
+                    || -> std::process::Command { panic!("job isn't due") },
+                );
+                workflow.append_item(Item::new("Result"));
+                Ok(())
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut first = workflow_in(dir.path());
+        let job_dir = first.jobs_dir().join("refresh");
+        std::fs::create_dir_all(&job_dir).unwrap();
+        std::fs::write(job_dir.join("job.last_run"), "").unwrap();
+        std::fs::write(job_dir.join("job.last_exit"), "3").unwrap();
+        std::fs::write(job_dir.join("job.failures"), "1").unwrap();
+        Cached::new("search", Duration::from_secs(60), FailedJob)
+            .run(&mut first)
+            .unwrap();
+        assert_eq!(first.response.items.len(), 2);
+        assert_eq!(first.response.rerun, None);
+
+        // The job recovers; the cached output doesn't replay its failure
+        std::fs::remove_dir_all(&job_dir).unwrap();
+        let mut second = workflow_in(dir.path());
+        Cached::new("search", Duration::from_secs(60), FailedJob)
+            .run(&mut second)
+            .unwrap();
+        assert_eq!(second.response.items, [Item::new("Result")]);
+    }
+
+    #[test]
+    fn test_does_not_cache_reruns() {
+        let dir = tempfile::tempdir().unwrap();
+        let calls = Rc::new(Cell::new(0));
+
+        cached(&calls, true)
+            .run(&mut workflow_in(dir.path()))
+            .unwrap();
+        cached(&calls, true)
+            .run(&mut workflow_in(dir.path()))
+            .unwrap();
+        assert_eq!(calls.get(), 2);
+    }
+}
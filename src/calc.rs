@@ -0,0 +1,448 @@
+//! Inline calculations and unit conversions as Items.
+//!
+//! calculate evaluates arithmetic expressions (`+ - * / % ^`, parentheses,
+//! functions such as `sqrt` and `sin`, and the constants `pi` and `e`) and
+//! unit conversions such as `10 km to mi` or `98.6 f in c`. The resulting
+//! Item follows Alfred's calculator: the result is the title, and choosing
+//! the item copies it.
+//!
+//! ```
+//! use alfrusco::calc;
+//!
+//! assert_eq!(calc::calculate("2 * (3 + 4)")?.formatted(), "14");
+//! assert_eq!(calc::calculate("1.5 kg to g")?.formatted(), "1500 g");
+//! // Queries that aren't calculations produce no item
+//! assert!(calc::item("hello world").is_none());
+//! # Ok::<(), alfrusco::Error>(())
+//! ```
+//!
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::{Error, Item, Result};
+
+/// The Calculator app, whose icon calculation items use.
+const CALCULATOR_APP: &str = "/System/Applications/Calculator.app";
+
+/// The number of decimal places results are rounded to.
+const DECIMALS: i32 = 10;
+
+/// Results at least this large are shown in scientific notation.
+const LARGE: f64 = 1e21;
+
+/// Calculation is the result of evaluating a query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calculation {
+    /// The query that was evaluated
+    pub expression: String,
+    pub value: f64,
+    /// The unit the value was converted to, for conversions
+    pub unit: Option<String>,
+}
+
+impl Calculation {
+    /// The value formatted for display, followed by its unit.
+    pub fn formatted(&self) -> String {
+        match &self.unit {
+            Some(unit) => format!("{} {}", format_number(self.value), unit),
+            None => format_number(self.value),
+        }
+    }
+
+    /// Returns an Item titled with the result. Choosing it copies the
+    /// number (without its unit) to the clipboard. See
+    /// Item::copy_on_action for the required workflow setup.
+    pub fn item(&self) -> Item {
+        Item::new(self.formatted())
+            .subtitle(format!(
+                "{} · Action this item to copy the result",
+                self.expression.trim()
+            ))
+            .icon_for_file(CALCULATOR_APP)
+            .copy_on_action(format_number(self.value))
+    }
+}
+
+/// Evaluates `query` as a unit conversion (`<expression> <unit> to
+/// <unit>`, or `in` instead of `to`) if it has that form, or as an
+/// arithmetic expression otherwise.
+pub fn calculate(query: &str) -> Result<Calculation> {
+    let (value, unit) = match parse_conversion(query) {
+        Some((expression, from, to)) => {
+            let value = convert(evaluate(expression)?, from, to)?;
+            (value, Some(to.to_string()))
+        }
+        None => (evaluate(query)?, None),
+    };
+    Ok(Calculation {
+        expression: query.to_string(),
+        value,
+        unit,
+    })
+}
+
+/// Returns the item for `query`'s result, or None if it isn't a valid
+/// calculation. This suits workflows that offer a calculation alongside
+/// their other results.
+pub fn item(query: &str) -> Option<Item> {
+    calculate(query).ok().map(|calculation| calculation.item())
+}
+
+/// Splits "10 km to mi" into ("10", "km", "mi").
+fn parse_conversion(query: &str) -> Option<(&str, &str, &str)> {
+    let query = query.trim();
+    let (left, to) = query
+        .rsplit_once(" to ")
+        .or_else(|| query.rsplit_once(" in "))?;
+    let left = left.trim_end();
+    let unit_start = left
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphabetic() || *c == '°')
+        .last()?
+        .0;
+    let (expression, from) = left.split_at(unit_start);
+    if expression.trim().is_empty() {
+        return None;
+    }
+    Some((expression, from, to.trim()))
+}
+
+/// Evaluates an arithmetic expression.
+pub fn evaluate(expression: &str) -> Result<f64> {
+    let mut parser = Parser {
+        chars: expression.chars().peekable(),
+    };
+    let value = parser.expression()?;
+    parser.skip_whitespace();
+    if let Some(c) = parser.chars.next() {
+        return Err(invalid(&format!("unexpected '{}'", c)));
+    }
+    if !value.is_finite() {
+        return Err(invalid("the result is not a number"));
+    }
+    Ok(value)
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::Workflow(format!("invalid calculation: {}", reason))
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.peek().copied()
+    }
+
+    fn expression(&mut self) -> Result<f64> {
+        let mut value = self.term()?;
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.chars.next();
+            let rhs = self.term()?;
+            value = if op == '+' { value + rhs } else { value - rhs };
+        }
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<f64> {
+        let mut value = self.unary()?;
+        while let Some(op @ ('*' | '×' | '/' | '÷' | '%')) = self.peek() {
+            self.chars.next();
+            let rhs = self.unary()?;
+            value = match op {
+                '*' | '×' => value * rhs,
+                '%' => value % rhs,
+                _ => value / rhs,
+            };
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<f64> {
+        match self.peek() {
+            Some('-') => {
+                self.chars.next();
+                Ok(-self.unary()?)
+            }
+            Some('+') => {
+                self.chars.next();
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    /// Powers bind tighter than unary minus and are right associative, so
+    /// -2^2 is -4 and 2^3^2 is 512.
+    fn power(&mut self) -> Result<f64> {
+        let base = self.primary()?;
+        if self.peek() == Some('^') {
+            self.chars.next();
+            return Ok(base.powf(self.unary()?));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<f64> {
+        match self.peek() {
+            Some('(') => {
+                self.chars.next();
+                let value = self.expression()?;
+                if self.peek() != Some(')') {
+                    return Err(invalid("missing ')'"));
+                }
+                self.chars.next();
+                Ok(value)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_alphabetic() => {
+                let mut name = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_alphanumeric()) {
+                    name.push(c);
+                }
+                self.named(&name.to_lowercase())
+            }
+            Some(c) => Err(invalid(&format!("unexpected '{}'", c))),
+            None => Err(invalid("unexpected end")),
+        }
+    }
+
+    fn number(&mut self) -> Result<f64> {
+        let mut number = String::new();
+        while let Some(c) = self.chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+            number.push(c);
+        }
+        // Scientific notation, e.g. 1.5e3 or 2e-4
+        if self.chars.peek() == Some(&'e') {
+            let mut lookahead = self.chars.clone();
+            lookahead.next();
+            let sign = lookahead.next_if(|c| *c == '-' || *c == '+');
+            if lookahead.peek().is_some_and(char::is_ascii_digit) {
+                number.push('e');
+                number.extend(sign);
+                self.chars = lookahead;
+                while let Some(c) = self.chars.next_if(char::is_ascii_digit) {
+                    number.push(c);
+                }
+            }
+        }
+        number
+            .parse()
+            .map_err(|_| invalid(&format!("invalid number '{}'", number)))
+    }
+
+    /// Evaluates a constant, or a function applied to a parenthesized
+    /// argument.
+    fn named(&mut self, name: &str) -> Result<f64> {
+        match name {
+            "pi" => return Ok(std::f64::consts::PI),
+            "e" => return Ok(std::f64::consts::E),
+            _ => {}
+        }
+        let function: fn(f64) -> f64 = match name {
+            "sqrt" => f64::sqrt,
+            "abs" => f64::abs,
+            "round" => f64::round,
+            "floor" => f64::floor,
+            "ceil" => f64::ceil,
+            "ln" => f64::ln,
+            "log" => f64::log10,
+            "log2" => f64::log2,
+            "exp" => f64::exp,
+            "sin" => f64::sin,
+            "cos" => f64::cos,
+            "tan" => f64::tan,
+            "asin" => f64::asin,
+            "acos" => f64::acos,
+            "atan" => f64::atan,
+            _ => return Err(invalid(&format!("unknown name '{}'", name))),
+        };
+        if self.peek() != Some('(') {
+            return Err(invalid(&format!(
+                "{} needs an argument in parentheses",
+                name
+            )));
+        }
+        Ok(function(self.primary()?))
+    }
+}
+
+/// Formats a result the way Alfred's calculator does: rounded to 10
+/// decimal places, without trailing zeros. Very small values use
+/// scientific notation rather than rounding to 0, and so do very large
+/// ones, which would otherwise overflow to infinity when scaled for
+/// rounding (and have no decimal places left to round anyway).
+fn format_number(value: f64) -> String {
+    let magnitude = value.abs();
+    if value != 0.0 && (magnitude < 10f64.powi(-DECIMALS / 2) || magnitude >= LARGE) {
+        return format!("{:e}", value);
+    }
+    let scale = 10f64.powi(DECIMALS);
+    let rounded = (value * scale).round() / scale;
+    // Avoid "-0"
+    format!("{}", rounded + 0.0)
+}
+
+/// Dimension groups the units that can be converted into each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dimension {
+    Length,
+    Mass,
+    Time,
+    Volume,
+    Data,
+    Temperature,
+}
+
+/// Returns a unit's dimension and its size in the dimension's base unit
+/// (meters, kilograms, seconds, liters, bytes, or kelvins).
+fn unit(name: &str) -> Option<(Dimension, f64)> {
+    use Dimension::*;
+
+    let unit = match name.to_lowercase().as_str() {
+        "mm" | "millimeter" | "millimeters" | "millimetre" | "millimetres" => (Length, 0.001),
+        "cm" | "centimeter" | "centimeters" | "centimetre" | "centimetres" => (Length, 0.01),
+        "m" | "meter" | "meters" | "metre" | "metres" => (Length, 1.0),
+        "km" | "kilometer" | "kilometers" | "kilometre" | "kilometres" => (Length, 1000.0),
+        "in" | "inch" | "inches" => (Length, 0.0254),
+        "ft" | "foot" | "feet" => (Length, 0.3048),
+        "yd" | "yard" | "yards" => (Length, 0.9144),
+        "mi" | "mile" | "miles" => (Length, 1609.344),
+        "mg" | "milligram" | "milligrams" => (Mass, 1e-6),
+        "g" | "gram" | "grams" => (Mass, 0.001),
+        "kg" | "kilogram" | "kilograms" => (Mass, 1.0),
+        "t" | "tonne" | "tonnes" => (Mass, 1000.0),
+        "oz" | "ounce" | "ounces" => (Mass, 0.028349523125),
+        "lb" | "lbs" | "pound" | "pounds" => (Mass, 0.45359237),
+        "st" | "stone" | "stones" => (Mass, 6.35029318),
+        "ms" | "millisecond" | "milliseconds" => (Time, 0.001),
+        "s" | "sec" | "secs" | "second" | "seconds" => (Time, 1.0),
+        "min" | "mins" | "minute" | "minutes" => (Time, 60.0),
+        "h" | "hr" | "hrs" | "hour" | "hours" => (Time, 3600.0),
+        "d" | "day" | "days" => (Time, 86400.0),
+        "wk" | "week" | "weeks" => (Time, 604800.0),
+        "ml" | "milliliter" | "milliliters" | "millilitre" | "millilitres" => (Volume, 0.001),
+        "l" | "liter" | "liters" | "litre" | "litres" => (Volume, 1.0),
+        "floz" => (Volume, 0.0295735295625),
+        "cup" | "cups" => (Volume, 0.2365882365),
+        "pt" | "pint" | "pints" => (Volume, 0.473176473),
+        "qt" | "quart" | "quarts" => (Volume, 0.946352946),
+        "gal" | "gallon" | "gallons" => (Volume, 3.785411784),
+        "b" | "byte" | "bytes" => (Data, 1.0),
+        "kb" => (Data, 1e3),
+        "mb" => (Data, 1e6),
+        "gb" => (Data, 1e9),
+        "tb" => (Data, 1e12),
+        "kib" => (Data, 1024.0),
+        "mib" => (Data, 1024f64.powi(2)),
+        "gib" => (Data, 1024f64.powi(3)),
+        "tib" => (Data, 1024f64.powi(4)),
+        "k" | "kelvin" | "kelvins" => (Temperature, 1.0),
+        // Celsius and Fahrenheit are offset from kelvins; see convert
+        "c" | "°c" | "celsius" => (Temperature, 1.0),
+        "f" | "°f" | "fahrenheit" => (Temperature, 5.0 / 9.0),
+        _ => return None,
+    };
+    Some(unit)
+}
+
+/// Returns the offset (in kelvins) of a temperature unit's zero.
+fn temperature_offset(name: &str) -> f64 {
+    match name.to_lowercase().as_str() {
+        "c" | "°c" | "celsius" => 273.15,
+        "f" | "°f" | "fahrenheit" => 459.67 * 5.0 / 9.0,
+        _ => 0.0,
+    }
+}
+
+/// Converts `value` from one unit to another, e.g. `convert(1.0, "mi",
+/// "km")`. Units are case insensitive and may be abbreviated ("km") or
+/// spelled out ("kilometers").
+pub fn convert(value: f64, from: &str, to: &str) -> Result<f64> {
+    let unknown = |name: &str| invalid(&format!("unknown unit '{}'", name));
+    let (from_dimension, from_size) = unit(from).ok_or_else(|| unknown(from))?;
+    let (to_dimension, to_size) = unit(to).ok_or_else(|| unknown(to))?;
+    if from_dimension != to_dimension {
+        return Err(invalid(&format!("can't convert {} to {}", from, to)));
+    }
+    let base = value * from_size + temperature_offset(from);
+    Ok((base - temperature_offset(to)) / to_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calc(query: &str) -> String {
+        calculate(query).unwrap().formatted()
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(calc("1 + 2 * 3"), "7");
+        assert_eq!(calc("(1 + 2) * 3"), "9");
+        assert_eq!(calc("10 / 4"), "2.5");
+        assert_eq!(calc("10 % 4"), "2");
+        assert_eq!(calc("-2^2"), "-4");
+        assert_eq!(calc("2^3^2"), "512");
+        assert_eq!(calc("2 ^ -1"), "0.5");
+        assert_eq!(calc("6 × 7 ÷ 2"), "21");
+        assert_eq!(calc("0.1 + 0.2"), "0.3");
+        assert_eq!(calc("1.5e3 + 2e-1"), "1500.2");
+        assert_eq!(calc("sqrt(16) + abs(-2)"), "6");
+        assert_eq!(calc("round(pi * 100) / 100"), "3.14");
+        assert_eq!(calc("cos(0) - 1"), "0");
+        assert_eq!(calc("1 / 3"), "0.3333333333");
+        assert_eq!(calc("0.000000001"), "1e-9");
+        assert_eq!(calc("1e20"), "100000000000000000000");
+        assert_eq!(calc("1e300 * 2"), "2e300");
+        assert_eq!(calc("-5 * 1e299"), "-5e299");
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        for query in [
+            "", "1 +", "(1 + 2", "1 / 0", "sqrt 4", "foo(1)", "2 3", "hello",
+        ] {
+            assert!(calculate(query).is_err(), "{:?}", query);
+        }
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(calc("10 km to mi"), "6.2137119224 mi");
+        assert_eq!(calc("1.5 kg to g"), "1500 g");
+        assert_eq!(calc("100 c to f"), "212 f");
+        assert_eq!(calc("98.6 °F in celsius"), "37 celsius");
+        assert_eq!(calc("0 k to c"), "-273.15 c");
+        assert_eq!(calc("2 * 90 min to hours"), "3 hours");
+        assert_eq!(calc("1 GiB to MB"), "1073.741824 MB");
+        assert!(calculate("1 kg to km").is_err());
+        assert!(calculate("1 parsec to km").is_err());
+    }
+
+    #[test]
+    fn test_item() {
+        let json = serde_json::to_value(item("10 km to mi").unwrap()).unwrap();
+        assert_eq!(json["title"], "6.2137119224 mi");
+        assert_eq!(
+            json["subtitle"],
+            "10 km to mi · Action this item to copy the result"
+        );
+        assert_eq!(json["arg"], "6.2137119224");
+        assert_eq!(
+            json["variables"]["ALFRUSCO_INTERNAL"],
+            r#"{"command":"copy","text":"6.2137119224"}"#
+        );
+        assert!(item("what is the time").is_none());
+    }
+}
@@ -1,59 +1,300 @@
-use std::env::var;
-use std::process::Command;
-
+#[cfg(target_os = "macos")]
 use clipboard::{ClipboardContext, ClipboardProvider};
 use hex::encode;
-use log::{debug, info};
-
-use crate::Response;
-
-pub fn handle_clipboard() {
-    let cmd = var("ALFRUSCO_COMMAND").ok();
-    let title = var("TITLE").ok();
-    let url = var("URL").ok();
-    if let Some(cmd) = cmd {
-        debug!("ALFRUSCO_COMMAND provided. Alfrusco will handle this request");
-
-        if cmd == "richtext" || cmd == "markdown" {
-            if let (Some(title), Some(url)) = (title, url) {
-                if cmd == "richtext" {
-                    copy_rich_text_link_to_clipboard(title, url);
-                } else if cmd == "markdown" {
-                    copy_markdown_link_to_clipboard(title, url);
+use log::info;
+
+use crate::system::SystemActions;
+use crate::text::escape_applescript;
+use crate::Result;
+
+pub fn format_markdown_link(title: &str, url: &str) -> String {
+    format!("[{}]({})", title, url)
+}
+
+pub fn format_markdown_image(alt_text: &str, url: &str) -> String {
+    format!("![{}]({})", alt_text, url)
+}
+
+pub fn format_org_link(title: &str, url: &str) -> String {
+    format!("[[{}][{}]]", url, title)
+}
+
+pub fn format_html_link(title: &str, url: &str) -> String {
+    format!("<a href=\"{}\">{}</a>", url, title)
+}
+
+pub fn format_title_and_url(title: &str, url: &str) -> String {
+    format!("{} — {}", title, url)
+}
+
+/// Formats a link as an RTF document with a HYPERLINK field.
+pub fn format_rtf_link(title: &str, url: &str) -> String {
+    format!(
+        "{{\\rtf1\\ansi\\deff0{{\\fonttbl{{\\f0 Helvetica;}}}}\\f0 \
+         {{\\field{{\\*\\fldinst{{HYPERLINK \"{}\"}}}}{{\\fldrslt{{{}}}}}}}}}",
+        escape_rtf(&url.replace('"', "%22")),
+        escape_rtf(title)
+    )
+}
+
+/// Escapes RTF's control characters, and writes characters outside ASCII
+/// as `\\uN?` escapes (UTF-16 code units, as signed 16-bit numbers).
+fn escape_rtf(text: &str) -> String {
+    let mut rtf = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '{' | '}' => {
+                rtf.push('\\');
+                rtf.push(c);
+            }
+            '\n' => rtf.push_str("\\line "),
+            c if c.is_ascii() => rtf.push(c),
+            c => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    rtf.push_str(&format!("\\u{}?", *unit as i16));
                 }
-                Response::new().write(std::io::stdout()).unwrap();
-                std::process::exit(0);
             }
         }
     }
+    rtf
+}
+
+/// Formats rows of cells as an HTML table. The first row is rendered as
+/// the header row. Cell contents are HTML-escaped.
+pub fn format_html_table<R, C>(rows: R) -> String
+where
+    R: IntoIterator,
+    R::Item: IntoIterator<Item = C>,
+    C: AsRef<str>,
+{
+    let mut html = String::from("<table>");
+    for (i, row) in rows.into_iter().enumerate() {
+        let tag = if i == 0 { "th" } else { "td" };
+        html.push_str("<tr>");
+        for cell in row {
+            html.push_str(&format!("<{tag}>{}</{tag}>", escape_html(cell.as_ref())));
+        }
+        html.push_str("</tr>");
+    }
+    html.push_str("</table>");
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Copies plain text to the clipboard.
+pub fn copy_text_to_clipboard(text: impl Into<String>) {
+    let text = text.into();
+    set_clipboard_text(&text);
+    info!("wrote text: {} to the clipboard", text);
 }
 
 pub fn copy_markdown_link_to_clipboard(title: impl Into<String>, url: impl Into<String>) {
-    let markdown = format!("[{}]({})", title.into(), url.into());
-    let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
-    ctx.set_contents(markdown.clone()).unwrap();
+    let markdown = format_markdown_link(&title.into(), &url.into());
+    set_clipboard_text(&markdown);
     info!("wrote Markdown: {} to the clipboard", markdown);
 }
 
-pub fn copy_rich_text_link_to_clipboard(title: impl Into<String>, url: impl Into<String>) {
-    let html = format!("<a href=\"{}\">{}</a>", url.into(), title.into());
+pub fn copy_markdown_image_to_clipboard(alt_text: impl Into<String>, url: impl Into<String>) {
+    let markdown = format_markdown_image(&alt_text.into(), &url.into());
+    set_clipboard_text(&markdown);
+    info!("wrote Markdown image: {} to the clipboard", markdown);
+}
+
+pub fn copy_org_link_to_clipboard(title: impl Into<String>, url: impl Into<String>) {
+    let org = format_org_link(&title.into(), &url.into());
+    set_clipboard_text(&org);
+    info!("wrote org-mode link: {} to the clipboard", org);
+}
 
+pub fn copy_html_table_to_clipboard<R, C>(system: &dyn SystemActions, rows: R) -> Result<()>
+where
+    R: IntoIterator,
+    R::Item: IntoIterator<Item = C>,
+    C: AsRef<str>,
+{
+    let html = format_html_table(rows);
+    copy_html_to_clipboard(system, &html)?;
+    info!("wrote HTML table to the clipboard as rich text: {}", html);
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn set_clipboard_text(text: &str) {
+    let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
+    ctx.set_contents(text.to_string()).unwrap();
+}
+
+/// Clipboard access is unavailable off macOS, so this only logs.
+#[cfg(not(target_os = "macos"))]
+fn set_clipboard_text(text: &str) {
+    log::warn!(
+        "clipboard unavailable on this platform, not copying: {}",
+        text
+    );
+}
+
+pub fn copy_rich_text_link_to_clipboard(
+    system: &dyn SystemActions,
+    title: impl Into<String>,
+    url: impl Into<String>,
+) -> Result<()> {
+    let html = format_html_link(&title.into(), &url.into());
+    copy_html_to_clipboard(system, &html)?;
+    info!("wrote HTML to the clipboard as rich text: {}", html);
+    Ok(())
+}
+
+/// Copies a link to the clipboard as RTF rather than HTML. Some apps,
+/// such as Mail and Pages, paste the HTML from
+/// copy_rich_text_link_to_clipboard poorly but handle RTF well.
+pub fn copy_rich_text_link_rtf(
+    system: &dyn SystemActions,
+    title: impl Into<String>,
+    url: impl Into<String>,
+) -> Result<()> {
+    let title = title.into();
+    let rtf = format_rtf_link(&title, &url.into());
+    let apple_script = format!(
+        "set the clipboard to {{text:\"{}\", «class RTF »:«data RTF {}»}}",
+        escape_applescript(&title),
+        encode(rtf.as_bytes()),
+    );
+    system.run_osascript(&apple_script)?;
+    info!("wrote RTF to the clipboard as rich text: {}", rtf);
+    Ok(())
+}
+
+fn copy_html_to_clipboard(system: &dyn SystemActions, html: &str) -> Result<()> {
     let apple_script = format!(
         "set the clipboard to {{text:\" \", «class HTML»:«data HTML{}»}}",
         encode(html.as_bytes()),
     );
+    system.run_osascript(&apple_script)?;
+    Ok(())
+}
+
+/// Pastes `text` into the frontmost application.
+///
+/// The text is placed on the clipboard, Cmd-V is sent via System Events,
+/// and the previous (plain text) clipboard contents are restored
+/// afterwards. Sending keystrokes requires the Accessibility permission
+/// for Alfred.
+///
+pub fn paste_to_frontmost(system: &dyn SystemActions, text: impl Into<String>) -> Result<()> {
+    let text = text.into();
+    let previous = system.run_osascript("return the clipboard as text").ok();
 
-    // Prepare and execute the osascript command
-    let output = Command::new("osascript")
-        .arg("-e")
-        .arg(&apple_script)
-        .output()
-        .expect("Failed to execute command");
+    system.run_osascript(&format!(
+        "set the clipboard to \"{}\"",
+        escape_applescript(&text)
+    ))?;
+    system.run_osascript(
+        "tell application \"System Events\" to keystroke \"v\" using command down",
+    )?;
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        panic!("osascript command failed: {}", stderr);
+    if let Some(previous) = previous {
+        // Give the target application time to read the clipboard before
+        // putting the old contents back.
+        let previous = previous.strip_suffix('\n').unwrap_or(&previous);
+        system.run_osascript(&format!(
+            "delay 0.3\nset the clipboard to \"{}\"",
+            escape_applescript(previous)
+        ))?;
     }
 
-    info!("wrote HTML to the clipboard as rich text: {}", html);
+    info!(
+        "pasted {} characters to the frontmost application",
+        text.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{MockSystemActions, SystemCall};
+
+    #[test]
+    fn test_paste_to_frontmost_restores_clipboard() {
+        let mock = MockSystemActions::new().osascript_output("previous\n");
+        paste_to_frontmost(&mock, "say \"hi\"").unwrap();
+
+        let scripts: Vec<String> = mock
+            .calls()
+            .into_iter()
+            .map(|call| match call {
+                SystemCall::RunOsascript(script) => script,
+                other => panic!("unexpected call {:?}", other),
+            })
+            .collect();
+        assert_eq!(scripts.len(), 4);
+        assert_eq!(scripts[0], "return the clipboard as text");
+        assert_eq!(scripts[1], "set the clipboard to \"say \\\"hi\\\"\"");
+        assert!(scripts[2].contains("keystroke \"v\" using command down"));
+        assert_eq!(scripts[3], "delay 0.3\nset the clipboard to \"previous\"");
+    }
+
+    #[test]
+    fn test_link_formats() {
+        let (title, url) = ("Rust", "https://www.rust-lang.org/");
+        assert_eq!(
+            format_markdown_link(title, url),
+            "[Rust](https://www.rust-lang.org/)"
+        );
+        assert_eq!(
+            format_markdown_image(title, url),
+            "![Rust](https://www.rust-lang.org/)"
+        );
+        assert_eq!(
+            format_org_link(title, url),
+            "[[https://www.rust-lang.org/][Rust]]"
+        );
+        assert_eq!(
+            format_title_and_url(title, url),
+            "Rust — https://www.rust-lang.org/"
+        );
+        assert_eq!(
+            format_html_link(title, url),
+            "<a href=\"https://www.rust-lang.org/\">Rust</a>"
+        );
+    }
+
+    #[test]
+    fn test_format_rtf_link() {
+        assert_eq!(
+            format_rtf_link("Rust {lang}", "https://www.rust-lang.org/?q=\"a\""),
+            "{\\rtf1\\ansi\\deff0{\\fonttbl{\\f0 Helvetica;}}\\f0 \
+             {\\field{\\*\\fldinst{HYPERLINK \"https://www.rust-lang.org/?q=%22a%22\"}}\
+             {\\fldrslt{Rust \\{lang\\}}}}}"
+        );
+        assert_eq!(escape_rtf("café 🦀"), "caf\\u233? \\u-10178?\\u-8832?");
+    }
+
+    #[test]
+    fn test_format_html_table() {
+        let html = format_html_table([vec!["Name", "Score"], vec!["R&D", "<1>"]]);
+        assert_eq!(
+            html,
+            "<table><tr><th>Name</th><th>Score</th></tr>\
+             <tr><td>R&amp;D</td><td>&lt;1&gt;</td></tr></table>"
+        );
+    }
+
+    #[test]
+    fn test_copy_html_table_runs_osascript() {
+        let mock = MockSystemActions::new();
+        copy_html_table_to_clipboard(&mock, [["a", "b"]]).unwrap();
+        let expected = encode("<table><tr><th>a</th><th>b</th></tr></table>");
+        match mock.calls().as_slice() {
+            [SystemCall::RunOsascript(script)] => assert!(script.contains(&expected)),
+            calls => panic!("unexpected calls: {:?}", calls),
+        }
+    }
 }
@@ -0,0 +1,184 @@
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::memoize::write_atomically;
+use crate::workflow::Workflow;
+use crate::Result;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression is the format used for memoized values and Cached items
+/// on disk. Each format is behind the feature of the same name.
+///
+/// Compressed files are named for their format (`.json.gz` or
+/// `.json.zst`) and recognized by their contents, so values cached before
+/// compression was enabled (or with another enabled format) are still
+/// read. Run `cargo bench --bench cache_compression --features
+/// gzip,zstd` to compare sizes and timings.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// gzip at its default level: widely readable, slower to write
+    #[cfg(feature = "gzip")]
+    Gzip,
+
+    /// zstd at its default level: smaller and faster than gzip
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// The extension of files written in this format
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => "json.gz",
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => "json.zst",
+        }
+    }
+
+    fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => {
+                use std::io::Write;
+
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(bytes)?;
+                Ok(encoder.finish()?)
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Ok(zstd::encode_all(bytes, 0)?),
+        }
+    }
+}
+
+/// Returns the JSON held in a cached file's contents, decompressing them
+/// if they were compressed.
+pub(crate) fn decompress(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    if bytes.starts_with(GZIP_MAGIC) {
+        #[cfg(feature = "gzip")]
+        {
+            use std::io::Read;
+
+            let mut json = Vec::new();
+            flate2::read::GzDecoder::new(&bytes[..]).read_to_end(&mut json)?;
+            return Ok(json);
+        }
+        #[cfg(not(feature = "gzip"))]
+        return Err(crate::Error::Workflow(
+            "gzip support is not enabled".to_string(),
+        ));
+    }
+    if bytes.starts_with(ZSTD_MAGIC) {
+        #[cfg(feature = "zstd")]
+        return Ok(zstd::decode_all(&bytes[..])?);
+        #[cfg(not(feature = "zstd"))]
+        return Err(crate::Error::Workflow(
+            "zstd support is not enabled".to_string(),
+        ));
+    }
+    Ok(bytes)
+}
+
+/// Writes `value` as compressed JSON.
+pub(crate) fn write_compressed<T: Serialize>(
+    path: &Path,
+    value: &T,
+    compression: Compression,
+) -> Result<()> {
+    let json = serde_json::to_vec(value)?;
+    write_atomically(path, &compression.compress(&json)?)
+}
+
+impl Workflow {
+    /// Compresses memoized values and Cached items on disk, keeping the
+    /// cache directory small for workflows caching tens of thousands of
+    /// items. Pass None to write plain JSON again.
+    pub fn set_cache_compression(&mut self, compression: Option<Compression>) {
+        self.cache_compression = compression;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::memoize::read_fresh;
+    use crate::test_support::workflow_in;
+
+    fn large_value() -> Vec<String> {
+        (0..5_000).map(|i| format!("cached item {}", i)).collect()
+    }
+
+    fn enabled() -> Vec<(Compression, &'static [u8])> {
+        vec![
+            #[cfg(feature = "gzip")]
+            (Compression::Gzip, GZIP_MAGIC),
+            #[cfg(feature = "zstd")]
+            (Compression::Zstd, ZSTD_MAGIC),
+        ]
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let json_len = serde_json::to_vec(&large_value()).unwrap().len();
+        for (compression, magic) in enabled() {
+            let path = dir
+                .path()
+                .join(format!("value.{}", compression.extension()));
+            write_compressed(&path, &large_value(), compression).unwrap();
+
+            let bytes = read(&path).unwrap();
+            assert!(bytes.starts_with(magic));
+            assert!(bytes.len() < json_len / 4, "{:?}", compression);
+            let value: Vec<String> = read_fresh(&path, Duration::MAX).unwrap();
+            assert_eq!(value, large_value());
+        }
+    }
+
+    #[test]
+    fn test_memoize_compresses_values() {
+        for (compression, magic) in enabled() {
+            let dir = tempfile::tempdir().unwrap();
+            let ttl = Duration::from_secs(60);
+            let mut workflow = workflow_in(dir.path());
+            // Plain JSON cached before compression was enabled is still read
+            workflow.memoize("plain", ttl, || Ok(vec![1, 2])).unwrap();
+            workflow.set_cache_compression(Some(compression));
+
+            workflow
+                .memoize("large", ttl, || Ok(large_value()))
+                .unwrap();
+            let cached: Vec<String> = workflow
+                .memoize("large", ttl, || panic!("should be cached"))
+                .unwrap();
+            assert_eq!(cached, large_value());
+            let plain: Vec<i32> = workflow
+                .memoize("plain", ttl, || panic!("should be cached"))
+                .unwrap();
+            assert_eq!(plain, [1, 2]);
+
+            let path = workflow.memoize_dir().join(format!(
+                "{}.{}",
+                crate::memoize::key_file_stem("large"),
+                compression.extension()
+            ));
+            assert!(read(path).unwrap().starts_with(magic));
+
+            // Values cached before the compression changed are still read
+            workflow.set_cache_compression(None);
+            let cached: Vec<String> = workflow
+                .memoize("large", ttl, || panic!("should be cached"))
+                .unwrap();
+            assert_eq!(cached, large_value());
+        }
+    }
+}
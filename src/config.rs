@@ -1,7 +1,9 @@
-use std::env;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use crate::Result;
+use crate::system::{DefaultSystemActions, SystemActions};
+use crate::{Color, Error, InternalCommand, Item, Result, ICON_ALERT_STOP, INTERNAL_VAR};
 
 const VAR_PREFERENCES: &str = "alfred_preferences";
 const VAR_PREFERENCES_LOCALHASH: &str = "alfred_preferences_localhash";
@@ -47,12 +49,103 @@ pub struct WorkflowConfig {
     pub workflow_uid: Option<String>,
     pub workflow_keyword: Option<String>,
     pub debug: bool,
+
+    /// The environment the configuration was read from. Workflow code
+    /// should look variables up here rather than in the process
+    /// environment, so that the whole execution sees one consistent
+    /// snapshot.
+    pub env: EnvMap,
+}
+
+/// EnvMap is a snapshot of environment variables.
+///
+/// Alfred passes its configuration, the workflow's User Configuration and
+/// the variables of the selected item through the environment. Capturing
+/// them once keeps an execution consistent even if the process
+/// environment changes, and lets tests or embedding applications supply
+/// their own variables.
+///
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct EnvMap(HashMap<String, String>);
+
+/// Redacts the value of a secret being saved (see InternalCommand), so
+/// debug-printing the environment doesn't log it.
+impl std::fmt::Debug for EnvMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut map = f.debug_map();
+        for (name, value) in &self.0 {
+            let secret = match name.as_str() {
+                INTERNAL_VAR => InternalCommand::parse_json(value).is_some_and(|c| c.has_secret()),
+                "SECRET_VALUE" => true,
+                _ => false,
+            };
+            map.entry(name, &if secret { "<redacted>" } else { value.as_str() });
+        }
+        map.finish()
+    }
+}
+
+impl EnvMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Captures the current process environment. Variables whose names or
+    /// values aren't valid Unicode are skipped.
+    pub fn from_process() -> Self {
+        EnvMap(std::env::vars().collect())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    /// Returns the variable, or Error::MissingEnvVar if it isn't set.
+    pub fn require(&self, name: &str) -> Result<&str> {
+        self.get(name)
+            .ok_or_else(|| Error::MissingEnvVar(name.to_string()))
+    }
+
+    pub fn set(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.0.insert(name.into(), value.into());
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        self.0.remove(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for EnvMap {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        EnvMap(
+            iter.into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        )
+    }
 }
 
 /// ConfigProvider provides a strategy pattern solution for providing
 /// the critical Alfred configuration data to a workflow.
 pub trait ConfigProvider {
     fn config(&self) -> Result<WorkflowConfig>;
+
+    /// Whether execute should install alfrusco's logger, writing to
+    /// workflow.log in the cache directory. See logging::auto_init.
+    fn auto_logging(&self) -> bool {
+        true
+    }
+
+    /// The SystemActions execute gives the Workflow, and uses to perform
+    /// internal commands. Override it to run a workflow against a
+    /// MockSystemActions.
+    fn system_actions(&self) -> Box<dyn SystemActions> {
+        Box::new(DefaultSystemActions::default())
+    }
 }
 
 /// AlfredEnvProvider reads workflow configuration values from environment
@@ -68,61 +161,147 @@ pub struct AlfredEnvProvider;
 
 impl ConfigProvider for AlfredEnvProvider {
     fn config(&self) -> Result<WorkflowConfig> {
-        let debug = env::var(VAR_DEBUG).unwrap_or_default();
-        let debug = debug == "1" || debug.to_lowercase() == "true";
+        WorkflowConfig::from_env(EnvMap::from_process())
+    }
+}
 
-        let config = WorkflowConfig {
-            // Required configuration values. Return Err with specific message if missing
-            workflow_bundleid: env::var(VAR_WORKFLOW_BUNDLEID).map_err(|_| {
-                format!(
-                    "Missing required environment variable: {}",
-                    VAR_WORKFLOW_BUNDLEID
-                )
-            })?,
-            workflow_cache: env::var(VAR_WORKFLOW_CACHE)
-                .map_err(|_| {
-                    format!(
-                        "Missing required environment variable: {}",
-                        VAR_WORKFLOW_CACHE
-                    )
-                })?
-                .into(),
-            workflow_data: env::var(VAR_WORKFLOW_DATA)
-                .map_err(|_| {
-                    format!(
-                        "Missing required environment variable: {}",
-                        VAR_WORKFLOW_DATA
-                    )
-                })?
-                .into(),
-            version: env::var(VAR_VERSION)
-                .map_err(|_| format!("Missing required environment variable: {}", VAR_VERSION))?,
-            version_build: env::var(VAR_VERSION_BUILD).map_err(|_| {
-                format!(
-                    "Missing required environment variable: {}",
-                    VAR_VERSION_BUILD
-                )
-            })?,
-            workflow_name: env::var(VAR_WORKFLOW_NAME).map_err(|_| {
-                format!(
-                    "Missing required environment variable: {}",
-                    VAR_WORKFLOW_NAME
-                )
-            })?,
-            // Optional configuration values. Set to None if not provided
-            workflow_version: env::var(VAR_WORKFLOW_VERSION).ok(),
-            preferences: env::var(VAR_PREFERENCES).ok(),
-            preferences_localhash: env::var(VAR_PREFERENCES_LOCALHASH).ok(),
-            theme: env::var(VAR_THEME).ok(),
-            theme_background: env::var(VAR_THEME_BACKGROUND).ok(),
-            theme_selection_background: env::var(VAR_THEME_SELECTION_BACKGROUND).ok(),
-            theme_subtext: env::var(VAR_THEME_SUBTEXT).ok(),
-            workflow_description: env::var(VAR_WORKFLOW_DESCRIPTION).ok(),
-            workflow_uid: env::var(VAR_WORKFLOW_UID).ok(),
-            workflow_keyword: env::var(VAR_WORKFLOW_KEYWORD).ok(),
-            debug,
+/// Returns the item shown when the workflow's configuration can't be
+/// loaded, naming the problem and how to fix it. When none of Alfred's
+/// variables are in `env`, the binary is most likely being run outside
+/// Alfred.
+pub(crate) fn config_error_item(error: &Error, env: &EnvMap) -> Item {
+    let strings = crate::i18n::current();
+    let message = match error {
+        Error::Workflow(message) => message.clone(),
+        error => error.to_string(),
+    };
+    let inside_alfred = env.iter().any(|(name, _)| name.starts_with("alfred_"));
+    let hint = if inside_alfred {
+        "config.check"
+    } else {
+        "config.outside_alfred"
+    };
+    Item::new(strings.get("config.title"))
+        .subtitle(strings.format(hint, &[("error", &message)]))
+        .icon(ICON_ALERT_STOP.into())
+        .valid(false)
+}
+
+/// EnvMapProvider reads workflow configuration values from the provided
+/// EnvMap instead of the process environment, for embedding alfrusco or
+/// replaying a captured environment. It has the same requirements as
+/// AlfredEnvProvider.
+pub struct EnvMapProvider(pub EnvMap);
+
+impl ConfigProvider for EnvMapProvider {
+    fn config(&self) -> Result<WorkflowConfig> {
+        WorkflowConfig::from_env(self.0.clone())
+    }
+}
+
+/// FileProvider reads the workflow configuration from a file, for running
+/// a workflow binary in CI, a container or an integration test without
+/// setting a dozen environment variables.
+///
+/// The file holds a flat table of the variables Alfred would set, along
+/// with any of the workflow's own variables. The same variables are
+/// required as for AlfredEnvProvider. Relative alfred_workflow_cache and
+/// alfred_workflow_data paths are resolved against the file's directory.
+/// Numbers and booleans are accepted and converted to strings.
+///
+/// ```json
+/// {
+///     "alfred_workflow_bundleid": "com.example.search",
+///     "alfred_workflow_name": "Search",
+///     "alfred_workflow_cache": "cache",
+///     "alfred_workflow_data": "data",
+///     "alfred_version": "5.5",
+///     "alfred_version_build": 2257,
+///     "alfred_debug": true,
+///     "API_URL": "http://localhost:8080"
+/// }
+/// ```
+///
+/// Files ending in .toml are read as TOML, which requires the `toml`
+/// feature. Anything else is read as JSON.
+///
+pub struct FileProvider {
+    path: PathBuf,
+    inherit_env: bool,
+}
+
+impl FileProvider {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileProvider {
+            path: path.into(),
+            inherit_env: false,
+        }
+    }
+
+    /// Starts from the process environment, with the file's variables
+    /// taking precedence. By default only the file is used.
+    pub fn inherit_env(mut self, inherit: bool) -> Self {
+        self.inherit_env = inherit;
+        self
+    }
+
+    fn read_vars(&self) -> Result<Vec<(String, String)>> {
+        let contents = std::fs::read_to_string(&self.path)?;
+        let is_toml = self.path.extension().is_some_and(|ext| ext == "toml");
+        let table: serde_json::Map<String, serde_json::Value> = if is_toml {
+            parse_toml(&contents)?
+        } else {
+            serde_json::from_str(&contents)?
         };
-        Ok(config)
+        table
+            .into_iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    serde_json::Value::String(value) => value,
+                    serde_json::Value::Number(_) | serde_json::Value::Bool(_) => value.to_string(),
+                    _ => {
+                        return Err(Error::Workflow(format!(
+                            "config variable {} must be a string, number or boolean",
+                            name
+                        )))
+                    }
+                };
+                Ok((name, value))
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "toml")]
+fn parse_toml(contents: &str) -> Result<serde_json::Map<String, serde_json::Value>> {
+    toml::from_str(contents).map_err(|e| Error::Workflow(format!("invalid TOML config: {}", e)))
+}
+
+#[cfg(not(feature = "toml"))]
+fn parse_toml(_contents: &str) -> Result<serde_json::Map<String, serde_json::Value>> {
+    Err(Error::Workflow(
+        "reading TOML config files requires alfrusco's toml feature".to_string(),
+    ))
+}
+
+impl ConfigProvider for FileProvider {
+    fn config(&self) -> Result<WorkflowConfig> {
+        let mut env = if self.inherit_env {
+            EnvMap::from_process()
+        } else {
+            EnvMap::new()
+        };
+        let base = self.path.parent().unwrap_or(Path::new(""));
+        for (name, value) in self.read_vars()? {
+            let value = match name.as_str() {
+                VAR_WORKFLOW_CACHE | VAR_WORKFLOW_DATA => {
+                    base.join(value).to_string_lossy().into_owned()
+                }
+                _ => value,
+            };
+            env.set(name, value);
+        }
+        WorkflowConfig::from_env(env)
     }
 }
 
@@ -135,14 +314,67 @@ impl ConfigProvider for AlfredEnvProvider {
 ///
 /// Typical usage is based around directories created by the tempfile crate
 ///
-/// let dir = tempfile::tempdir().unwrap().into_path();
+/// let dir = tempfile::tempdir().unwrap().keep();
 /// config::TestingProvider(dir)
 ///
 pub struct TestingProvider(pub PathBuf);
 
 impl ConfigProvider for TestingProvider {
     fn config(&self) -> Result<WorkflowConfig> {
+        Ok(WorkflowConfig::for_tests(&self.0))
+    }
+
+    // Tests shouldn't install a process-wide logger
+    fn auto_logging(&self) -> bool {
+        false
+    }
+}
+
+impl WorkflowConfig {
+    /// Reads the workflow configuration from the Alfred variables in
+    /// `env`, keeping `env` on the config for later lookups. Returns
+    /// Error::MissingEnvVar naming the first required variable that is
+    /// missing.
+    pub fn from_env(env: EnvMap) -> Result<WorkflowConfig> {
+        let required = |name: &str| env.require(name).map(str::to_string);
+        let optional = |name: &str| env.get(name).map(str::to_string);
+        let debug = env.get(VAR_DEBUG).unwrap_or_default();
+        let debug = debug == "1" || debug.to_lowercase() == "true";
+
         Ok(WorkflowConfig {
+            // Required configuration values. Return Err naming the variable if missing
+            workflow_bundleid: required(VAR_WORKFLOW_BUNDLEID)?,
+            workflow_cache: required(VAR_WORKFLOW_CACHE)?.into(),
+            workflow_data: required(VAR_WORKFLOW_DATA)?.into(),
+            version: required(VAR_VERSION)?,
+            version_build: required(VAR_VERSION_BUILD)?,
+            workflow_name: required(VAR_WORKFLOW_NAME)?,
+            // Optional configuration values. Set to None if not provided
+            workflow_version: optional(VAR_WORKFLOW_VERSION),
+            preferences: optional(VAR_PREFERENCES),
+            preferences_localhash: optional(VAR_PREFERENCES_LOCALHASH),
+            theme: optional(VAR_THEME),
+            theme_background: optional(VAR_THEME_BACKGROUND),
+            theme_selection_background: optional(VAR_THEME_SELECTION_BACKGROUND),
+            theme_subtext: optional(VAR_THEME_SUBTEXT),
+            workflow_description: optional(VAR_WORKFLOW_DESCRIPTION),
+            workflow_uid: optional(VAR_WORKFLOW_UID),
+            workflow_keyword: optional(VAR_WORKFLOW_KEYWORD),
+            debug,
+            env,
+        })
+    }
+
+    /// Returns a WorkflowConfig suitable for unit tests, operating inside
+    /// the provided directory (workflow_data/ and workflow_cache/
+    /// subdirectories). All other fields are set to hard-coded test values.
+    ///
+    /// This is not gated behind cfg(test) so that crates building on
+    /// alfrusco can use it in their own tests.
+    ///
+    pub fn for_tests(dir: impl AsRef<Path>) -> WorkflowConfig {
+        let dir = dir.as_ref();
+        WorkflowConfig {
             preferences: Some("/Users/Crayons/Dropbox/Alfred/Alfred.alfredpreferences".to_string()),
             preferences_localhash: Some("adbd4f66bc3ae8493832af61a41ee609b20d8705".to_string()),
             theme: Some("alfred.theme.yosemite".to_string()),
@@ -152,8 +384,8 @@ impl ConfigProvider for TestingProvider {
             version: "5.0".to_string(),
             version_build: "2058".to_string(),
             workflow_bundleid: "com.alfredapp.googlesuggest".to_string(),
-            workflow_cache: self.0.join("workflow_cache"),
-            workflow_data: self.0.join("workflow_data"),
+            workflow_cache: dir.join("workflow_cache"),
+            workflow_data: dir.join("workflow_data"),
             workflow_name: "Test Workflow".to_string(),
             workflow_description: Some(
                 "The description of the workflow we use for testing".to_string(),
@@ -162,8 +394,34 @@ impl ConfigProvider for TestingProvider {
             workflow_uid: Some("user.workflow.B0AC54EC-601C-479A-9428-01F9FD732959".to_string()),
             workflow_keyword: None,
             debug: true,
-        })
+            env: EnvMap::new(),
+        }
+    }
+
+    /// Parses theme_background into a Color. Returns None when Alfred
+    /// didn't provide it or it can't be parsed.
+    pub fn theme_background_color(&self) -> Option<Color> {
+        self.theme_background.as_deref()?.parse().ok()
     }
+
+    /// Parses theme_selection_background into a Color. Returns None when
+    /// Alfred didn't provide it or it can't be parsed.
+    pub fn theme_selection_background_color(&self) -> Option<Color> {
+        self.theme_selection_background.as_deref()?.parse().ok()
+    }
+}
+
+/// Parses a human-friendly duration such as "15m", "1h 30m" or "2days",
+/// as typically entered in a workflow's User Configuration. A bare number
+/// is treated as a number of seconds.
+///
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Ok(Duration::from_secs(seconds));
+    }
+    humantime::parse_duration(value)
+        .map_err(|e| Error::Workflow(format!("invalid duration {:?}: {}", value, e)))
 }
 
 #[cfg(test)]
@@ -176,17 +434,71 @@ mod tests {
         temp_env::with_vars(
             [
                 (VAR_DEBUG, Some("true")),
+                (VAR_WORKFLOW_BUNDLEID, Some("com.example.test")),
                 (VAR_WORKFLOW_CACHE, None),
                 (VAR_WORKFLOW_DATA, None),
             ],
             || {
                 let provider = AlfredEnvProvider;
                 let result = provider.config();
-                assert!(result.is_err(), "{:?}", result);
+                assert!(
+                    matches!(&result, Err(Error::MissingEnvVar(name)) if name == VAR_WORKFLOW_CACHE),
+                    "{:?}",
+                    result
+                );
             },
         );
     }
 
+    #[test]
+    fn test_config_error_item() {
+        let error = WorkflowConfig::from_env(EnvMap::new()).unwrap_err();
+        assert!(matches!(
+            &error,
+            Error::MissingEnvVar(name) if name == VAR_WORKFLOW_BUNDLEID
+        ));
+        let item = config_error_item(&error, &EnvMap::new());
+        assert_eq!(
+            item.get_title(),
+            "Unable to load the workflow's configuration"
+        );
+        assert_eq!(
+            item.get_subtitle(),
+            Some(
+                "Missing environment variable: alfred_workflow_bundleid. \
+                 Run the workflow from Alfred, which sets the alfred_* variables"
+            )
+        );
+        assert_eq!(item.valid, Some(false));
+
+        let env: EnvMap = [(VAR_VERSION, "5.5")].into_iter().collect();
+        let item = config_error_item(&error, &env);
+        assert!(item
+            .get_subtitle()
+            .unwrap()
+            .ends_with("Check the workflow's environment variables"));
+    }
+
+    #[test]
+    fn test_execute_writes_config_error_item() {
+        struct Unreachable;
+
+        impl crate::Runnable for Unreachable {
+            type Error = Error;
+            fn run(self, _: &mut crate::Workflow) -> Result<()> {
+                unreachable!("the workflow can't run without its configuration")
+            }
+        }
+
+        let mut buffer = Vec::new();
+        crate::execute(&EnvMapProvider(EnvMap::new()), Unreachable, &mut buffer);
+        let response: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(
+            response["items"][0]["title"],
+            "Unable to load the workflow's configuration"
+        );
+    }
+
     #[test]
     fn test_alfred_env_provider_with_required_envvars() {
         temp_env::with_vars(
@@ -208,9 +520,134 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_env_map_provider() {
+        let env: EnvMap = [
+            (VAR_WORKFLOW_CACHE, "/made/up/cache_dir"),
+            (VAR_WORKFLOW_DATA, "/made/up/data_dir"),
+            (VAR_WORKFLOW_BUNDLEID, "com.alfredapp.googlesuggest"),
+            (VAR_VERSION, "5.0"),
+            (VAR_VERSION_BUILD, "2058"),
+            (VAR_WORKFLOW_NAME, "Test Workflow"),
+            (VAR_DEBUG, "1"),
+            ("API_URL", "https://example.com"),
+        ]
+        .into_iter()
+        .collect();
+
+        // The process environment is not consulted
+        temp_env::with_var(VAR_WORKFLOW_NAME, Some("Process Workflow"), || {
+            let config = EnvMapProvider(env.clone()).config().unwrap();
+            assert_eq!(config.workflow_name, "Test Workflow");
+            assert_eq!(config.workflow_data, PathBuf::from("/made/up/data_dir"));
+            assert!(config.debug);
+            assert_eq!(config.env.get("API_URL"), Some("https://example.com"));
+        });
+
+        let mut missing = env;
+        missing.remove(VAR_VERSION);
+        let error = EnvMapProvider(missing).config().unwrap_err();
+        assert!(error.to_string().contains(VAR_VERSION), "{}", error);
+    }
+
+    #[test]
+    fn test_env_map() {
+        let mut env = EnvMap::new();
+        env.set("NAME", "value");
+        assert_eq!(env.get("NAME"), Some("value"));
+        assert_eq!(env.require("NAME").unwrap(), "value");
+        assert!(matches!(
+            env.require("OTHER"),
+            Err(Error::MissingEnvVar(name)) if name == "OTHER"
+        ));
+        assert_eq!(env.iter().collect::<Vec<_>>(), vec![("NAME", "value")]);
+    }
+
+    const CONFIG_JSON: &str = r#"{
+        "alfred_workflow_bundleid": "com.example.search",
+        "alfred_workflow_name": "Search",
+        "alfred_workflow_cache": "cache",
+        "alfred_workflow_data": "/absolute/data",
+        "alfred_version": "5.5",
+        "alfred_version_build": 2257,
+        "alfred_debug": true,
+        "API_URL": "http://localhost:8080"
+    }"#;
+
+    #[test]
+    fn test_file_provider() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("alfred.json");
+        std::fs::write(&path, CONFIG_JSON).unwrap();
+
+        let config = temp_env::with_var("FROM_PROCESS", Some("1"), || {
+            FileProvider::new(&path).config().unwrap()
+        });
+        assert_eq!(config.workflow_name, "Search");
+        assert_eq!(config.workflow_cache, dir.path().join("cache"));
+        assert_eq!(config.workflow_data, PathBuf::from("/absolute/data"));
+        assert_eq!(config.version_build, "2257");
+        assert!(config.debug);
+        assert_eq!(config.env.get("API_URL"), Some("http://localhost:8080"));
+        assert_eq!(config.env.get("FROM_PROCESS"), None);
+
+        let config = temp_env::with_var("FROM_PROCESS", Some("1"), || {
+            FileProvider::new(&path).inherit_env(true).config().unwrap()
+        });
+        assert_eq!(config.env.get("FROM_PROCESS"), Some("1"));
+    }
+
+    #[test]
+    fn test_file_provider_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("alfred.json");
+        assert!(FileProvider::new(&path).config().is_err());
+
+        std::fs::write(&path, r#"{"alfred_workflow_name": ["nested"]}"#).unwrap();
+        let error = FileProvider::new(&path).config().unwrap_err();
+        assert!(
+            error.to_string().contains("alfred_workflow_name"),
+            "{}",
+            error
+        );
+
+        std::fs::write(&path, r#"{"alfred_workflow_name": "Search"}"#).unwrap();
+        let error = FileProvider::new(&path).config().unwrap_err();
+        assert!(
+            matches!(&error, Error::MissingEnvVar(name) if name == VAR_WORKFLOW_BUNDLEID),
+            "{}",
+            error
+        );
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_file_provider_toml() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("alfred.toml");
+        std::fs::write(
+            &path,
+            r#"
+alfred_workflow_bundleid = "com.example.search"
+alfred_workflow_name = "Search"
+alfred_workflow_cache = "cache"
+alfred_workflow_data = "data"
+alfred_version = "5.5"
+alfred_version_build = 2257
+API_URL = "http://localhost:8080"
+"#,
+        )
+        .unwrap();
+
+        let config = FileProvider::new(&path).config().unwrap();
+        assert_eq!(config.workflow_data, dir.path().join("data"));
+        assert_eq!(config.version_build, "2257");
+        assert!(!config.debug);
+    }
+
     #[test]
     fn test_testing_provider() {
-        let dir = tempfile::tempdir().unwrap().into_path();
+        let dir = tempfile::tempdir().unwrap().keep();
         let provider = TestingProvider(dir);
         let config = provider.config().unwrap();
         assert_eq!(config.workflow_bundleid, "com.alfredapp.googlesuggest");
@@ -218,4 +655,50 @@ mod tests {
         assert_eq!(config.version, "5.0");
         assert_eq!(config.version_build, "2058");
     }
+
+    #[test]
+    fn test_for_tests() {
+        let config = WorkflowConfig::for_tests("/tmp/alfrusco");
+        assert_eq!(
+            config.workflow_cache,
+            PathBuf::from("/tmp/alfrusco/workflow_cache")
+        );
+        assert_eq!(
+            config.workflow_data,
+            PathBuf::from("/tmp/alfrusco/workflow_data")
+        );
+        assert_eq!(
+            config,
+            TestingProvider("/tmp/alfrusco".into()).config().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_theme_background_color() {
+        let mut config = WorkflowConfig::for_tests("/tmp/alfrusco");
+        assert_eq!(
+            config.theme_background_color(),
+            Some(Color::new(255, 255, 255, 0.98))
+        );
+        config.theme_background = Some("not a color".to_string());
+        assert_eq!(config.theme_background_color(), None);
+        config.theme_background = None;
+        assert_eq!(config.theme_background_color(), None);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("15m").unwrap(), Duration::from_secs(900));
+        assert_eq!(
+            parse_duration(" 1h 30m ").unwrap(),
+            Duration::from_secs(5400)
+        );
+        assert_eq!(
+            parse_duration("2days").unwrap(),
+            Duration::from_secs(172800)
+        );
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert!(parse_duration("soon").is_err());
+        assert!(parse_duration("").is_err());
+    }
 }
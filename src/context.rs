@@ -0,0 +1,290 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::retry::{with_backoff, RetryPolicy};
+use crate::system::SystemActions;
+use crate::{Error, Result};
+
+/// FrontmostApp describes the application the user was using when the
+/// workflow was invoked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrontmostApp {
+    pub name: String,
+    pub bundle_id: String,
+    pub path: PathBuf,
+}
+
+/// Browser is a web browser whose current tab can be read via
+/// AppleScript.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Browser {
+    Safari,
+    SafariTechnologyPreview,
+    Chrome,
+    Chromium,
+    Brave,
+    Edge,
+    Vivaldi,
+    Arc,
+}
+
+impl Browser {
+    /// The name AppleScript uses to address the browser.
+    pub fn app_name(&self) -> &'static str {
+        match self {
+            Browser::Safari => "Safari",
+            Browser::SafariTechnologyPreview => "Safari Technology Preview",
+            Browser::Chrome => "Google Chrome",
+            Browser::Chromium => "Chromium",
+            Browser::Brave => "Brave Browser",
+            Browser::Edge => "Microsoft Edge",
+            Browser::Vivaldi => "Vivaldi",
+            Browser::Arc => "Arc",
+        }
+    }
+
+    /// Returns the Browser with the given bundle identifier, if supported.
+    /// Useful with frontmost_app() to read the tab of whichever browser
+    /// the user was in.
+    pub fn from_bundle_id(bundle_id: &str) -> Option<Browser> {
+        match bundle_id {
+            "com.apple.Safari" => Some(Browser::Safari),
+            "com.apple.SafariTechnologyPreview" => Some(Browser::SafariTechnologyPreview),
+            "com.google.Chrome" => Some(Browser::Chrome),
+            "org.chromium.Chromium" => Some(Browser::Chromium),
+            "com.brave.Browser" => Some(Browser::Brave),
+            "com.microsoft.edgemac" => Some(Browser::Edge),
+            "com.vivaldi.Vivaldi" => Some(Browser::Vivaldi),
+            "company.thebrowser.Browser" => Some(Browser::Arc),
+            _ => None,
+        }
+    }
+
+    fn current_tab_script(&self) -> String {
+        let (tab, title) = match self {
+            Browser::Safari | Browser::SafariTechnologyPreview => ("current tab", "name"),
+            _ => ("active tab", "title"),
+        };
+        format!(
+            "tell application \"{}\" to return (URL of {tab} of front window) & linefeed & ({title} of {tab} of front window)",
+            self.app_name(),
+        )
+    }
+}
+
+/// BrowserTab is the URL and title of a browser tab.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrowserTab {
+    pub url: String,
+    pub title: String,
+}
+
+/// Returns the name, bundle identifier and path of the frontmost
+/// application.
+pub fn frontmost_app(system: &dyn SystemActions) -> Result<FrontmostApp> {
+    let output = query(
+        system,
+        concat!(
+            "tell application \"System Events\"\n",
+            "set frontApp to first application process whose frontmost is true\n",
+            "return (name of frontApp) & linefeed & (bundle identifier of frontApp) & linefeed & ",
+            "(POSIX path of (application file of frontApp))\n",
+            "end tell",
+        ),
+    )?;
+    let mut lines = output.lines();
+    match (lines.next(), lines.next(), lines.next()) {
+        (Some(name), Some(bundle_id), Some(path)) => Ok(FrontmostApp {
+            name: name.to_string(),
+            bundle_id: bundle_id.to_string(),
+            path: PathBuf::from(path),
+        }),
+        _ => Err(Error::Workflow(format!(
+            "unexpected frontmost application output: {:?}",
+            output
+        ))),
+    }
+}
+
+/// Returns the paths of the files and folders selected in the Finder.
+/// Returns an empty Vec when nothing is selected.
+pub fn finder_selection(system: &dyn SystemActions) -> Result<Vec<PathBuf>> {
+    let output = query(
+        system,
+        concat!(
+            "set output to \"\"\n",
+            "tell application \"Finder\"\n",
+            "repeat with selected in (selection as alias list)\n",
+            "set output to output & POSIX path of selected & linefeed\n",
+            "end repeat\n",
+            "end tell\n",
+            "return output",
+        ),
+    )?;
+    Ok(output
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Returns the URL and title of the current tab in the browser's front
+/// window.
+pub fn browser_current_tab(system: &dyn SystemActions, browser: Browser) -> Result<BrowserTab> {
+    let output = query(system, &browser.current_tab_script())?;
+    let output = output.trim_end_matches('\n');
+    match output.split_once('\n') {
+        Some((url, title)) if !url.is_empty() => Ok(BrowserTab {
+            url: url.to_string(),
+            title: title.to_string(),
+        }),
+        _ => Err(Error::Workflow(format!(
+            "no current tab found in {}",
+            browser.app_name()
+        ))),
+    }
+}
+
+/// Runs one of the read-only scripts above. Applications that are busy or
+/// still launching can fail to answer Apple Events, so failures are retried
+/// briefly before giving up.
+fn query(system: &dyn SystemActions, script: &str) -> Result<String> {
+    let policy = RetryPolicy::new().initial_delay(Duration::from_millis(100));
+    with_backoff(&policy, || system.run_osascript(script)).map_err(|e| e.last_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::{MockSystemActions, SystemCall};
+
+    /// Fails the first `failures` scripts it runs, then answers like Finder
+    /// with one selected file
+    #[derive(Debug)]
+    struct Flaky {
+        failures: u32,
+        calls: AtomicU32,
+    }
+
+    impl SystemActions for Flaky {
+        fn open_path(&self, _: &Path) -> Result<()> {
+            unimplemented!()
+        }
+
+        fn open_url(&self, _: &str) -> Result<()> {
+            unimplemented!()
+        }
+
+        fn run_osascript(&self, _: &str) -> Result<String> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(Error::Workflow("AppleEvent timed out".to_string()));
+            }
+            Ok("/Users/crayons/a.txt\n".to_string())
+        }
+    }
+
+    fn only_script(mock: &MockSystemActions) -> String {
+        match mock.calls().as_slice() {
+            [SystemCall::RunOsascript(script)] => script.clone(),
+            calls => panic!("unexpected calls {:?}", calls),
+        }
+    }
+
+    #[test]
+    fn test_frontmost_app() {
+        let mock = MockSystemActions::new()
+            .osascript_output("Safari\ncom.apple.Safari\n/Applications/Safari.app/\n");
+        let app = frontmost_app(&mock).unwrap();
+        assert_eq!(
+            app,
+            FrontmostApp {
+                name: "Safari".to_string(),
+                bundle_id: "com.apple.Safari".to_string(),
+                path: PathBuf::from("/Applications/Safari.app/"),
+            }
+        );
+        assert!(only_script(&mock).contains("frontmost is true"));
+    }
+
+    #[test]
+    fn test_frontmost_app_unexpected_output() {
+        let mock = MockSystemActions::new().osascript_output("");
+        assert!(frontmost_app(&mock).is_err());
+    }
+
+    #[test]
+    fn test_finder_selection() {
+        let mock = MockSystemActions::new()
+            .osascript_output("/Users/crayons/a.txt\n/Users/crayons/My Folder/\n\n");
+        assert_eq!(
+            finder_selection(&mock).unwrap(),
+            vec![
+                PathBuf::from("/Users/crayons/a.txt"),
+                PathBuf::from("/Users/crayons/My Folder/"),
+            ]
+        );
+
+        let mock = MockSystemActions::new().osascript_output("\n");
+        assert!(finder_selection(&mock).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_queries_are_retried() {
+        let flaky = Flaky {
+            failures: 2,
+            calls: AtomicU32::new(0),
+        };
+        assert_eq!(
+            finder_selection(&flaky).unwrap(),
+            vec![PathBuf::from("/Users/crayons/a.txt")]
+        );
+        assert_eq!(flaky.calls.load(Ordering::SeqCst), 3);
+
+        let failing = Flaky {
+            failures: u32::MAX,
+            calls: AtomicU32::new(0),
+        };
+        let error = finder_selection(&failing).unwrap_err();
+        assert_eq!(error.to_string(), "Workflow Error: AppleEvent timed out");
+        assert_eq!(failing.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_browser_current_tab() {
+        let mock = MockSystemActions::new()
+            .osascript_output("https://www.rust-lang.org/\nRust Programming Language\n");
+        let tab = browser_current_tab(&mock, Browser::Chrome).unwrap();
+        assert_eq!(tab.url, "https://www.rust-lang.org/");
+        assert_eq!(tab.title, "Rust Programming Language");
+        assert_eq!(
+            only_script(&mock),
+            "tell application \"Google Chrome\" to return (URL of active tab of front window) & linefeed & (title of active tab of front window)"
+        );
+
+        let mock = MockSystemActions::new().osascript_output("https://a.com\nA\n");
+        browser_current_tab(&mock, Browser::Safari).unwrap();
+        assert!(only_script(&mock).contains("(name of current tab of front window)"));
+    }
+
+    #[test]
+    fn test_browser_current_tab_without_window() {
+        let mock = MockSystemActions::new().osascript_output("\n");
+        assert!(browser_current_tab(&mock, Browser::Arc).is_err());
+    }
+
+    #[test]
+    fn test_browser_from_bundle_id() {
+        assert_eq!(
+            Browser::from_bundle_id("com.google.Chrome"),
+            Some(Browser::Chrome)
+        );
+        assert_eq!(
+            Browser::from_bundle_id("company.thebrowser.Browser"),
+            Some(Browser::Arc)
+        );
+        assert_eq!(Browser::from_bundle_id("com.apple.finder"), None);
+    }
+}
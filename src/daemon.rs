@@ -0,0 +1,470 @@
+//! An optional long-running server mode for very low-latency workflows.
+//!
+//! A Script Filter normally starts from scratch on every keystroke. When a
+//! workflow needs an expensive in-memory index (thousands of bookmarks, a
+//! parsed project tree, ...), it can instead run as a daemon that holds
+//! the index and answers queries over a Unix socket in the workflow's cache
+//! directory. Each Script Filter invocation becomes a thin client which
+//! forwards the query and prints the daemon's response.
+//!
+//! The client spawns the daemon automatically (by re-running the current
+//! executable with `ALFRUSCO_DAEMON` set) when none is listening. The
+//! daemon exits once it has been idle for a while, once it reaches its
+//! maximum age, or when its executable is replaced (e.g. by a workflow
+//! update), so a fresh one is started on the next query.
+//!
+//! ```no_run
+//! use alfrusco::config::{AlfredEnvProvider, ConfigProvider};
+//! use alfrusco::daemon::Daemon;
+//! use alfrusco::Item;
+//!
+//! let config = AlfredEnvProvider.config().unwrap();
+//! let daemon = Daemon::new(&config);
+//! if Daemon::is_daemon_process() {
+//!     let index = vec!["alpha".to_string(), "beta".to_string()];
+//!     daemon
+//!         .serve(|query| {
+//!             Ok(index
+//!                 .iter()
+//!                 .filter(|entry| entry.contains(query))
+//!                 .map(Item::new)
+//!                 .collect())
+//!         })
+//!         .unwrap();
+//! } else {
+//!     let query = std::env::args().nth(1).unwrap_or_default();
+//!     daemon.respond(&query, std::io::stdout()).unwrap();
+//! }
+//! ```
+//!
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, SystemTime};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::config::WorkflowConfig;
+use crate::{Error, Item, Response, Result, WorkflowError};
+
+/// The environment variable set on the spawned daemon process.
+pub const DAEMON_VAR: &str = "ALFRUSCO_DAEMON";
+
+/// How often the daemon checks for idleness and staleness while waiting
+/// for connections.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// A single request sent from a client to the daemon, as one line of JSON.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Request {
+    Query(String),
+    Shutdown,
+}
+
+/// Daemon is both the server and client side of the daemon protocol for
+/// one workflow. See the module documentation for an overview.
+#[derive(Debug)]
+pub struct Daemon {
+    socket_path: PathBuf,
+    idle_timeout: Duration,
+    max_age: Option<Duration>,
+    startup_timeout: Duration,
+    request_timeout: Duration,
+    spawn_command: Option<Command>,
+}
+
+impl Daemon {
+    /// Creates a Daemon whose socket is `daemon.sock` in the workflow's
+    /// cache directory.
+    ///
+    /// Unix socket paths are limited to about 100 bytes; if the cache
+    /// directory is deeply nested, use with_socket_path instead.
+    pub fn new(config: &WorkflowConfig) -> Self {
+        Self::with_socket_path(config.workflow_cache.join("daemon.sock"))
+    }
+
+    pub fn with_socket_path(socket_path: impl Into<PathBuf>) -> Self {
+        Daemon {
+            socket_path: socket_path.into(),
+            idle_timeout: Duration::from_secs(15 * 60),
+            max_age: None,
+            startup_timeout: Duration::from_secs(2),
+            request_timeout: Duration::from_secs(2),
+            spawn_command: None,
+        }
+    }
+
+    /// How long the daemon waits without receiving a query before it
+    /// exits. Defaults to 15 minutes.
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// How long the daemon runs before it exits so that the next query
+    /// starts a fresh one, e.g. to rebuild an index. Unlimited by default.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// How long a client waits for a newly spawned daemon to start
+    /// listening. Defaults to 2 seconds.
+    pub fn startup_timeout(mut self, startup_timeout: Duration) -> Self {
+        self.startup_timeout = startup_timeout;
+        self
+    }
+
+    /// How long a client waits for the daemon to answer a query. Defaults
+    /// to 2 seconds.
+    pub fn request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    /// Overrides the command used to start the daemon. By default the
+    /// current executable is re-run with the same arguments. In either
+    /// case `ALFRUSCO_DAEMON` is set on the spawned process.
+    pub fn spawn_command(mut self, command: Command) -> Self {
+        self.spawn_command = Some(command);
+        self
+    }
+
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    /// Returns true when this process was spawned as a daemon, meaning it
+    /// should call serve rather than act as a client.
+    pub fn is_daemon_process() -> bool {
+        std::env::var_os(DAEMON_VAR).is_some()
+    }
+
+    /// Listens on the socket and answers each query with the items
+    /// returned by `handler`, until the daemon is idle, too old, stale or
+    /// asked to shut down. Errors from the handler are sent back as an
+    /// error item.
+    ///
+    /// Returns immediately if another daemon is already listening.
+    pub fn serve<F>(&self, mut handler: F) -> Result<()>
+    where
+        F: FnMut(&str) -> Result<Vec<Item>>,
+    {
+        if UnixStream::connect(&self.socket_path).is_ok() {
+            info!("daemon already listening on {}", self.socket_path.display());
+            return Ok(());
+        }
+        if let Some(dir) = self.socket_path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        remove_socket(&self.socket_path)?;
+        let listener = UnixListener::bind(&self.socket_path)?;
+        listener.set_nonblocking(true)?;
+        info!("daemon listening on {}", self.socket_path.display());
+
+        let started = Instant::now();
+        let started_at = SystemTime::now();
+        let mut last_request = Instant::now();
+
+        let result = loop {
+            if let Some(reason) = self.exit_reason(started, started_at, last_request) {
+                info!("daemon exiting: {}", reason);
+                break Ok(());
+            }
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    last_request = Instant::now();
+                    match self.handle_connection(stream, &mut handler) {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            info!("daemon exiting: shutdown requested");
+                            break Ok(());
+                        }
+                        Err(e) => warn!("daemon request failed: {}", e),
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(POLL_INTERVAL),
+                Err(e) => break Err(e.into()),
+            }
+        };
+        remove_socket(&self.socket_path)?;
+        result
+    }
+
+    /// Sends `query` to the daemon, spawning one if none is listening, and
+    /// returns its response as Script Filter JSON.
+    pub fn query(&self, query: &str) -> Result<String> {
+        let request = Request::Query(query.to_string());
+        match self.send(&request) {
+            Ok(response) => Ok(response),
+            Err(e) => {
+                // Nothing listening, or a stale daemon hung up on us
+                debug!("daemon unavailable ({}), spawning", e);
+                self.spawn()?;
+                self.wait_until_listening()?;
+                self.send(&request)
+            }
+        }
+    }
+
+    /// Sends `query` to the daemon (see query) and writes its response to
+    /// `writer`, as alfrusco::execute would for a Script Filter.
+    pub fn respond<W: Write>(&self, query: &str, mut writer: W) -> Result<()> {
+        let response = self.query(query)?;
+        writer.write_all(response.as_bytes())?;
+        Ok(())
+    }
+
+    /// Asks a running daemon to exit. Does nothing if none is listening.
+    pub fn stop(&self) -> Result<()> {
+        match UnixStream::connect(&self.socket_path) {
+            Ok(stream) => self.write_request(stream, &Request::Shutdown).map(|_| ()),
+            Err(_) => Ok(()),
+        }
+    }
+
+    fn send(&self, request: &Request) -> Result<String> {
+        let stream = UnixStream::connect(&self.socket_path)?;
+        let response = self.write_request(stream, request)?;
+        if response.is_empty() {
+            return Err(Error::Workflow("daemon closed the connection".to_string()));
+        }
+        Ok(response)
+    }
+
+    fn write_request(&self, mut stream: UnixStream, request: &Request) -> Result<String> {
+        stream.set_read_timeout(Some(self.request_timeout))?;
+        stream.set_write_timeout(Some(self.request_timeout))?;
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+        stream.write_all(line.as_bytes())?;
+
+        let mut response = String::new();
+        BufReader::new(stream).read_line(&mut response)?;
+        Ok(response.trim_end().to_string())
+    }
+
+    /// Answers one connection. Returns false when the daemon should exit.
+    fn handle_connection<F>(&self, stream: UnixStream, handler: &mut F) -> Result<bool>
+    where
+        F: FnMut(&str) -> Result<Vec<Item>>,
+    {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(self.request_timeout))?;
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+
+        let query = match serde_json::from_str(&line)? {
+            Request::Query(query) => query,
+            Request::Shutdown => return Ok(false),
+        };
+        debug!("daemon query {:?}", query);
+        let items = handler(&query).unwrap_or_else(|e| vec![e.error_item()]);
+
+        let mut response = serde_json::to_vec(&Response::new_with_items(items))?;
+        response.push(b'\n');
+        (&stream).write_all(&response)?;
+        Ok(true)
+    }
+
+    fn exit_reason(
+        &self,
+        started: Instant,
+        started_at: SystemTime,
+        last_request: Instant,
+    ) -> Option<&'static str> {
+        if last_request.elapsed() >= self.idle_timeout {
+            return Some("idle timeout reached");
+        }
+        if self
+            .max_age
+            .is_some_and(|max_age| started.elapsed() >= max_age)
+        {
+            return Some("maximum age reached");
+        }
+        if executable_modified_since(started_at) {
+            return Some("executable was replaced");
+        }
+        if !self.socket_path.exists() {
+            return Some("socket was removed");
+        }
+        None
+    }
+
+    fn spawn(&self) -> Result<()> {
+        let mut command = match &self.spawn_command {
+            Some(command) => clone_command(command),
+            None => {
+                let mut command = Command::new(std::env::current_exe()?);
+                command.args(std::env::args_os().skip(1));
+                command
+            }
+        };
+        command
+            .env(DAEMON_VAR, &self.socket_path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::inherit());
+        let child = command.spawn()?;
+        debug!("spawned daemon with pid {}", child.id());
+        Ok(())
+    }
+
+    fn wait_until_listening(&self) -> Result<()> {
+        let started = Instant::now();
+        while started.elapsed() < self.startup_timeout {
+            if UnixStream::connect(&self.socket_path).is_ok() {
+                return Ok(());
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        Err(Error::Workflow(format!(
+            "daemon did not start listening on {} within {}",
+            self.socket_path.display(),
+            humantime::format_duration(self.startup_timeout)
+        )))
+    }
+}
+
+fn remove_socket(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Returns true if the running executable was modified after `time`.
+fn executable_modified_since(time: SystemTime) -> bool {
+    std::env::current_exe()
+        .and_then(|exe| exe.metadata())
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| modified > time)
+}
+
+fn clone_command(command: &Command) -> Command {
+    let mut clone = Command::new(command.get_program());
+    clone.args(command.get_args());
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => clone.env(key, value),
+            None => clone.env_remove(key),
+        };
+    }
+    if let Some(dir) = command.get_current_dir() {
+        clone.current_dir(dir);
+    }
+    clone
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::JoinHandle;
+
+    use super::*;
+
+    fn start(daemon: Daemon) -> JoinHandle<Result<()>> {
+        let socket_path = daemon.socket_path().to_path_buf();
+        let handle = std::thread::spawn(move || {
+            daemon.serve(|query| match query {
+                "fail" => Err("index unavailable".into()),
+                query => Ok(vec![Item::new(format!("You typed {}", query))]),
+            })
+        });
+        while UnixStream::connect(&socket_path).is_err() {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        handle
+    }
+
+    fn client(dir: &Path) -> Daemon {
+        // A spawn command that never starts a daemon, so tests can't
+        // accidentally re-run the test binary
+        Daemon::with_socket_path(dir.join("daemon.sock"))
+            .spawn_command(Command::new("true"))
+            .startup_timeout(Duration::from_millis(100))
+    }
+
+    #[test]
+    fn test_query_and_stop() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = start(Daemon::with_socket_path(dir.path().join("daemon.sock")));
+        let client = client(dir.path());
+
+        assert_eq!(
+            client.query("rust").unwrap(),
+            r#"{"items":[{"title":"You typed rust"}]}"#
+        );
+        let mut buffer = Vec::new();
+        client.respond("go", &mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            r#"{"items":[{"title":"You typed go"}]}"#
+        );
+
+        client.stop().unwrap();
+        server.join().unwrap().unwrap();
+        assert!(!client.socket_path().exists());
+    }
+
+    #[test]
+    fn test_handler_error_becomes_error_item() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = start(Daemon::with_socket_path(dir.path().join("daemon.sock")));
+        let client = client(dir.path());
+
+        let response = client.query("fail").unwrap();
+        assert!(response.contains("index unavailable"), "{}", response);
+
+        client.stop().unwrap();
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = start(
+            Daemon::with_socket_path(dir.path().join("daemon.sock"))
+                .idle_timeout(Duration::from_millis(100)),
+        );
+        server.join().unwrap().unwrap();
+        assert!(!dir.path().join("daemon.sock").exists());
+    }
+
+    #[test]
+    fn test_query_without_daemon_fails_after_startup_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = client(dir.path()).query("rust").unwrap_err();
+        assert!(error.to_string().contains("did not start"), "{}", error);
+    }
+
+    #[test]
+    fn test_stop_without_daemon() {
+        let dir = tempfile::tempdir().unwrap();
+        client(dir.path()).stop().unwrap();
+    }
+
+    #[test]
+    fn test_request_format() {
+        assert_eq!(
+            serde_json::to_string(&Request::Query("rust".to_string())).unwrap(),
+            r#"{"query":"rust"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Request::Shutdown).unwrap(),
+            r#""shutdown""#
+        );
+    }
+
+    #[test]
+    fn test_is_daemon_process() {
+        temp_env::with_var(DAEMON_VAR, Some("/tmp/daemon.sock"), || {
+            assert!(Daemon::is_daemon_process());
+        });
+        temp_env::with_var(DAEMON_VAR, None::<&str>, || {
+            assert!(!Daemon::is_daemon_process());
+        });
+    }
+}
@@ -0,0 +1,61 @@
+use std::fmt::Debug;
+
+use log::debug;
+
+use crate::workflow::Workflow;
+use crate::{Item, ICON_TOOLBAR_ADVANCED};
+
+impl Workflow {
+    /// Shows a piece of internal state as a non-actionable item at the end
+    /// of the results, but only while Alfred's workflow debugger is open
+    /// (alfred_debug=1). Users can copy the items into bug reports, and
+    /// the value is also logged. Debug items are added after filtering
+    /// and limits are applied, so they are always visible.
+    ///
+    pub fn debug_item(&mut self, label: &str, value: impl Debug) {
+        if !self.config.debug {
+            return;
+        }
+        let value = format!("{:?}", value);
+        debug!("{}: {}", label, value);
+        let item = Item::new(label)
+            .subtitle(value.clone())
+            .icon(ICON_TOOLBAR_ADVANCED.into())
+            .copy_text(format!("{}: {}", label, value))
+            .large_type_text(value)
+            .valid(false);
+        self.debug_items.push(item);
+    }
+
+    pub(crate) fn append_debug_items(&mut self) {
+        let items = std::mem::take(&mut self.debug_items);
+        self.response.append_items(items);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::workflow_in;
+
+    #[test]
+    fn test_debug_item_only_when_debugging() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+
+        workflow.config.debug = false;
+        workflow.debug_item("query", "rust");
+        workflow.append_debug_items();
+        assert!(workflow.response.items.is_empty());
+
+        workflow.config.debug = true;
+        workflow.debug_item("query", "rust");
+        workflow.debug_item("pending", 3);
+        workflow.append_debug_items();
+        let items = &workflow.response.items;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].get_title(), "query");
+        assert_eq!(items[0].get_subtitle(), Some("\"rust\""));
+        assert_eq!(items[0].valid, Some(false));
+        assert_eq!(items[1].get_subtitle(), Some("3"));
+    }
+}
@@ -0,0 +1,265 @@
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+use crate::config::WorkflowConfig;
+use crate::error::Result;
+use crate::item::Item;
+use crate::workflow::Workflow;
+use crate::ICON_ALERT_CAUTION_BADGE;
+
+/// Set to "1" (or "true") to use "data" and "cache" subdirectories when
+/// alfred_workflow_data and alfred_workflow_cache name the same directory.
+/// It's opt-in because anything already stored in the shared directory
+/// stays where it is, and is no longer found once the subdirectories are
+/// used.
+pub const SEPARATE_DIRS_VAR: &str = "ALFRUSCO_SEPARATE_DIRS";
+
+impl Workflow {
+    /// Returns true when the workflow's data or cache directory couldn't
+    /// be created and a temporary directory is being used in its place.
+    /// Anything stored there may not survive a reboot.
+    pub fn is_degraded(&self) -> bool {
+        !self.degraded_dirs.is_empty()
+    }
+
+    /// Returns the problems found with the configured data and cache
+    /// paths, such as relative paths or both naming the same directory.
+    /// They're also logged as warnings when the workflow runs.
+    pub fn dir_diagnostics(&self) -> &[String] {
+        &self.dir_diagnostics
+    }
+
+    /// Prepends a sticky item explaining which directories were replaced,
+    /// so the problem is visible in Alfred rather than only in the logs.
+    pub(crate) fn add_degraded_notice(&mut self) {
+        if !self.is_degraded() {
+            return;
+        }
+        let dirs = self
+            .degraded_dirs
+            .iter()
+            .map(|dir| dir.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let item = Item::new(self.strings.get("degraded.title"))
+            .subtitle(self.strings.format("degraded.subtitle", &[("dirs", &dirs)]))
+            .icon(ICON_ALERT_CAUTION_BADGE.into())
+            .valid(false)
+            .sticky_with_priority(u8::MAX);
+        self.prepend_item(item);
+    }
+}
+
+/// The outcome of ensure_dirs.
+#[derive(Debug, Default)]
+pub(crate) struct DirSetup {
+    /// Configured directories replaced by temporary fallbacks
+    pub(crate) degraded: Vec<PathBuf>,
+    /// Problems found with the configured paths
+    pub(crate) diagnostics: Vec<String>,
+}
+
+/// Checks the workflow's data and cache paths and creates the
+/// directories.
+///
+/// Relative paths are resolved against the current directory. When both
+/// variables name the same directory and SEPARATE_DIRS_VAR is set, "data"
+/// and "cache" subdirectories are used instead, so clearing the cache
+/// can't delete the workflow's data. When a directory can't be created or
+/// written to, a directory under the system temp dir is used instead and
+/// the original path is recorded so the workflow can report it. `run_id`
+/// keeps the write check's file name unique to this execution.
+pub(crate) fn ensure_dirs(config: &mut WorkflowConfig, run_id: &str) -> Result<DirSetup> {
+    let mut setup = DirSetup::default();
+    for (dir, var) in [
+        (&mut config.workflow_data, "alfred_workflow_data"),
+        (&mut config.workflow_cache, "alfred_workflow_cache"),
+    ] {
+        if dir.is_relative() {
+            let absolute = std::env::current_dir()?.join(&*dir);
+            setup.diagnostics.push(format!(
+                "{} is a relative path ({}); using {}",
+                var,
+                dir.display(),
+                absolute.display()
+            ));
+            *dir = absolute;
+        }
+    }
+
+    let separate = config
+        .env
+        .get(SEPARATE_DIRS_VAR)
+        .is_some_and(|value| value == "1" || value.eq_ignore_ascii_case("true"));
+    if config.workflow_data == config.workflow_cache && separate {
+        let shared = config.workflow_data.clone();
+        config.workflow_data = shared.join("data");
+        config.workflow_cache = shared.join("cache");
+        setup.diagnostics.push(format!(
+            "alfred_workflow_data and alfred_workflow_cache are both {}; \
+             using its data and cache subdirectories",
+            shared.display()
+        ));
+    } else if config.workflow_data == config.workflow_cache {
+        setup.diagnostics.push(format!(
+            "alfred_workflow_data and alfred_workflow_cache are both {}; \
+             clearing the cache will delete the workflow's data (set {}=1 to \
+             use data and cache subdirectories)",
+            config.workflow_data.display(),
+            SEPARATE_DIRS_VAR
+        ));
+    } else if config.workflow_data.starts_with(&config.workflow_cache) {
+        setup.diagnostics.push(format!(
+            "alfred_workflow_data ({}) is inside alfred_workflow_cache ({}); \
+             clearing the cache will delete the workflow's data",
+            config.workflow_data.display(),
+            config.workflow_cache.display()
+        ));
+    }
+
+    let fallback_root = std::env::temp_dir()
+        .join("alfrusco")
+        .join(&config.workflow_bundleid);
+    for (dir, name) in [
+        (&mut config.workflow_data, "workflow_data"),
+        (&mut config.workflow_cache, "workflow_cache"),
+    ] {
+        if let Err(e) = create_writable_dir(dir, run_id) {
+            let fallback = fallback_root.join(name);
+            warn!(
+                "unable to use {}: {}; falling back to {}",
+                dir.display(),
+                e,
+                fallback.display()
+            );
+            setup.diagnostics.push(format!(
+                "{} isn't writable ({}); using {}",
+                dir.display(),
+                e,
+                fallback.display()
+            ));
+            std::fs::create_dir_all(&fallback)?;
+            setup.degraded.push(std::mem::replace(dir, fallback));
+        }
+    }
+    Ok(setup)
+}
+
+/// Creates `dir` and checks that files can be written in it. The probe
+/// file is named after the process and run, so concurrent executions
+/// don't remove each other's.
+fn create_writable_dir(dir: &Path, run_id: &str) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let probe = dir.join(format!(
+        ".alfrusco-write-check-{}-{}",
+        std::process::id(),
+        run_id
+    ));
+    std::fs::write(&probe, b"")?;
+    match std::fs::remove_file(probe) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unwritable_dirs_fall_back_to_temp() {
+        let dir = tempfile::tempdir().unwrap();
+        // A regular file can't have subdirectories, even when running as root
+        let blocker = dir.path().join("blocker");
+        std::fs::write(&blocker, "").unwrap();
+
+        let mut config = WorkflowConfig::for_tests(dir.path());
+        config.workflow_bundleid = "com.alfrusco.degraded-test".to_string();
+        config.workflow_data = blocker.join("workflow_data");
+
+        let mut workflow = Workflow::new(config).unwrap();
+        assert!(workflow.is_degraded());
+        assert!(workflow.dir_diagnostics()[0].contains("isn't writable"));
+        assert!(workflow.data_dir().starts_with(std::env::temp_dir()));
+        assert!(workflow.data_dir().exists());
+        assert_eq!(workflow.cache_dir(), dir.path().join("workflow_cache"));
+
+        workflow.append_item(Item::new("Result"));
+        workflow.add_degraded_notice();
+        let notice = &workflow.response.items[0];
+        assert_eq!(notice.title, "Running in degraded mode");
+        assert!(notice
+            .subtitle
+            .as_deref()
+            .unwrap()
+            .contains(&blocker.join("workflow_data").display().to_string()));
+        assert_eq!(notice.sticky, Some(u8::MAX));
+    }
+
+    #[test]
+    fn test_shared_dir_is_kept_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = WorkflowConfig::for_tests(dir.path());
+        config.workflow_cache = dir.path().join("shared");
+        config.workflow_data = dir.path().join("shared");
+
+        let workflow = Workflow::new(config).unwrap();
+        assert_eq!(workflow.data_dir(), dir.path().join("shared"));
+        assert_eq!(workflow.cache_dir(), dir.path().join("shared"));
+        let diagnostics = workflow.dir_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(
+            diagnostics[0].contains(SEPARATE_DIRS_VAR),
+            "{}",
+            diagnostics[0]
+        );
+    }
+
+    #[test]
+    fn test_shared_dir_is_namespaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = WorkflowConfig::for_tests(dir.path());
+        config.workflow_cache = dir.path().join("shared");
+        config.workflow_data = dir.path().join("shared/");
+        config.env.set(SEPARATE_DIRS_VAR, "1");
+
+        let workflow = Workflow::new(config).unwrap();
+        assert_eq!(workflow.data_dir(), dir.path().join("shared/data"));
+        assert_eq!(workflow.cache_dir(), dir.path().join("shared/cache"));
+        assert!(workflow.cache_dir().is_dir());
+        assert!(!workflow.is_degraded());
+        let diagnostics = workflow.dir_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].contains("are both"), "{}", diagnostics[0]);
+    }
+
+    #[test]
+    fn test_data_inside_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = WorkflowConfig::for_tests(dir.path());
+        config.workflow_data = config.workflow_cache.join("data");
+
+        let workflow = Workflow::new(config).unwrap();
+        assert_eq!(workflow.data_dir(), dir.path().join("workflow_cache/data"));
+        assert!(workflow.dir_diagnostics()[0].contains("is inside alfred_workflow_cache"));
+    }
+
+    #[test]
+    fn test_writable_dirs_are_not_degraded() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = crate::test_support::workflow_in(dir.path());
+        assert!(!workflow.is_degraded());
+        assert!(workflow.dir_diagnostics().is_empty());
+        let probes = std::fs::read_dir(workflow.data_dir())
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_string_lossy().starts_with(".alfrusco-write-check")
+            })
+            .count();
+        assert_eq!(probes, 0);
+        workflow.add_degraded_notice();
+        assert!(workflow.response.items.is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use log::{debug, warn};
+
+use crate::item::{Item, ItemType};
+use crate::workflow::Workflow;
+use crate::{InternalCommand, Result, ICON_ALERT_STOP};
+
+/// The internal query that writes the response to a file.
+pub(crate) const DUMP_QUERY: &str = "workflow:dumpjson";
+
+impl Workflow {
+    /// Writes the current response, as indented JSON, to `response.json`
+    /// in the cache directory and returns its path. This is usually easier
+    /// to read than Alfred's debug console.
+    pub fn dump_response(&self) -> Result<PathBuf> {
+        let path = self.cache_dir().join("response.json");
+        let file = BufWriter::new(File::create(&path)?);
+        self.response.write_pretty(file)?;
+        Ok(path)
+    }
+
+    /// Dumps the response items produced for this query, replacing the
+    /// items with one describing where it was written. Actioning the item
+    /// opens the file.
+    pub(crate) fn show_response_dump(&mut self) {
+        let item = match self.dump_response() {
+            Ok(path) => {
+                debug!("wrote response to {}", path.display());
+                let path = path.to_string_lossy().to_string();
+                Item::new(self.strings.get("dump.title"))
+                    .subtitle(&path)
+                    .arg(&path)
+                    .item_type(ItemType::File)
+                    .internal_command(InternalCommand::new("open_path").arg("open_path", &path))
+            }
+            Err(e) => {
+                warn!("unable to dump response: {}", e);
+                Item::new(
+                    self.strings
+                        .format("dump.error", &[("error", &e.to_string())]),
+                )
+                .icon(ICON_ALERT_STOP.into())
+                .valid(false)
+            }
+        };
+        self.response.items = vec![item];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::MockSystemActions;
+    use crate::INTERNAL_VAR;
+
+    #[test]
+    fn test_dumpjson_internal_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = crate::test_support::workflow_in(dir.path());
+        let mock = MockSystemActions::new();
+        workflow.set_system_actions(mock.clone());
+        workflow.items(vec![Item::new("Rust").arg("rust")]);
+        workflow.set_filter_keyword(DUMP_QUERY.to_string());
+        workflow.handle_internal_query();
+        workflow.filter_and_sort();
+
+        let path = dir.path().join("workflow_cache").join("response.json");
+        assert!(mock.calls().is_empty());
+        let dumped: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(dumped["items"][0]["title"], "Rust");
+
+        let items = &workflow.response.items;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].title, "Wrote response JSON");
+        assert_eq!(items[0].get_subtitle(), Some(&*path.to_string_lossy()));
+        let command = InternalCommand::new("open_path").arg("open_path", path.to_string_lossy());
+        assert_eq!(items[0].get_var(INTERNAL_VAR), Some(&*command.to_json()));
+    }
+}
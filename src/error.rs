@@ -1,6 +1,4 @@
-use std::any::type_name_of_val;
-
-use crate::Item;
+use crate::{Icon, Item, ICON_ALERT_STOP};
 
 #[derive(Debug)]
 pub enum Error {
@@ -12,6 +10,12 @@ pub enum Error {
     Var(std::env::VarError),
     MissingEnvVar(String),
     Workflow(String),
+    /// An error annotated with what was being attempted when it occurred.
+    /// See Error::context and ResultExt::context.
+    Context {
+        context: String,
+        source: Box<Error>,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -27,10 +31,57 @@ impl std::fmt::Display for Error {
             Error::Var(ref err) => write!(f, "Var Error: {}", err),
             Error::MissingEnvVar(ref var) => write!(f, "Missing environment variable: {}", var),
             Error::Workflow(ref msg) => write!(f, "Workflow Error: {}", msg),
+            Error::Context { ref context, .. } => write!(f, "{}", context),
         }
     }
 }
 
+impl Error {
+    /// Wraps this error with a description of what was being attempted.
+    /// The original error remains available as the source.
+    pub fn context(self, context: impl Into<String>) -> Error {
+        Error::Context {
+            context: context.into(),
+            source: Box::new(self),
+        }
+    }
+
+    /// Returns the innermost error in the source chain, or this error if it
+    /// has no source.
+    pub fn root_cause(&self) -> &(dyn std::error::Error + 'static) {
+        root_cause(self)
+    }
+}
+
+/// ResultExt adds `context` and `with_context` to any Result whose error
+/// converts into an alfrusco Error.
+pub trait ResultExt<T> {
+    fn context(self, context: impl Into<String>) -> Result<T>;
+
+    /// Like context, but the message is only built on error.
+    fn with_context<C: Into<String>>(self, f: impl FnOnce() -> C) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for std::result::Result<T, E> {
+    fn context(self, context: impl Into<String>) -> Result<T> {
+        self.map_err(|e| e.into().context(context))
+    }
+
+    fn with_context<C: Into<String>>(self, f: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|e| e.into().context(f()))
+    }
+}
+
+fn root_cause<'a>(
+    error: &'a (dyn std::error::Error + 'static),
+) -> &'a (dyn std::error::Error + 'static) {
+    let mut cause = error;
+    while let Some(source) = cause.source() {
+        cause = source;
+    }
+    cause
+}
+
 impl From<std::io::Error> for Error {
     fn from(err: std::io::Error) -> Error {
         Error::Io(err)
@@ -90,22 +141,407 @@ impl std::error::Error for Error {
             Error::Var(ref err) => Some(err),
             Error::MissingEnvVar(_) => None,
             Error::Workflow(_) => None,
+            Error::Context { ref source, .. } => Some(source.as_ref()),
         }
     }
 }
 
+/// WorkflowError is implemented by the error types of Runnables, turning a
+/// failed run into the item shown to the user.
+///
+/// For most error types the default error_item is all that's needed, so
+/// the workflow_error! macro implements the trait in one line, optionally
+/// with a closure customizing the item (e.g. per enum variant). Display
+/// and std::error::Error can come from thiserror's derive, as in the
+/// random_user example, leaving no hand-written boilerplate:
+///
+/// ```
+/// use alfrusco::{default_error_item, workflow_error, Item, ICON_ALERT_NOTE};
+///
+/// #[derive(Debug)]
+/// enum SearchError {
+///     NoToken,
+///     Io(std::io::Error),
+/// }
+/// # impl std::fmt::Display for SearchError {
+/// #     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+/// #         match self {
+/// #             SearchError::NoToken => write!(f, "no API token"),
+/// #             SearchError::Io(e) => write!(f, "{}", e),
+/// #         }
+/// #     }
+/// # }
+/// # impl std::error::Error for SearchError {}
+///
+/// workflow_error!(SearchError, |error| match error {
+///     SearchError::NoToken => Item::new("Set your API token")
+///         .subtitle("Open the workflow's configuration to add it")
+///         .icon(ICON_ALERT_NOTE.into()),
+///     _ => default_error_item(error),
+/// });
+/// ```
+///
+/// A blanket implementation for every std::error::Error isn't provided:
+/// it would rule out custom error_item implementations.
+///
 pub trait WorkflowError: std::error::Error + std::fmt::Display {
+    /// The item shown when a run fails with this error. Errors with a
+    /// source chain show the deepest cause as the subtitle, unless the
+    /// error's own message already includes it.
     fn error_item(&self) -> Item {
-        match self.source() {
-            Some(source) => {
-                let type_name = type_name_of_val(source);
-                Item::new(format!("Error: {}", self)).subtitle(type_name.to_string())
+        default_error_item(self)
+    }
+}
+
+/// The item WorkflowError::error_item returns by default: the error as the
+/// title and, if it has a source chain, the deepest cause as the subtitle.
+/// Wrapper errors like Error::Io ("IO Error: {}") already end with their
+/// cause, so they get no subtitle rather than repeating it.
+pub fn default_error_item<E: std::error::Error + ?Sized>(error: &E) -> Item {
+    let strings = crate::i18n::current();
+    let message = error.to_string();
+    let cause = error
+        .source()
+        .map(|source| root_cause(source).to_string())
+        .filter(|cause| !message.ends_with(cause.as_str()));
+    match cause {
+        Some(cause) => Item::new(strings.format("error.title_with_source", &[("error", &message)]))
+            .subtitle(cause),
+        None => Item::new(strings.format("error.title", &[("error", &message)])),
+    }
+}
+
+/// Implements WorkflowError for a type that already implements
+/// std::error::Error, either with the default error item or with one
+/// built by a closure-like expression taking the error by reference.
+///
+/// ```
+/// # #[derive(Debug)]
+/// # struct MyError;
+/// # impl std::fmt::Display for MyError {
+/// #     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+/// #         write!(f, "my error")
+/// #     }
+/// # }
+/// # impl std::error::Error for MyError {}
+/// alfrusco::workflow_error!(MyError);
+/// ```
+///
+#[macro_export]
+macro_rules! workflow_error {
+    ($type:ty) => {
+        impl $crate::WorkflowError for $type {}
+    };
+    ($type:ty, |$error:ident| $item:expr $(,)?) => {
+        impl $crate::WorkflowError for $type {
+            fn error_item(&self) -> $crate::Item {
+                let $error = self;
+                $item
             }
-            None => Item::new(format!("An error occurred: {}", self)),
         }
+    };
+}
+
+workflow_error!(Error);
+
+/// RunnableError is the bound on Runnable::Error and AsyncRunnable::Error:
+/// anything that can be shown as an error item and passed to hooks.
+///
+/// Every WorkflowError is a RunnableError. With the `anyhow` feature,
+/// anyhow::Error is one too, so workflows built on anyhow can use
+/// `type Error = anyhow::Error` (anyhow::Error doesn't implement
+/// std::error::Error, so it can't be a WorkflowError).
+///
+pub trait RunnableError {
+    /// The item shown when a run fails with this error. Named differently
+    /// from WorkflowError::error_item so glob imports of both traits don't
+    /// make calls ambiguous.
+    fn to_error_item(&self) -> Item;
+
+    /// This error as a std::error::Error, for Hook::on_error.
+    fn as_error(&self) -> &(dyn std::error::Error + 'static);
+}
+
+impl<E: WorkflowError + 'static> RunnableError for E {
+    fn to_error_item(&self) -> Item {
+        self.error_item()
+    }
+
+    fn as_error(&self) -> &(dyn std::error::Error + 'static) {
+        self
+    }
+}
+
+/// The error item for an anyhow::Error shows its outermost message as the
+/// title, like other errors, and the rest of its context chain as the
+/// subtitle (e.g. "reading config.json: No such file or directory").
+#[cfg(feature = "anyhow")]
+impl RunnableError for anyhow::Error {
+    fn to_error_item(&self) -> Item {
+        let strings = crate::i18n::current();
+        let error = self.to_string();
+        let causes: Vec<String> = self.chain().skip(1).map(ToString::to_string).collect();
+        if causes.is_empty() {
+            Item::new(strings.format("error.title", &[("error", &error)]))
+        } else {
+            Item::new(strings.format("error.title_with_source", &[("error", &error)]))
+                .subtitle(causes.join(": "))
+        }
+    }
+
+    fn as_error(&self) -> &(dyn std::error::Error + 'static) {
+        self.as_ref()
+    }
+}
+
+/// ErrorStyle controls how the item for a failed run is presented.
+///
+/// By default error items get ICON_ALERT_STOP and are made sticky, so they
+/// stay visible at the top of the results even when a filter keyword is
+/// set. Items from a custom WorkflowError::error_item keep any icon or
+/// sticky priority they set themselves.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorStyle {
+    icon: Option<Icon>,
+    sticky: bool,
+}
+
+impl Default for ErrorStyle {
+    fn default() -> Self {
+        ErrorStyle {
+            icon: Some(ICON_ALERT_STOP.into()),
+            sticky: true,
+        }
+    }
+}
+
+impl ErrorStyle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The icon given to error items that don't set their own.
+    pub fn icon(mut self, icon: Icon) -> Self {
+        self.icon = Some(icon);
+        self
+    }
+
+    /// Leaves error items without an icon.
+    pub fn no_icon(mut self) -> Self {
+        self.icon = None;
+        self
+    }
+
+    pub fn sticky(mut self, sticky: bool) -> Self {
+        self.sticky = sticky;
+        self
+    }
+
+    pub(crate) fn apply(&self, mut item: Item) -> Item {
+        if item.icon.is_none() {
+            item.icon = self.icon.clone();
+        }
+        if self.sticky && item.sticky.is_none() {
+            item = item.sticky(true);
+        }
+        item
     }
 }
 
-impl WorkflowError for Error {
-    // Default implementation is sufficient
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn not_found() -> Error {
+        std::io::Error::new(std::io::ErrorKind::NotFound, "config.json missing").into()
+    }
+
+    #[test]
+    fn test_context() {
+        let error = not_found().context("loading settings");
+        assert_eq!(error.to_string(), "loading settings");
+        let source = std::error::Error::source(&error).unwrap();
+        assert_eq!(source.to_string(), "IO Error: config.json missing");
+        assert_eq!(error.root_cause().to_string(), "config.json missing");
+    }
+
+    #[test]
+    fn test_result_context() {
+        let result: std::result::Result<(), std::io::Error> = Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "config.json missing",
+        ));
+        let error = result
+            .context("reading config.json")
+            .with_context(|| format!("loading {}", "settings"))
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "loading settings");
+        let chain: Vec<String> =
+            std::iter::successors(Some(&error as &(dyn std::error::Error + 'static)), |e| {
+                e.source()
+            })
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            chain,
+            [
+                "loading settings",
+                "reading config.json",
+                "IO Error: config.json missing",
+                "config.json missing",
+            ]
+        );
+
+        let ok: std::result::Result<u8, Error> = Ok(1);
+        assert_eq!(ok.context("unused").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_error_item_shows_deepest_cause() {
+        let item = not_found()
+            .context("reading config.json")
+            .context("loading settings")
+            .error_item();
+        assert_eq!(item.title, "Error: loading settings");
+        assert_eq!(item.subtitle.as_deref(), Some("config.json missing"));
+
+        // The wrapper's message already ends with the cause
+        let item = not_found().error_item();
+        assert_eq!(
+            item.title,
+            "An error occurred: IO Error: config.json missing"
+        );
+        assert_eq!(item.subtitle, None);
+
+        let item = not_found().context("reading config.json").error_item();
+        assert_eq!(item.title, "Error: reading config.json");
+        assert_eq!(item.subtitle.as_deref(), Some("config.json missing"));
+    }
+
+    #[test]
+    fn test_default_error_style() {
+        let item = ErrorStyle::default().apply(Error::from("boom").error_item());
+        assert_eq!(item.icon, Some(ICON_ALERT_STOP.into()));
+        assert_eq!(item.sticky, Some(0));
+    }
+
+    #[test]
+    fn test_custom_error_style() {
+        let style = ErrorStyle::new().no_icon().sticky(false);
+        let item = style.apply(Error::from("boom").error_item());
+        assert_eq!(item.icon, None);
+        assert_eq!(item.sticky, None);
+
+        let icon = Icon::from("/tmp/error.png");
+        let style = ErrorStyle::new().icon(icon.clone());
+        assert_eq!(style.apply(Item::new("boom")).icon, Some(icon));
+    }
+
+    #[test]
+    fn test_error_style_set_by_runnable() {
+        struct Fails;
+
+        impl crate::Runnable for Fails {
+            type Error = Error;
+            fn run(self, workflow: &mut crate::Workflow) -> Result<()> {
+                workflow.set_error_style(ErrorStyle::new().no_icon());
+                Err("boom".into())
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            crate::test_support::run_in(dir.path(), Fails),
+            r#"{"items":[{"title":"An error occurred: Workflow Error: boom"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_error_style_keeps_item_settings() {
+        let item = Item::new("boom")
+            .icon_for_filetype("public.folder")
+            .sticky_with_priority(7);
+        let styled = ErrorStyle::default().apply(item.clone());
+        assert_eq!(styled, item);
+    }
+
+    #[cfg(feature = "anyhow")]
+    #[test]
+    fn test_anyhow_error_item() {
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "No such file");
+        let error = anyhow::Error::new(io)
+            .context("reading config.json")
+            .context("loading settings");
+        let item = error.to_error_item();
+        assert_eq!(item.title, "Error: loading settings");
+        assert_eq!(
+            item.subtitle.as_deref(),
+            Some("reading config.json: No such file")
+        );
+        assert_eq!(error.as_error().to_string(), "loading settings");
+
+        let item = anyhow::anyhow!("offline").to_error_item();
+        assert_eq!(item.title, "An error occurred: offline");
+        assert_eq!(item.subtitle, None);
+    }
+
+    #[cfg(feature = "anyhow")]
+    #[test]
+    fn test_anyhow_runnable() {
+        struct Failing;
+
+        impl crate::Runnable for Failing {
+            type Error = anyhow::Error;
+            fn run(self, _: &mut crate::Workflow) -> anyhow::Result<()> {
+                anyhow::bail!("offline")
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = crate::test_support::run_in(dir.path(), Failing);
+        assert!(output.contains("offline"), "{}", output);
+    }
+
+    #[derive(Debug)]
+    enum SearchError {
+        NoToken,
+        Io(std::io::Error),
+    }
+
+    impl std::fmt::Display for SearchError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                SearchError::NoToken => write!(f, "no API token"),
+                SearchError::Io(_) => write!(f, "search failed"),
+            }
+        }
+    }
+
+    impl std::error::Error for SearchError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                SearchError::NoToken => None,
+                SearchError::Io(e) => Some(e),
+            }
+        }
+    }
+
+    workflow_error!(SearchError, |error| match error {
+        SearchError::NoToken => Item::new("Set your API token"),
+        _ => default_error_item(error),
+    });
+
+    #[test]
+    fn test_workflow_error_macro() {
+        assert_eq!(
+            SearchError::NoToken.error_item().title,
+            "Set your API token"
+        );
+        let io = std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out");
+        let item = SearchError::Io(io).error_item();
+        assert_eq!(item.title, "Error: search failed");
+        assert_eq!(item.subtitle.as_deref(), Some("timed out"));
+    }
 }
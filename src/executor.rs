@@ -0,0 +1,207 @@
+//! A minimal executor for running AsyncRunnables without Tokio.
+//!
+//! Most workflows do a little I/O and exit, so a full Tokio runtime is
+//! more than they need. block_on drives a future on the current thread,
+//! parking it while the future waits, and spawn runs a future on its own
+//! thread, which is plenty for a handful of concurrent requests. There is
+//! no reactor: futures that rely on Tokio's I/O or timers (e.g. reqwest)
+//! still need a Tokio runtime, so use execute_async from `#[tokio::main]`
+//! for those.
+//!
+//! Building alfrusco with `default-features = false` drops the `tokio`
+//! feature, leaving only the small part of Tokio that CancellationToken
+//! needs.
+//!
+use std::future::Future;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::pin::{pin, Pin};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs the future to completion on the current thread.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// The result of a spawned future, shared between its thread and the
+/// JoinHandle.
+struct Shared<T> {
+    output: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+/// JoinHandle is a future resolving to the output of a spawned future.
+/// If the future panicked, awaiting the handle resumes the panic.
+pub struct JoinHandle<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Blocks the current thread until the spawned future completes.
+    pub fn join(self) -> T {
+        block_on(self)
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut shared = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+        match shared.output.take() {
+            Some(Ok(output)) => Poll::Ready(output),
+            Some(Err(panic)) => resume_unwind(panic),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Runs the future on a new thread, returning a handle to await its
+/// output.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let shared = Arc::new(Mutex::new(Shared {
+        output: None,
+        waker: None,
+    }));
+    let task_shared = shared.clone();
+    thread::spawn(move || {
+        let output = catch_unwind(AssertUnwindSafe(|| block_on(future)));
+        let mut shared = task_shared.lock().unwrap_or_else(|e| e.into_inner());
+        shared.output = Some(output);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    });
+    JoinHandle { shared }
+}
+
+/// Sleep is the future returned by sleep.
+pub struct Sleep {
+    deadline: Instant,
+    waker: Option<Arc<Mutex<Waker>>>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+        match &self.waker {
+            Some(waker) => {
+                let mut waker = waker.lock().unwrap_or_else(|e| e.into_inner());
+                waker.clone_from(cx.waker());
+            }
+            None => {
+                // A timer thread wakes whichever task polled us last
+                let waker = Arc::new(Mutex::new(cx.waker().clone()));
+                let timer_waker = waker.clone();
+                let deadline = self.deadline;
+                thread::spawn(move || {
+                    thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                    timer_waker
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .wake_by_ref();
+                });
+                self.waker = Some(waker);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// Returns a future that completes after `duration`, without needing a
+/// Tokio runtime.
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep {
+        deadline: Instant::now() + duration,
+        waker: None,
+    }
+}
+
+/// Sleeps on Tokio's timer when running inside a Tokio runtime (so paused
+/// test time applies), and on a timer thread otherwise, e.g. under
+/// block_on.
+pub(crate) async fn runtime_sleep(duration: Duration) {
+    #[cfg(feature = "tokio")]
+    if tokio::runtime::Handle::try_current().is_ok() {
+        return tokio::time::sleep(duration).await;
+    }
+    sleep(duration).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_on() {
+        assert_eq!(block_on(async { 40 + 2 }), 42);
+    }
+
+    #[test]
+    fn test_sleep() {
+        let start = Instant::now();
+        block_on(sleep(Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_spawn_runs_concurrently() {
+        let start = Instant::now();
+        let outputs = block_on(async {
+            let handles: Vec<_> = (0..4)
+                .map(|i| {
+                    spawn(async move {
+                        sleep(Duration::from_millis(50)).await;
+                        i * 2
+                    })
+                })
+                .collect();
+            let mut outputs = Vec::new();
+            for handle in handles {
+                outputs.push(handle.await);
+            }
+            outputs
+        });
+        assert_eq!(outputs, [0, 2, 4, 6]);
+        assert!(start.elapsed() < Duration::from_millis(200));
+    }
+
+    #[test]
+    #[should_panic(expected = "task failed")]
+    fn test_spawn_propagates_panics() {
+        spawn(async { panic!("task failed") }).join();
+    }
+}
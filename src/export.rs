@@ -0,0 +1,16 @@
+//! Files in Alfred's import formats.
+//!
+//! Workflows can generate snippet collections for users to import by
+//! opening the written file, and projects can generate their workflow's
+//! info.plist instead of wiring objects up in Alfred's editor.
+//!
+mod info_plist;
+mod snippets;
+mod zip;
+
+pub use self::info_plist::{
+    Argument, CopyToClipboard, InfoPlist, KeywordInput, Notification, Object, ObjectId, OpenUrl,
+    RunScript, ScriptFilter,
+};
+pub use self::snippets::{Snippet, SnippetCollection};
+use crate::memoize::fnv1a;
@@ -0,0 +1,715 @@
+use std::path::Path;
+
+use serde_json::{json, Map, Value};
+
+use super::fnv1a;
+use crate::memoize::write_atomically;
+use crate::xml::escape_text;
+use crate::{Key, Result};
+
+/// The horizontal and vertical spacing of objects in Alfred's editor.
+const COLUMN_WIDTH: i64 = 220;
+const ROW_HEIGHT: i64 = 130;
+
+/// Argument is whether an input object takes an argument after its
+/// keyword.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Argument {
+    Required,
+    #[default]
+    Optional,
+    None,
+}
+
+impl Argument {
+    fn code(self) -> i64 {
+        match self {
+            Argument::Required => 0,
+            Argument::Optional => 1,
+            Argument::None => 2,
+        }
+    }
+}
+
+/// ScriptFilter is a Script Filter input: it runs `script` with the
+/// query as its first argument ("$1") and shows the items it prints.
+/// Scripts run in the workflow's directory, so a bundled binary is run
+/// with e.g. `./my-workflow "$1"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptFilter {
+    keyword: String,
+    script: String,
+    title: String,
+    subtext: String,
+    running_subtext: String,
+    argument: Argument,
+}
+
+impl ScriptFilter {
+    pub fn new(keyword: impl Into<String>, script: impl Into<String>) -> Self {
+        ScriptFilter {
+            keyword: keyword.into(),
+            script: script.into(),
+            title: String::new(),
+            subtext: String::new(),
+            running_subtext: String::new(),
+            argument: Argument::default(),
+        }
+    }
+
+    /// The title shown for the keyword before a query is typed.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub fn subtext(mut self, subtext: impl Into<String>) -> Self {
+        self.subtext = subtext.into();
+        self
+    }
+
+    /// The subtitle shown while the script runs, e.g. "Searching...".
+    pub fn running_subtext(mut self, running_subtext: impl Into<String>) -> Self {
+        self.running_subtext = running_subtext.into();
+        self
+    }
+
+    pub fn argument(mut self, argument: Argument) -> Self {
+        self.argument = argument;
+        self
+    }
+}
+
+/// KeywordInput is a Keyword input, which passes its argument on
+/// without running anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeywordInput {
+    keyword: String,
+    title: String,
+    subtext: String,
+    argument: Argument,
+}
+
+impl KeywordInput {
+    pub fn new(keyword: impl Into<String>, title: impl Into<String>) -> Self {
+        KeywordInput {
+            keyword: keyword.into(),
+            title: title.into(),
+            subtext: String::new(),
+            argument: Argument::default(),
+        }
+    }
+
+    pub fn subtext(mut self, subtext: impl Into<String>) -> Self {
+        self.subtext = subtext.into();
+        self
+    }
+
+    pub fn argument(mut self, argument: Argument) -> Self {
+        self.argument = argument;
+        self
+    }
+}
+
+/// RunScript is a Run Script action, which runs `script` with its input
+/// as the first argument ("$1"). Connecting a Script Filter to a Run
+/// Script invoking the same binary is what alfrusco's internal commands
+/// (see InternalCommand) need.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunScript {
+    script: String,
+}
+
+impl RunScript {
+    pub fn new(script: impl Into<String>) -> Self {
+        RunScript {
+            script: script.into(),
+        }
+    }
+}
+
+/// OpenUrl is an Open URL action, opening its input in the default
+/// browser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenUrl {
+    url: String,
+}
+
+impl OpenUrl {
+    pub fn new() -> Self {
+        OpenUrl {
+            url: "{query}".to_string(),
+        }
+    }
+
+    /// The URL to open, with "{query}" replaced by the input. Defaults to
+    /// "{query}".
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+}
+
+impl Default for OpenUrl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// CopyToClipboard is a Copy to Clipboard output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CopyToClipboard {
+    text: String,
+    autopaste: bool,
+}
+
+impl CopyToClipboard {
+    pub fn new() -> Self {
+        CopyToClipboard {
+            text: "{query}".to_string(),
+            autopaste: false,
+        }
+    }
+
+    /// The text to copy, with "{query}" replaced by the input. Defaults
+    /// to "{query}".
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.text = text.into();
+        self
+    }
+
+    /// Pastes the copied text into the frontmost app.
+    pub fn autopaste(mut self, autopaste: bool) -> Self {
+        self.autopaste = autopaste;
+        self
+    }
+}
+
+impl Default for CopyToClipboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Notification is a Post Notification output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    title: String,
+    text: String,
+}
+
+impl Notification {
+    pub fn new(title: impl Into<String>) -> Self {
+        Notification {
+            title: title.into(),
+            text: "{query}".to_string(),
+        }
+    }
+
+    /// The notification's text, with "{query}" replaced by the input.
+    /// Defaults to "{query}".
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.text = text.into();
+        self
+    }
+}
+
+/// Object is any of the workflow objects InfoPlist can generate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Object {
+    ScriptFilter(ScriptFilter),
+    KeywordInput(KeywordInput),
+    RunScript(RunScript),
+    OpenUrl(OpenUrl),
+    CopyToClipboard(CopyToClipboard),
+    Notification(Notification),
+}
+
+macro_rules! impl_from_object {
+    ($($variant:ident),*) => {
+        $(impl From<$variant> for Object {
+            fn from(object: $variant) -> Self {
+                Object::$variant(object)
+            }
+        })*
+    };
+}
+
+impl_from_object!(
+    ScriptFilter,
+    KeywordInput,
+    RunScript,
+    OpenUrl,
+    CopyToClipboard,
+    Notification
+);
+
+impl Object {
+    /// Returns the object's type and the version of its config.
+    fn kind(&self) -> (&'static str, i64) {
+        match self {
+            Object::ScriptFilter(_) => ("alfred.workflow.input.scriptfilter", 3),
+            Object::KeywordInput(_) => ("alfred.workflow.input.keyword", 1),
+            Object::RunScript(_) => ("alfred.workflow.action.script", 2),
+            Object::OpenUrl(_) => ("alfred.workflow.action.openurl", 1),
+            Object::CopyToClipboard(_) => ("alfred.workflow.output.clipboard", 3),
+            Object::Notification(_) => ("alfred.workflow.output.notification", 1),
+        }
+    }
+
+    /// Returns the object's config. Scripts run with /bin/bash (type 0)
+    /// and receive their input as an argument (scriptargtype 1), so they
+    /// need no escaping.
+    fn config(&self) -> Value {
+        match self {
+            Object::ScriptFilter(filter) => json!({
+                "alfredfiltersresults": false,
+                "alfredfiltersresultsmatchmode": 0,
+                "argumenttreatemptyqueryasnil": true,
+                "argumenttrimmode": 0,
+                "argumenttype": filter.argument.code(),
+                "escaping": 0,
+                "keyword": filter.keyword,
+                "queuedelaycustom": 3,
+                "queuedelayimmediatelyinitially": true,
+                "queuedelaymode": 0,
+                "queuemode": 1,
+                "runningsubtext": filter.running_subtext,
+                "script": filter.script,
+                "scriptargtype": 1,
+                "scriptfile": "",
+                "subtext": filter.subtext,
+                "title": filter.title,
+                "type": 0,
+                "withspace": true,
+            }),
+            Object::KeywordInput(keyword) => json!({
+                "argumenttype": keyword.argument.code(),
+                "keyword": keyword.keyword,
+                "subtext": keyword.subtext,
+                "text": keyword.title,
+                "withspace": true,
+            }),
+            Object::RunScript(script) => json!({
+                "concurrently": false,
+                "escaping": 0,
+                "script": script.script,
+                "scriptargtype": 1,
+                "scriptfile": "",
+                "type": 0,
+            }),
+            Object::OpenUrl(open) => json!({
+                "browser": "",
+                "spaces": "",
+                "url": open.url,
+            }),
+            Object::CopyToClipboard(copy) => json!({
+                "autopaste": copy.autopaste,
+                "clipboardtext": copy.text,
+                "ignoredynamicplaceholders": false,
+                "transient": false,
+            }),
+            Object::Notification(notification) => json!({
+                "lastpathcomponent": false,
+                "onlyshowifquerypopulated": false,
+                "removeextension": false,
+                "text": notification.text,
+                "title": notification.title,
+            }),
+        }
+    }
+}
+
+/// ObjectId refers to an object added to an InfoPlist, for connecting it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjectId(usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Connection {
+    from: ObjectId,
+    to: ObjectId,
+    modifiers: i64,
+    subtext: String,
+}
+
+/// InfoPlist generates a workflow's info.plist: its metadata, objects,
+/// and the connections between them. Objects are laid out left to right
+/// in the order they're connected.
+///
+/// ```no_run
+/// use alfrusco::export::{InfoPlist, Notification, OpenUrl, ScriptFilter};
+/// use alfrusco::Key;
+///
+/// let mut plist = InfoPlist::new("com.example.search", "Search")
+///     .created_by("Ada")
+///     .version("1.0.0");
+/// let filter = plist.add(ScriptFilter::new("s", "./search \"$1\"").title("Search"));
+/// let open = plist.add(OpenUrl::new());
+/// let notify = plist.add(Notification::new("Search").text("Opened {query}"));
+/// plist.connect(filter, open);
+/// plist.connect(open, notify);
+/// plist.write("workflow/info.plist")?;
+/// # Ok::<(), alfrusco::Error>(())
+/// ```
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InfoPlist {
+    bundle_id: String,
+    name: String,
+    created_by: String,
+    description: String,
+    version: String,
+    web_address: String,
+    readme: String,
+    category: String,
+    variables: Map<String, Value>,
+    objects: Vec<Object>,
+    connections: Vec<Connection>,
+}
+
+impl InfoPlist {
+    pub fn new(bundle_id: impl Into<String>, name: impl Into<String>) -> Self {
+        InfoPlist {
+            bundle_id: bundle_id.into(),
+            name: name.into(),
+            created_by: String::new(),
+            description: String::new(),
+            version: String::new(),
+            web_address: String::new(),
+            readme: String::new(),
+            category: String::new(),
+            variables: Map::new(),
+            objects: Vec::new(),
+            connections: Vec::new(),
+        }
+    }
+
+    pub fn created_by(mut self, created_by: impl Into<String>) -> Self {
+        self.created_by = created_by.into();
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = version.into();
+        self
+    }
+
+    pub fn web_address(mut self, web_address: impl Into<String>) -> Self {
+        self.web_address = web_address.into();
+        self
+    }
+
+    pub fn readme(mut self, readme: impl Into<String>) -> Self {
+        self.readme = readme.into();
+        self
+    }
+
+    /// The category shown in Alfred's workflow list, e.g. "Productivity".
+    pub fn category(mut self, category: impl Into<String>) -> Self {
+        self.category = category.into();
+        self
+    }
+
+    /// Adds a workflow environment variable.
+    pub fn variable(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.variables
+            .insert(name.into(), Value::String(value.into()));
+        self
+    }
+
+    /// Adds an object, returning the id to connect it with.
+    pub fn add(&mut self, object: impl Into<Object>) -> ObjectId {
+        self.objects.push(object.into());
+        ObjectId(self.objects.len() - 1)
+    }
+
+    /// Connects `from`'s output to `to`. Panics like connect_modified.
+    pub fn connect(&mut self, from: ObjectId, to: ObjectId) {
+        self.connect_modified(from, to, &[], "");
+    }
+
+    /// Connects `from` to `to` when the item is actioned while holding
+    /// `keys`, showing `subtext` as the subtitle while they're held.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `from` or `to` wasn't returned by this InfoPlist's add.
+    pub fn connect_modified(
+        &mut self,
+        from: ObjectId,
+        to: ObjectId,
+        keys: &[Key],
+        subtext: impl Into<String>,
+    ) {
+        assert!(
+            from.0 < self.objects.len() && to.0 < self.objects.len(),
+            "connected objects must be added to this InfoPlist"
+        );
+        self.connections.push(Connection {
+            from,
+            to,
+            modifiers: keys.iter().map(|key| modifier_flag(*key)).sum(),
+            subtext: subtext.into(),
+        });
+    }
+
+    /// The object's uid. Uids are derived from the bundle id and the
+    /// order objects were added, so regenerating the plist keeps them.
+    fn uid(&self, id: ObjectId) -> String {
+        let index = id.0.to_string();
+        let high = fnv1a(&[&self.bundle_id, &index]);
+        let low = fnv1a(&[&index, &self.bundle_id]);
+        format!(
+            "{:08X}-{:04X}-{:04X}-{:04X}-{:012X}",
+            high >> 32,
+            (high >> 16) & 0xffff,
+            high & 0xffff,
+            low >> 48,
+            low & 0xffff_ffff_ffff
+        )
+    }
+
+    /// Returns each object's column: how many connections it is from an
+    /// object nothing connects to.
+    fn columns(&self) -> Vec<i64> {
+        let mut columns = vec![0; self.objects.len()];
+        // Relax at most once per object, so cycles can't loop forever
+        for _ in 0..self.objects.len() {
+            let mut changed = false;
+            for connection in &self.connections {
+                let column = columns[connection.from.0] + 1;
+                if column > columns[connection.to.0] {
+                    columns[connection.to.0] = column;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        columns
+    }
+
+    /// Returns the plist as a JSON value, with the structure it's written
+    /// in.
+    pub fn to_value(&self) -> Value {
+        let mut objects = Vec::new();
+        let mut uidata = Map::new();
+        let mut rows = Vec::new();
+        for (index, (object, column)) in self.objects.iter().zip(self.columns()).enumerate() {
+            let uid = self.uid(ObjectId(index));
+            let (kind, version) = object.kind();
+            objects.push(json!({
+                "config": object.config(),
+                "type": kind,
+                "uid": uid,
+                "version": version,
+            }));
+            let row = rows.iter().filter(|c| **c == column).count() as i64;
+            rows.push(column);
+            uidata.insert(
+                uid,
+                json!({"xpos": 30 + column * COLUMN_WIDTH, "ypos": 15 + row * ROW_HEIGHT}),
+            );
+        }
+
+        let mut connections = Map::new();
+        for connection in &self.connections {
+            let destinations = connections
+                .entry(self.uid(connection.from))
+                .or_insert_with(|| Value::Array(Vec::new()));
+            if let Value::Array(destinations) = destinations {
+                destinations.push(json!({
+                    "destinationuid": self.uid(connection.to),
+                    "modifiers": connection.modifiers,
+                    "modifiersubtext": connection.subtext,
+                    "vitoclose": false,
+                }));
+            }
+        }
+
+        json!({
+            "bundleid": self.bundle_id,
+            "category": self.category,
+            "connections": connections,
+            "createdby": self.created_by,
+            "description": self.description,
+            "disabled": false,
+            "name": self.name,
+            "objects": objects,
+            "readme": self.readme,
+            "uidata": uidata,
+            "userconfigurationconfig": [],
+            "variables": self.variables,
+            "version": self.version,
+            "webaddress": self.web_address,
+        })
+    }
+
+    /// Returns the info.plist XML.
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+            "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+            "<plist version=\"1.0\">\n",
+        ));
+        write_plist_value(&mut xml, &self.to_value(), 0);
+        xml.push_str("</plist>\n");
+        xml
+    }
+
+    /// Writes the info.plist XML to `path`.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        write_atomically(path.as_ref(), self.to_xml().as_bytes())
+    }
+}
+
+/// The bit Alfred uses for each modifier key in connections.
+fn modifier_flag(key: Key) -> i64 {
+    match key {
+        Key::Shift => 1 << 17,
+        Key::Ctrl => 1 << 18,
+        Key::Alt => 1 << 19,
+        Key::Cmd => 1 << 20,
+        Key::Fn => 1 << 23,
+    }
+}
+
+fn write_plist_value(xml: &mut String, value: &Value, depth: usize) {
+    let indent = "\t".repeat(depth);
+    match value {
+        Value::Null => xml.push_str(&format!("{}<string></string>\n", indent)),
+        Value::Bool(value) => xml.push_str(&format!("{}<{}/>\n", indent, value)),
+        Value::Number(number) if number.is_f64() => {
+            xml.push_str(&format!("{}<real>{}</real>\n", indent, number))
+        }
+        Value::Number(number) => {
+            xml.push_str(&format!("{}<integer>{}</integer>\n", indent, number))
+        }
+        Value::String(text) => xml.push_str(&format!(
+            "{}<string>{}</string>\n",
+            indent,
+            escape_text(text)
+        )),
+        Value::Array(values) if values.is_empty() => xml.push_str(&format!("{}<array/>\n", indent)),
+        Value::Array(values) => {
+            xml.push_str(&format!("{}<array>\n", indent));
+            for value in values {
+                write_plist_value(xml, value, depth + 1);
+            }
+            xml.push_str(&format!("{}</array>\n", indent));
+        }
+        Value::Object(map) if map.is_empty() => xml.push_str(&format!("{}<dict/>\n", indent)),
+        Value::Object(map) => {
+            xml.push_str(&format!("{}<dict>\n", indent));
+            // Keys are sorted, as Alfred writes them
+            let mut keys: Vec<_> = map.keys().collect();
+            keys.sort();
+            for key in keys {
+                xml.push_str(&format!("{}\t<key>{}</key>\n", indent, escape_text(key)));
+                write_plist_value(xml, &map[key], depth + 1);
+            }
+            xml.push_str(&format!("{}</dict>\n", indent));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::bookmarks::parse_plist_xml;
+
+    fn plist() -> (InfoPlist, [ObjectId; 4]) {
+        let mut plist = InfoPlist::new("com.example.search", "Search")
+            .created_by("Ada")
+            .version("1.0.0")
+            .variable("API_URL", "https://example.com");
+        let filter = plist.add(
+            ScriptFilter::new("s", "./search \"$1\"")
+                .title("Search")
+                .argument(Argument::Required),
+        );
+        let open = plist.add(OpenUrl::new());
+        let copy = plist.add(CopyToClipboard::new());
+        let notify = plist.add(Notification::new("Search").text("Opened {query}"));
+        plist.connect(filter, open);
+        plist.connect_modified(filter, copy, &[Key::Cmd, Key::Shift], "Copy the URL");
+        plist.connect(open, notify);
+        (plist, [filter, open, copy, notify])
+    }
+
+    #[test]
+    fn test_to_value() {
+        let (plist, [filter, open, copy, notify]) = plist();
+        let value = plist.to_value();
+        assert_eq!(value["bundleid"], "com.example.search");
+        assert_eq!(value["variables"]["API_URL"], "https://example.com");
+
+        let objects = value["objects"].as_array().unwrap();
+        assert_eq!(objects.len(), 4);
+        assert_eq!(objects[0]["type"], "alfred.workflow.input.scriptfilter");
+        assert_eq!(objects[0]["config"]["keyword"], "s");
+        assert_eq!(objects[0]["config"]["argumenttype"], 0);
+        assert_eq!(objects[0]["config"]["script"], "./search \"$1\"");
+        assert_eq!(objects[3]["config"]["text"], "Opened {query}");
+
+        let from_filter = value["connections"][plist.uid(filter)].as_array().unwrap();
+        assert_eq!(from_filter.len(), 2);
+        assert_eq!(from_filter[0]["destinationuid"], plist.uid(open));
+        assert_eq!(from_filter[0]["modifiers"], 0);
+        assert_eq!(from_filter[1]["destinationuid"], plist.uid(copy));
+        assert_eq!(from_filter[1]["modifiers"], 1048576 + 131072);
+        assert_eq!(from_filter[1]["modifiersubtext"], "Copy the URL");
+
+        // Columns follow the connections; rows stack within a column
+        let position = |id| {
+            let ui = &value["uidata"][plist.uid(id)];
+            (ui["xpos"].as_i64().unwrap(), ui["ypos"].as_i64().unwrap())
+        };
+        assert_eq!(position(filter), (30, 15));
+        assert_eq!(position(open), (250, 15));
+        assert_eq!(position(copy), (250, 145));
+        assert_eq!(position(notify), (470, 15));
+    }
+
+    #[test]
+    fn test_uids_are_stable_and_unique() {
+        let (first, ids) = plist();
+        let (second, _) = plist();
+        let uids: Vec<_> = ids.iter().map(|id| first.uid(*id)).collect();
+        assert_eq!(uids, ids.map(|id| second.uid(id)));
+        assert_eq!(uids[0].len(), 36);
+        for (i, uid) in uids.iter().enumerate() {
+            assert!(!uids[i + 1..].contains(uid));
+        }
+    }
+
+    #[test]
+    fn test_to_xml_round_trips() {
+        let (mut plist, [filter, ..]) = plist();
+        let script = plist.add(RunScript::new("./search copy <<EOF\n\t\"$1\" & done\nEOF"));
+        plist.connect(filter, script);
+
+        let xml = plist.to_xml();
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE plist"));
+        assert!(xml.contains("\t<key>bundleid</key>\n\t<string>com.example.search</string>\n"));
+        assert!(xml.contains("<key>userconfigurationconfig</key>\n\t<array/>"));
+        assert_eq!(parse_plist_xml(&xml).unwrap(), plist.to_value());
+    }
+
+    #[test]
+    fn test_cycles_terminate() {
+        let mut plist = InfoPlist::new("com.example.cycle", "Cycle");
+        let a = plist.add(KeywordInput::new("a", "A"));
+        let b = plist.add(RunScript::new("./a"));
+        plist.connect(a, b);
+        plist.connect(b, a);
+        assert_eq!(plist.columns().len(), 2);
+    }
+}
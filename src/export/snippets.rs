@@ -0,0 +1,256 @@
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::fnv1a;
+use super::zip::stored_zip;
+use crate::memoize::write_atomically;
+use crate::xml::escape;
+use crate::Result;
+
+/// Snippet is one text expansion in a SnippetCollection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    keyword: String,
+    snippet: String,
+    name: Option<String>,
+    uid: Option<String>,
+    dont_autoexpand: bool,
+}
+
+impl Snippet {
+    /// Creates a snippet that expands `keyword` (plus the collection's
+    /// prefix and suffix) into `snippet`.
+    pub fn new(keyword: impl Into<String>, snippet: impl Into<String>) -> Self {
+        Snippet {
+            keyword: keyword.into(),
+            snippet: snippet.into(),
+            name: None,
+            uid: None,
+            dont_autoexpand: false,
+        }
+    }
+
+    /// The name shown in Alfred's snippet list. Defaults to the keyword.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Identifies the snippet across imports. Defaults to a hash of the
+    /// keyword and name, so importing a regenerated collection updates
+    /// the snippets instead of duplicating them.
+    pub fn uid(mut self, uid: impl Into<String>) -> Self {
+        self.uid = Some(uid.into());
+        self
+    }
+
+    /// Stops the snippet from expanding as it's typed; it's only
+    /// available from Alfred's snippet viewer.
+    pub fn dont_autoexpand(mut self, dont_autoexpand: bool) -> Self {
+        self.dont_autoexpand = dont_autoexpand;
+        self
+    }
+
+    fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.keyword)
+    }
+
+    fn effective_uid(&self) -> String {
+        match &self.uid {
+            Some(uid) => uid.clone(),
+            None => format!("{:016X}", fnv1a(&[&self.keyword, self.display_name()])),
+        }
+    }
+
+    /// The archive entry: "<name> [<uid>].json", as Alfred exports them.
+    fn entry(&self) -> Result<(String, Vec<u8>)> {
+        let uid = self.effective_uid();
+        let json = SnippetFile {
+            alfredsnippet: SnippetJson {
+                snippet: &self.snippet,
+                dontautoexpand: self.dont_autoexpand,
+                uid: &uid,
+                name: self.display_name(),
+                keyword: &self.keyword,
+            },
+        };
+        let file_name = self.display_name().replace(['/', ':'], "-");
+        Ok((
+            format!("{} [{}].json", file_name, uid),
+            serde_json::to_vec_pretty(&json)?,
+        ))
+    }
+}
+
+#[derive(Serialize)]
+struct SnippetFile<'a> {
+    alfredsnippet: SnippetJson<'a>,
+}
+
+#[derive(Serialize)]
+struct SnippetJson<'a> {
+    snippet: &'a str,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    dontautoexpand: bool,
+    uid: &'a str,
+    name: &'a str,
+    keyword: &'a str,
+}
+
+/// SnippetCollection writes snippets as an `.alfredsnippets` file, which
+/// users import into Alfred's Snippets preferences by opening it.
+///
+/// ```no_run
+/// use alfrusco::export::{Snippet, SnippetCollection};
+///
+/// SnippetCollection::new()
+///     .prefix(";")
+///     .snippet(Snippet::new("sig", "Best,\nAda").name("Signature"))
+///     .snippet(Snippet::new("addr", "1 Infinite Loop"))
+///     .write("/tmp/My Snippets.alfredsnippets")?;
+/// # Ok::<(), alfrusco::Error>(())
+/// ```
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnippetCollection {
+    snippets: Vec<Snippet>,
+    prefix: Option<String>,
+    suffix: Option<String>,
+}
+
+impl SnippetCollection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Text typed before every keyword in the collection, e.g. ";".
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Text typed after every keyword in the collection.
+    pub fn suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.suffix = Some(suffix.into());
+        self
+    }
+
+    pub fn snippet(mut self, snippet: Snippet) -> Self {
+        self.snippets.push(snippet);
+        self
+    }
+
+    pub fn snippets(mut self, snippets: impl IntoIterator<Item = Snippet>) -> Self {
+        self.snippets.extend(snippets);
+        self
+    }
+
+    /// Returns the contents of the `.alfredsnippets` file: a zip archive of
+    /// one JSON file per snippet, plus an info.plist holding the prefix
+    /// and suffix if either is set.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut entries = self
+            .snippets
+            .iter()
+            .map(Snippet::entry)
+            .collect::<Result<Vec<_>>>()?;
+        if self.prefix.is_some() || self.suffix.is_some() {
+            entries.push(("info.plist".to_string(), self.info_plist().into_bytes()));
+        }
+        stored_zip(&entries)
+    }
+
+    /// Writes the `.alfredsnippets` file to `path`. Alfred names the
+    /// imported collection after the file.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        write_atomically(path.as_ref(), &self.to_bytes()?)
+    }
+
+    fn info_plist(&self) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>snippetkeywordprefix</key>
+	<string>{}</string>
+	<key>snippetkeywordsuffix</key>
+	<string>{}</string>
+</dict>
+</plist>
+"#,
+            escape(self.prefix.as_deref().unwrap_or_default()),
+            escape(self.suffix.as_deref().unwrap_or_default()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::zip::read_stored_zip;
+
+    #[test]
+    fn test_snippet_entry() {
+        let (name, json) = Snippet::new("sig", "Best,\nAda")
+            .name("Email: signature")
+            .uid("ABC")
+            .dont_autoexpand(true)
+            .entry()
+            .unwrap();
+        assert_eq!(name, "Email- signature [ABC].json");
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"alfredsnippet": {
+                "snippet": "Best,\nAda",
+                "dontautoexpand": true,
+                "uid": "ABC",
+                "name": "Email: signature",
+                "keyword": "sig"
+            }})
+        );
+
+        let (_, json) = Snippet::new("addr", "1 Infinite Loop").entry().unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["alfredsnippet"]["name"], "addr");
+        assert!(json["alfredsnippet"].get("dontautoexpand").is_none());
+    }
+
+    #[test]
+    fn test_default_uid_is_stable() {
+        let uid = Snippet::new("addr", "one").effective_uid();
+        assert_eq!(uid.len(), 16);
+        assert_eq!(uid, Snippet::new("addr", "two").effective_uid());
+        assert_ne!(uid, Snippet::new("addr", "one").name("x").effective_uid());
+    }
+
+    #[test]
+    fn test_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Mine.alfredsnippets");
+        SnippetCollection::new()
+            .prefix("<;")
+            .snippets([
+                Snippet::new("a", "A").uid("1"),
+                Snippet::new("b", "B").uid("2"),
+            ])
+            .write(&path)
+            .unwrap();
+
+        let entries = read_stored_zip(&std::fs::read(&path).unwrap());
+        let names: Vec<_> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["a [1].json", "b [2].json", "info.plist"]);
+        let plist = String::from_utf8(entries[2].1.clone()).unwrap();
+        assert!(plist.contains("<string>&lt;;</string>"));
+        assert!(plist.contains("<key>snippetkeywordsuffix</key>\n\t<string></string>"));
+
+        // Without a prefix or suffix there's no info.plist
+        let bytes = SnippetCollection::new()
+            .snippet(Snippet::new("a", "A"))
+            .to_bytes()
+            .unwrap();
+        assert_eq!(read_stored_zip(&bytes).len(), 1);
+    }
+}
@@ -0,0 +1,169 @@
+//! A minimal writer for uncompressed (stored) zip archives, which is all
+//! Alfred's import formats need.
+
+use crate::{Error, Result};
+
+/// The DOS date of 1980-01-01, the earliest a zip entry can have. Entries
+/// use it so archives are reproducible.
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+/// General purpose flag marking entry names as UTF-8.
+const UTF8_NAMES: u16 = 1 << 11;
+
+/// Version 2.0, the minimum needed to extract the archive.
+const VERSION: u16 = 20;
+
+/// Returns a zip archive of `entries` (name, contents), stored without
+/// compression. Fails if the archive needs ZIP64 (more than 65535 entries,
+/// names longer than 65535 bytes or sizes over 4 GiB), which this writer
+/// doesn't support.
+pub(crate) fn stored_zip(entries: &[(String, Vec<u8>)]) -> Result<Vec<u8>> {
+    let mut archive = Vec::new();
+    let mut central = Vec::new();
+    for (name, data) in entries {
+        let offset = field("archive size", archive.len())?;
+        let crc = crc32(data);
+
+        put_u32(&mut archive, 0x04034b50);
+        put_u16(&mut archive, VERSION);
+        put_common(&mut archive, name, data, crc)?;
+        put_u16(&mut archive, 0); // extra field length
+        archive.extend_from_slice(name.as_bytes());
+        archive.extend_from_slice(data);
+
+        put_u32(&mut central, 0x02014b50);
+        put_u16(&mut central, VERSION); // version made by
+        put_u16(&mut central, VERSION);
+        put_common(&mut central, name, data, crc)?;
+        put_u16(&mut central, 0); // extra field length
+        put_u16(&mut central, 0); // comment length
+        put_u16(&mut central, 0); // disk number
+        put_u16(&mut central, 0); // internal attributes
+        put_u32(&mut central, 0); // external attributes
+        put_u32(&mut central, offset);
+        central.extend_from_slice(name.as_bytes());
+    }
+
+    let central_offset = field("archive size", archive.len())?;
+    let central_size = field("central directory size", central.len())?;
+    let count = field("entry count", entries.len())?;
+    archive.extend(central);
+    put_u32(&mut archive, 0x06054b50);
+    put_u16(&mut archive, 0); // this disk
+    put_u16(&mut archive, 0); // disk with the central directory
+    put_u16(&mut archive, count);
+    put_u16(&mut archive, count);
+    put_u32(&mut archive, central_size);
+    put_u32(&mut archive, central_offset);
+    put_u16(&mut archive, 0); // comment length
+    Ok(archive)
+}
+
+/// Writes the header fields shared by local and central directory
+/// entries, up to the name length.
+fn put_common(out: &mut Vec<u8>, name: &str, data: &[u8], crc: u32) -> Result<()> {
+    let size = field(&format!("size of {}", name), data.len())?;
+    put_u16(out, UTF8_NAMES);
+    put_u16(out, 0); // stored
+    put_u16(out, 0); // time
+    put_u16(out, DOS_DATE);
+    put_u32(out, crc);
+    put_u32(out, size); // compressed size
+    put_u32(out, size);
+    put_u16(out, field("entry name length", name.len())?);
+    Ok(())
+}
+
+/// Converts a length to a header field, failing if it doesn't fit.
+fn field<T: TryFrom<usize>>(what: &str, len: usize) -> Result<T> {
+    T::try_from(len)
+        .map_err(|_| Error::Workflow(format!("{} ({}) is too large for a zip archive", what, len)))
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb88320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Reads the entries back out of a stored zip archive.
+#[cfg(test)]
+pub(crate) fn read_stored_zip(archive: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let u16_at = |at: usize| u16::from_le_bytes([archive[at], archive[at + 1]]) as usize;
+    let u32_at = |at: usize| {
+        u32::from_le_bytes([
+            archive[at],
+            archive[at + 1],
+            archive[at + 2],
+            archive[at + 3],
+        ])
+    };
+    let mut entries = Vec::new();
+    let mut at = 0;
+    while u32_at(at) == 0x04034b50 {
+        assert_eq!(u16_at(at + 8), 0, "entry isn't stored");
+        let crc = u32_at(at + 14);
+        let size = u32_at(at + 18) as usize;
+        let name_len = u16_at(at + 26);
+        let name_start = at + 30;
+        let data_start = name_start + name_len;
+        let data = archive[data_start..data_start + size].to_vec();
+        assert_eq!(crc32(&data), crc);
+        let name = String::from_utf8(archive[name_start..data_start].to_vec()).unwrap();
+        entries.push((name, data));
+        at = data_start + size;
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn test_stored_zip() {
+        let entries = vec![
+            ("a.json".to_string(), b"{}".to_vec()),
+            ("café [1].json".to_string(), b"snippet".to_vec()),
+        ];
+        let archive = stored_zip(&entries).unwrap();
+        assert_eq!(read_stored_zip(&archive), entries);
+
+        // The end of central directory record counts both entries
+        let end = archive.len() - 22;
+        assert_eq!(&archive[end..end + 4], &0x06054b50u32.to_le_bytes());
+        assert_eq!(&archive[end + 10..end + 12], &2u16.to_le_bytes());
+    }
+
+    #[test]
+    fn test_stored_zip_too_many_entries() {
+        let entries = vec![(String::new(), Vec::new()); 65_536];
+        let error = stored_zip(&entries).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Workflow Error: entry count (65536) is too large for a zip archive"
+        );
+
+        let name = "a".repeat(65_536);
+        assert!(stored_zip(&[(name, Vec::new())]).is_err());
+    }
+}
@@ -0,0 +1,230 @@
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+
+use crate::Item;
+
+/// The signature of a function producing the text an Item is fuzzy matched
+/// against.
+pub type FilterKeyFn = dyn Fn(&Item) -> String + Send + Sync;
+
+/// The default text items are matched against: "subtitle : title".
+pub fn default_filter_key(item: &Item) -> String {
+    let subtitle = item.subtitle.as_deref().unwrap_or_default();
+    format!("{} : {}", subtitle, item.title)
+}
+
+/// Scores each item against the query using the same fuzzy matcher
+/// alfrusco uses when filtering, returning only the items that match,
+/// paired with their (search_weight adjusted) score, best first.
+///
+/// Unlike filter_and_sort_items, sticky items receive no special
+/// treatment. Use this when a workflow needs custom post-processing such
+/// as score thresholds or grouping into score bands.
+///
+pub fn rank(items: Vec<Item>, query: &str) -> Vec<(Item, i64)> {
+    rank_by(items, query, &default_filter_key)
+}
+
+/// Like rank, but matches against the text produced by `filter_key`.
+pub fn rank_by(items: Vec<Item>, query: &str, filter_key: &FilterKeyFn) -> Vec<(Item, i64)> {
+    let matcher = SkimMatcherV2::default();
+
+    let mut ranked: Vec<(Item, i64)> = items
+        .into_iter()
+        .filter_map(|item| {
+            let combined = filter_key(&item);
+            matcher.fuzzy_match(&combined, query).map(|score| {
+                let score = item.search_weight.apply(score);
+                (item, score)
+            })
+        })
+        .collect();
+
+    // Sort by score in descending order
+    ranked.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+    ranked
+}
+
+/// Filters the items against the query and sorts them for display.
+///
+/// The resulting order is total and deterministic:
+///
+/// 1. Sticky items, regardless of whether they match the query, ordered by
+///    descending sticky priority. Items sharing a priority keep their
+///    original relative order.
+/// 2. Non-sticky items matching the query, ordered by descending (weighted)
+///    score. Items with equal scores keep their original relative order.
+///
+pub fn filter_and_sort_items(items: Vec<Item>, query: String) -> Vec<Item> {
+    filter_and_sort_items_by(items, query, &default_filter_key)
+}
+
+/// Like filter_and_sort_items, but matches the query against the text
+/// produced by `filter_key` for each item rather than the default
+/// "subtitle : title" text.
+pub fn filter_and_sort_items_by(
+    items: Vec<Item>,
+    query: String,
+    filter_key: &FilterKeyFn,
+) -> Vec<Item> {
+    let (mut sticky_items, items): (Vec<Item>, Vec<Item>) =
+        items.into_iter().partition(|item| item.sticky.is_some());
+    sticky_items.sort_by_key(|item| std::cmp::Reverse(item.sticky));
+
+    let ranked = rank_by(items, &query, filter_key);
+    sticky_items.extend(ranked.into_iter().map(|(item, _)| item));
+    sticky_items
+}
+
+/// Unicode combining low line, rendered as an underline beneath the
+/// preceding character.
+const COMBINING_UNDERLINE: char = '\u{0332}';
+
+/// Underlines the characters of each item's title that fuzzy match the
+/// query, so users can see why a result matched.
+///
+/// The undecorated title is preserved in the item's `match` field (unless
+/// one is already set) so that Alfred's own matching and learning are not
+/// affected by the combining characters.
+pub fn highlight_matches(items: Vec<Item>, query: &str) -> Vec<Item> {
+    let matcher = SkimMatcherV2::default();
+    items
+        .into_iter()
+        .map(|mut item| {
+            if let Some((_, indices)) = matcher.fuzzy_indices(&item.title, query) {
+                let highlighted = underline_chars(&item.title, &indices);
+                let original = std::mem::replace(&mut item.title, highlighted);
+                item.r#match.get_or_insert(original);
+            }
+            item
+        })
+        .collect()
+}
+
+fn underline_chars(text: &str, indices: &[usize]) -> String {
+    let mut result = String::with_capacity(text.len() * 2);
+    for (i, c) in text.chars().enumerate() {
+        result.push(c);
+        if indices.contains(&i) {
+            result.push(COMBINING_UNDERLINE);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank_returns_scores_best_first() {
+        let items = vec![
+            Item::new("python"),
+            Item::new("rustacean"),
+            Item::new("rust"),
+        ];
+        let ranked = rank(items, "rst");
+        let titles: Vec<&str> = ranked.iter().map(|(item, _)| item.title.as_str()).collect();
+        assert_eq!(titles.len(), 2);
+        assert!(!titles.contains(&"python"));
+        assert!(ranked.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+    }
+
+    #[test]
+    fn test_rank_ignores_stickiness() {
+        let items = vec![Item::new("unrelated").sticky(true), Item::new("rust")];
+        let ranked = rank(items, "rust");
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].0.title, "rust");
+    }
+
+    #[test]
+    fn test_rank_applies_search_weight() {
+        let plain = rank(vec![Item::new("rust")], "rust")[0].1;
+        let boosted = rank(vec![Item::new("rust").search_weight(2.0)], "rust")[0].1;
+        assert_eq!(boosted, plain * 2);
+    }
+
+    #[test]
+    fn test_search_weight_boosts_score() {
+        let items = vec![
+            Item::new("rust book"),
+            Item::new("rust blog").search_weight(3.0),
+        ];
+        let filtered = filter_and_sort_items(items, "rust b".to_string());
+        assert_eq!(filtered[0].title, "rust blog");
+        assert_eq!(filtered[1].title, "rust book");
+    }
+
+    #[test]
+    fn test_search_weight_does_not_keep_non_matches() {
+        let items = vec![Item::new("python").search_weight(10.0), Item::new("rust")];
+        let filtered = filter_and_sort_items(items, "rust".to_string());
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].title, "rust");
+    }
+
+    #[test]
+    fn test_sticky_items_kept_and_first() {
+        let items = vec![
+            Item::new("rust"),
+            Item::new("unrelated").sticky(true),
+            Item::new("rustacean"),
+        ];
+        let filtered = filter_and_sort_items(items, "rust".to_string());
+        let titles: Vec<&str> = filtered.iter().map(|i| i.title.as_str()).collect();
+        assert_eq!(titles, vec!["unrelated", "rust", "rustacean"]);
+    }
+
+    #[test]
+    fn test_sticky_priority_ordering() {
+        let items = vec![
+            Item::new("low a").sticky(true),
+            Item::new("high").sticky_with_priority(9),
+            Item::new("match"),
+            Item::new("mid").sticky_with_priority(5),
+            Item::new("low b").sticky_with_priority(0),
+        ];
+        let filtered = filter_and_sort_items(items, "match".to_string());
+        let titles: Vec<&str> = filtered.iter().map(|i| i.title.as_str()).collect();
+        assert_eq!(titles, vec!["high", "mid", "low a", "low b", "match"]);
+    }
+
+    #[test]
+    fn test_filter_and_sort_items_by_custom_key() {
+        let items = vec![
+            Item::new("★★★").var("NAME", "rust"),
+            Item::new("★★").var("NAME", "python"),
+        ];
+        let key = |item: &Item| item.get_var("NAME").unwrap_or_default().to_string();
+        let filtered = filter_and_sort_items_by(items, "rust".to_string(), &key);
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].title, "★★★");
+    }
+
+    #[test]
+    fn test_default_filter_key() {
+        let item = Item::new("Title").subtitle("Subtitle");
+        assert_eq!(default_filter_key(&item), "Subtitle : Title");
+        assert_eq!(default_filter_key(&Item::new("Title")), " : Title");
+    }
+
+    #[test]
+    fn test_highlight_matches() {
+        let items = highlight_matches(vec![Item::new("Rust Blog")], "rb");
+        assert_eq!(items[0].title, "R\u{0332}ust B\u{0332}log");
+        assert_eq!(items[0].r#match, Some("Rust Blog".to_string()));
+    }
+
+    #[test]
+    fn test_highlight_matches_preserves_existing_match() {
+        let items = highlight_matches(
+            vec![Item::new("Rust").matches("rust lang"), Item::new("Go")],
+            "ru",
+        );
+        assert_eq!(items[0].title, "R\u{0332}u\u{0332}st");
+        assert_eq!(items[0].r#match, Some("rust lang".to_string()));
+        assert_eq!(items[1].title, "Go");
+        assert_eq!(items[1].r#match, None);
+    }
+}
@@ -0,0 +1,147 @@
+use std::io;
+use std::time::Duration;
+
+use crate::workflow::Workflow;
+use crate::Result;
+
+/// FinalizeReport describes the response written by Workflow::finalize,
+/// so tests and telemetry can check its shape without parsing the JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FinalizeReport {
+    /// The number of items written, including any notice items alfrusco
+    /// added (such as the truncation item). 0 for an Output.
+    pub items_emitted: usize,
+
+    /// The size of the written response in bytes.
+    pub bytes_written: usize,
+
+    /// The number of items removed by keyword filtering.
+    pub filtered_out: usize,
+
+    /// The time from the Workflow's creation until the response was
+    /// written.
+    pub duration: Duration,
+}
+
+impl Workflow {
+    /// Runs the finish hooks, applies filtering, sorting, limits and the
+    /// other finishing touches, and writes the response (or Output) to
+    /// `writer`. execute calls this after the Runnable returns; call it
+    /// directly when driving a Workflow by hand.
+    pub fn finalize(mut self, writer: &mut dyn io::Write) -> Result<FinalizeReport> {
+        self.fire_finish();
+        // Internal queries replace the items, so add notices after them
+        self.handle_internal_query();
+        self.add_degraded_notice();
+        self.deliver_queued_notifications();
+        self.validate();
+        self.check_icons();
+        self.apply_keyword_autocomplete();
+        let unfiltered = self.response.items.len();
+        self.filter_and_sort();
+        let filtered_out = unfiltered.saturating_sub(self.response.items.len());
+        self.apply_limits();
+        self.track_reruns();
+        self.append_debug_items();
+
+        let mut writer = CountingWriter {
+            inner: writer,
+            count: 0,
+        };
+        let items_emitted = match &self.output {
+            Some(output) => {
+                output.write(&mut writer)?;
+                0
+            }
+            None => {
+                self.response.write_as(self.output_format, &mut writer)?;
+                self.response.items.len()
+            }
+        };
+        Ok(FinalizeReport {
+            items_emitted,
+            bytes_written: writer.count,
+            filtered_out,
+            duration: self.started_at.elapsed(),
+        })
+    }
+}
+
+/// CountingWriter counts the bytes written through it.
+struct CountingWriter<'a> {
+    inner: &'a mut dyn io::Write,
+    count: usize,
+}
+
+impl io::Write for CountingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memoize::write_cached;
+    use crate::notify::QueuedNotification;
+    use crate::test_support::workflow_in;
+    use crate::{Item, Output};
+
+    #[test]
+    fn test_finalize_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        workflow.set_filter_keyword("rust".to_string());
+        workflow.append_items(vec![
+            Item::new("Rust"),
+            Item::new("Cargo"),
+            Item::new("Rustup"),
+        ]);
+
+        let mut buffer = Vec::new();
+        let report = workflow.finalize(&mut buffer).unwrap();
+        assert_eq!(report.items_emitted, 2);
+        assert_eq!(report.filtered_out, 1);
+        assert_eq!(report.bytes_written, buffer.len());
+    }
+
+    #[test]
+    fn test_finalize_report_for_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        workflow.output(Output::new().arg("done"));
+
+        let mut buffer = Vec::new();
+        let report = workflow.finalize(&mut buffer).unwrap();
+        assert_eq!(report.items_emitted, 0);
+        assert_eq!(report.filtered_out, 0);
+        assert_eq!(buffer, br#"{"alfredworkflow":{"arg":"done"}}"#);
+        assert_eq!(report.bytes_written, buffer.len());
+    }
+
+    #[test]
+    fn test_queued_notifications_survive_internal_queries() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        let queued = vec![QueuedNotification {
+            title: "Sync done".to_string(),
+            message: "42 items".to_string(),
+        }];
+        let notifications = workflow.data_dir().join("notifications.json");
+        write_cached(&notifications, &queued).unwrap();
+        workflow.set_filter_keyword("workflow:stats".to_string());
+
+        let mut buffer = Vec::new();
+        workflow.finalize(&mut buffer).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+        let items = json["items"].as_array().unwrap();
+        assert_eq!(items[0]["title"], "Sync done");
+        assert!(items.len() > 1, "{}", json);
+        assert!(!notifications.exists());
+    }
+}
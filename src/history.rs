@@ -0,0 +1,198 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+
+use crate::item::Item;
+use crate::memoize::write_cached;
+use crate::workflow::Workflow;
+use crate::Result;
+
+/// The internal query that lists the recent queries as items.
+pub(crate) const HISTORY_QUERY: &str = "workflow:history";
+
+impl Workflow {
+    /// Opt in to remembering the last `capacity` queries in the workflow's
+    /// data directory. When enabled, the filter keyword is recorded when
+    /// the workflow finishes, and typing `workflow:history` lists the
+    /// recent queries as autocomplete items.
+    ///
+    pub fn enable_query_history(&mut self, capacity: usize) {
+        self.query_history = Some(capacity);
+    }
+
+    /// Returns up to `n` of the most recent queries, newest first. Empty
+    /// unless query history is enabled.
+    pub fn recent_queries(&self, n: usize) -> Vec<String> {
+        if self.query_history.is_none() {
+            return Vec::new();
+        }
+        read_history(&self.history_path())
+            .into_iter()
+            .rev()
+            .take(n)
+            .collect()
+    }
+
+    /// Records `query` in the query history, for workflows that don't use
+    /// set_filter_keyword. Does nothing unless query history is enabled.
+    ///
+    /// Script Filters run on every keystroke, so a query that extends (or
+    /// is a prefix of) the most recent one replaces it rather than adding
+    /// an entry per character typed.
+    ///
+    pub fn record_query(&self, query: &str) -> Result<()> {
+        let Some(capacity) = self.query_history else {
+            return Ok(());
+        };
+        let query = query.trim();
+        if query.is_empty() || query.starts_with("workflow:") {
+            return Ok(());
+        }
+
+        let path = self.history_path();
+        let mut history = read_history(&path);
+        if let Some(last) = history.back() {
+            if query.starts_with(last.as_str()) || last.starts_with(query) {
+                history.pop_back();
+            }
+        }
+        history.retain(|previous| previous != query);
+        history.push_back(query.to_string());
+        while history.len() > capacity {
+            history.pop_front();
+        }
+        write_cached(&path, &history)
+    }
+
+    /// Records the filter keyword, if any. Called automatically when the
+    /// workflow finishes.
+    pub(crate) fn record_filter_keyword(&self) {
+        if let Some(keyword) = &self.keyword {
+            if let Err(e) = self.record_query(keyword) {
+                warn!("unable to record query history: {}", e);
+            }
+        }
+    }
+
+    /// Replaces the response items with the recent queries.
+    pub(crate) fn show_query_history(&mut self) {
+        debug!("listing query history");
+        let queries = self.recent_queries(usize::MAX);
+        let items = if queries.is_empty() {
+            vec![Item::new(self.strings.get("history.empty")).valid(false)]
+        } else {
+            queries
+                .into_iter()
+                .map(|query| {
+                    Item::new(&query)
+                        .subtitle(self.strings.get("history.subtitle"))
+                        .autocomplete_query(query)
+                        .valid(false)
+                })
+                .collect()
+        };
+        self.response.items = items;
+    }
+
+    fn history_path(&self) -> PathBuf {
+        self.config.workflow_data.join("query_history.json")
+    }
+}
+
+fn read_history(path: &Path) -> VecDeque<String> {
+    match std::fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+            warn!(
+                "ignoring unreadable query history {}: {}",
+                path.display(),
+                e
+            );
+            VecDeque::new()
+        }),
+        Err(_) => VecDeque::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn test_workflow() -> (Workflow, TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = crate::test_support::workflow_in(dir.path());
+        workflow.enable_query_history(3);
+        (workflow, dir)
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let workflow = crate::test_support::workflow_in(dir.path());
+        workflow.record_query("rust").unwrap();
+        assert!(workflow.recent_queries(10).is_empty());
+        assert!(!workflow.history_path().exists());
+    }
+
+    #[test]
+    fn test_recent_queries_newest_first_and_capped() {
+        let (workflow, _dir) = test_workflow();
+        for query in ["rust", "go", "zig", "python"] {
+            workflow.record_query(query).unwrap();
+        }
+        assert_eq!(workflow.recent_queries(10), vec!["python", "zig", "go"]);
+        assert_eq!(workflow.recent_queries(1), vec!["python"]);
+    }
+
+    #[test]
+    fn test_keystrokes_collapse_into_one_entry() {
+        let (workflow, _dir) = test_workflow();
+        for query in ["go", "r", "ru", "rus", "rust", "rus"] {
+            workflow.record_query(query).unwrap();
+        }
+        assert_eq!(workflow.recent_queries(10), vec!["rus", "go"]);
+    }
+
+    #[test]
+    fn test_repeated_queries_move_to_front() {
+        let (workflow, _dir) = test_workflow();
+        for query in ["rust", "go", "rust", "", "workflow:history"] {
+            workflow.record_query(query).unwrap();
+        }
+        assert_eq!(workflow.recent_queries(10), vec!["rust", "go"]);
+    }
+
+    #[test]
+    fn test_show_query_history() {
+        let (mut workflow, _dir) = test_workflow();
+        workflow.show_query_history();
+        assert_eq!(workflow.response.items[0].title, "No recent queries");
+
+        workflow.record_query("rust").unwrap();
+        workflow.record_query("go").unwrap();
+        workflow.show_query_history();
+        let items = &workflow.response.items;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].title, "go");
+        assert_eq!(items[0].autocomplete, Some("go".to_string()));
+        assert_eq!(items[1].title, "rust");
+    }
+
+    #[test]
+    fn test_history_internal_query() {
+        let (mut workflow, _dir) = test_workflow();
+        workflow.set_filter_keyword("rust".to_string());
+        workflow.handle_internal_query();
+        assert_eq!(workflow.recent_queries(10), vec!["rust"]);
+
+        workflow.items(vec![Item::new("Unrelated")]);
+        workflow.set_filter_keyword(HISTORY_QUERY.to_string());
+        workflow.handle_internal_query();
+        workflow.filter_and_sort();
+        assert_eq!(workflow.response.items.len(), 1);
+        assert_eq!(workflow.response.items[0].title, "rust");
+        assert_eq!(workflow.recent_queries(10), vec!["rust"]);
+    }
+}
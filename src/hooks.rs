@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use crate::workflow::Workflow;
+
+/// Hook lets reusable components (telemetry, cleanup, cache GC, etc.)
+/// observe a workflow execution without changing each workflow's run()
+/// body.
+///
+/// Hooks can be registered with Workflow::add_hook, or returned from
+/// Runnable::hooks / AsyncRunnable::hooks so they are in place before
+/// on_start fires. All methods default to doing nothing.
+///
+pub trait Hook: Send + Sync {
+    /// Called after the workflow is set up, before the runnable executes.
+    fn on_start(&self, _workflow: &mut Workflow) {}
+
+    /// Called when the runnable returns an error, before the error item
+    /// is added to the response.
+    fn on_error(&self, _workflow: &mut Workflow, _error: &dyn std::error::Error) {}
+
+    /// Called after the runnable completes (successfully or not), before
+    /// the response is filtered and written.
+    fn on_finish(&self, _workflow: &mut Workflow) {}
+}
+
+/// Hooks holds the hooks registered on a Workflow.
+#[derive(Default, Clone)]
+pub(crate) struct Hooks(Vec<Arc<dyn Hook>>);
+
+impl std::fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Hooks({})", self.0.len())
+    }
+}
+
+impl Workflow {
+    /// Registers a lifecycle hook. Hooks fire in registration order.
+    pub fn add_hook(&mut self, hook: impl Hook + 'static) {
+        self.hooks.0.push(Arc::new(hook));
+    }
+
+    pub(crate) fn add_boxed_hooks(&mut self, hooks: Vec<Box<dyn Hook>>) {
+        self.hooks.0.extend(hooks.into_iter().map(Arc::from));
+    }
+
+    pub(crate) fn fire_start(&mut self) {
+        for hook in self.hooks.clone().0 {
+            hook.on_start(self);
+        }
+    }
+
+    pub(crate) fn fire_error(&mut self, error: &dyn std::error::Error) {
+        for hook in self.hooks.clone().0 {
+            hook.on_error(self, error);
+        }
+    }
+
+    pub(crate) fn fire_finish(&mut self) {
+        for hook in self.hooks.clone().0 {
+            hook.on_finish(self);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::test_support::{run_in, workflow_in};
+    use crate::{Error, Item};
+
+    #[derive(Default, Clone)]
+    struct RecordingHook(Arc<Mutex<Vec<String>>>);
+
+    impl Hook for RecordingHook {
+        fn on_start(&self, workflow: &mut Workflow) {
+            self.0.lock().unwrap().push("start".to_string());
+            workflow.append_item(Item::new("added by hook"));
+        }
+
+        fn on_error(&self, _workflow: &mut Workflow, error: &dyn std::error::Error) {
+            self.0.lock().unwrap().push(format!("error: {}", error));
+        }
+
+        fn on_finish(&self, _workflow: &mut Workflow) {
+            self.0.lock().unwrap().push("finish".to_string());
+        }
+    }
+
+    #[test]
+    fn test_hooks_fire_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+
+        let hook = RecordingHook::default();
+        workflow.add_hook(hook.clone());
+        workflow.fire_start();
+        workflow.fire_error(&Error::Workflow("boom".to_string()));
+        workflow.fire_finish();
+
+        assert_eq!(
+            *hook.0.lock().unwrap(),
+            vec!["start", "error: Workflow Error: boom", "finish"]
+        );
+        assert_eq!(workflow.response.items[0].title, "added by hook");
+    }
+
+    #[test]
+    fn test_hooks_from_runnable() {
+        struct Failing(RecordingHook);
+
+        impl crate::Runnable for Failing {
+            type Error = Error;
+
+            fn hooks(&self) -> Vec<Box<dyn Hook>> {
+                vec![Box::new(self.0.clone())]
+            }
+
+            fn run(self, _workflow: &mut Workflow) -> std::result::Result<(), Error> {
+                Err(Error::Workflow("failed".to_string()))
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let hook = RecordingHook::default();
+        let output = run_in(dir.path(), Failing(hook.clone()));
+
+        assert_eq!(
+            *hook.0.lock().unwrap(),
+            vec!["start", "error: Workflow Error: failed", "finish"]
+        );
+        assert!(output.contains("added by hook"));
+    }
+}
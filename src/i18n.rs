@@ -0,0 +1,413 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::{debug, warn};
+
+use crate::config::EnvMap;
+use crate::workflow::Workflow;
+
+/// The English text of alfrusco's built-in items. Locale files may
+/// override any of these keys.
+const ENGLISH: &[(&str, &str)] = &[
+    ("error.title", "An error occurred: {error}"),
+    ("error.title_with_source", "Error: {error}"),
+    (
+        "config.title",
+        "Unable to load the workflow's configuration",
+    ),
+    (
+        "config.outside_alfred",
+        "{error}. Run the workflow from Alfred, which sets the alfred_* variables",
+    ),
+    (
+        "config.check",
+        "{error}. Check the workflow's environment variables",
+    ),
+    ("degraded.title", "Running in degraded mode"),
+    (
+        "degraded.subtitle",
+        "Could not create {dirs}; using a temporary directory instead",
+    ),
+    ("dump.title", "Wrote response JSON"),
+    ("dump.error", "Unable to write response JSON: {error}"),
+    ("history.empty", "No recent queries"),
+    ("history.subtitle", "Search again"),
+    ("job.title", "Background Job '{job}'"),
+    (
+        "job.stale",
+        "Job is stale by {staleness}, running for {duration}",
+    ),
+    (
+        "job.never_run",
+        "First run in progress, running for {duration}",
+    ),
+    (
+        "job.retrying",
+        "Last run failed (exit {code}), retrying for {duration}",
+    ),
+    (
+        "job.failed",
+        "Last refresh failed (exit {code}) {staleness} ago",
+    ),
+    ("job.error", "Error starting job: {error}"),
+    ("jobs.empty", "No background jobs have run"),
+    ("jobs.running", "Running for {duration}"),
+    ("jobs.last_run", "Last run {staleness} ago"),
+    (
+        "jobs.failed_run",
+        "Last run {staleness} ago failed (exit {code})",
+    ),
+    ("jobs.never_run", "Never finished a run"),
+    ("jobs.command", "{policy}: {command}"),
+    ("jobs.refresh", "Run again the next time it is checked"),
+    ("jobs.cancel", "Stop the running job"),
+    ("jobs.open_log", "Open the job's output"),
+    (
+        "jobs.refreshed",
+        "'{job}' will run the next time it is checked",
+    ),
+    ("jobs.cancelled", "Stopped '{job}'"),
+    ("jobs.not_running", "'{job}' wasn't running"),
+    ("jobs.failed", "Unable to update '{job}': {error}"),
+    (
+        "limits.truncated",
+        "Showing first {shown} of {total} results",
+    ),
+    (
+        "limits.refine",
+        "Refine your query to narrow down the results",
+    ),
+    ("reset.title", "Reset {name}"),
+    (
+        "reset.subtitle",
+        "Delete the workflow's cache, data, background jobs and logs",
+    ),
+    ("reset.confirm_title", "Delete all of {name}'s data?"),
+    (
+        "reset.confirm_subtitle",
+        "This can't be undone. Press Return to delete everything",
+    ),
+    ("reset.done", "{name} was reset"),
+    ("reset.failed", "Unable to reset the workflow: {error}"),
+    ("stats.empty", "No usage recorded yet"),
+    ("stats.count", "Used {count} times"),
+    ("stream.failed", "Unable to load results"),
+];
+
+/// The directory, relative to the workflow directory, holding locale files
+/// such as `en.json`, `de.json` or `pt_BR.json`. Each is a flat JSON object
+/// mapping keys to translated text.
+pub const LOCALES_DIR: &str = "locales";
+
+/// Strings holds the translated text for one locale.
+///
+/// Lookups fall back from the region-specific file (`pt_BR.json`) to the
+/// language file (`pt.json`), then to `en.json`, then to alfrusco's
+/// built-in English text, and finally to the key itself.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Strings {
+    dir: PathBuf,
+    locale: String,
+    translations: HashMap<String, String>,
+}
+
+impl Default for Strings {
+    fn default() -> Self {
+        Strings {
+            dir: PathBuf::from(LOCALES_DIR),
+            locale: "en".to_string(),
+            translations: english(),
+        }
+    }
+}
+
+impl Strings {
+    /// Loads the translations for `locale` (e.g. "de" or "pt_BR.UTF-8")
+    /// from the locale files in `dir`. Missing files are skipped.
+    pub fn load(dir: impl Into<PathBuf>, locale: &str) -> Self {
+        let dir = dir.into();
+        let locale = normalize_locale(locale);
+        let mut translations = english();
+
+        let mut names = vec!["en".to_string()];
+        if let Some((language, _)) = locale.split_once('_') {
+            names.push(language.to_string());
+        }
+        names.push(locale.clone());
+        names.dedup();
+
+        for name in names {
+            translations.extend(read_locale_file(&dir.join(format!("{}.json", name))));
+        }
+        Strings {
+            dir,
+            locale,
+            translations,
+        }
+    }
+
+    /// The locale these strings were loaded for, e.g. "pt_BR".
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    /// Returns the text for `key`, or the key itself if it has none.
+    pub fn get(&self, key: &str) -> String {
+        self.translations
+            .get(key)
+            .cloned()
+            .unwrap_or_else(|| key.to_string())
+    }
+
+    /// Returns the text for `key` with each `{name}` placeholder replaced
+    /// by the matching value in `args`. Placeholders are only replaced in
+    /// the text itself, never in the values substituted for them, and
+    /// those without a value are left as they are.
+    pub fn format(&self, key: &str, args: &[(&str, &str)]) -> String {
+        let template = self.get(key);
+        let mut text = String::with_capacity(template.len());
+        let mut rest = template.as_str();
+        while let Some(start) = rest.find('{') {
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let value = rest[1..].find('}').and_then(|end| {
+                let name = &rest[1..end + 1];
+                let value = args.iter().find(|(arg, _)| *arg == name)?.1;
+                Some((value, end + 2))
+            });
+            match value {
+                Some((value, len)) => {
+                    text.push_str(value);
+                    rest = &rest[len..];
+                }
+                None => {
+                    text.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        text.push_str(rest);
+        text
+    }
+}
+
+impl Workflow {
+    /// Returns the localized text for `key`. See Strings for how locale
+    /// files are found.
+    pub fn t(&self, key: &str) -> String {
+        self.strings.get(key)
+    }
+
+    /// Returns the localized text for `key` with `{name}` placeholders
+    /// replaced by the values in `args`.
+    pub fn t_with(&self, key: &str, args: &[(&str, &str)]) -> String {
+        self.strings.format(key, args)
+    }
+
+    /// Overrides the locale detected from the environment (LC_ALL,
+    /// LC_MESSAGES or LANG).
+    pub fn set_locale(&mut self, locale: &str) {
+        self.strings = Arc::new(Strings::load(self.strings.dir.clone(), locale));
+    }
+
+    pub fn strings(&self) -> &Strings {
+        &self.strings
+    }
+}
+
+/// Returns the locale from the environment, defaulting to English.
+pub(crate) fn locale_from_env(env: &EnvMap) -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| env.get(var))
+        .find(|value| !value.is_empty())
+        .unwrap_or("en")
+        .to_string()
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<Strings>>> = const { RefCell::new(None) };
+}
+
+/// Makes `strings` available to code without access to the Workflow (such
+/// as the default WorkflowError::error_item) for the duration of `f`.
+pub(crate) fn with_strings<R>(strings: &Arc<Strings>, f: impl FnOnce() -> R) -> R {
+    let previous = CURRENT.with(|current| current.replace(Some(strings.clone())));
+    let result = f();
+    CURRENT.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// The strings set by with_strings, or the built-in English strings.
+pub(crate) fn current() -> Arc<Strings> {
+    CURRENT
+        .with(|current| current.borrow().clone())
+        .unwrap_or_default()
+}
+
+fn english() -> HashMap<String, String> {
+    ENGLISH
+        .iter()
+        .map(|(key, text)| (key.to_string(), text.to_string()))
+        .collect()
+}
+
+/// Strips any encoding or modifier ("de_DE.UTF-8@euro" becomes "de_DE")
+/// and converts BCP 47 style separators ("pt-BR") to underscores.
+fn normalize_locale(locale: &str) -> String {
+    let locale = locale.split(['.', '@']).next().unwrap_or_default().trim();
+    match locale {
+        "" | "C" | "POSIX" => "en".to_string(),
+        locale => locale.replace('-', "_"),
+    }
+}
+
+fn read_locale_file(path: &Path) -> HashMap<String, String> {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+    match serde_json::from_str(&contents) {
+        Ok(translations) => {
+            debug!("loaded translations from {}", path.display());
+            translations
+        }
+        Err(e) => {
+            warn!("ignoring invalid locale file {}: {}", path.display(), e);
+            HashMap::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::write;
+
+    use super::*;
+    use crate::test_support::workflow_in;
+
+    fn locales_dir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path().join("en.json"),
+            r#"{"greeting": "Hello", "farewell": "Goodbye", "search": "Search {service}"}"#,
+        )
+        .unwrap();
+        write(
+            dir.path().join("pt.json"),
+            r#"{"greeting": "Olá", "farewell": "Adeus", "search": "Pesquisar {service}"}"#,
+        )
+        .unwrap();
+        write(dir.path().join("pt_BR.json"), r#"{"farewell": "Tchau"}"#).unwrap();
+        write(dir.path().join("de.json"), "not json").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_fallback_chain() {
+        let dir = locales_dir();
+        let strings = Strings::load(dir.path(), "pt_BR.UTF-8");
+        assert_eq!(strings.locale(), "pt_BR");
+        assert_eq!(strings.get("farewell"), "Tchau");
+        assert_eq!(strings.get("greeting"), "Olá");
+        assert_eq!(strings.get("job.error"), "Error starting job: {error}");
+        assert_eq!(strings.get("missing.key"), "missing.key");
+    }
+
+    #[test]
+    fn test_invalid_and_missing_locales_fall_back_to_english() {
+        let dir = locales_dir();
+        assert_eq!(Strings::load(dir.path(), "de_DE").get("greeting"), "Hello");
+        assert_eq!(Strings::load(dir.path(), "fr").get("greeting"), "Hello");
+        assert_eq!(Strings::load(dir.path(), "C").locale(), "en");
+        assert_eq!(Strings::load(dir.path(), "pt-BR").get("farewell"), "Tchau");
+    }
+
+    #[test]
+    fn test_format() {
+        let dir = locales_dir();
+        let strings = Strings::load(dir.path(), "pt");
+        assert_eq!(
+            strings.format("search", &[("service", "GitHub")]),
+            "Pesquisar GitHub"
+        );
+    }
+
+    #[test]
+    fn test_format_replaces_placeholders_once() {
+        let strings = Strings::default();
+        assert_eq!(
+            strings.format(
+                "job.retrying",
+                &[("code", "{duration}"), ("duration", "5s")]
+            ),
+            "Last run failed (exit {duration}), retrying for 5s"
+        );
+        assert_eq!(
+            strings.format("job.error", &[("error", "bad {error} {x")]),
+            strings
+                .get("job.error")
+                .replace("{error}", "bad {error} {x")
+        );
+        assert_eq!(strings.format("{a} {b} {{a}}", &[("a", "1")]), "1 {b} {1}");
+    }
+
+    #[test]
+    fn test_workflow_t() {
+        let dir = tempfile::tempdir().unwrap();
+        let locales = locales_dir();
+        let mut workflow = workflow_in(dir.path());
+        assert_eq!(workflow.t("job.title"), "Background Job '{job}'");
+
+        workflow.strings = Arc::new(Strings::load(locales.path(), "en"));
+        assert_eq!(workflow.t("greeting"), "Hello");
+        workflow.set_locale("pt_BR");
+        assert_eq!(workflow.t("greeting"), "Olá");
+        assert_eq!(
+            workflow.t_with("search", &[("service", "Jira")]),
+            "Pesquisar Jira"
+        );
+    }
+
+    #[test]
+    fn test_with_strings() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path().join("de.json"),
+            r#"{"error.title": "Ein Fehler ist aufgetreten: {error}"}"#,
+        )
+        .unwrap();
+        let strings = Arc::new(Strings::load(dir.path(), "de"));
+
+        assert_eq!(current().locale(), "en");
+        let title = with_strings(&strings, || current().get("error.title"));
+        assert_eq!(title, "Ein Fehler ist aufgetreten: {error}");
+        assert_eq!(current().locale(), "en");
+    }
+
+    #[test]
+    fn test_localized_error_item() {
+        use crate::{Error, WorkflowError};
+
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path().join("de.json"),
+            r#"{"error.title": "Ein Fehler ist aufgetreten: {error}"}"#,
+        )
+        .unwrap();
+        let strings = Arc::new(Strings::load(dir.path(), "de"));
+        let error = Error::Workflow("kaputt".to_string());
+
+        let item = with_strings(&strings, || error.error_item());
+        assert_eq!(
+            item.title,
+            "Ein Fehler ist aufgetreten: Workflow Error: kaputt"
+        );
+        assert_eq!(
+            error.error_item().title,
+            "An error occurred: Workflow Error: kaputt"
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use log::warn;
+
+use crate::item::Icon;
+use crate::workflow::Workflow;
+
+impl Workflow {
+    /// Opt in to checking that each item's icon file exists when the
+    /// workflow finishes. Missing icons (which Alfred renders as a blank
+    /// tile) are replaced with `fallback` and a warning is logged.
+    ///
+    /// Image and `fileicon` paths are checked; `filetype` icons name a
+    /// type rather than a file and are left alone. Relative paths are
+    /// resolved against the workflow directory (see Workflow::workflow_dir).
+    ///
+    pub fn replace_missing_icons(&mut self, fallback: Icon) {
+        self.icon_fallback = Some(fallback);
+    }
+
+    /// Replaces missing item and modifier icons with the fallback icon, if
+    /// one was configured. Called automatically when the workflow finishes.
+    pub(crate) fn check_icons(&mut self) {
+        let Some(fallback) = self.icon_fallback.clone() else {
+            return;
+        };
+        let workflow_dir = self.workflow_dir.clone();
+        // Items frequently share an icon, so only stat each path once
+        let mut exists: HashMap<String, bool> = HashMap::new();
+        let mut check = |icon: &mut Option<Icon>| {
+            let Some(current) = icon else {
+                return;
+            };
+            if current.type_.as_deref() == Some("filetype") {
+                return;
+            }
+            let found = *exists
+                .entry(current.path.clone())
+                .or_insert_with(|| workflow_dir.join(&current.path).exists());
+            if !found {
+                warn!("icon {:?} does not exist, using fallback", current.path);
+                *icon = Some(fallback.clone());
+            }
+        };
+
+        for item in &mut self.response.items {
+            check(&mut item.icon);
+            for modifier in item.modifiers.values_mut() {
+                check(&mut modifier.icon);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Item, Key, Modifier, ICON_ALERT_NOTE};
+
+    #[test]
+    fn test_missing_icons_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let present = dir.path().join("icon.png");
+        std::fs::write(&present, "").unwrap();
+        let missing = dir.path().join("missing.png");
+        let present = present.to_string_lossy().to_string();
+        let missing = missing.to_string_lossy().to_string();
+
+        let mut workflow = crate::test_support::workflow_in(dir.path());
+        workflow.items(vec![
+            Item::new("Present").icon_from_image(&present),
+            Item::new("Missing")
+                .icon_from_image(&missing)
+                .modifier(Modifier::new(Key::Cmd).icon_from_image(&missing)),
+            Item::new("Missing file").icon_for_file(&missing),
+            Item::new("Filetype").icon_for_filetype("public.folder"),
+            Item::new("No icon"),
+        ]);
+        workflow.replace_missing_icons(ICON_ALERT_NOTE.into());
+        workflow.check_icons();
+
+        let fallback = Some(Icon::from(ICON_ALERT_NOTE));
+        let items = &workflow.response.items;
+        assert_eq!(items[0].icon, Some(Icon::from(present)));
+        assert_eq!(items[1].icon, fallback);
+        assert_eq!(items[1].modifiers["cmd"].icon, fallback);
+        assert_eq!(items[2].icon, fallback);
+        assert_eq!(
+            items[3].icon,
+            Some(
+                Item::new("")
+                    .icon_for_filetype("public.folder")
+                    .icon
+                    .unwrap()
+            )
+        );
+        assert_eq!(items[4].icon, None);
+    }
+
+    #[test]
+    fn test_relative_icons_resolved_against_workflow_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("icons")).unwrap();
+        std::fs::write(dir.path().join("icons/star.png"), "").unwrap();
+
+        let mut workflow = crate::test_support::workflow_in(dir.path());
+        workflow.set_workflow_dir(dir.path());
+        workflow.append_item(Item::new("Star").icon_from_image("icons/star.png"));
+        workflow.append_item(Item::new("Moon").icon_from_image("icons/moon.png"));
+        workflow.replace_missing_icons(ICON_ALERT_NOTE.into());
+        workflow.check_icons();
+
+        let items = &workflow.response.items;
+        assert_eq!(items[0].icon, Some(Icon::from("icons/star.png")));
+        assert_eq!(items[1].icon, Some(Icon::from(ICON_ALERT_NOTE)));
+    }
+
+    #[test]
+    fn test_icons_unchecked_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = crate::test_support::workflow_in(dir.path());
+        workflow.append_item(Item::new("Missing").icon_from_image("/no/such/icon.png"));
+        workflow.check_icons();
+        assert_eq!(
+            workflow.response.items[0].icon,
+            Some(Icon::from("/no/such/icon.png"))
+        );
+    }
+}
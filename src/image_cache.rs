@@ -0,0 +1,305 @@
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use log::{debug, warn};
+
+use crate::memoize::{fnv1a, write_atomically};
+use crate::workflow::Workflow;
+use crate::Result;
+
+/// The default size limit of an ImageCache, in megabytes.
+pub const DEFAULT_MAX_MEGABYTES: u64 = 50;
+
+/// ImageCache stores downloaded images (thumbnails, avatars, album art) on
+/// disk so items can use them as icons without fetching them on every
+/// keystroke. Files are named after a hash of their URL and the cache is
+/// kept under a size limit by evicting the least recently used files.
+///
+/// ImageCache doesn't download anything itself; callers provide the bytes
+/// with whichever HTTP client they already use.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl ImageCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        ImageCache {
+            dir: dir.into(),
+            max_bytes: DEFAULT_MAX_MEGABYTES * 1024 * 1024,
+        }
+    }
+
+    pub fn max_megabytes(self, megabytes: u64) -> Self {
+        self.max_bytes(megabytes * 1024 * 1024)
+    }
+
+    pub fn max_bytes(mut self, bytes: u64) -> Self {
+        self.max_bytes = bytes;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The path the image for `url` is (or would be) stored at. The URL's
+    /// file extension is kept so Alfred can recognize the image type.
+    pub fn path_for(&self, url: &str) -> PathBuf {
+        let name = format!("{:016x}", fnv1a(&[url]));
+        match extension(url) {
+            Some(ext) => self.dir.join(format!("{}.{}", name, ext)),
+            None => self.dir.join(name),
+        }
+    }
+
+    /// Returns the path to the cached image for `url`, if any, and marks
+    /// it as recently used.
+    pub fn get(&self, url: &str) -> Option<PathBuf> {
+        let path = self.path_for(url);
+        if !path.is_file() {
+            return None;
+        }
+        if let Err(e) = touch(&path) {
+            warn!("unable to touch cached image {}: {}", path.display(), e);
+        }
+        Some(path)
+    }
+
+    /// Stores `bytes` as the image for `url`, then evicts older images if
+    /// the cache is over its size limit. Failing to evict is logged, not
+    /// returned, since the image itself was stored.
+    pub fn insert(&self, url: &str, bytes: &[u8]) -> Result<PathBuf> {
+        let path = self.path_for(url);
+        write_atomically(&path, bytes)?;
+        if let Err(e) = self.evict() {
+            warn!("unable to evict cached images: {}", e);
+        }
+        Ok(path)
+    }
+
+    /// Returns the cached image for `url`, calling `fetch` to download it
+    /// on a miss. Errors from `fetch` are returned and nothing is cached.
+    pub fn get_or_fetch<F>(&self, url: &str, fetch: F) -> Result<PathBuf>
+    where
+        F: FnOnce(&str) -> Result<Vec<u8>>,
+    {
+        if let Some(path) = self.get(url) {
+            return Ok(path);
+        }
+        debug!("image cache miss for {}", url);
+        let bytes = fetch(url)?;
+        self.insert(url, &bytes)
+    }
+
+    /// The total size of the cached images, in bytes.
+    pub fn size(&self) -> Result<u64> {
+        Ok(self.entries()?.iter().map(|entry| entry.1).sum())
+    }
+
+    /// Removes the least recently used images until the cache fits within
+    /// its size limit. Returns the number of bytes removed.
+    pub fn evict(&self) -> Result<u64> {
+        let mut entries = self.entries()?;
+        let mut total: u64 = entries.iter().map(|entry| entry.1).sum();
+        if total <= self.max_bytes {
+            return Ok(0);
+        }
+
+        entries.sort_by_key(|entry| entry.2);
+        let mut removed = 0;
+        for (path, size, _) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            debug!("evicting cached image {}", path.display());
+            remove(&path)?;
+            total -= size;
+            removed += size;
+        }
+        Ok(removed)
+    }
+
+    /// Removes every cached image.
+    pub fn clear(&self) -> Result<()> {
+        for (path, _, _) in self.entries()? {
+            remove(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Lists the cached images. Other executions may be writing or
+    /// evicting at the same time, so in-progress writes (hidden temporary
+    /// files) and entries that can't be read are skipped.
+    fn entries(&self) -> Result<Vec<(PathBuf, u64, SystemTime)>> {
+        let dir = match fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries = Vec::new();
+        for entry in dir.flatten() {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            entries.push((entry.path(), metadata.len(), modified));
+        }
+        Ok(entries)
+    }
+}
+
+impl Workflow {
+    /// Returns an ImageCache stored in the workflow's cache directory,
+    /// limited to DEFAULT_MAX_MEGABYTES. Use ImageCache::max_megabytes on
+    /// the result to change the limit.
+    pub fn image_cache(&self) -> ImageCache {
+        ImageCache::new(self.reserved_cache_dir("images"))
+    }
+}
+
+/// Removes a cached image, ignoring one another execution already removed.
+fn remove(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the short alphanumeric file extension of the URL's path, if any.
+fn extension(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next()?;
+    let name = path.rsplit('/').next()?;
+    let (_, ext) = name.rsplit_once('.')?;
+    let valid = (1..=5).contains(&ext.len()) && ext.chars().all(|c| c.is_ascii_alphanumeric());
+    valid.then(|| ext.to_ascii_lowercase())
+}
+
+fn touch(path: &Path) -> std::io::Result<()> {
+    File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::Error;
+
+    fn set_mtime(path: &Path, seconds_ago: u64) {
+        let time = SystemTime::now() - Duration::from_secs(seconds_ago);
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_path_for() {
+        let cache = ImageCache::new("/tmp/images");
+        let path = cache.path_for("https://example.com/a/cover.JPG?size=64");
+        assert_eq!(path.extension().unwrap(), "jpg");
+        assert_eq!(
+            path,
+            cache.path_for("https://example.com/a/cover.JPG?size=64")
+        );
+        assert_ne!(
+            path,
+            cache.path_for("https://example.com/a/cover.JPG?size=128")
+        );
+        assert_eq!(
+            cache.path_for("https://example.com/avatar").extension(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_get_or_fetch() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ImageCache::new(dir.path());
+        let calls = Cell::new(0);
+        let fetch = |_: &str| {
+            calls.set(calls.get() + 1);
+            Ok(vec![1, 2, 3])
+        };
+
+        let url = "https://example.com/a.png";
+        let path = cache.get_or_fetch(url, fetch).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), vec![1, 2, 3]);
+        assert_eq!(cache.get_or_fetch(url, fetch).unwrap(), path);
+        assert_eq!(calls.get(), 1);
+
+        let result = cache.get_or_fetch("https://example.com/b.png", |_| {
+            Err(Error::Workflow("offline".to_string()))
+        });
+        assert!(result.is_err());
+        assert_eq!(cache.size().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ImageCache::new(dir.path()).max_bytes(25);
+
+        let a = cache.insert("https://example.com/a.png", &[0; 10]).unwrap();
+        let b = cache.insert("https://example.com/b.png", &[0; 10]).unwrap();
+        set_mtime(&a, 60);
+        set_mtime(&b, 30);
+
+        // Using a makes b the least recently used
+        assert_eq!(cache.get("https://example.com/a.png"), Some(a.clone()));
+        let c = cache.insert("https://example.com/c.png", &[0; 10]).unwrap();
+
+        assert!(a.exists());
+        assert!(!b.exists());
+        assert!(c.exists());
+        assert_eq!(cache.size().unwrap(), 20);
+    }
+
+    #[test]
+    fn test_concurrent_inserts() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ImageCache::new(dir.path()).max_bytes(15);
+        std::thread::scope(|scope| {
+            for i in 0..8u8 {
+                let cache = &cache;
+                scope.spawn(move || {
+                    for j in 0..20 {
+                        let url = format!("https://example.com/{}.png", j % 3);
+                        cache.insert(&url, &[i; 10]).unwrap();
+                    }
+                });
+            }
+        });
+        assert!(cache.size().unwrap() <= 15);
+        let names: Vec<_> = fs::read_dir(dir.path()).unwrap().flatten().collect();
+        // No temporary files are left behind
+        assert!(names.len() <= 1, "{:?}", names);
+    }
+
+    #[test]
+    fn test_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ImageCache::new(dir.path().join("images"));
+        assert_eq!(cache.size().unwrap(), 0);
+        cache.insert("https://example.com/a.png", &[0; 4]).unwrap();
+        cache.clear().unwrap();
+        assert_eq!(cache.size().unwrap(), 0);
+    }
+}
@@ -0,0 +1,599 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use log::{debug, error, warn};
+
+use crate::clipboard::{
+    copy_html_table_to_clipboard, copy_markdown_image_to_clipboard,
+    copy_markdown_link_to_clipboard, copy_org_link_to_clipboard, copy_rich_text_link_rtf,
+    copy_rich_text_link_to_clipboard, copy_text_to_clipboard, format_html_link,
+    format_title_and_url, paste_to_frontmost,
+};
+use crate::config::EnvMap;
+use crate::secrets::{KeychainSecretStore, SecretStore};
+use crate::system::SystemActions;
+use crate::{Item, Modifier, Response, Result};
+
+/// The variable carrying alfrusco's internal commands: a JSON object with
+/// the command's name and its arguments, such as
+/// `{"command":"copy","text":"abc"}`. Keeping them in one namespaced
+/// variable stops the arguments colliding with the workflow's own
+/// variables. See InternalCommand.
+pub const INTERNAL_VAR: &str = "ALFRUSCO_INTERNAL";
+
+/// The variables earlier alfrusco versions set for internal commands:
+/// ALFRUSCO_COMMAND for the command and one variable per argument, named
+/// in uppercase. With the `legacy-vars` feature (on by default) they are
+/// still handled when INTERNAL_VAR isn't set, so workflows shouldn't use
+/// these names for their own variables.
+pub const LEGACY_INTERNAL_VARS: &[&str] = &[
+    "ALFRUSCO_COMMAND",
+    "TITLE",
+    "URL",
+    "TEXT",
+    "TABLE",
+    "OPEN_PATH",
+    "OPEN_WITH",
+    "OPEN_WITH_ARGS",
+    "RICHTEXT_FORMAT",
+    "SECRET_NAME",
+    "SECRET_VALUE",
+    "RESET_TOKEN",
+];
+
+/// InternalCommand is a request for one of alfrusco's internal commands
+/// (see the list below), performed when the workflow binary is run again
+/// by a Run Script action connected to the Script Filter. Attach it to an
+/// item or modifier with Item::internal_command or
+/// Modifier::internal_command.
+///
+/// Commands and their arguments:
+///
+/// - `markdown` / `richtext` / `org` / `html`: copy a link built from
+///   `title` and `url`. `richtext` copies HTML, or RTF when
+///   `richtext_format` is "rtf"
+/// - `url` / `title_url`: copy `url`, or "title — url", as plain text
+/// - `copy`: copy `text` as plain text
+/// - `markdown_image`: copy a Markdown image with `title` as the alt text
+/// - `html_table`: copy `table` (a JSON array of string arrays) as rich
+///   text
+/// - `open_url`: open `url` in its default handler
+/// - `open_with`: open `url` in the application whose bundle id is
+///   `open_with`, passing it the args in `open_with_args` (a JSON array of
+///   strings) if set
+/// - `open_path`: open the file or directory `open_path`
+/// - `paste`: paste `text` into the frontmost application
+/// - `set_secret`: save `secret_value` as `secret_name` in the workflow's
+///   Keychain secrets
+/// - `reset`: delete the workflow's data, given its bundle id as
+///   `reset_token` (see Workflow::purge_all_data)
+///
+/// ```
+/// use alfrusco::{InternalCommand, Item};
+///
+/// let item = Item::new("Copy the build number")
+///     .internal_command(InternalCommand::new("copy").arg("text", "2257"));
+/// ```
+///
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct InternalCommand {
+    command: String,
+    args: BTreeMap<String, String>,
+}
+
+/// The arguments holding secrets, whose values are redacted when an
+/// InternalCommand (or an EnvMap carrying one) is debug-printed.
+const SECRET_ARGS: &[&str] = &["secret_value"];
+
+impl std::fmt::Debug for InternalCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let args: BTreeMap<&str, &str> = self
+            .args
+            .iter()
+            .map(|(name, value)| {
+                let value = if SECRET_ARGS.contains(&name.as_str()) {
+                    "<redacted>"
+                } else {
+                    value.as_str()
+                };
+                (name.as_str(), value)
+            })
+            .collect();
+        f.debug_struct("InternalCommand")
+            .field("command", &self.command)
+            .field("args", &args)
+            .finish()
+    }
+}
+
+impl InternalCommand {
+    pub fn new(command: impl Into<String>) -> Self {
+        InternalCommand {
+            command: command.into(),
+            args: BTreeMap::new(),
+        }
+    }
+
+    pub fn arg(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.args.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.args.get(name).map(String::as_str)
+    }
+
+    /// Returns true if the command carries a secret, such as set_secret's
+    /// value.
+    pub(crate) fn has_secret(&self) -> bool {
+        SECRET_ARGS.iter().any(|name| self.args.contains_key(*name))
+    }
+
+    /// Returns the value of INTERNAL_VAR for this command.
+    pub fn to_json(&self) -> String {
+        command_json(
+            &self.command,
+            self.args
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        )
+    }
+
+    /// Reads the command from INTERNAL_VAR in `env`, or from the legacy
+    /// variables when the `legacy-vars` feature is on. Returns None when
+    /// there's no command, or INTERNAL_VAR can't be parsed.
+    pub fn from_env(env: &EnvMap) -> Option<InternalCommand> {
+        let Some(json) = env.get(INTERNAL_VAR) else {
+            return Self::from_legacy_env(env);
+        };
+        match serde_json::from_str(json) {
+            Ok(args) => Self::from_args(args),
+            Err(e) => {
+                warn!("ignoring invalid {}: {}", INTERNAL_VAR, e);
+                None
+            }
+        }
+    }
+
+    /// Parses a value of INTERNAL_VAR, returning None if it's invalid.
+    pub(crate) fn parse_json(json: &str) -> Option<InternalCommand> {
+        Self::from_args(serde_json::from_str(json).ok()?)
+    }
+
+    fn from_args(mut args: BTreeMap<String, String>) -> Option<InternalCommand> {
+        let command = args.remove("command")?;
+        Some(InternalCommand { command, args })
+    }
+
+    #[cfg(feature = "legacy-vars")]
+    fn from_legacy_env(env: &EnvMap) -> Option<InternalCommand> {
+        let (command, names) = LEGACY_INTERNAL_VARS.split_first()?;
+        let command = InternalCommand::new(env.get(command)?);
+        Some(
+            names
+                .iter()
+                .fold(command, |command, name| match env.get(name) {
+                    Some(value) => command.arg(name.to_lowercase(), value),
+                    None => command,
+                }),
+        )
+    }
+
+    #[cfg(not(feature = "legacy-vars"))]
+    fn from_legacy_env(_env: &EnvMap) -> Option<InternalCommand> {
+        None
+    }
+}
+
+/// Returns the value of INTERNAL_VAR for the command and arguments,
+/// serialized straight from the borrowed strings.
+fn command_json<'a, I>(command: &str, args: I) -> String
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+    I::IntoIter: Clone,
+{
+    use serde::ser::{SerializeMap, Serializer};
+
+    let args = args.into_iter();
+    // Room for the strings plus their quotes and separators, so the buffer
+    // is allocated once for typical (unescaped) values
+    let len = args
+        .clone()
+        .map(|(name, value)| name.len() + value.len() + 6)
+        .sum::<usize>();
+    let mut json = Vec::with_capacity(command.len() + len + 16);
+    let mut serializer = serde_json::Serializer::new(&mut json);
+    // Writing to a Vec can't fail
+    let mut map = serializer.serialize_map(None).unwrap();
+    map.serialize_entry("command", command).unwrap();
+    for (name, value) in args {
+        if name != "command" {
+            map.serialize_entry(name, value).unwrap();
+        }
+    }
+    map.end().unwrap();
+    String::from_utf8(json).expect("serde_json writes UTF-8")
+}
+
+impl Item {
+    /// Attaches an internal command, performed when the item is actioned.
+    /// Makes the item valid.
+    pub fn internal_command(self, command: InternalCommand) -> Self {
+        self.var(INTERNAL_VAR, command.to_json()).valid(true)
+    }
+}
+
+impl Modifier {
+    /// Attaches an internal command, performed when the item is actioned
+    /// with this modifier held. Makes the modifier valid.
+    pub fn internal_command(self, command: InternalCommand) -> Self {
+        self.var(INTERNAL_VAR, command.to_json()).valid(true)
+    }
+
+    /// Like internal_command, but borrowing the arguments rather than
+    /// copying them into an InternalCommand first. URLItem uses this for
+    /// the modifiers it adds to every item.
+    pub(crate) fn internal_command_with(self, command: &str, args: &[(&str, &str)]) -> Self {
+        self.var(INTERNAL_VAR, command_json(command, args.iter().copied()))
+            .valid(true)
+    }
+}
+
+/// Checks the environment snapshot for an internal command (see
+/// InternalCommand) and, if one is present and recognized, performs it
+/// and exits the process with an empty response.
+///
+/// The `reset` command needs the workflow's directories, so it's handled
+/// once the workflow is set up.
+///
+pub fn handle(system: &dyn SystemActions, env: &EnvMap) {
+    if perform(system, env) {
+        Response::new().write(std::io::stdout()).unwrap();
+        std::process::exit(0);
+    }
+}
+
+/// Performs the internal command in `env`, if there is a recognized one,
+/// and returns whether it did. Failures are logged.
+pub(crate) fn perform(system: &dyn SystemActions, env: &EnvMap) -> bool {
+    let Some(internal) = InternalCommand::from_env(env) else {
+        return false;
+    };
+    let cmd = internal.command();
+    let var = |name: &str| internal.get(name).map(str::to_string);
+    debug!(
+        "internal command '{}' provided. Alfrusco will handle this request",
+        cmd
+    );
+
+    let result = match cmd {
+        "richtext" | "markdown" | "markdown_image" | "org" | "html" | "url" | "title_url" => {
+            match (var("title"), var("url")) {
+                (Some(title), Some(url)) => {
+                    let rtf = internal.get("richtext_format") == Some("rtf");
+                    Some(copy_link(system, cmd, title, url, rtf))
+                }
+                _ => None,
+            }
+        }
+        "copy" => var("text").map(|text| {
+            copy_text_to_clipboard(text);
+            Ok(())
+        }),
+        "html_table" => var("table").map(|table| {
+            let rows: Vec<Vec<String>> = serde_json::from_str(&table)?;
+            copy_html_table_to_clipboard(system, rows)
+        }),
+        "open_url" => var("url").map(|url| open_url(system, &url)),
+        "open_with" => match (var("url"), var("open_with")) {
+            (Some(url), Some(bundle_id)) => Some(open_url_with(
+                system,
+                &url,
+                &bundle_id,
+                internal.get("open_with_args"),
+            )),
+            _ => None,
+        },
+        "open_path" => var("open_path").map(|path| open_path(system, Path::new(&path))),
+        "paste" => var("text").map(|text| paste_to_frontmost(system, text)),
+        "set_secret" => match (
+            env.get("alfred_workflow_bundleid"),
+            var("secret_name"),
+            var("secret_value"),
+        ) {
+            (Some(service), Some(name), Some(value)) => {
+                Some(KeychainSecretStore::new(service).set(&name, &value))
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+
+    match result {
+        Some(result) => {
+            if let Err(e) = result {
+                error!("internal command '{}' failed: {}", cmd, e);
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+fn copy_link(
+    system: &dyn SystemActions,
+    cmd: &str,
+    title: String,
+    url: String,
+    rtf: bool,
+) -> Result<()> {
+    match cmd {
+        "richtext" if rtf => copy_rich_text_link_rtf(system, title, url)?,
+        "richtext" => copy_rich_text_link_to_clipboard(system, title, url)?,
+        "markdown_image" => copy_markdown_image_to_clipboard(title, url),
+        "org" => copy_org_link_to_clipboard(title, url),
+        "html" => copy_text_to_clipboard(format_html_link(&title, &url)),
+        "url" => copy_text_to_clipboard(url),
+        "title_url" => copy_text_to_clipboard(format_title_and_url(&title, &url)),
+        _ => copy_markdown_link_to_clipboard(title, url),
+    }
+    Ok(())
+}
+
+pub fn open_path(system: &dyn SystemActions, path: &Path) -> Result<()> {
+    debug!("opening path {}", path.display());
+    system.open_path(path)
+}
+
+pub fn open_url(system: &dyn SystemActions, url: &str) -> Result<()> {
+    debug!("opening URL {}", url);
+    system.open_url(url)
+}
+
+fn open_url_with(
+    system: &dyn SystemActions,
+    url: &str,
+    bundle_id: &str,
+    args: Option<&str>,
+) -> Result<()> {
+    let args: Vec<String> = match args {
+        Some(args) => serde_json::from_str(args)?,
+        None => Vec::new(),
+    };
+    debug!("opening URL {} with {} {:?}", url, bundle_id, args);
+    system.open_url_with(url, bundle_id, &args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ConfigProvider, TestingProvider, WorkflowConfig};
+    use crate::system::{MockSystemActions, SystemCall};
+
+    #[test]
+    fn test_handle_ignores_process_env() {
+        let mock = MockSystemActions::new();
+        temp_env::with_var("ALFRUSCO_COMMAND", Some("unknown"), || {
+            // Only the provided snapshot is consulted, and it is empty
+            handle(&mock, &EnvMap::new());
+        });
+        assert!(mock.calls().is_empty());
+    }
+
+    #[test]
+    fn test_execute_uses_the_providers_system_actions() {
+        struct MockProvider(TestingProvider, MockSystemActions);
+
+        impl ConfigProvider for MockProvider {
+            fn config(&self) -> Result<WorkflowConfig> {
+                let mut config = self.0.config()?;
+                let command = InternalCommand::new("open_url").arg("url", "https://crates.io/");
+                config.env.set(INTERNAL_VAR, command.to_json());
+                Ok(config)
+            }
+
+            fn auto_logging(&self) -> bool {
+                false
+            }
+
+            fn system_actions(&self) -> Box<dyn SystemActions> {
+                Box::new(self.1.clone())
+            }
+        }
+
+        struct Unreachable;
+
+        impl crate::Runnable for Unreachable {
+            type Error = crate::Error;
+
+            fn run(self, _: &mut crate::Workflow) -> Result<()> {
+                panic!("the internal command should have been handled");
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let mock = MockSystemActions::new();
+        let provider = MockProvider(TestingProvider(dir.path().to_path_buf()), mock.clone());
+        let mut buffer = Vec::new();
+        crate::execute(&provider, Unreachable, &mut buffer);
+        assert_eq!(
+            mock.calls(),
+            vec![SystemCall::OpenUrl("https://crates.io/".to_string())]
+        );
+        assert_eq!(buffer, br#"{"items":[]}"#);
+    }
+
+    #[test]
+    fn test_internal_command_round_trip() {
+        let command = InternalCommand::new("markdown")
+            .arg("title", "Rust \"lang\"")
+            .arg("url", "https://www.rust-lang.org/");
+        let json = command.to_json();
+        assert_eq!(
+            json,
+            r#"{"command":"markdown","title":"Rust \"lang\"","url":"https://www.rust-lang.org/"}"#
+        );
+        let env: EnvMap = [(INTERNAL_VAR, json.as_str())].into_iter().collect();
+        assert_eq!(InternalCommand::from_env(&env), Some(command.clone()));
+
+        let borrowed = Modifier::new(crate::Key::Cmd).internal_command_with(
+            "markdown",
+            &[
+                ("title", "Rust \"lang\""),
+                ("url", "https://www.rust-lang.org/"),
+            ],
+        );
+        assert_eq!(
+            borrowed,
+            Modifier::new(crate::Key::Cmd).internal_command(command)
+        );
+
+        for invalid in ["{}", "[1]", r#"{"command":"copy","text":1}"#] {
+            let env: EnvMap = [(INTERNAL_VAR, invalid)].into_iter().collect();
+            assert_eq!(InternalCommand::from_env(&env), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_debug_redacts_secrets() {
+        let command = InternalCommand::new("set_secret")
+            .arg("secret_name", "api_token")
+            .arg("secret_value", "hunter2");
+        let debug = format!("{:?}", command);
+        assert!(debug.contains("api_token"), "{}", debug);
+        assert!(!debug.contains("hunter2"), "{}", debug);
+
+        let env: EnvMap = [
+            (INTERNAL_VAR, command.to_json().as_str()),
+            ("SECRET_VALUE", "hunter3"),
+            ("OTHER", "visible"),
+        ]
+        .into_iter()
+        .collect();
+        let debug = format!("{:?}", env);
+        assert!(!debug.contains("hunter"), "{}", debug);
+        assert!(debug.contains("visible"), "{}", debug);
+
+        // Commands without secrets are shown as is
+        let env: EnvMap = [(
+            INTERNAL_VAR,
+            InternalCommand::new("paste").to_json().as_str(),
+        )]
+        .into_iter()
+        .collect();
+        assert!(format!("{:?}", env).contains("paste"));
+    }
+
+    #[test]
+    fn test_internal_command_legacy_vars() {
+        let env: EnvMap = [
+            ("ALFRUSCO_COMMAND", "copy"),
+            ("TEXT", "abc"),
+            ("OTHER", "x"),
+        ]
+        .into_iter()
+        .collect();
+        let command = InternalCommand::from_env(&env);
+        if cfg!(feature = "legacy-vars") {
+            assert_eq!(
+                command,
+                Some(InternalCommand::new("copy").arg("text", "abc"))
+            );
+        } else {
+            assert_eq!(command, None);
+        }
+
+        // The namespaced variable wins
+        let mut env = env;
+        env.set(INTERNAL_VAR, InternalCommand::new("paste").to_json());
+        assert_eq!(InternalCommand::from_env(&env).unwrap().command(), "paste");
+    }
+
+    #[test]
+    fn test_open_path() {
+        let mock = MockSystemActions::new();
+        open_path(&mock, Path::new("/Applications")).unwrap();
+        assert_eq!(
+            mock.calls(),
+            vec![SystemCall::OpenPath("/Applications".into())]
+        );
+    }
+
+    #[test]
+    fn test_open_url() {
+        let mock = MockSystemActions::new();
+        open_url(&mock, "https://crates.io/").unwrap();
+        assert_eq!(
+            mock.calls(),
+            vec![SystemCall::OpenUrl("https://crates.io/".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_open_url_with() {
+        let mock = MockSystemActions::new();
+        open_url_with(&mock, "https://crates.io/", "org.mozilla.firefox", None).unwrap();
+        open_url_with(
+            &mock,
+            "https://crates.io/",
+            "com.google.Chrome",
+            Some(r#"["--profile-directory=Profile 1"]"#),
+        )
+        .unwrap();
+        assert!(
+            open_url_with(&mock, "https://crates.io/", "com.google.Chrome", Some("[")).is_err()
+        );
+        assert_eq!(
+            mock.calls(),
+            vec![
+                SystemCall::OpenUrlWith(
+                    "https://crates.io/".to_string(),
+                    "org.mozilla.firefox".to_string(),
+                    Vec::new()
+                ),
+                SystemCall::OpenUrlWith(
+                    "https://crates.io/".to_string(),
+                    "com.google.Chrome".to_string(),
+                    vec!["--profile-directory=Profile 1".to_string()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rich_text_runs_osascript() {
+        let mock = MockSystemActions::new();
+        copy_rich_text_link_to_clipboard(&mock, "Rust", "https://www.rust-lang.org/").unwrap();
+        match mock.calls().as_slice() {
+            [SystemCall::RunOsascript(script)] => {
+                assert!(script.starts_with("set the clipboard to"));
+                assert!(script.contains(&hex::encode("<a href=\"https://www.rust-lang.org/\">")));
+            }
+            calls => panic!("unexpected calls: {:?}", calls),
+        }
+    }
+
+    #[test]
+    fn test_rich_text_as_rtf() {
+        let mock = MockSystemActions::new();
+        copy_link(
+            &mock,
+            "richtext",
+            "Rust".to_string(),
+            "https://www.rust-lang.org/".to_string(),
+            true,
+        )
+        .unwrap();
+        match mock.calls().as_slice() {
+            [SystemCall::RunOsascript(script)] => {
+                assert!(script.starts_with("set the clipboard to {text:\"Rust\", «class RTF »"));
+                assert!(script.contains(&hex::encode("HYPERLINK \"https://www.rust-lang.org/\"")));
+            }
+            calls => panic!("unexpected calls: {:?}", calls),
+        }
+    }
+}
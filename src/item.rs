@@ -1,39 +1,24 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-use fuzzy_matcher::skim::SkimMatcherV2;
-use fuzzy_matcher::FuzzyMatcher;
-use serde::Serialize;
+use log::warn;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::internal_handlers::InternalCommand;
 
 mod arg;
+mod command;
 pub mod icon;
+mod item_type;
 mod modifiers;
 mod text;
 
 pub use arg::Arg;
+pub use command::command_lines;
 pub use icon::Icon;
+pub use item_type::ItemType;
 pub use modifiers::{Key, Modifier};
 pub use text::Text;
 
-pub fn filter_and_sort_items(items: Vec<Item>, query: String) -> Vec<Item> {
-    let matcher = SkimMatcherV2::default();
-
-    let mut filtered_items: Vec<(Item, i64)> = items
-        .into_iter()
-        .filter_map(|item| {
-            let subtitle = item.subtitle.as_deref().unwrap_or_default();
-            let combined = format!("{} : {}", subtitle, item.title);
-            matcher
-                .fuzzy_match(&combined, &query)
-                .map(|score| (item, score))
-        })
-        .collect();
-
-    // Sort by score in descending order
-    filtered_items.sort_unstable_by(|a, b| b.1.cmp(&a.1));
-
-    filtered_items.into_iter().map(|(item, _)| item).collect()
-}
-
 /// Item represents a single choice in the Alfred selection UI.
 ///
 /// The fields here are designed around the Script Filter JSON format defined
@@ -46,8 +31,13 @@ pub fn filter_and_sort_items(items: Vec<Item>, query: String) -> Vec<Item> {
 /// Builder functions are provided for each field to allow for easy
 /// specification of each field.
 ///
+/// Items can also be deserialized from Script Filter JSON. Settings that
+/// only affect alfrusco's own processing (sticky, search weight and so
+/// on) aren't part of the JSON, so they take their defaults.
+///
 #[non_exhaustive]
-#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Item {
     pub(crate) title: String,
 
@@ -66,13 +56,20 @@ pub struct Item {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) icon: Option<Icon>,
 
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub(crate) item_type: Option<ItemType>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) valid: Option<bool>,
 
     #[serde(rename = "match", skip_serializing_if = "Option::is_none")]
     pub(crate) r#match: Option<String>,
 
-    #[serde(rename = "mods", skip_serializing_if = "HashMap::is_empty")]
+    #[serde(
+        rename = "mods",
+        skip_serializing_if = "HashMap::is_empty",
+        deserialize_with = "deserialize_modifiers"
+    )]
     pub(crate) modifiers: HashMap<String, Modifier>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -84,8 +81,78 @@ pub struct Item {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) text: Option<Text>,
 
-    #[serde(skip_serializing)]
-    pub(crate) sticky: bool,
+    /// Extra keys set with Item::raw_field, written after the typed ones
+    #[serde(flatten)]
+    pub(crate) raw_fields: BTreeMap<String, serde_json::Value>,
+
+    /// The sticky priority of the item, if it is sticky. See
+    /// filter_and_sort_items for how this affects ordering.
+    #[serde(skip)]
+    pub(crate) sticky: Option<u8>,
+
+    #[serde(skip)]
+    pub(crate) search_weight: SearchWeight,
+
+    /// When true, the workflow keyword is prefixed to autocomplete when
+    /// the response is written. See Item::autocomplete_query.
+    #[serde(skip)]
+    pub(crate) autocomplete_keyword: bool,
+
+    /// Set by Item::bufferable, so validation can check the item stays
+    /// usable in Alfred's buffer
+    #[serde(skip)]
+    pub(crate) bufferable: bool,
+}
+
+/// The JSON keys written from Item's typed fields, which raw_field can't
+/// override.
+const ITEM_KEYS: &[&str] = &[
+    "title",
+    "subtitle",
+    "uid",
+    "arg",
+    "variables",
+    "icon",
+    "type",
+    "valid",
+    "match",
+    "mods",
+    "autocomplete",
+    "quicklookurl",
+    "text",
+];
+
+/// Deserializes the mods map, restoring each Modifier's keys from its key
+/// in the map.
+fn deserialize_modifiers<'de, D>(deserializer: D) -> Result<HashMap<String, Modifier>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut modifiers = HashMap::<String, Modifier>::deserialize(deserializer)?;
+    for (keys, modifier) in modifiers.iter_mut() {
+        modifier.keys = keys.clone();
+    }
+    Ok(modifiers)
+}
+
+/// SearchWeight is a multiplier applied to an Item's fuzzy match score
+/// during filtering. NaN and negative weights are rejected by
+/// Item::search_weight, which makes the Eq impl sound.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SearchWeight(pub(crate) f32);
+
+impl Eq for SearchWeight {}
+
+impl Default for SearchWeight {
+    fn default() -> Self {
+        SearchWeight(1.0)
+    }
+}
+
+impl SearchWeight {
+    pub(crate) fn apply(self, score: i64) -> i64 {
+        (score as f64 * f64::from(self.0)).round() as i64
+    }
 }
 
 impl Item {
@@ -121,11 +188,64 @@ impl Item {
         self
     }
 
+    /// Makes choosing the item copy `text` to the clipboard as plain text.
+    ///
+    /// The item carries a `copy` InternalCommand, so the workflow's Script Filter
+    /// must be connected to a Run Script action invoking the workflow
+    /// binary again, which performs the copy. No other downstream objects
+    /// are needed.
+    pub fn copy_on_action(self, text: impl Into<String>) -> Self {
+        let text = text.into();
+        self.arg(text.clone())
+            .internal_command(InternalCommand::new("copy").arg("text", text))
+    }
+
     pub fn uid(mut self, uid: impl Into<String>) -> Self {
         self.uid = Some(uid.into());
         self
     }
 
+    /// Prepares the item for Alfred's buffer, which lets the user collect
+    /// several results (⌥↑ adds the selected one) and act on them
+    /// together. Buffered items must be actionable and are tracked by
+    /// uid, so this makes the item valid and, if it has no uid yet, uses
+    /// its arg (or else its title) as the uid. Set the arg first.
+    ///
+    /// ```
+    /// use alfrusco::Item;
+    ///
+    /// let item = Item::new("report.pdf")
+    ///     .subtitle("~/Documents")
+    ///     .arg("/Users/me/Documents/report.pdf")
+    ///     .bufferable();
+    /// assert_eq!(item.get_uid(), Some("/Users/me/Documents/report.pdf"));
+    /// ```
+    ///
+    /// In debug mode, validation warns about bufferable items that later
+    /// lost their uid or were made invalid.
+    pub fn bufferable(mut self) -> Self {
+        if self.uid.is_none() {
+            self.uid = Some(match &self.arg {
+                Some(Arg::One(arg)) => arg.clone(),
+                Some(Arg::Many(args)) if !args.is_empty() => args.join("\t"),
+                _ => self.title.clone(),
+            });
+        }
+        self.bufferable = true;
+        self.valid(true)
+    }
+
+    /// Removes the item's uid so Alfred won't learn from it being chosen,
+    /// keeping it in the position the workflow put it.
+    ///
+    /// Alfred only learns from items with a uid, so this only has an
+    /// effect while the response allows learning (see LearningPolicy).
+    /// With LearningPolicy::NeverLearn nothing is learned anyway.
+    pub fn no_learning(mut self) -> Self {
+        self.uid = None;
+        self
+    }
+
     pub fn valid(mut self, valid: bool) -> Self {
         self.valid = Some(valid);
         self
@@ -144,6 +264,15 @@ impl Item {
         self
     }
 
+    /// Uses the Finder icon of the file at `path` as the item's icon.
+    pub fn icon_for_file(mut self, path: impl Into<String>) -> Self {
+        self.icon = Some(Icon {
+            type_: Some("fileicon".to_string()),
+            path: path.into(),
+        });
+        self
+    }
+
     pub fn icon_from_image(mut self, path_to_image: impl Into<String>) -> Self {
         self.icon = Some(Icon {
             type_: None,
@@ -152,6 +281,11 @@ impl Item {
         self
     }
 
+    pub fn item_type(mut self, item_type: ItemType) -> Self {
+        self.item_type = Some(item_type);
+        self
+    }
+
     pub fn modifier(mut self, modifier: Modifier) -> Self {
         self.modifiers.insert(modifier.keys.clone(), modifier);
         self
@@ -159,6 +293,18 @@ impl Item {
 
     pub fn autocomplete(mut self, autocomplete: impl Into<String>) -> Self {
         self.autocomplete = Some(autocomplete.into());
+        self.autocomplete_keyword = false;
+        self
+    }
+
+    /// Sets autocomplete to `query` prefixed with the Script Filter's
+    /// keyword (alfred_workflow_keyword), so that pressing Tab keeps the
+    /// user inside the same Script Filter with `query` as its argument.
+    /// The prefix is added when the response is written; without a
+    /// keyword, `query` is used as-is.
+    pub fn autocomplete_query(mut self, query: impl Into<String>) -> Self {
+        self.autocomplete = Some(query.into());
+        self.autocomplete_keyword = true;
         self
     }
 
@@ -182,8 +328,83 @@ impl Item {
         self
     }
 
+    /// Adds an arbitrary key to the item's JSON, for Alfred features
+    /// alfrusco doesn't model yet. Setting the same key again replaces the
+    /// value. Keys written from the typed fields (title, arg, mods, ...)
+    /// can't be overridden this way: they're ignored with a warning.
+    ///
+    /// ```
+    /// use alfrusco::Item;
+    /// use serde_json::json;
+    ///
+    /// let item = Item::new("Item").raw_field("newfeature", json!({"enabled": true}));
+    /// assert_eq!(item.get_raw_field("newfeature"), Some(&json!({"enabled": true})));
+    /// ```
+    ///
+    pub fn raw_field(mut self, key: impl Into<String>, value: serde_json::Value) -> Self {
+        let key = key.into();
+        if ITEM_KEYS.contains(&key.as_str()) {
+            warn!(
+                "ignoring raw field {:?} on item {:?}: use its typed builder instead",
+                key, self.title
+            );
+            return self;
+        }
+        self.raw_fields.insert(key, value);
+        self
+    }
+
+    pub fn get_raw_field(&self, key: &str) -> Option<&serde_json::Value> {
+        self.raw_fields.get(key)
+    }
+
+    pub fn get_title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn get_subtitle(&self) -> Option<&str> {
+        self.subtitle.as_deref()
+    }
+
+    pub fn get_uid(&self) -> Option<&str> {
+        self.uid.as_deref()
+    }
+
+    pub fn get_arg(&self) -> Option<&Arg> {
+        self.arg.as_ref()
+    }
+
+    pub fn get_var(&self, key: &str) -> Option<&str> {
+        self.variables.get(key).map(String::as_str)
+    }
+
+    pub fn get_match(&self) -> Option<&str> {
+        self.r#match.as_deref()
+    }
+
+    /// Sticky items are never removed by filtering and are always shown
+    /// before filtered results. This is equivalent to
+    /// `sticky_with_priority(0)`.
     pub fn sticky(mut self, is_sticky: bool) -> Self {
-        self.sticky = is_sticky;
+        self.sticky = is_sticky.then_some(0);
+        self
+    }
+
+    /// Marks the item sticky with an explicit priority. Sticky items with a
+    /// higher priority are shown before those with a lower one.
+    pub fn sticky_with_priority(mut self, priority: u8) -> Self {
+        self.sticky = Some(priority);
+        self
+    }
+
+    /// Multiplies this item's fuzzy match score by `weight` when results
+    /// are filtered, so favorites can be boosted (> 1.0) or demoted (< 1.0)
+    /// without making them sticky. Non-matching items are still removed.
+    /// Negative and NaN weights are ignored.
+    pub fn search_weight(mut self, weight: f32) -> Self {
+        if weight >= 0.0 {
+            self.search_weight = SearchWeight(weight);
+        }
         self
     }
 }
@@ -196,6 +417,76 @@ mod tests {
     use super::*;
     use crate::ICON_TOOLBAR_FAVORITES;
 
+    #[test]
+    fn test_no_learning() {
+        let item = Item::new("Item").uid("item").no_learning();
+        assert_eq!(item.get_uid(), None);
+    }
+
+    #[test]
+    fn test_bufferable() {
+        let item = Item::new("Item").bufferable();
+        assert_eq!(item.get_uid(), Some("Item"));
+        assert_eq!(item.valid, Some(true));
+
+        let item = Item::new("Item").uid("item-1").arg("one").bufferable();
+        assert_eq!(item.get_uid(), Some("item-1"));
+
+        let item = Item::new("Item").args(["a", "b"]).bufferable();
+        assert_eq!(item.get_uid(), Some("a\tb"));
+    }
+
+    #[test]
+    fn test_sticky_false() {
+        let item = Item::new("Item").sticky(true).sticky(false);
+        assert_eq!(item.sticky, None);
+    }
+
+    #[test]
+    fn test_search_weight_ignores_invalid() {
+        let item = Item::new("Item")
+            .search_weight(-1.0)
+            .search_weight(f32::NAN);
+        assert_eq!(item.search_weight, SearchWeight::default());
+    }
+
+    #[test]
+    fn test_raw_field() {
+        let item = Item::new("Item")
+            .arg("one")
+            .raw_field("zoom", json!(2))
+            .raw_field("action", json!({"text": "hi"}))
+            .raw_field("zoom", json!(3))
+            .raw_field("arg", json!("overridden"))
+            .raw_field("title", json!("overridden"));
+        assert_eq!(
+            serde_json::to_string(&item).unwrap(),
+            r#"{"title":"Item","arg":"one","action":{"text":"hi"},"zoom":3}"#
+        );
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let item = Item::new("Item")
+            .subtitle("Subtitle")
+            .uid("item")
+            .args(["a", "b"])
+            .var("KEY", "value")
+            .icon_for_filetype("public.folder")
+            .item_type(ItemType::FileSkipCheck)
+            .valid(true)
+            .matches("item")
+            .modifier(Modifier::new(Key::Cmd).subtitle("Open"))
+            .autocomplete("it")
+            .quicklook_url("https://example.com")
+            .copy_text("copied")
+            .raw_field("future", json!([1, 2]));
+        let json = serde_json::to_string(&item).unwrap();
+        let deserialized: Item = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, item);
+        assert_eq!(deserialized.modifiers["cmd"].keys, "cmd");
+    }
+
     #[test]
     fn test_arg() {
         let item = Item::new("Item").arg("singlearg");
@@ -236,6 +527,19 @@ mod tests {
         assert_eq!(item.text.unwrap().copy, Some("www.google.com".to_string()));
     }
 
+    #[test]
+    fn test_copy_on_action() {
+        let item = Item::new("API token").copy_on_action("abc123");
+        let json = serde_json::to_value(&item).unwrap();
+        let expected = json!({
+            "title": "API token",
+            "arg": "abc123",
+            "valid": true,
+            "variables": {"ALFRUSCO_INTERNAL": r#"{"command":"copy","text":"abc123"}"#}
+        });
+        assert_eq!(json, expected);
+    }
+
     #[test]
     fn test_quicklook_url() {
         let item = Item::new("Google").quicklook_url("https://www.google.com");
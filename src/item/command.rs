@@ -0,0 +1,151 @@
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use super::Item;
+use crate::{Error, Result, WorkflowError, ICON_ALERT_STOP};
+
+impl Item {
+    /// Runs `cmd` and maps each non-empty line of its stdout to an Item,
+    /// the common shape of a workflow wrapping a command line tool.
+    ///
+    /// If the command can't be started, exits unsuccessfully, or is still
+    /// running after `timeout` (in which case it is killed), a single error
+    /// item describing the failure is returned instead.
+    ///
+    pub fn from_command_lines<F>(cmd: Command, timeout: Duration, to_item: F) -> Vec<Item>
+    where
+        F: FnMut(&str) -> Item,
+    {
+        match command_lines(cmd, timeout) {
+            Ok(lines) => lines.iter().map(String::as_str).map(to_item).collect(),
+            Err(e) => vec![e.error_item().icon(ICON_ALERT_STOP.into()).valid(false)],
+        }
+    }
+}
+
+/// Runs `cmd`, waiting at most `timeout`, and returns the non-empty lines
+/// of its stdout.
+pub fn command_lines(mut cmd: Command, timeout: Duration) -> Result<Vec<String>> {
+    let program = cmd.get_program().to_string_lossy().to_string();
+    debug!("running {:?} with a {:?} timeout", cmd, timeout);
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::Workflow(format!("unable to run {}: {}", program, e)))?;
+
+    // Drain the pipes on their own threads so a chatty command can't block
+    // on a full pipe while we wait for it to exit
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(Error::Workflow(format!(
+                "{} timed out after {}",
+                program,
+                humantime::format_duration(timeout)
+            )));
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    if !status.success() {
+        let stderr = stderr.join().unwrap_or_default();
+        let stderr = String::from_utf8_lossy(&stderr);
+        return Err(Error::Workflow(format!(
+            "{} failed ({}): {}",
+            program,
+            status,
+            stderr.trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&stdout)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+fn read_in_background<R: Read + Send + 'static>(
+    pipe: Option<R>,
+) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Command {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(script);
+        cmd
+    }
+
+    #[test]
+    fn test_from_command_lines() {
+        let items = Item::from_command_lines(
+            sh("printf 'alpha\\n\\nbeta\\n'"),
+            Duration::from_secs(5),
+            |line| Item::new(line).arg(line),
+        );
+        assert_eq!(
+            items,
+            vec![
+                Item::new("alpha").arg("alpha"),
+                Item::new("beta").arg("beta")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_command_lines_failure() {
+        let items = Item::from_command_lines(
+            sh("echo nope >&2; exit 3"),
+            Duration::from_secs(5),
+            |line| Item::new(line),
+        );
+        assert_eq!(items.len(), 1);
+        assert!(items[0].title.contains("sh failed"), "{}", items[0].title);
+        assert!(items[0].title.contains("nope"), "{}", items[0].title);
+        assert_eq!(items[0].icon, Some(ICON_ALERT_STOP.into()));
+        assert_eq!(items[0].valid, Some(false));
+    }
+
+    #[test]
+    fn test_from_command_lines_missing_program() {
+        let items = Item::from_command_lines(
+            Command::new("alfrusco-no-such-program"),
+            Duration::from_secs(5),
+            |line| Item::new(line),
+        );
+        assert_eq!(items.len(), 1);
+        assert!(items[0].title.contains("unable to run"));
+    }
+
+    #[test]
+    fn test_command_lines_timeout() {
+        let started = Instant::now();
+        let result = command_lines(sh("sleep 5"), Duration::from_millis(100));
+        assert!(result.unwrap_err().to_string().contains("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(4));
+    }
+}
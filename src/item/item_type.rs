@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+/// ItemType controls how Alfred treats an Item.
+///
+/// File items enable Alfred's file actions (e.g. the file buffer and
+/// action panel) on the item's arg, which must be a file path. Alfred
+/// checks that the file exists unless FileSkipCheck is used.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ItemType {
+    #[serde(rename = "default")]
+    Default,
+    #[serde(rename = "file")]
+    File,
+    #[serde(rename = "file:skipcheck")]
+    FileSkipCheck,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::Item;
+
+    #[test]
+    fn test_item_type() {
+        let cases = [
+            (ItemType::Default, "default"),
+            (ItemType::File, "file"),
+            (ItemType::FileSkipCheck, "file:skipcheck"),
+        ];
+        for (item_type, expected) in cases {
+            let item = Item::new("Item").item_type(item_type);
+            let json = serde_json::to_value(&item).unwrap();
+            assert_eq!(json, json!({"title": "Item", "type": expected}));
+        }
+    }
+}
@@ -2,15 +2,16 @@
 use std::collections::HashMap;
 
 // Third-party imports
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 // Local imports
-use crate::{Arg, Icon};
+use crate::{Arg, Error, Icon};
 
 /// Key represents one of the modifier Keys (Cmd, Ctrl, etc)
 ///
 /// These are used as the key in the mods object within an
 /// Alfred Item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Key {
     Cmd,
     Ctrl,
@@ -31,6 +32,22 @@ impl std::fmt::Display for Key {
     }
 }
 
+impl std::str::FromStr for Key {
+    type Err = Error;
+
+    /// Parses the key names Alfred uses in an item's mods, such as "cmd".
+    fn from_str(name: &str) -> crate::Result<Self> {
+        match name.trim() {
+            "cmd" => Ok(Key::Cmd),
+            "ctrl" => Ok(Key::Ctrl),
+            "alt" => Ok(Key::Alt),
+            "shift" => Ok(Key::Shift),
+            "fn" => Ok(Key::Fn),
+            other => Err(Error::Workflow(format!("unknown modifier key {:?}", other))),
+        }
+    }
+}
+
 /// Modifier provides a data structure to represent an item in the
 /// `mods` object within an Alfred item.
 ///
@@ -40,9 +57,11 @@ impl std::fmt::Display for Key {
 /// See more on the spec on the Alfred site:
 /// https://www.alfredapp.com/help/workflows/inputs/script-filter/json/
 ///
-#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Modifier {
-    #[serde(skip_serializing)]
+    /// The keys are written as the key of the item's mods map, and
+    /// restored from it when an Item is deserialized.
+    #[serde(skip)]
     pub keys: String,
 
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -73,12 +92,17 @@ impl Modifier {
     }
 
     pub fn new_combo(keys: &[Key]) -> Self {
+        use std::fmt::Write;
+
+        let mut combo = String::new();
+        for (i, key) in keys.iter().enumerate() {
+            if i > 0 {
+                combo.push('+');
+            }
+            let _ = write!(combo, "{}", key);
+        }
         Self {
-            keys: keys
-                .iter()
-                .map(|key| format!("{}", key))
-                .collect::<Vec<String>>()
-                .join("+"),
+            keys: combo,
             ..Self::default()
         }
     }
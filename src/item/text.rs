@@ -1,4 +1,4 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 /// Text defines the two text options (copy and largetext) for an Alfred
 /// Item.
@@ -6,7 +6,7 @@ use serde::Serialize;
 /// The copy property is the text that is copied to the clipboard when
 /// the user pressed CMD-C. The largetype property is the content displayed
 /// when the user presses CMD-L.
-#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Text {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) copy: Option<String>,
@@ -0,0 +1,215 @@
+use std::fs::{create_dir_all, remove_file, write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use log::{debug, warn};
+
+use crate::config::EnvMap;
+use crate::workflow::Workflow;
+use crate::xml::escape_text;
+use crate::{Error, Result};
+
+impl Workflow {
+    /// Installs (or replaces) a per-workflow LaunchAgent that runs the
+    /// current workflow binary with `args` every `interval`, so data can
+    /// be refreshed even while the user isn't typing.
+    ///
+    /// The agent receives the Alfred environment variables needed by
+    /// AlfredEnvProvider, and its output is logged to `launchd.log` in the
+    /// workflow cache directory. Returns the path of the installed plist.
+    ///
+    pub fn schedule_refresh(&self, interval: Duration, args: &[&str]) -> Result<PathBuf> {
+        let dir = launch_agents_dir(&self.config.env)?;
+        let path = self.install_launch_agent(&dir, interval, args)?;
+        launchctl(&["unload", &path.to_string_lossy()]).ok();
+        launchctl(&["load", "-w", &path.to_string_lossy()])?;
+        Ok(path)
+    }
+
+    /// Unloads and removes the LaunchAgent installed by schedule_refresh.
+    /// Succeeds if no agent is installed.
+    pub fn unschedule_refresh(&self) -> Result<()> {
+        let path = launch_agents_dir(&self.config.env)?
+            .join(format!("{}.plist", self.launch_agent_label()));
+        if !path.exists() {
+            return Ok(());
+        }
+        launchctl(&["unload", "-w", &path.to_string_lossy()]).ok();
+        remove_file(&path)?;
+        Ok(())
+    }
+
+    /// The launchd label used for this workflow's refresh agent.
+    pub fn launch_agent_label(&self) -> String {
+        format!("{}.alfrusco.refresh", self.config.workflow_bundleid)
+    }
+
+    pub(crate) fn install_launch_agent(
+        &self,
+        dir: &Path,
+        interval: Duration,
+        args: &[&str],
+    ) -> Result<PathBuf> {
+        create_dir_all(dir)?;
+        let exe = std::env::current_exe()?;
+        let mut program_arguments = vec![exe.to_string_lossy().to_string()];
+        program_arguments.extend(args.iter().map(|arg| arg.to_string()));
+
+        let path = dir.join(format!("{}.plist", self.launch_agent_label()));
+        write(&path, self.launch_agent_plist(&program_arguments, interval))?;
+        debug!("wrote LaunchAgent {}", path.display());
+        Ok(path)
+    }
+
+    pub(crate) fn launch_agent_plist(
+        &self,
+        program_arguments: &[String],
+        interval: Duration,
+    ) -> String {
+        let config = &self.config;
+        let log_path = config.workflow_cache.join("launchd.log");
+        let environment = [
+            ("alfred_workflow_bundleid", config.workflow_bundleid.clone()),
+            (
+                "alfred_workflow_cache",
+                config.workflow_cache.to_string_lossy().to_string(),
+            ),
+            (
+                "alfred_workflow_data",
+                config.workflow_data.to_string_lossy().to_string(),
+            ),
+            ("alfred_version", config.version.clone()),
+            ("alfred_version_build", config.version_build.clone()),
+            ("alfred_workflow_name", config.workflow_name.clone()),
+        ];
+
+        let mut plist = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+            "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+            "<plist version=\"1.0\">\n<dict>\n",
+        ));
+        plist.push_str(&format!(
+            "  <key>Label</key>\n  <string>{}</string>\n",
+            escape_text(&self.launch_agent_label())
+        ));
+        plist.push_str("  <key>ProgramArguments</key>\n  <array>\n");
+        for arg in program_arguments {
+            plist.push_str(&format!("    <string>{}</string>\n", escape_text(arg)));
+        }
+        plist.push_str("  </array>\n");
+        plist.push_str(&format!(
+            "  <key>StartInterval</key>\n  <integer>{}</integer>\n",
+            interval.as_secs().max(1)
+        ));
+        plist.push_str("  <key>EnvironmentVariables</key>\n  <dict>\n");
+        for (key, value) in environment {
+            plist.push_str(&format!(
+                "    <key>{}</key>\n    <string>{}</string>\n",
+                key,
+                escape_text(&value)
+            ));
+        }
+        plist.push_str("  </dict>\n");
+        plist.push_str(&format!(
+            "  <key>WorkingDirectory</key>\n  <string>{}</string>\n",
+            escape_text(&self.workflow_dir.to_string_lossy())
+        ));
+        for key in ["StandardOutPath", "StandardErrorPath"] {
+            plist.push_str(&format!(
+                "  <key>{}</key>\n  <string>{}</string>\n",
+                key,
+                escape_text(&log_path.to_string_lossy())
+            ));
+        }
+        plist.push_str("</dict>\n</plist>\n");
+        plist
+    }
+}
+
+fn launch_agents_dir(env: &EnvMap) -> Result<PathBuf> {
+    let home = env.require("HOME")?;
+    Ok(PathBuf::from(home).join("Library/LaunchAgents"))
+}
+
+fn launchctl(args: &[&str]) -> Result<()> {
+    if !cfg!(target_os = "macos") {
+        warn!(
+            "launchctl unavailable on this platform, skipping {:?}",
+            args
+        );
+        return Ok(());
+    }
+    let output = Command::new("launchctl").args(args).output()?;
+    if !output.status.success() {
+        return Err(Error::Workflow(format!(
+            "launchctl {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read_to_string;
+
+    use super::*;
+    use crate::test_support::workflow_in as test_workflow;
+
+    #[test]
+    fn test_launch_agent_label() {
+        let dir = tempfile::tempdir().unwrap();
+        let workflow = test_workflow(dir.path());
+        assert_eq!(
+            workflow.launch_agent_label(),
+            "com.alfredapp.googlesuggest.alfrusco.refresh"
+        );
+    }
+
+    #[test]
+    fn test_launch_agent_plist() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = test_workflow(dir.path());
+        workflow.set_workflow_dir("/Applications/My Workflow");
+        let plist = workflow.launch_agent_plist(
+            &[
+                "/path/to/binary".to_string(),
+                "refresh".to_string(),
+                "a&b".to_string(),
+            ],
+            Duration::from_secs(900),
+        );
+
+        assert!(plist.contains("<string>com.alfredapp.googlesuggest.alfrusco.refresh</string>"));
+        assert!(plist.contains(
+            "<array>\n    <string>/path/to/binary</string>\n    <string>refresh</string>\n    <string>a&amp;b</string>\n  </array>"
+        ));
+        assert!(plist.contains("<key>StartInterval</key>\n  <integer>900</integer>"));
+        assert!(
+            plist.contains("<key>alfred_workflow_name</key>\n    <string>Test Workflow</string>")
+        );
+        assert!(plist.contains("launchd.log</string>"));
+        assert!(plist
+            .contains("<key>WorkingDirectory</key>\n  <string>/Applications/My Workflow</string>"));
+    }
+
+    #[test]
+    fn test_install_launch_agent() {
+        let dir = tempfile::tempdir().unwrap();
+        let workflow = test_workflow(dir.path());
+        let agents = dir.path().join("LaunchAgents");
+
+        let path = workflow
+            .install_launch_agent(&agents, Duration::from_secs(60), &["refresh"])
+            .unwrap();
+        assert_eq!(
+            path,
+            agents.join("com.alfredapp.googlesuggest.alfrusco.refresh.plist")
+        );
+        let plist = read_to_string(path).unwrap();
+        assert!(plist.contains("<string>refresh</string>"));
+    }
+}
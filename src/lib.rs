@@ -1,42 +1,129 @@
 // External crate dependencies
 use async_trait::async_trait;
+pub use tokio_util::sync::CancellationToken;
 
 // Internal modules
+mod action;
+mod autocomplete;
 mod background;
 mod background_job;
-mod clipboard;
+mod cached;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+mod compression;
+mod debug_items;
+mod degraded;
+mod dump;
 mod error;
+mod finalize;
+mod history;
+mod hooks;
+mod i18n;
+mod icon_check;
+mod internal_handlers;
 mod item;
+mod launchd;
+mod limits;
+mod memoize;
+mod notify;
+mod output;
+mod palette;
+mod reset;
 mod response;
+mod router;
+mod schedule;
+mod stats;
+mod stream;
+mod system;
+mod theme;
 mod url_item;
+mod validation;
 mod workflow;
+mod xml;
 
 // Pub re-exports
+pub mod calc;
+pub mod clipboard;
 pub mod config;
-use item::filter_and_sort_items;
+pub mod context;
+#[cfg(unix)]
+pub mod daemon;
+pub mod executor;
+pub mod export;
+pub mod filter;
+pub mod image_cache;
+pub mod logging;
+pub mod mdfind;
+pub mod progress;
+pub mod retry;
+pub mod secrets;
+pub mod sources;
+pub mod test_support;
+pub mod text;
 
-pub use self::error::{Error, Result, WorkflowError};
+pub use self::action::{
+    dispatch, ActionContext, ActionRunnable, ACTION_MODIFIER_VAR, ACTION_STAGE_VAR, ACTION_VARS_VAR,
+};
+pub use self::background::{JOBS_PENDING_VAR, PENDING_JOBS_VAR};
+pub use self::background_job::{
+    BackgroundJobStatus, FailureBackoff, IntoCommand, JobCommand, JobInfo, JobOptions, JobOutput,
+    JobReport, QosClass, COMMON_PATHS,
+};
+pub use self::cached::Cached;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub use self::compression::Compression;
+pub use self::degraded::SEPARATE_DIRS_VAR;
+pub use self::error::{
+    default_error_item, Error, ErrorStyle, Result, ResultExt, RunnableError, WorkflowError,
+};
+pub use self::finalize::FinalizeReport;
+pub use self::hooks::Hook;
+pub use self::i18n::{Strings, LOCALES_DIR};
+pub use self::internal_handlers::{InternalCommand, INTERNAL_VAR, LEGACY_INTERNAL_VARS};
 pub use self::item::icon::*;
-pub use self::item::{Arg, Icon, Item, Key, Modifier, Text};
-pub use self::response::Response;
-pub use self::url_item::URLItem;
-pub use self::workflow::Workflow;
+pub use self::item::{command_lines, Arg, Icon, Item, ItemType, Key, Modifier, Text};
+pub use self::output::Output;
+pub use self::palette::{Action, ActionPalette, ACTION_VAR};
+pub use self::response::{
+    CacheSettings, LearningPolicy, OutputFormat, Response, API_RERUN, API_RERUN_JITTER, MAX_RERUN,
+    MIN_RERUN,
+};
+pub use self::router::{CommandRouter, Route};
+pub use self::schedule::{CronSchedule, RunPolicy};
+pub use self::stream::StreamSink;
+pub use self::system::{
+    DefaultSystemActions, MacSystemActions, MockSystemActions, NoopSystemActions, SystemActions,
+    SystemCall,
+};
+pub use self::theme::{Appearance, Color};
+pub use self::url_item::{Browser, ColumnMapping, CopyFormat, CopyFormats, URLItem};
+pub use self::workflow::{Workflow, RESERVED_CACHE_SUBDIRS};
 
 pub fn handle() {
-    clipboard::handle_clipboard()
+    internal_handlers::handle(&DefaultSystemActions::default(), &EnvMap::from_process())
 }
 
-use crate::clipboard::handle_clipboard;
-use crate::config::ConfigProvider;
+use crate::config::{ConfigProvider, EnvMap};
 
 pub trait Runnable {
-    type Error: WorkflowError;
+    type Error: RunnableError;
+
+    /// Lifecycle hooks to register before this runnable executes.
+    fn hooks(&self) -> Vec<Box<dyn Hook>> {
+        Vec::new()
+    }
+
     fn run(self, workflow: &mut Workflow) -> std::result::Result<(), Self::Error>;
 }
 
 #[async_trait]
 pub trait AsyncRunnable {
-    type Error: WorkflowError;
+    type Error: RunnableError;
+
+    /// Lifecycle hooks to register before this runnable executes.
+    fn hooks(&self) -> Vec<Box<dyn Hook>> {
+        Vec::new()
+    }
+
     async fn run_async(self, workflow: &mut Workflow) -> std::result::Result<(), Self::Error>;
 }
 
@@ -45,52 +132,109 @@ pub fn execute<R: Runnable>(
     runnable: R,
     writer: &mut dyn std::io::Write,
 ) {
-    let mut workflow = setup_workflow(provider);
-    if let Err(e) = runnable.run(&mut workflow) {
-        workflow.prepend_item(e.error_item());
-    }
+    let Some(mut workflow) = setup_workflow(provider, writer) else {
+        return;
+    };
+    run_runnable(&mut workflow, runnable);
     finalize_workflow(workflow, writer);
 }
 
+/// Registers the runnable's hooks and runs it, showing an error it returns
+/// as an item at the top of the response.
+fn run_runnable<R: Runnable>(workflow: &mut Workflow, runnable: R) {
+    workflow.add_boxed_hooks(runnable.hooks());
+    workflow.fire_start();
+    if let Err(e) = runnable.run(workflow) {
+        show_error(workflow, &e);
+    }
+}
+
+fn show_error(workflow: &mut Workflow, e: &dyn RunnableError) {
+    workflow.fire_error(e.as_error());
+    let item = i18n::with_strings(&workflow.strings, || e.to_error_item());
+    workflow.prepend_item(workflow.error_style.apply(item));
+}
+
 pub async fn execute_async<R: AsyncRunnable>(
     provider: &dyn ConfigProvider,
     runnable: R,
     writer: &mut dyn std::io::Write,
 ) {
-    let mut workflow = setup_workflow(provider);
+    let Some(mut workflow) = setup_workflow(provider, writer) else {
+        return;
+    };
+    workflow.add_boxed_hooks(runnable.hooks());
+    workflow.fire_start();
     if let Err(e) = runnable.run_async(&mut workflow).await {
-        workflow.prepend_item(e.error_item());
+        show_error(&mut workflow, &e);
     }
     finalize_workflow(workflow, writer);
 }
 
-fn setup_workflow(provider: &dyn ConfigProvider) -> Workflow {
-    handle_clipboard();
-    let config = provider.config();
-    if config.is_err() {
-        eprintln!("Error loading config: {}", config.unwrap_err());
-        std::process::exit(1);
-    }
-    match Workflow::new(config.unwrap()) {
+/// Runs an AsyncRunnable to completion on alfrusco's bundled executor,
+/// for workflows built without a Tokio runtime. See the executor module
+/// for its limits.
+pub fn execute_blocking<R: AsyncRunnable>(
+    provider: &dyn ConfigProvider,
+    runnable: R,
+    writer: &mut dyn std::io::Write,
+) {
+    executor::block_on(execute_async(provider, runnable, writer))
+}
+
+/// Creates the Workflow for an execution. If the configuration can't be
+/// loaded, an item explaining how to fix it is written instead (so Alfred
+/// doesn't show an empty row) and None is returned.
+fn setup_workflow(
+    provider: &dyn ConfigProvider,
+    writer: &mut dyn std::io::Write,
+) -> Option<Workflow> {
+    let config = match provider.config() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error loading config: {}", e);
+            let item = config::config_error_item(&e, &EnvMap::from_process());
+            if let Err(e) = Response::new_with_items(vec![item]).write(writer) {
+                eprintln!("Error writing response: {}", e);
+                std::process::exit(1);
+            }
+            return None;
+        }
+    };
+    let mut workflow = match Workflow::new(config) {
         Ok(workflow) => workflow,
         Err(e) => {
             eprintln!("Error creating workflow: {}", e);
             std::process::exit(1);
         }
+    };
+    workflow.system = provider.system_actions();
+    if internal_handlers::perform(workflow.system(), &workflow.config.env)
+        || workflow.handle_reset_command()
+        || workflow.handle_job_command()
+    {
+        if let Err(e) = Response::new().write(writer) {
+            eprintln!("Error writing response: {}", e);
+        }
+        return None;
+    }
+    if provider.auto_logging() {
+        logging::auto_init(&workflow.cache_dir(), workflow.config.debug);
     }
+    // Logged here rather than in Workflow::new, once the logger is set up
+    for diagnostic in workflow.dir_diagnostics() {
+        log::warn!("{}", diagnostic);
+    }
+    if logging::is_initialized() {
+        let run_id = workflow.run_id().to_string();
+        workflow.response.var(logging::RUN_ID_VAR, run_id);
+    }
+    Some(workflow)
 }
 
-fn finalize_workflow(mut workflow: Workflow, writer: &mut dyn std::io::Write) {
-    if workflow.sort_and_filter_results {
-        if let Some(keyword) = workflow.keyword.clone() {
-            workflow.response.items = filter_and_sort_items(workflow.response.items, keyword);
-        }
-    }
-    match workflow.response.write(writer) {
-        Ok(_) => {}
-        Err(e) => {
-            eprintln!("Error writing response: {}", e);
-            std::process::exit(1);
-        }
+fn finalize_workflow(workflow: Workflow, writer: &mut dyn std::io::Write) {
+    if let Err(e) = workflow.finalize(writer) {
+        eprintln!("Error writing response: {}", e);
+        std::process::exit(1);
     }
 }
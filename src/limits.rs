@@ -0,0 +1,197 @@
+use log::debug;
+
+use crate::i18n::Strings;
+use crate::item::Item;
+use crate::workflow::Workflow;
+
+impl Workflow {
+    /// Caps the number of items written to Alfred. Alfred becomes sluggish
+    /// with very large result sets, so when more items are produced only
+    /// the first `max_items` are kept (after filtering and sorting) and a
+    /// "Showing first N of M results" item is appended.
+    ///
+    pub fn limit_items(&mut self, max_items: usize) {
+        self.item_limit = Some(max_items);
+    }
+
+    /// Caps the size of the serialized response in bytes. Items are
+    /// dropped from the end (after filtering and sorting) until the
+    /// response, including the appended "Showing first N of M results"
+    /// item, fits.
+    ///
+    pub fn limit_bytes(&mut self, max_bytes: usize) {
+        self.byte_limit = Some(max_bytes);
+    }
+
+    /// Applies the item and byte limits to the response items.
+    pub(crate) fn apply_limits(&mut self) {
+        if self.item_limit.is_none() && self.byte_limit.is_none() {
+            return;
+        }
+
+        let total = self.response.items.len();
+        let mut keep = self.item_limit.map_or(total, |max| max.min(total));
+
+        if let Some(max_bytes) = self.byte_limit {
+            if keep < total || self.serialized_len() > max_bytes {
+                keep = self.items_fitting(keep, total, max_bytes);
+            }
+        }
+
+        if keep < total {
+            debug!("truncating response from {} to {} items", total, keep);
+            self.response.items.truncate(keep);
+            self.response
+                .items
+                .push(truncation_item(&self.strings, keep, total));
+        }
+    }
+
+    /// The number of leading items (at most `keep`) that fit in `max_bytes`
+    /// alongside the truncation item.
+    fn items_fitting(&mut self, keep: usize, total: usize, max_bytes: usize) -> usize {
+        let items = std::mem::replace(
+            &mut self.response.items,
+            vec![truncation_item(&self.strings, keep, total)],
+        );
+        // The truncation item can only get shorter as fewer items are kept,
+        // so measuring with the widest count keeps us under the limit.
+        let mut used = self.serialized_len();
+        self.response.items = items;
+
+        let mut fit = 0;
+        for item in &self.response.items[..keep] {
+            // Each additional item costs its own JSON plus a separating comma
+            let size = serde_json::to_vec(item).map_or(usize::MAX, |json| json.len()) + 1;
+            if used.saturating_add(size) > max_bytes {
+                break;
+            }
+            used += size;
+            fit += 1;
+        }
+        fit
+    }
+
+    fn serialized_len(&self) -> usize {
+        serde_json::to_vec(&self.response).map_or(usize::MAX, |json| json.len())
+    }
+}
+
+fn truncation_item(strings: &Strings, shown: usize, total: usize) -> Item {
+    let (shown, total) = (shown.to_string(), total.to_string());
+    Item::new(strings.format("limits.truncated", &[("shown", &shown), ("total", &total)]))
+        .subtitle(strings.get("limits.refine"))
+        .valid(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::workflow_in;
+
+    fn numbered_items(count: usize) -> Vec<Item> {
+        (0..count)
+            .map(|i| Item::new(format!("Item {}", i)))
+            .collect()
+    }
+
+    fn long_items(count: usize) -> Vec<Item> {
+        (0..count)
+            .map(|i| Item::new(format!("Item {} {}", i, "with a long title ".repeat(10))))
+            .collect()
+    }
+
+    #[test]
+    fn test_no_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        workflow.items(numbered_items(10));
+        workflow.apply_limits();
+        assert_eq!(workflow.response.items.len(), 10);
+    }
+
+    #[test]
+    fn test_limit_items_at_boundary() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        workflow.items(numbered_items(5));
+        workflow.limit_items(5);
+        workflow.apply_limits();
+        assert_eq!(workflow.response.items.len(), 5);
+        assert_eq!(workflow.response.items[4].title, "Item 4");
+    }
+
+    #[test]
+    fn test_limit_items_truncates() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        workflow.items(numbered_items(6));
+        workflow.limit_items(5);
+        workflow.apply_limits();
+
+        let items = &workflow.response.items;
+        assert_eq!(items.len(), 6);
+        assert_eq!(items[4].title, "Item 4");
+        assert_eq!(items[5].title, "Showing first 5 of 6 results");
+        assert_eq!(items[5].valid, Some(false));
+    }
+
+    #[test]
+    fn test_limit_bytes_at_boundary() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        workflow.items(long_items(3));
+        let untruncated = workflow.serialized_len();
+
+        workflow.limit_bytes(untruncated);
+        workflow.apply_limits();
+        assert_eq!(workflow.response.items.len(), 3);
+
+        // The exact size of the first two items plus the truncation item
+        let mut expected = long_items(2);
+        expected.push(truncation_item(&workflow.strings, 2, 3));
+        workflow.items(expected.clone());
+        let two_items = workflow.serialized_len();
+
+        workflow.items(long_items(3));
+        workflow.limit_bytes(two_items);
+        workflow.apply_limits();
+        assert_eq!(workflow.response.items, expected);
+
+        workflow.items(long_items(3));
+        workflow.limit_bytes(two_items - 1);
+        workflow.apply_limits();
+        assert_eq!(workflow.response.items.len(), 2);
+        assert!(workflow.serialized_len() < two_items);
+    }
+
+    #[test]
+    fn test_limit_bytes_fits_under_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        workflow.items(numbered_items(100));
+        workflow.limit_bytes(1024);
+        workflow.apply_limits();
+
+        assert!(workflow.serialized_len() <= 1024);
+        let last = workflow.response.items.last().unwrap();
+        assert!(last.title.starts_with("Showing first "));
+        assert!(last.title.ends_with(" of 100 results"));
+    }
+
+    #[test]
+    fn test_limit_items_and_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        workflow.items(numbered_items(100));
+        workflow.limit_items(10);
+        workflow.limit_bytes(1_000_000);
+        workflow.apply_limits();
+
+        assert_eq!(workflow.response.items.len(), 11);
+        assert_eq!(
+            workflow.response.items[10].title,
+            "Showing first 10 of 100 results"
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::hash_map::RandomState;
+use std::fs::{create_dir_all, rename, File};
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use chrono::{SecondsFormat, Utc};
+use env_logger::{Env, WriteStyle};
+use log::{Log, Metadata, Record};
+
+use crate::{Error, Result};
+
+/// The response variable holding the current run's ID.
+pub const RUN_ID_VAR: &str = "ALFRUSCO_RUN_ID";
+
+// The run ID is process-wide rather than thread-local so log lines from
+// tokio worker threads and spawned tasks carry it too. Each Alfred
+// invocation is its own process, so there is one run per process.
+static RUN_ID: RwLock<String> = RwLock::new(String::new());
+
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+static AUTO_INIT: AtomicBool = AtomicBool::new(true);
+
+/// The name of the log file execute writes to in the cache directory.
+pub const LOG_FILE: &str = "workflow.log";
+
+/// The size at which the log file is moved aside to workflow.log.old when
+/// a run starts. Background jobs' log files are capped at this size too.
+pub(crate) const MAX_LOG_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Returns the ID of the current run, or an empty string before a
+/// Workflow has been created.
+pub fn run_id() -> String {
+    RUN_ID.read().map(|id| id.clone()).unwrap_or_default()
+}
+
+pub(crate) fn set_run_id(id: &str) {
+    if let Ok(mut run_id) = RUN_ID.write() {
+        *run_id = id.to_string();
+    }
+}
+
+/// Returns a new random 8 character hex ID.
+pub(crate) fn generate_run_id() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    format!("{:08x}", hasher.finish() as u32)
+}
+
+/// Returns true once init or try_init has installed the logger.
+pub fn is_initialized() -> bool {
+    INITIALIZED.load(Ordering::Relaxed)
+}
+
+/// LogOptions configures the logger installed by init_with.
+///
+/// Log lines always go to stderr, which Alfred shows in its workflow
+/// debugger. They can also be appended to a file; file lines are always
+/// plain text so the log stays readable in an editor.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogOptions {
+    file: Option<PathBuf>,
+    colors: Option<bool>,
+    default_filter: Option<String>,
+}
+
+impl LogOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also appends log lines, without colors, to the file at `path`.
+    pub fn file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.file = Some(path.into());
+        self
+    }
+
+    /// Forces colors on stderr on or off. By default colors are used when
+    /// stderr is a terminal and the NO_COLOR variable isn't set.
+    pub fn colors(mut self, colors: bool) -> Self {
+        self.colors = Some(colors);
+        self
+    }
+
+    /// The env_logger filter (e.g. "info" or "my_workflow=debug") used
+    /// when RUST_LOG isn't set. Without one, only errors are logged.
+    pub fn default_filter(mut self, filter: impl Into<String>) -> Self {
+        self.default_filter = Some(filter.into());
+        self
+    }
+
+    fn write_style(&self) -> WriteStyle {
+        // Read from the process rather than a WorkflowConfig's env: the
+        // logger is process-wide, can be installed before any config is
+        // read, and NO_COLOR describes the terminal stderr is attached to
+        let no_color = std::env::var("NO_COLOR").ok();
+        match (self.colors, no_color.as_deref()) {
+            (Some(true), _) => WriteStyle::Always,
+            (Some(false), _) => WriteStyle::Never,
+            (None, Some(value)) if !value.is_empty() => WriteStyle::Never,
+            (None, _) => WriteStyle::Auto,
+        }
+    }
+
+    fn build(&self) -> std::io::Result<Logger> {
+        let env = match &self.default_filter {
+            Some(filter) => Env::default().default_filter_or(filter.as_str()),
+            None => Env::default(),
+        };
+        let stderr = env_logger::Builder::from_env(env)
+            .write_style(self.write_style())
+            .format(|buf, record| {
+                let style = buf.default_level_style(record.level());
+                writeln!(
+                    buf,
+                    "{} [{}] {style}{:<5}{style:#} {}: {}",
+                    buf.timestamp_millis(),
+                    run_id(),
+                    record.level(),
+                    record.target(),
+                    record.args()
+                )
+            })
+            .build();
+        let file = match &self.file {
+            Some(path) => {
+                if let Some(dir) = path.parent() {
+                    create_dir_all(dir)?;
+                }
+                let file = File::options().create(true).append(true).open(path)?;
+                Some(Mutex::new(file))
+            }
+            None => None,
+        };
+        Ok(Logger { stderr, file })
+    }
+}
+
+/// Logger writes colored lines to stderr through env_logger, and the same
+/// lines as plain text to the optional log file.
+struct Logger {
+    stderr: env_logger::Logger,
+    file: Option<Mutex<File>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.stderr.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.stderr.matches(record) {
+            return;
+        }
+        self.stderr.log(record);
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.write_all(plain_line(record).as_bytes());
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+fn plain_line(record: &Record) -> String {
+    format!(
+        "{} [{}] {:<5} {}: {}\n",
+        Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        run_id(),
+        record.level(),
+        record.target(),
+        record.args()
+    )
+}
+
+/// Initializes a logger (filtered by RUST_LOG as usual with env_logger)
+/// that prefixes every line with the run ID. Alfred often starts a new
+/// execution on each keystroke while earlier ones are still running, so
+/// the ID is what tells their interleaved log lines apart.
+///
+/// Once initialized, the run ID is also passed to downstream objects in
+/// the ALFRUSCO_RUN_ID response variable, so their logs can be matched to
+/// the Script Filter run that produced them.
+///
+/// Does nothing if a logger has already been installed.
+///
+pub fn init() {
+    let _ = try_init();
+}
+
+/// Like init, but returns an error if a logger is already installed.
+pub fn try_init() -> Result<()> {
+    try_init_with(LogOptions::default())
+}
+
+/// Like init, with LogOptions controlling colors and an optional log file.
+pub fn init_with(options: LogOptions) {
+    let _ = try_init_with(options);
+}
+
+/// Like init_with, but returns an error if a logger is already installed
+/// or the log file can't be opened.
+pub fn try_init_with(options: LogOptions) -> Result<()> {
+    let logger = options.build()?;
+    let max_level = logger.stderr.filter();
+    log::set_boxed_logger(Box::new(logger))
+        .map_err(|e| Error::Workflow(format!("unable to install logger: {}", e)))?;
+    log::set_max_level(max_level);
+    INITIALIZED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Stops execute from installing a logger. Call this before execute when
+/// the workflow wants no logging at all. Workflows that install their own
+/// logger first don't need it: an existing logger is always kept.
+pub fn disable_auto_init() {
+    AUTO_INIT.store(false, Ordering::Relaxed);
+}
+
+/// Called by execute so errors and debug output are captured in the cache
+/// directory's workflow.log even when the workflow never initialized
+/// logging. Unless RUST_LOG says otherwise, warnings are logged, or
+/// everything down to debug while Alfred's debugger is open. Does nothing
+/// if auto init was disabled or a logger (ours or another) is already
+/// installed.
+pub(crate) fn auto_init(cache_dir: &Path, debug: bool) {
+    if !AUTO_INIT.load(Ordering::Relaxed) || is_initialized() {
+        return;
+    }
+    let path = cache_dir.join(LOG_FILE);
+    rotate(&path, MAX_LOG_FILE_BYTES);
+    let filter = if debug { "debug" } else { "warn" };
+    let _ = try_init_with(LogOptions::new().file(path).default_filter(filter));
+}
+
+/// Moves the log file aside (appending ".old" to its name) once it grows
+/// past max_bytes so it can't grow without bound. Only the previous file
+/// is kept.
+pub(crate) fn rotate(path: &Path, max_bytes: u64) {
+    let too_big = path
+        .metadata()
+        .map(|metadata| metadata.len() > max_bytes)
+        .unwrap_or(false);
+    if too_big {
+        let mut old = path.as_os_str().to_owned();
+        old.push(".old");
+        let _ = rename(path, old);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_run_id() {
+        let id = generate_run_id();
+        assert_eq!(id.len(), 8);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(id, generate_run_id());
+    }
+
+    #[test]
+    fn test_write_style() {
+        temp_env::with_var("NO_COLOR", Some("1"), || {
+            assert_eq!(LogOptions::new().write_style(), WriteStyle::Never);
+            assert_eq!(
+                LogOptions::new().colors(true).write_style(),
+                WriteStyle::Always
+            );
+        });
+        temp_env::with_var("NO_COLOR", Some(""), || {
+            assert_eq!(LogOptions::new().write_style(), WriteStyle::Auto);
+            assert_eq!(
+                LogOptions::new().colors(false).write_style(),
+                WriteStyle::Never
+            );
+        });
+    }
+
+    #[test]
+    fn test_file_lines_are_plain() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs").join("workflow.log");
+        let logger = temp_env::with_var("RUST_LOG", Some("info"), || {
+            LogOptions::new().file(&path).colors(true).build().unwrap()
+        });
+
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Warn)
+                .target("alfrusco")
+                .args(format_args!("disk is full"))
+                .build(),
+        );
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Debug)
+                .target("alfrusco")
+                .args(format_args!("filtered out"))
+                .build(),
+        );
+        logger.flush();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 1);
+        assert!(contents.ends_with("] WARN  alfrusco: disk is full\n"));
+        assert!(!contents.contains('\x1b'));
+    }
+
+    #[test]
+    fn test_rotate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOG_FILE);
+        std::fs::write(&path, "0123456789").unwrap();
+
+        rotate(&path, 10);
+        assert!(path.exists());
+
+        rotate(&path, 5);
+        assert!(!path.exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("workflow.log.old")).unwrap(),
+            "0123456789"
+        );
+    }
+
+    #[test]
+    fn test_workflow_has_run_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let workflow = crate::test_support::workflow_in(dir.path());
+        assert_eq!(workflow.run_id().len(), 8);
+        // Without alfrusco's logger there are no log lines to correlate
+        assert!(!workflow.response.variables.contains_key(RUN_ID_VAR));
+    }
+}
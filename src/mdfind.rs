@@ -0,0 +1,346 @@
+//! Spotlight file search via the `mdfind` command.
+//!
+//! Query builds a Spotlight query from typed metadata attributes and
+//! search scopes, runs it, and converts the matching paths into file
+//! Items (with their Finder icon, file type and Quick Look preview).
+//!
+//! ```no_run
+//! use alfrusco::config::EnvMap;
+//! use alfrusco::mdfind::Query;
+//!
+//! # let env = EnvMap::from_process();
+//! let items = Query::new()
+//!     .name_contains("invoice")
+//!     .content_type("com.adobe.pdf")
+//!     .only_in("/Users/crayons/Documents")
+//!     .limit(20)
+//!     .items(&env)?;
+//! # Ok::<(), alfrusco::Error>(())
+//! ```
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use log::debug;
+
+use crate::config::EnvMap;
+use crate::{Error, Item, ItemType, Result};
+
+/// Attribute is a Spotlight metadata attribute that can be queried.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Attribute {
+    /// The localized name shown in the Finder
+    DisplayName,
+    /// The file name on disk
+    FsName,
+    /// The file's UTI, e.g. "com.adobe.pdf"
+    ContentType,
+    /// The file's UTI and all the UTIs it conforms to, e.g. "public.image"
+    ContentTypeTree,
+    /// The localized kind, e.g. "PDF document"
+    Kind,
+    /// The indexed text content of the file
+    TextContent,
+    /// The authors of the document
+    Authors,
+    /// When the file was last opened
+    LastUsedDate,
+    /// When the file's content was last modified
+    ContentModificationDate,
+    /// Any other attribute, by its full name (e.g. "kMDItemPixelHeight")
+    Other(String),
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Attribute::DisplayName => "kMDItemDisplayName",
+            Attribute::FsName => "kMDItemFSName",
+            Attribute::ContentType => "kMDItemContentType",
+            Attribute::ContentTypeTree => "kMDItemContentTypeTree",
+            Attribute::Kind => "kMDItemKind",
+            Attribute::TextContent => "kMDItemTextContent",
+            Attribute::Authors => "kMDItemAuthors",
+            Attribute::LastUsedDate => "kMDItemLastUsedDate",
+            Attribute::ContentModificationDate => "kMDItemContentModificationDate",
+            Attribute::Other(name) => name,
+        };
+        f.write_str(name)
+    }
+}
+
+/// Query builds an mdfind search. All conditions must match (they are
+/// joined with `&&`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    conditions: Vec<String>,
+    scopes: Vec<PathBuf>,
+    limit: Option<usize>,
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches files whose attribute equals `value`. The comparison is
+    /// case and diacritic insensitive, and `*` may be used as a wildcard.
+    pub fn equals(self, attribute: Attribute, value: &str) -> Self {
+        self.matches(attribute, &escape(value))
+    }
+
+    /// Matches files whose attribute contains `text`, ignoring case and
+    /// diacritics. A `*` in `text` matches itself.
+    pub fn contains(self, attribute: Attribute, text: &str) -> Self {
+        let pattern = format!("*{}*", escape(text).replace('*', "\\*"));
+        self.matches(attribute, &pattern)
+    }
+
+    /// Adds a case and diacritic insensitive comparison with an already
+    /// escaped pattern.
+    fn matches(mut self, attribute: Attribute, pattern: &str) -> Self {
+        self.conditions
+            .push(format!("{} == \"{}\"cd", attribute, pattern));
+        self
+    }
+
+    /// Matches files whose display name contains `text`.
+    pub fn name_contains(self, text: &str) -> Self {
+        self.contains(Attribute::DisplayName, text)
+    }
+
+    /// Matches files with the given UTI or one conforming to it, so
+    /// "public.image" matches PNGs, JPEGs, etc.
+    pub fn content_type(mut self, uti: &str) -> Self {
+        self.conditions.push(format!(
+            "{} == \"{}\"",
+            Attribute::ContentTypeTree,
+            escape(uti)
+        ));
+        self
+    }
+
+    /// Adds a raw Spotlight query expression, for conditions the builder
+    /// doesn't cover (e.g. date ranges).
+    pub fn raw(mut self, expression: impl Into<String>) -> Self {
+        self.conditions.push(expression.into());
+        self
+    }
+
+    /// Restricts the search to files within `dir`. May be called more
+    /// than once to search several directories.
+    pub fn only_in(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.scopes.push(dir.into());
+        self
+    }
+
+    /// Returns at most `limit` results.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// The Spotlight query expression passed to mdfind.
+    pub fn query_string(&self) -> String {
+        self.conditions.join(" && ")
+    }
+
+    /// The mdfind command that runs this query.
+    pub fn command(&self) -> Command {
+        let mut command = Command::new("mdfind");
+        command.arg("-0");
+        for scope in &self.scopes {
+            command.arg("-onlyin").arg(scope);
+        }
+        command.arg(self.query_string());
+        command
+    }
+
+    /// Runs the query and returns the paths of the matching files.
+    pub fn paths(&self) -> Result<Vec<PathBuf>> {
+        if self.conditions.is_empty() {
+            return Err(Error::Workflow(
+                "mdfind query has no conditions".to_string(),
+            ));
+        }
+        debug!("mdfind {:?} in {:?}", self.query_string(), self.scopes);
+        let output = self.command().output()?;
+        if !output.status.success() {
+            return Err(Error::Workflow(format!(
+                "mdfind failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(parse_output(&output.stdout, self.limit))
+    }
+
+    /// Runs the query and returns an Item for each matching file. `env`
+    /// (usually the workflow's config.env) supplies HOME for file_item.
+    pub fn items(&self, env: &EnvMap) -> Result<Vec<Item>> {
+        Ok(self
+            .paths()?
+            .iter()
+            .map(|path| file_item(path, env))
+            .collect())
+    }
+}
+
+/// Returns a file Item for `path`: titled with the file name, showing the
+/// path as the subtitle (with the HOME directory in `env` shown as `~`),
+/// with its Finder icon, Quick Look preview, and Alfred's file actions
+/// enabled.
+pub fn file_item(path: &Path, env: &EnvMap) -> Item {
+    let path_str = path.to_string_lossy().to_string();
+    let title = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path_str.clone());
+    Item::new(title)
+        .subtitle(abbreviate_home(&path_str, env))
+        .uid(path_str.clone())
+        .arg(path_str.clone())
+        .item_type(ItemType::File)
+        .icon_for_file(path_str.clone())
+        .quicklook_url(path_str)
+}
+
+fn parse_output(stdout: &[u8], limit: Option<usize>) -> Vec<PathBuf> {
+    String::from_utf8_lossy(stdout)
+        .split('\0')
+        .filter(|path| !path.trim().is_empty())
+        .take(limit.unwrap_or(usize::MAX))
+        .map(PathBuf::from)
+        .collect()
+}
+
+fn abbreviate_home(path: &str, env: &EnvMap) -> String {
+    let home = env.get("HOME").unwrap_or_default().trim_end_matches('/');
+    match path.strip_prefix(home) {
+        Some(rest) if !home.is_empty() && (rest.is_empty() || rest.starts_with('/')) => {
+            format!("~{}", rest)
+        }
+        _ => path.to_string(),
+    }
+}
+
+/// Escapes a value for use inside a quoted Spotlight query string.
+pub(crate) fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_string() {
+        let query = Query::new()
+            .name_contains("report")
+            .content_type("com.adobe.pdf")
+            .equals(Attribute::Other("kMDItemAuthors".to_string()), "Crayons")
+            .raw("kMDItemLastUsedDate >= $time.today(-7)");
+        assert_eq!(
+            query.query_string(),
+            concat!(
+                "kMDItemDisplayName == \"*report*\"cd",
+                " && kMDItemContentTypeTree == \"com.adobe.pdf\"",
+                " && kMDItemAuthors == \"Crayons\"cd",
+                " && kMDItemLastUsedDate >= $time.today(-7)"
+            )
+        );
+    }
+
+    #[test]
+    fn test_query_escapes_values() {
+        let query = Query::new().contains(Attribute::TextContent, "say \"hi\" *");
+        assert_eq!(
+            query.query_string(),
+            r#"kMDItemTextContent == "*say \"hi\" \**"cd"#
+        );
+
+        // Only contains escapes wildcards
+        let query = Query::new().equals(Attribute::DisplayName, "a\\b*");
+        assert_eq!(query.query_string(), r#"kMDItemDisplayName == "a\\b*"cd"#);
+    }
+
+    #[test]
+    fn test_command_args() {
+        let query = Query::new()
+            .name_contains("notes")
+            .only_in("/Users/crayons/Documents")
+            .only_in("/Users/crayons/Desktop");
+        let command = query.command();
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(command.get_program(), "mdfind");
+        assert_eq!(
+            args,
+            [
+                "-0",
+                "-onlyin",
+                "/Users/crayons/Documents",
+                "-onlyin",
+                "/Users/crayons/Desktop",
+                "kMDItemDisplayName == \"*notes*\"cd",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_query() {
+        assert!(Query::new().paths().is_err());
+    }
+
+    #[test]
+    fn test_parse_output() {
+        let stdout = b"/a/one.txt\0/b/two words.pdf\0\n";
+        assert_eq!(
+            parse_output(stdout, None),
+            vec![
+                PathBuf::from("/a/one.txt"),
+                PathBuf::from("/b/two words.pdf")
+            ]
+        );
+        assert_eq!(
+            parse_output(stdout, Some(1)),
+            vec![PathBuf::from("/a/one.txt")]
+        );
+    }
+
+    #[test]
+    fn test_file_item() {
+        let env: EnvMap = [("HOME", "/Users/crayons")].into_iter().collect();
+        let item = file_item(Path::new("/Users/crayons/Documents/report.pdf"), &env);
+        let json = serde_json::to_value(&item).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "title": "report.pdf",
+                "subtitle": "~/Documents/report.pdf",
+                "uid": "/Users/crayons/Documents/report.pdf",
+                "arg": "/Users/crayons/Documents/report.pdf",
+                "type": "file",
+                "icon": {
+                    "type": "fileicon",
+                    "path": "/Users/crayons/Documents/report.pdf"
+                },
+                "quicklookurl": "/Users/crayons/Documents/report.pdf"
+            })
+        );
+    }
+
+    #[test]
+    fn test_abbreviate_home() {
+        let env: EnvMap = [("HOME", "/Users/bob")].into_iter().collect();
+        assert_eq!(abbreviate_home("/Users/bob", &env), "~");
+        assert_eq!(abbreviate_home("/Users/bob/notes.txt", &env), "~/notes.txt");
+        assert_eq!(
+            abbreviate_home("/Users/bobby/notes.txt", &env),
+            "/Users/bobby/notes.txt"
+        );
+        assert_eq!(
+            abbreviate_home("/Users/bob/a", &EnvMap::new()),
+            "/Users/bob/a"
+        );
+    }
+}
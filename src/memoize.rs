@@ -0,0 +1,272 @@
+use std::fs::{create_dir_all, read, remove_file};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use log::{debug, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::workflow::Workflow;
+use crate::Result;
+
+impl Workflow {
+    /// Returns the value cached on disk under `key` if it is younger than
+    /// `ttl`, otherwise calls `compute` and caches its result (as JSON) for
+    /// later executions.
+    ///
+    /// This is intended for expensive local computations, such as
+    /// Spotlight queries or scanning git repositories, whose results can
+    /// be reused across keystrokes. Errors from `compute` are returned and
+    /// not cached. Failing to write the cache is logged, not returned.
+    ///
+    pub fn memoize<T, F>(&self, key: &str, ttl: Duration, compute: F) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Result<T>,
+    {
+        let dir = self.memoize_dir();
+        if let Some(value) = self.read_cache_file(&dir, key, ttl) {
+            debug!("memoize '{}': using cached value", key);
+            return Ok(value);
+        }
+
+        debug!("memoize '{}': computing value", key);
+        let value = compute()?;
+        if let Err(e) = self.write_cache_file(&dir, key, &value) {
+            warn!("memoize '{}': unable to cache value: {}", key, e);
+        }
+        Ok(value)
+    }
+
+    /// Returns the path to the cache subdirectory where memoized values
+    /// are held
+    pub fn memoize_dir(&self) -> PathBuf {
+        self.reserved_cache_dir("memoize")
+    }
+
+    /// Returns the value cached under `key` in `dir` if it is younger than
+    /// `ttl`, including one written before the cache compression changed
+    pub(crate) fn read_cache_file<T: DeserializeOwned>(
+        &self,
+        dir: &Path,
+        key: &str,
+        ttl: Duration,
+    ) -> Option<T> {
+        let current = self.cache_extension();
+        std::iter::once(current)
+            .chain(CACHE_EXTENSIONS.iter().copied().filter(|e| *e != current))
+            .find_map(|extension| read_fresh(&cache_file(dir, key, extension), ttl))
+    }
+
+    /// Writes a value cached by this workflow under `key` in `dir`,
+    /// compressing it when cache compression is enabled. Copies written
+    /// with another compression are removed so they can't be read later.
+    pub(crate) fn write_cache_file<T: Serialize>(
+        &self,
+        dir: &Path,
+        key: &str,
+        value: &T,
+    ) -> Result<()> {
+        let current = self.cache_extension();
+        let path = cache_file(dir, key, current);
+        self.write_value(&path, value)?;
+        for extension in CACHE_EXTENSIONS.iter().filter(|e| **e != current) {
+            let _ = remove_file(cache_file(dir, key, extension));
+        }
+        Ok(())
+    }
+
+    fn write_value<T: Serialize>(&self, path: &Path, value: &T) -> Result<()> {
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        if let Some(compression) = self.cache_compression {
+            return crate::compression::write_compressed(path, value, compression);
+        }
+        write_cached(path, value)
+    }
+
+    fn cache_extension(&self) -> &'static str {
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        if let Some(compression) = self.cache_compression {
+            return compression.extension();
+        }
+        "json"
+    }
+}
+
+/// The extensions of cached values: plain JSON and each compression format
+const CACHE_EXTENSIONS: &[&str] = &["json", "json.gz", "json.zst"];
+
+fn cache_file(dir: &Path, key: &str, extension: &str) -> PathBuf {
+    dir.join(format!("{}.{}", key_file_stem(key), extension))
+}
+
+/// Returns a file name stem for a cache key: the hex encoded SHA-256 hash
+/// of the key, so any string, however long, is a safe file name.
+pub(crate) fn key_file_stem(key: &str) -> String {
+    hex::encode(Sha256::digest(key))
+}
+
+/// 64-bit FNV-1a over the parts, each followed by a zero byte. Unlike
+/// std's DefaultHasher, its output is stable across builds and Rust
+/// releases, so it suits exported uids and short cache file names.
+pub(crate) fn fnv1a(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for part in parts {
+        for byte in part.bytes().chain([0]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+pub(crate) fn read_fresh<T: DeserializeOwned>(path: &Path, ttl: Duration) -> Option<T> {
+    let modified = path.metadata().and_then(|m| m.modified()).ok()?;
+    let age = SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default();
+    if age >= ttl {
+        return None;
+    }
+    let bytes = read(path).ok()?;
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    let bytes = match crate::compression::decompress(bytes) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!(
+                "ignoring undecompressable cached value {}: {}",
+                path.display(),
+                e
+            );
+            return None;
+        }
+    };
+    match serde_json::from_slice(&bytes) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!("ignoring unreadable cached value {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+pub(crate) fn write_cached<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    write_atomically(path, &serde_json::to_vec(value)?)
+}
+
+pub(crate) fn write_atomically(path: &Path, bytes: &[u8]) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    create_dir_all(dir)?;
+    // Write a uniquely named file then rename it, so concurrent executions
+    // never read a partial file or write over each other's
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+    tmp.write_all(bytes)?;
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::test_support::workflow_in;
+    use crate::Error;
+
+    #[test]
+    fn test_memoize_caches_value() {
+        let dir = tempfile::tempdir().unwrap();
+        let workflow = workflow_in(dir.path());
+        let calls = Cell::new(0);
+        let compute = || {
+            calls.set(calls.get() + 1);
+            Ok(vec!["a".to_string(), "b".to_string()])
+        };
+
+        let ttl = Duration::from_secs(60);
+        let first: Vec<String> = workflow.memoize("scan /tmp", ttl, compute).unwrap();
+        let second: Vec<String> = workflow.memoize("scan /tmp", ttl, compute).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(calls.get(), 1);
+        assert!(workflow
+            .memoize_dir()
+            .join(format!("{}.json", key_file_stem("scan /tmp")))
+            .exists());
+    }
+
+    #[test]
+    fn test_memoize_long_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let workflow = workflow_in(dir.path());
+        let key = "k".repeat(1000);
+        let value: u32 = workflow
+            .memoize(&key, Duration::from_secs(60), || Ok(7))
+            .unwrap();
+        assert_eq!(value, 7);
+        let cached: u32 = workflow
+            .memoize(&key, Duration::from_secs(60), || Ok(8))
+            .unwrap();
+        assert_eq!(cached, 7);
+        assert_eq!(key_file_stem(&key).len(), 64);
+        assert_ne!(key_file_stem(&key), key_file_stem("k"));
+    }
+
+    #[test]
+    fn test_write_atomically_leaves_no_temp_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("value.json");
+        write_atomically(&path, b"1").unwrap();
+        write_atomically(&path, b"2").unwrap();
+        assert_eq!(read(&path).unwrap(), b"2");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_memoize_expires() {
+        let dir = tempfile::tempdir().unwrap();
+        let workflow = workflow_in(dir.path());
+        let calls = Cell::new(0);
+        let compute = || {
+            calls.set(calls.get() + 1);
+            Ok(calls.get())
+        };
+
+        assert_eq!(
+            workflow.memoize("count", Duration::ZERO, compute).unwrap(),
+            1
+        );
+        assert_eq!(
+            workflow.memoize("count", Duration::ZERO, compute).unwrap(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_memoize_does_not_cache_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let workflow = workflow_in(dir.path());
+        let ttl = Duration::from_secs(60);
+
+        let result: Result<u32> = workflow.memoize("flaky", ttl, || Err(Error::from("nope")));
+        assert!(result.is_err());
+        assert_eq!(workflow.memoize("flaky", ttl, || Ok(7)).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_memoize_recomputes_unreadable_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let workflow = workflow_in(dir.path());
+        let ttl = Duration::from_secs(60);
+
+        workflow
+            .memoize("value", ttl, || Ok("text".to_string()))
+            .unwrap();
+        let value: u32 = workflow.memoize("value", ttl, || Ok(42)).unwrap();
+        assert_eq!(value, 42);
+    }
+}
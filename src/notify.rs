@@ -0,0 +1,217 @@
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::config::EnvMap;
+use crate::memoize::{read_fresh, write_cached};
+use crate::text::escape_applescript;
+use crate::workflow::Workflow;
+use crate::{Item, Result, ICON_ALERT_NOTE};
+
+/// The environment variable set on processes spawned by run_in_background,
+/// marking them as background invocations.
+pub(crate) const BACKGROUND_VAR: &str = "ALFRUSCO_BACKGROUND_JOB";
+
+/// A notification that couldn't be delivered while Focus (Do Not Disturb)
+/// was on, kept until the next foreground run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct QueuedNotification {
+    pub(crate) title: String,
+    pub(crate) message: String,
+}
+
+impl Workflow {
+    /// Posts a macOS notification, e.g. from a background job when a long
+    /// refresh finishes or fails.
+    ///
+    /// When notifications are deferred (see defer_notifications) and Focus
+    /// or Do Not Disturb is on, the notification is instead queued in the
+    /// data directory and shown as an item the next time the Script Filter
+    /// runs in the foreground, so it isn't silently lost.
+    ///
+    pub fn notify(&self, title: &str, message: &str) -> Result<()> {
+        if self.defer_notifications && focus_active(&self.config.env) {
+            debug!("Focus is on, queueing notification '{}'", title);
+            let mut queued = self.queued_notifications();
+            queued.push(QueuedNotification {
+                title: title.to_string(),
+                message: message.to_string(),
+            });
+            return write_cached(&self.notifications_path(), &queued);
+        }
+        self.system().run_osascript(&format!(
+            "display notification \"{}\" with title \"{}\"",
+            escape_applescript(message),
+            escape_applescript(title)
+        ))?;
+        Ok(())
+    }
+
+    /// When enabled, notify queues notifications posted while Focus or Do
+    /// Not Disturb is on instead of posting them.
+    pub fn defer_notifications(&mut self, defer: bool) {
+        self.defer_notifications = defer;
+    }
+
+    /// Returns true when this process was started by run_in_background or
+    /// to perform a stream's fetch, rather than by Alfred.
+    pub fn is_background(&self) -> bool {
+        self.config.env.get(BACKGROUND_VAR).is_some()
+            || self.config.env.get(crate::stream::STREAM_VAR).is_some()
+    }
+
+    /// Prepends queued notifications as sticky items and clears the queue.
+    /// Background runs leave the queue alone, since nobody sees their
+    /// output.
+    pub(crate) fn deliver_queued_notifications(&mut self) {
+        if self.is_background() {
+            return;
+        }
+        let queued = self.queued_notifications();
+        if queued.is_empty() {
+            return;
+        }
+        if let Err(e) = std::fs::remove_file(self.notifications_path()) {
+            warn!("unable to clear queued notifications: {}", e);
+            return;
+        }
+        for notification in queued.into_iter().rev() {
+            let item = Item::new(notification.title)
+                .subtitle(notification.message)
+                .icon(ICON_ALERT_NOTE.into())
+                .valid(false)
+                .sticky(true);
+            self.prepend_item(item);
+        }
+    }
+
+    fn queued_notifications(&self) -> Vec<QueuedNotification> {
+        read_fresh(&self.notifications_path(), std::time::Duration::MAX).unwrap_or_default()
+    }
+
+    fn notifications_path(&self) -> PathBuf {
+        self.config.workflow_data.join("notifications.json")
+    }
+}
+
+/// Returns true if Focus (macOS 12+) or Do Not Disturb (earlier versions)
+/// is on. Focus is detected from the active assertions in
+/// ~/Library/DoNotDisturb/DB/Assertions.json, and Do Not Disturb from the
+/// notificationcenterui defaults. Anything unreadable counts as off.
+pub(crate) fn focus_active(env: &EnvMap) -> bool {
+    if let Some(home) = env.get("HOME") {
+        let path = Path::new(home).join("Library/DoNotDisturb/DB/Assertions.json");
+        if let Ok(json) = read_to_string(path) {
+            return assertions_active(&json);
+        }
+    }
+    cfg!(target_os = "macos") && legacy_dnd_active()
+}
+
+fn assertions_active(json: &str) -> bool {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(json) else {
+        return false;
+    };
+    value["data"].as_array().is_some_and(|data| {
+        data.iter().any(|entry| {
+            entry["storeAssertionRecords"]
+                .as_array()
+                .is_some_and(|records| !records.is_empty())
+        })
+    })
+}
+
+fn legacy_dnd_active() -> bool {
+    Command::new("defaults")
+        .args([
+            "-currentHost",
+            "read",
+            "com.apple.notificationcenterui",
+            "doNotDisturb",
+        ])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == "1")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, write};
+
+    use super::*;
+    use crate::system::{MockSystemActions, SystemCall};
+    use crate::test_support::workflow_in;
+
+    const FOCUS_ON: &str = r#"{"data":[{"storeAssertionRecords":[{"assertionDetails":{}}]}]}"#;
+
+    fn set_focus(home: &Path, json: &str) {
+        let dir = home.join("Library/DoNotDisturb/DB");
+        create_dir_all(&dir).unwrap();
+        write(dir.join("Assertions.json"), json).unwrap();
+    }
+
+    #[test]
+    fn test_assertions_active() {
+        assert!(assertions_active(FOCUS_ON));
+        assert!(!assertions_active(
+            r#"{"data":[{"storeAssertionRecords":[]}]}"#
+        ));
+        assert!(!assertions_active(r#"{"data":[{}]}"#));
+        assert!(!assertions_active("not json"));
+    }
+
+    #[test]
+    fn test_notify_posts_notification() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        let mock = MockSystemActions::new();
+        workflow.set_system_actions(mock.clone());
+
+        workflow.notify("Sync \"done\"", "42 items").unwrap();
+        assert_eq!(
+            mock.calls(),
+            vec![SystemCall::RunOsascript(
+                r#"display notification "42 items" with title "Sync \"done\"""#.to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_notify_queues_during_focus() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        let mock = MockSystemActions::new();
+        workflow.set_system_actions(mock.clone());
+        workflow
+            .config
+            .env
+            .set("HOME", dir.path().to_string_lossy());
+        set_focus(dir.path(), FOCUS_ON);
+        workflow.defer_notifications(true);
+
+        workflow.notify("Sync failed", "offline").unwrap();
+        workflow.notify("Sync done", "42 items").unwrap();
+        assert!(mock.calls().is_empty());
+
+        // Background runs don't consume the queue
+        workflow.config.env.set(BACKGROUND_VAR, "sync");
+        workflow.deliver_queued_notifications();
+        assert!(workflow.response.items.is_empty());
+
+        workflow.config.env.remove(BACKGROUND_VAR);
+        workflow.deliver_queued_notifications();
+        let titles: Vec<_> = workflow
+            .response
+            .items
+            .iter()
+            .map(|item| item.get_title())
+            .collect();
+        assert_eq!(titles, ["Sync failed", "Sync done"]);
+
+        workflow.response.items(Vec::new());
+        workflow.deliver_queued_notifications();
+        assert!(workflow.response.items.is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::io;
+
+use serde::Serialize;
+
+use crate::{Arg, Result};
+
+/// Output represents the JSON object a Run Script (or other non-Script
+/// Filter) stage can print to pass an arg, variables and downstream object
+/// configuration on to the next objects in the workflow.
+///
+/// Alfred expects this JSON wrapped in an `alfredworkflow` object:
+///
+/// {"alfredworkflow": {"arg": "...", "variables": {...}, "config": {...}}}
+///
+/// See https://www.alfredapp.com/help/workflows/utilities/json/
+///
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Output {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) arg: Option<Arg>,
+
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) variables: HashMap<String, String>,
+
+    /// Configuration overrides for the object receiving this output (e.g.
+    /// the `triggerid` of an External Trigger, or a Post Notification's
+    /// `title`).
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub(crate) config: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct OutputEnvelope<'a> {
+    alfredworkflow: &'a Output,
+}
+
+impl Output {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.arg = Some(Arg::One(arg.into()));
+        self
+    }
+
+    pub fn args(mut self, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.arg = Some(Arg::Many(args.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    pub fn var(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.variables.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn config(mut self, key: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        self.config.insert(key.into(), value.into());
+        self
+    }
+
+    /// Writes the Output in Alfred's `alfredworkflow` JSON format to the
+    /// provided writer.
+    pub fn write<W: io::Write>(&self, writer: W) -> Result<()> {
+        Ok(serde_json::to_writer(
+            writer,
+            &OutputEnvelope {
+                alfredworkflow: self,
+            },
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_empty_output() -> Result<()> {
+        assert_matches(json!({"alfredworkflow": {}}), Output::new())
+    }
+
+    #[test]
+    fn test_arg_and_variables() -> Result<()> {
+        let output = Output::new()
+            .arg("https://www.rust-lang.org/")
+            .var("TITLE", "Rust")
+            .var("SOURCE", "bookmarks");
+        assert_matches(
+            json!({
+                "alfredworkflow": {
+                    "arg": "https://www.rust-lang.org/",
+                    "variables": {"TITLE": "Rust", "SOURCE": "bookmarks"}
+                }
+            }),
+            output,
+        )
+    }
+
+    #[test]
+    fn test_args_and_config() -> Result<()> {
+        let output = Output::new()
+            .args(["one", "two"])
+            .config("triggerid", "refresh")
+            .config("withuid", false);
+        assert_matches(
+            json!({
+                "alfredworkflow": {
+                    "arg": ["one", "two"],
+                    "config": {"triggerid": "refresh", "withuid": false}
+                }
+            }),
+            output,
+        )
+    }
+
+    fn assert_matches(expected: serde_json::Value, output: Output) -> Result<()> {
+        let mut buffer = Vec::new();
+        output.write(&mut buffer)?;
+        let actual: serde_json::Value = serde_json::from_slice(&buffer)?;
+        assert_eq!(actual, expected);
+        Ok(())
+    }
+}
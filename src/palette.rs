@@ -0,0 +1,260 @@
+use crate::action::report_action_error;
+use crate::workflow::Workflow;
+use crate::{Error, Icon, Item, Runnable, RunnableError};
+
+/// The variable carrying the id of the action chosen from an
+/// ActionPalette to the run that performs it.
+pub const ACTION_VAR: &str = "ALFRUSCO_ACTION";
+
+type ActionHandler<E> = Box<dyn FnOnce(&mut Workflow) -> Result<(), E>>;
+
+/// Action is one named entry in an ActionPalette, such as "Clear cache"
+/// or "Sign out".
+pub struct Action<E> {
+    id: String,
+    title: String,
+    subtitle: Option<String>,
+    icon: Option<Icon>,
+    handler: ActionHandler<E>,
+}
+
+impl<E> Action<E> {
+    /// Creates an action. `id` identifies the action between runs, so it
+    /// must be unique within the palette and stable across releases.
+    pub fn new<F>(id: impl Into<String>, title: impl Into<String>, handler: F) -> Self
+    where
+        F: FnOnce(&mut Workflow) -> Result<(), E> + 'static,
+    {
+        Action {
+            id: id.into(),
+            title: title.into(),
+            subtitle: None,
+            icon: None,
+            handler: Box::new(handler),
+        }
+    }
+
+    pub fn subtitle(mut self, subtitle: impl Into<String>) -> Self {
+        self.subtitle = Some(subtitle.into());
+        self
+    }
+
+    pub fn icon(mut self, icon: Icon) -> Self {
+        self.icon = Some(icon);
+        self
+    }
+
+    fn item(&self) -> Item {
+        let mut item = Item::new(&self.title)
+            .uid(&self.id)
+            .arg(&self.id)
+            .var(ACTION_VAR, &self.id)
+            .valid(true);
+        if let Some(subtitle) = &self.subtitle {
+            item = item.subtitle(subtitle);
+        }
+        if let Some(icon) = &self.icon {
+            item = item.icon(icon.clone());
+        }
+        item
+    }
+}
+
+impl<E> std::fmt::Debug for Action<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Action")
+            .field("id", &self.id)
+            .field("title", &self.title)
+            .field("subtitle", &self.subtitle)
+            .finish_non_exhaustive()
+    }
+}
+
+/// ActionPalette builds a menu of actions (settings, maintenance tasks and
+/// other utilities) from a list of registered Actions, and dispatches the
+/// one the user picks.
+///
+/// Listed items set ALFRUSCO_ACTION to the action's id, so the Script
+/// Filter must be connected to a Run Script action invoking the workflow
+/// binary again with the same palette. On that run the chosen action's
+/// handler is called instead of listing the actions, and ALFRUSCO_ACTION
+/// is cleared so later runs list the actions again. Errors from the
+/// handler are posted as notifications.
+///
+/// ```
+/// use alfrusco::{Action, ActionPalette, Error, Workflow};
+///
+/// let palette: ActionPalette<Error> = ActionPalette::new("cache")
+///     .action(
+///         Action::new("clear_cache", "Clear cache", |workflow: &mut Workflow| {
+///             std::fs::remove_dir_all(workflow.cache_dir())?;
+///             Ok(())
+///         })
+///         .subtitle("Removes all cached results"),
+///     )
+///     .action(Action::new("open_data", "Open data directory", |workflow: &mut Workflow| {
+///         workflow.system().open_path(&workflow.data_dir())
+///     }));
+/// ```
+///
+#[derive(Debug)]
+pub struct ActionPalette<E> {
+    query: String,
+    actions: Vec<Action<E>>,
+}
+
+impl<E> ActionPalette<E> {
+    /// Creates an empty palette. Listed actions are fuzzy filtered by
+    /// `query`.
+    pub fn new(query: impl Into<String>) -> Self {
+        ActionPalette {
+            query: query.into(),
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn action(mut self, action: Action<E>) -> Self {
+        self.actions.push(action);
+        self
+    }
+}
+
+impl<E: RunnableError + From<Error>> Runnable for ActionPalette<E> {
+    type Error = E;
+
+    fn run(mut self, workflow: &mut Workflow) -> Result<(), E> {
+        let id = workflow
+            .config
+            .env
+            .remove(ACTION_VAR)
+            .filter(|id| !id.is_empty());
+        if let Some(id) = id {
+            let result = match self.actions.iter().position(|action| action.id == id) {
+                Some(i) => (self.actions.swap_remove(i).handler)(workflow),
+                None => Err(Error::Workflow(format!("unknown action {:?}", id)).into()),
+            };
+            if let Err(e) = result {
+                report_action_error(workflow, &e);
+            }
+            // Clear the action so reruns and downstream Script Filters don't
+            // perform it again
+            let output = workflow.output.take().unwrap_or_default();
+            workflow.output(output.var(ACTION_VAR, ""));
+            return Ok(());
+        }
+
+        workflow.append_items(self.actions.iter().map(Action::item).collect());
+        let query = self.query.trim();
+        if !query.is_empty() {
+            workflow.set_filter_keyword(query.to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::test_support::workflow_in;
+    use crate::{MockSystemActions, SystemCall};
+
+    fn palette(query: &str, ran: Rc<RefCell<Vec<&'static str>>>) -> ActionPalette<Error> {
+        let cleared = ran.clone();
+        ActionPalette::new(query)
+            .action(
+                Action::new("clear_cache", "Clear cache", move |_: &mut Workflow| {
+                    cleared.borrow_mut().push("clear_cache");
+                    Ok(())
+                })
+                .subtitle("Removes cached results"),
+            )
+            .action(Action::new(
+                "sign_out",
+                "Sign out",
+                move |_: &mut Workflow| {
+                    ran.borrow_mut().push("sign_out");
+                    Ok(())
+                },
+            ))
+    }
+
+    #[test]
+    fn test_lists_actions() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        let ran = Rc::new(RefCell::new(Vec::new()));
+
+        palette("", ran.clone()).run(&mut workflow).unwrap();
+        let items = &workflow.response.items;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].title, "Clear cache");
+        assert_eq!(items[0].subtitle.as_deref(), Some("Removes cached results"));
+        assert_eq!(items[0].get_var(ACTION_VAR), Some("clear_cache"));
+        assert_eq!(items[0].valid, Some(true));
+        assert!(ran.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_filters_actions() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+
+        palette("sign", Rc::default()).run(&mut workflow).unwrap();
+        workflow.filter_and_sort();
+        let titles: Vec<_> = workflow
+            .response
+            .items
+            .iter()
+            .map(|i| i.get_title())
+            .collect();
+        assert_eq!(titles, ["Sign out"]);
+    }
+
+    #[test]
+    fn test_dispatches_chosen_action() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        workflow.config.env.set(ACTION_VAR, "sign_out");
+        let ran = Rc::new(RefCell::new(Vec::new()));
+
+        palette("", ran.clone()).run(&mut workflow).unwrap();
+        assert_eq!(*ran.borrow(), ["sign_out"]);
+        assert!(workflow.response.items.is_empty());
+        let output = workflow.output.as_ref().unwrap();
+        assert_eq!(output.variables[ACTION_VAR], "");
+
+        // A second run lists the actions rather than dispatching again
+        palette("", ran.clone()).run(&mut workflow).unwrap();
+        assert_eq!(*ran.borrow(), ["sign_out"]);
+        assert_eq!(workflow.response.items.len(), 2);
+    }
+
+    #[test]
+    fn test_action_errors_are_notified() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        let mock = MockSystemActions::new();
+        workflow.set_system_actions(mock.clone());
+        workflow.config.env.set(ACTION_VAR, "missing");
+
+        palette("", Rc::default()).run(&mut workflow).unwrap();
+        assert!(matches!(
+            &mock.calls()[..],
+            [SystemCall::RunOsascript(script)] if script.contains("unknown action")
+        ));
+
+        let failing: ActionPalette<Error> =
+            ActionPalette::new("").action(Action::new("fail", "Fail", |_: &mut Workflow| {
+                Err(Error::Workflow("disk full".to_string()))
+            }));
+        workflow.config.env.set(ACTION_VAR, "fail");
+        failing.run(&mut workflow).unwrap();
+        assert!(matches!(
+            &mock.calls()[1..],
+            [SystemCall::RunOsascript(script)] if script.contains("disk full")
+        ));
+    }
+}
@@ -0,0 +1,138 @@
+//! Text progress bars and spinners for building animated progress items,
+//! e.g. while a background job or stream refreshes the results.
+//!
+//! Alfred has no native progress indicator, but a Script Filter that
+//! reruns can redraw its subtitle each time. The number of consecutive
+//! reruns is tracked in the ALFRUSCO_RERUN_COUNT variable, which Alfred
+//! passes back into each rerun, and selects the spinner frame.
+//!
+use crate::workflow::Workflow;
+use crate::{Item, ICON_CLOCK};
+
+/// The variable holding the number of consecutive reruns so far.
+pub const RERUN_COUNT_VAR: &str = "ALFRUSCO_RERUN_COUNT";
+
+/// The frames of the default spinner.
+pub const SPINNER_FRAMES: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// Returns a bar `width` characters wide, filled in proportion to
+/// `fraction` (clamped to 0.0..=1.0), e.g. "██████░░░░".
+pub fn bar(fraction: f64, width: usize) -> String {
+    let fraction = clamp_fraction(fraction);
+    let filled = (fraction * width as f64).round() as usize;
+    format!("{}{}", "█".repeat(filled), "░".repeat(width - filled))
+}
+
+fn clamp_fraction(fraction: f64) -> f64 {
+    if fraction.is_nan() {
+        0.0
+    } else {
+        fraction.clamp(0.0, 1.0)
+    }
+}
+
+/// Returns the spinner frame for the given frame number, wrapping around.
+pub fn spinner(frame: usize) -> &'static str {
+    SPINNER_FRAMES[frame % SPINNER_FRAMES.len()]
+}
+
+impl Workflow {
+    /// The number of consecutive times Alfred has rerun the Script Filter,
+    /// 0 for the first run.
+    pub fn rerun_count(&self) -> usize {
+        self.config
+            .env
+            .get(RERUN_COUNT_VAR)
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// The spinner frame for the current rerun.
+    pub fn spinner(&self) -> &'static str {
+        spinner(self.rerun_count())
+    }
+
+    /// Returns a non-actionable item whose subtitle animates across
+    /// reruns: a spinner, followed by a bar and percentage when the
+    /// fraction done is known. Set a rerun interval on the response to
+    /// keep it moving.
+    pub fn progress_item(&self, title: impl Into<String>, fraction: Option<f64>) -> Item {
+        let subtitle = match fraction {
+            Some(fraction) => format!(
+                "{} {} {:.0}%",
+                self.spinner(),
+                bar(fraction, 20),
+                clamp_fraction(fraction) * 100.0
+            ),
+            None => self.spinner().to_string(),
+        };
+        Item::new(title)
+            .subtitle(subtitle)
+            .icon(ICON_CLOCK.into())
+            .valid(false)
+    }
+
+    /// Passes the incremented rerun count to the next run when the
+    /// response asks Alfred to rerun.
+    pub(crate) fn track_reruns(&mut self) {
+        if self.response.rerun.is_some() {
+            let count = self.rerun_count() + 1;
+            self.response.var(RERUN_COUNT_VAR, count.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::test_support::workflow_in;
+
+    #[test]
+    fn test_bar() {
+        assert_eq!(bar(0.0, 4), "░░░░");
+        assert_eq!(bar(0.5, 4), "██░░");
+        assert_eq!(bar(1.0, 4), "████");
+        assert_eq!(bar(2.0, 4), "████");
+        assert_eq!(bar(-1.0, 4), "░░░░");
+        assert_eq!(bar(f64::NAN, 4), "░░░░");
+        assert_eq!(bar(0.5, 0), "");
+    }
+
+    #[test]
+    fn test_spinner_wraps() {
+        assert_eq!(spinner(0), SPINNER_FRAMES[0]);
+        assert_eq!(spinner(SPINNER_FRAMES.len() + 1), SPINNER_FRAMES[1]);
+    }
+
+    #[test]
+    fn test_rerun_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        assert_eq!(workflow.rerun_count(), 0);
+
+        workflow.track_reruns();
+        assert!(workflow.response.variables.is_empty());
+
+        workflow.config.env.set(RERUN_COUNT_VAR, "3");
+        workflow.response.rerun(Duration::from_millis(500));
+        workflow.track_reruns();
+        assert_eq!(workflow.rerun_count(), 3);
+        assert_eq!(workflow.response.variables[RERUN_COUNT_VAR], "4");
+    }
+
+    #[test]
+    fn test_progress_item() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        workflow.config.env.set(RERUN_COUNT_VAR, "2");
+
+        let item = workflow.progress_item("Refreshing", Some(0.25));
+        assert_eq!(item.get_subtitle(), Some("⠹ █████░░░░░░░░░░░░░░░ 25%"));
+        assert_eq!(item.valid, Some(false));
+
+        let item = workflow.progress_item("Refreshing", None);
+        assert_eq!(item.get_subtitle(), Some("⠹"));
+    }
+}
@@ -0,0 +1,234 @@
+use std::path::Path;
+
+use log::{debug, error, info};
+
+use crate::item::Item;
+use crate::workflow::Workflow;
+use crate::{Error, InternalCommand, Result, ICON_ALERT_CAUTION_BADGE, ICON_ALERT_STOP};
+
+/// The internal query that starts resetting the workflow.
+pub(crate) const RESET_QUERY: &str = "workflow:reset";
+
+/// The second step of the reset, reached from RESET_QUERY's item.
+pub(crate) const RESET_CONFIRM_QUERY: &str = "workflow:reset confirm";
+
+/// The internal command that purges the workflow's data.
+const RESET_COMMAND: &str = "reset";
+
+/// The reset command's argument carrying the confirm token.
+const RESET_TOKEN_ARG: &str = "reset_token";
+
+impl Workflow {
+    /// Deletes everything the workflow has stored: the cache and data
+    /// directories, which hold the background jobs' state, logs, query
+    /// history, usage counters and memoized results. Empty directories are
+    /// created in their place. Running background jobs and stream fetches
+    /// are stopped first, so they can't write their files back.
+    ///
+    /// `confirm_token` must be the workflow's bundle id, as a guard against
+    /// deleting everything by accident. Users can also reset a workflow by
+    /// typing `workflow:reset` and confirming, when the Script Filter is
+    /// connected to a Run Script action that runs the binary again.
+    ///
+    pub fn purge_all_data(&mut self, confirm_token: &str) -> Result<()> {
+        if confirm_token != self.config.workflow_bundleid {
+            return Err(Error::Workflow(format!(
+                "refusing to purge data: the confirm token must be {}",
+                self.config.workflow_bundleid
+            )));
+        }
+        for job in self.jobs() {
+            if job.running_for.is_some() {
+                self.cancel_job(&job.name)?;
+            }
+        }
+        self.stop_stream_fetches();
+        for dir in [self.cache_dir(), self.data_dir()] {
+            info!("purging {}", dir.display());
+            remove_dir_contents(&dir)?;
+        }
+        self.pending_jobs.clear();
+        self.job_snapshot = None;
+        Ok(())
+    }
+
+    /// Replaces the response items with the first step of the reset, an
+    /// item that autocompletes to the confirmation, or with the
+    /// confirmation itself, which runs the reset when actioned.
+    pub(crate) fn show_reset(&mut self, confirmed: bool) {
+        debug!("showing workflow reset, confirmed: {}", confirmed);
+        let name = self.config.workflow_name.clone();
+        let item = if confirmed {
+            Item::new(
+                self.strings
+                    .format("reset.confirm_title", &[("name", &name)]),
+            )
+            .subtitle(self.strings.get("reset.confirm_subtitle"))
+            .icon(ICON_ALERT_STOP.into())
+            .internal_command(
+                InternalCommand::new(RESET_COMMAND)
+                    .arg(RESET_TOKEN_ARG, &self.config.workflow_bundleid),
+            )
+        } else {
+            Item::new(self.strings.format("reset.title", &[("name", &name)]))
+                .subtitle(self.strings.get("reset.subtitle"))
+                .icon(ICON_ALERT_CAUTION_BADGE.into())
+                .autocomplete_query(RESET_CONFIRM_QUERY)
+                .valid(false)
+        };
+        self.response.items = vec![item];
+    }
+
+    /// Runs the reset when the confirmation item was actioned, posting a
+    /// notification with the outcome. Returns true if it was.
+    pub(crate) fn handle_reset_command(&mut self) -> bool {
+        let Some(command) = InternalCommand::from_env(&self.config.env) else {
+            return false;
+        };
+        if command.command() != RESET_COMMAND {
+            return false;
+        }
+        let token = command.get(RESET_TOKEN_ARG).unwrap_or_default().to_string();
+        let name = self.config.workflow_name.clone();
+        let message = match self.purge_all_data(&token) {
+            Ok(()) => self.strings.format("reset.done", &[("name", &name)]),
+            Err(e) => {
+                error!("unable to reset the workflow: {}", e);
+                self.strings
+                    .format("reset.failed", &[("error", &e.to_string())])
+            }
+        };
+        if let Err(e) = self.notify(&name, &message) {
+            error!("unable to post the reset notification: {}", e);
+        }
+        true
+    }
+}
+
+/// Removes everything inside `dir`, keeping `dir` itself.
+fn remove_dir_contents(dir: &Path) -> Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            std::fs::create_dir_all(dir)?;
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            std::fs::remove_dir_all(entry.path())?;
+        } else {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background_job::is_alive;
+    use crate::test_support::workflow_in;
+    use crate::{MockSystemActions, SystemCall, INTERNAL_VAR};
+
+    #[test]
+    fn test_purge_all_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        workflow.increment("search.performed");
+        std::fs::create_dir_all(workflow.jobs_dir().join("refresh")).unwrap();
+        std::fs::write(workflow.cache_dir().join("workflow.log"), "log").unwrap();
+
+        assert!(workflow.purge_all_data("com.example.other").is_err());
+        assert_eq!(workflow.counters().len(), 1);
+
+        workflow
+            .purge_all_data("com.alfredapp.googlesuggest")
+            .unwrap();
+        assert!(workflow.counters().is_empty());
+        assert!(!workflow.jobs_dir().exists());
+        assert_eq!(std::fs::read_dir(workflow.cache_dir()).unwrap().count(), 0);
+        assert!(workflow.data_dir().is_dir());
+    }
+
+    #[test]
+    fn test_purge_stops_background_work() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        let mut cmd = std::process::Command::new("sleep");
+        cmd.arg("30");
+        workflow.run_in_background("refresh", std::time::Duration::from_secs(60), cmd);
+        let pid_file = workflow.jobs_dir().join("refresh/job.pid");
+        let job_pid: u32 = std::fs::read_to_string(pid_file)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+
+        // A stream fetch, standing in for the detached process
+        let mut fetch = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let stream_dir = workflow.streams_dir().join("search");
+        std::fs::create_dir_all(&stream_dir).unwrap();
+        std::fs::write(stream_dir.join("fetch.pid"), fetch.id().to_string()).unwrap();
+
+        workflow
+            .purge_all_data("com.alfredapp.googlesuggest")
+            .unwrap();
+        // Killed rather than left to finish
+        assert!(!fetch.wait().unwrap().success());
+        for _ in 0..100 {
+            if !is_alive(job_pid) {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        panic!("job process {} is still running", job_pid);
+    }
+
+    #[test]
+    fn test_reset_internal_queries() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        workflow.set_filter_keyword(RESET_QUERY.to_string());
+        workflow.handle_internal_query();
+        let item = &workflow.response.items[0];
+        assert_eq!(item.get_title(), "Reset Test Workflow");
+        assert_eq!(item.autocomplete.as_deref(), Some(RESET_CONFIRM_QUERY));
+        assert_eq!(item.valid, Some(false));
+
+        workflow.set_filter_keyword(format!("  {} ", RESET_CONFIRM_QUERY));
+        workflow.handle_internal_query();
+        let item = &workflow.response.items[0];
+        assert_eq!(item.get_title(), "Delete all of Test Workflow's data?");
+        assert_eq!(
+            item.get_var(INTERNAL_VAR),
+            Some(r#"{"command":"reset","reset_token":"com.alfredapp.googlesuggest"}"#)
+        );
+        assert_eq!(item.valid, Some(true));
+    }
+
+    #[test]
+    fn test_handle_reset_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        let mock = MockSystemActions::new();
+        workflow.set_system_actions(mock.clone());
+        assert!(!workflow.handle_reset_command());
+
+        workflow.increment("search.performed");
+        let command =
+            InternalCommand::new("reset").arg(RESET_TOKEN_ARG, "com.alfredapp.googlesuggest");
+        workflow.config.env.set(INTERNAL_VAR, command.to_json());
+        assert!(workflow.handle_reset_command());
+        assert!(workflow.counters().is_empty());
+        assert!(matches!(
+            &mock.calls()[..],
+            [SystemCall::RunOsascript(script)] if script.contains("Test Workflow was reset")
+        ));
+    }
+}
@@ -1,10 +1,29 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
 use std::io;
 use std::time::Duration;
 
-use serde::{Serialize, Serializer};
+use log::warn;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use crate::retry::random_spread;
 use crate::{Item, Result};
 
+/// The shortest rerun interval Alfred accepts.
+pub const MIN_RERUN: Duration = Duration::from_millis(100);
+
+/// The longest rerun interval Alfred accepts.
+pub const MAX_RERUN: Duration = Duration::from_secs(5);
+
+/// A rerun interval for Script Filters polling a shared web API, long
+/// enough that every user of the workflow together stays well within
+/// typical rate limits. Use it with API_RERUN_JITTER.
+pub const API_RERUN: Duration = Duration::from_secs(2);
+
+/// The jitter to use with API_RERUN, spreading reruns between 1.5 and 2.5
+/// seconds.
+pub const API_RERUN_JITTER: Duration = Duration::from_millis(500);
+
 /// Represents the contents of a complete Alfred response to an execution.
 ///
 /// It consists of the `.items` to display in Alfred's UI and optional
@@ -13,31 +32,83 @@ use crate::{Item, Result};
 /// (skip_knowledge).
 ///
 #[non_exhaustive]
-#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Response {
     /// Interval in seconds to wait before re-running the script filter
     #[serde(
         skip_serializing_if = "Option::is_none",
-        serialize_with = "duration_as_seconds"
+        serialize_with = "duration_as_seconds",
+        deserialize_with = "duration_from_seconds"
     )]
-    rerun: Option<Duration>,
+    pub(crate) rerun: Option<Duration>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     cache: Option<CacheSettings>,
 
+    /// True once the workflow itself has called cache(), after which
+    /// suggestions from cache_if_not_set are ignored
+    #[serde(skip)]
+    cache_explicit: bool,
+
     /// If true, Alfred will not learn from the user's selection
     #[serde(rename = "skipknowledge", skip_serializing_if = "Option::is_none")]
     pub(crate) skip_knowledge: Option<bool>,
 
+    /// Variables passed to every item's downstream objects, and to the
+    /// Script Filter itself when it reruns
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) variables: HashMap<String, String>,
+
     /// The items to display in Alfred's output
     pub(crate) items: Vec<Item>,
+
+    /// Extra top-level keys set with Response::raw_field, written after
+    /// the items
+    #[serde(flatten)]
+    raw_fields: BTreeMap<String, serde_json::Value>,
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+/// The top-level JSON keys written from Response's typed fields, which
+/// raw_field can't override.
+const RESPONSE_KEYS: &[&str] = &["rerun", "cache", "skipknowledge", "variables", "items"];
+
+/// LearningPolicy controls whether Alfred learns from the items the user
+/// chooses, moving frequently chosen items up in later results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LearningPolicy {
+    /// Alfred learns from every item that has a uid.
+    AlwaysLearn,
+
+    /// Alfred never learns, keeping items in the order the workflow
+    /// returned them (`skipknowledge`).
+    NeverLearn,
+
+    /// Alfred's default: it learns from items with a uid and not from
+    /// those without (see Item::no_learning).
+    #[default]
+    PerItem,
+}
+
+/// OutputFormat selects how the response is written to Alfred.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Script Filter JSON, supported since Alfred 3.
+    #[default]
+    Json,
+
+    /// The legacy XML format, for Alfred 2 and older workflows whose
+    /// objects expect it. See Response::write_xml for what is written.
+    Xml,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CacheSettings {
     #[serde(
         skip_serializing_if = "Option::is_none",
-        serialize_with = "duration_as_seconds"
+        serialize_with = "duration_as_seconds",
+        deserialize_with = "duration_from_seconds"
     )]
     pub seconds: Option<Duration>,
 
@@ -65,12 +136,43 @@ impl Response {
         self
     }
 
+    /// Sets a rerun interval randomly chosen between `base - jitter` and
+    /// `base + jitter`, and within Alfred's limits (MIN_RERUN to
+    /// MAX_RERUN). With a fixed interval, the reruns of everyone using
+    /// the workflow line up into bursts against the same API; jitter
+    /// spreads them out. See API_RERUN for a safe default.
+    pub fn rerun_jittered(&mut self, base: Duration, jitter: Duration) -> &mut Self {
+        let spread = random_spread() * jitter.as_secs_f64();
+        let seconds =
+            (base.as_secs_f64() + spread).clamp(MIN_RERUN.as_secs_f64(), MAX_RERUN.as_secs_f64());
+        // Alfred's rerun is written with millisecond precision
+        self.rerun(Duration::from_millis((seconds * 1000.0).round() as u64))
+    }
+
     /// When set to true, Alfred will not learn from the user's selection.
     pub fn skip_knowledge(&mut self, skip_knowledge: bool) -> &mut Self {
         self.skip_knowledge = Some(skip_knowledge);
         self
     }
 
+    /// Sets a response-level variable. Alfred passes these on to the
+    /// objects connected to whichever item is chosen, and back into the
+    /// Script Filter when it reruns. Item variables take precedence.
+    pub fn var(&mut self, key: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.variables.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets the response's `skipknowledge` field from the LearningPolicy.
+    pub fn learning_policy(&mut self, policy: LearningPolicy) -> &mut Self {
+        self.skip_knowledge = match policy {
+            LearningPolicy::AlwaysLearn => Some(false),
+            LearningPolicy::NeverLearn => Some(true),
+            LearningPolicy::PerItem => None,
+        };
+        self
+    }
+
     /// Enables the Alfred 5.5+ cache feature with the provided cache duration.
     /// If loose_reload is true, Alfred will return the stale results while
     /// waiting for the cache to be updated.
@@ -80,6 +182,83 @@ impl Response {
             seconds: Some(duration),
             loose_reload: Some(loose_reload),
         });
+        self.cache_explicit = true;
+        self
+    }
+
+    /// Suggests cache settings without overriding ones the workflow set
+    /// explicitly with cache(). This is meant for library helpers that
+    /// know how long their results stay valid.
+    ///
+    /// When several helpers make suggestions they are merged
+    /// conservatively: the shortest duration wins, and loose reload is
+    /// only enabled if every suggestion allows it.
+    ///
+    pub fn cache_if_not_set(&mut self, duration: Duration, loose_reload: bool) -> &mut Self {
+        if self.cache_explicit {
+            return self;
+        }
+        let (duration, loose_reload) = match &self.cache {
+            Some(CacheSettings {
+                seconds: Some(seconds),
+                loose_reload: current,
+            }) => (
+                duration.min(*seconds),
+                loose_reload && current.unwrap_or(false),
+            ),
+            _ => (duration, loose_reload),
+        };
+        self.cache = Some(CacheSettings {
+            seconds: Some(duration),
+            loose_reload: Some(loose_reload),
+        });
+        self
+    }
+
+    /// The cache settings for this response, if any have been set or
+    /// suggested.
+    pub fn cache_settings(&self) -> Option<&CacheSettings> {
+        self.cache.as_ref()
+    }
+
+    /// The interval after which Alfred reruns the Script Filter, if set.
+    pub fn get_rerun(&self) -> Option<Duration> {
+        self.rerun
+    }
+
+    /// Whether Alfred is told not to learn from the user's selection, if
+    /// set.
+    pub fn get_skip_knowledge(&self) -> Option<bool> {
+        self.skip_knowledge
+    }
+
+    /// The response-level variables.
+    pub fn get_variables(&self) -> &HashMap<String, String> {
+        &self.variables
+    }
+
+    /// A top-level key set with raw_field (or, for parsed responses, any
+    /// key Response doesn't model).
+    pub fn get_raw_field(&self, key: &str) -> Option<&serde_json::Value> {
+        self.raw_fields.get(key)
+    }
+
+    /// Adds an arbitrary top-level key to the response's JSON, for Alfred
+    /// options alfrusco doesn't model yet. Raw keys are written after the
+    /// items, in key order, and setting the same key again replaces the
+    /// value. Keys written from the typed fields (rerun, cache,
+    /// skipknowledge, variables and items) can't be overridden this way:
+    /// they're ignored with a warning.
+    pub fn raw_field(&mut self, key: impl Into<String>, value: serde_json::Value) -> &mut Self {
+        let key = key.into();
+        if RESPONSE_KEYS.contains(&key.as_str()) {
+            warn!(
+                "ignoring raw response field {:?}: use its typed setter instead",
+                key
+            );
+            return self;
+        }
+        self.raw_fields.insert(key, value);
         self
     }
 
@@ -89,6 +268,31 @@ impl Response {
         self
     }
 
+    /// The items in the response so far. (The setter already uses the
+    /// name items.)
+    pub fn get_items(&self) -> &[Item] {
+        &self.items
+    }
+
+    /// Mutable access to the items, for post-processing a workflow's own
+    /// output before it is written. Item builders take the item by value,
+    /// so take it out of the slot to modify it:
+    ///
+    /// ```
+    /// use alfrusco::{Item, Response};
+    ///
+    /// let mut response = Response::new_with_items(vec![Item::new("a"), Item::new("b")]);
+    /// let total = response.get_items().len();
+    /// for (i, item) in response.items_mut().iter_mut().enumerate() {
+    ///     *item = std::mem::take(item).subtitle(format!("{} of {}", i + 1, total));
+    /// }
+    /// assert_eq!(response.get_items()[1].get_subtitle(), Some("2 of 2"));
+    /// ```
+    ///
+    pub fn items_mut(&mut self) -> &mut Vec<Item> {
+        &mut self.items
+    }
+
     /// Appends the provided items to the end of the existing items in the reponse.
     pub fn append_items(&mut self, items: Vec<Item>) {
         self.items.extend(items);
@@ -100,10 +304,71 @@ impl Response {
         self.items.splice(0..0, items);
     }
 
+    /// Sorts the items by title, ignoring case. Items with equal titles
+    /// keep their relative order.
+    ///
+    /// Sorting is for browse views that list items without a filter
+    /// keyword: when one is set, filtering orders items by match score
+    /// and replaces any order set here.
+    pub fn sort_by_title(&mut self) -> &mut Self {
+        self.sort_by_key(|item| item.title.to_lowercase())
+    }
+
+    /// Like sort_by_title, but in descending order. Items with equal titles
+    /// still keep their relative order.
+    pub fn sort_by_title_desc(&mut self) -> &mut Self {
+        self.sort_by_key_desc(|item| item.title.to_lowercase())
+    }
+
+    /// Sorts the items by the key `f` extracts, e.g.
+    /// `response.sort_by_key(|item| item.get_subtitle().map(str::to_string))`.
+    /// The sort is stable.
+    pub fn sort_by_key<K: Ord>(&mut self, f: impl FnMut(&Item) -> K) -> &mut Self {
+        self.items.sort_by_cached_key(f);
+        self
+    }
+
+    /// Like sort_by_key, but in descending order. Unlike reversing an
+    /// ascending sort, items with equal keys keep their relative order.
+    pub fn sort_by_key_desc<K: Ord>(&mut self, mut f: impl FnMut(&Item) -> K) -> &mut Self {
+        self.items
+            .sort_by_cached_key(|item| std::cmp::Reverse(f(item)));
+        self
+    }
+
+    /// Sorts the items with a comparator, as with slice::sort_by. The sort
+    /// is stable.
+    pub fn sort_by(&mut self, compare: impl FnMut(&Item, &Item) -> Ordering) -> &mut Self {
+        self.items.sort_by(compare);
+        self
+    }
+
     /// Writes the Alfred response to the provided writer.
     pub fn write<W: io::Write>(&self, writer: W) -> Result<()> {
         Ok(serde_json::to_writer(writer, self)?)
     }
+
+    /// Writes the Response in Alfred's legacy XML format. Only the basic
+    /// item fields are written: variables, rerun and cache settings have
+    /// no XML equivalent and are dropped, and multiple args are joined
+    /// with tabs.
+    pub fn write_xml<W: io::Write>(&self, writer: W) -> Result<()> {
+        crate::xml::write_response(self, writer)
+    }
+
+    /// Writes the Response in the given OutputFormat.
+    pub fn write_as<W: io::Write>(&self, format: OutputFormat, writer: W) -> Result<()> {
+        match format {
+            OutputFormat::Json => self.write(writer),
+            OutputFormat::Xml => self.write_xml(writer),
+        }
+    }
+
+    /// Writes the Alfred response as indented JSON, for reading while
+    /// debugging. Alfred accepts either form.
+    pub fn write_pretty<W: io::Write>(&self, writer: W) -> Result<()> {
+        Ok(serde_json::to_writer_pretty(writer, self)?)
+    }
 }
 
 /// Custom serializer for serializing a Duration as a floating point number
@@ -130,6 +395,17 @@ where
     }
 }
 
+/// Reads the seconds written by duration_as_seconds back into a Duration.
+fn duration_from_seconds<'de, D>(d: D) -> std::result::Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let seconds = Option::<f64>::deserialize(d)?;
+    seconds
+        .map(|seconds| Duration::try_from_secs_f64(seconds).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
@@ -157,6 +433,102 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_raw_field() -> Result<()> {
+        let mut response = Response::new_with_items(vec![Item::new("Title")]);
+        response
+            .rerun(Duration::from_secs(1))
+            .raw_field("zeta", json!(1))
+            .raw_field("alpha", json!({"on": true}))
+            .raw_field("zeta", json!(2))
+            .raw_field("rerun", json!(9))
+            .raw_field("items", json!([]));
+        assert_matches(
+            r#"{"rerun":1,"items":[{"title":"Title"}],"alpha":{"on":true},"zeta":2}"#,
+            response,
+        )
+    }
+
+    fn titles(response: &Response) -> Vec<&str> {
+        response.items.iter().map(Item::get_title).collect()
+    }
+
+    #[test]
+    fn test_items_mut() {
+        let mut response =
+            Response::new_with_items(vec![Item::new("a"), Item::new(""), Item::new("b")]);
+        response.items_mut().retain(|item| !item.title.is_empty());
+        assert_eq!(titles(&response), ["a", "b"]);
+        assert_eq!(response.get_items().len(), 2);
+    }
+
+    #[test]
+    fn test_sort_by_title() {
+        let mut response = Response::new_with_items(vec![
+            Item::new("banana").uid("1"),
+            Item::new("Apple"),
+            Item::new("cherry"),
+            Item::new("Banana").uid("2"),
+        ]);
+        response.sort_by_title();
+        assert_eq!(titles(&response), ["Apple", "banana", "Banana", "cherry"]);
+
+        response.sort_by_title_desc();
+        assert_eq!(titles(&response), ["cherry", "banana", "Banana", "Apple"]);
+    }
+
+    #[test]
+    fn test_sort_by_key_desc_is_stable() {
+        let mut response = Response::new_with_items(vec![
+            Item::new("a").subtitle("1"),
+            Item::new("b").subtitle("2"),
+            Item::new("c").subtitle("1"),
+            Item::new("d"),
+        ]);
+        response.sort_by_key_desc(|item| item.get_subtitle().map(str::to_string));
+        assert_eq!(titles(&response), ["b", "a", "c", "d"]);
+
+        response.sort_by_key(|item| item.get_subtitle().map(str::to_string));
+        assert_eq!(titles(&response), ["d", "a", "c", "b"]);
+
+        response.sort_by(|a, b| a.title.cmp(&b.title));
+        assert_eq!(titles(&response), ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_rerun_jittered() {
+        let mut response = Response::new();
+        let mut seen = std::collections::HashSet::new();
+        for _ in 0..50 {
+            let rerun = response
+                .rerun_jittered(API_RERUN, API_RERUN_JITTER)
+                .get_rerun()
+                .unwrap();
+            assert!(rerun >= Duration::from_millis(1500), "{:?}", rerun);
+            assert!(rerun <= Duration::from_millis(2500), "{:?}", rerun);
+            assert_eq!(rerun.subsec_nanos() % 1_000_000, 0);
+            seen.insert(rerun);
+        }
+        assert!(seen.len() > 1);
+
+        // Intervals are kept within Alfred's limits
+        let rerun = |base, jitter| {
+            Response::new()
+                .rerun_jittered(base, jitter)
+                .get_rerun()
+                .unwrap()
+        };
+        assert_eq!(rerun(Duration::ZERO, Duration::ZERO), MIN_RERUN);
+        assert_eq!(
+            rerun(Duration::from_secs(60), Duration::from_secs(1)),
+            MAX_RERUN
+        );
+        assert_eq!(
+            rerun(Duration::from_secs(1), Duration::ZERO),
+            Duration::from_secs(1)
+        );
+    }
+
     #[test]
     fn test_rerun_serialization() -> Result<()> {
         let mut response = Response::default();
@@ -171,6 +543,52 @@ mod tests {
         assert_matches(r#"{"skipknowledge":true,"items":[]}"#, response)
     }
 
+    #[test]
+    fn test_learning_policy() -> Result<()> {
+        let mut response = Response::default();
+        response.learning_policy(LearningPolicy::NeverLearn);
+        assert_matches(r#"{"skipknowledge":true,"items":[]}"#, response.clone())?;
+        response.learning_policy(LearningPolicy::AlwaysLearn);
+        assert_matches(r#"{"skipknowledge":false,"items":[]}"#, response.clone())?;
+        response.learning_policy(LearningPolicy::PerItem);
+        assert_matches(r#"{"items":[]}"#, response)
+    }
+
+    #[test]
+    fn test_variables() -> Result<()> {
+        let mut response = Response::default();
+        response.var("SOURCE", "bookmarks");
+        assert_matches(
+            r#"{"variables":{"SOURCE":"bookmarks"},"items":[]}"#,
+            response,
+        )
+    }
+
+    #[test]
+    fn test_cache_if_not_set_merges_suggestions() -> Result<()> {
+        let mut response = Response::default();
+        response
+            .cache_if_not_set(Duration::from_secs(300), true)
+            .cache_if_not_set(Duration::from_secs(60), true)
+            .cache_if_not_set(Duration::from_secs(600), false);
+        assert_matches(
+            r#"{"cache":{"seconds":60,"loosereload":false},"items":[]}"#,
+            response,
+        )
+    }
+
+    #[test]
+    fn test_cache_if_not_set_keeps_explicit_settings() -> Result<()> {
+        let mut response = Response::default();
+        response
+            .cache(Duration::from_secs(3600), true)
+            .cache_if_not_set(Duration::from_secs(60), false);
+        assert_matches(
+            r#"{"cache":{"seconds":3600,"loosereload":true},"items":[]}"#,
+            response,
+        )
+    }
+
     #[test]
     fn test_cache() -> Result<()> {
         let mut response = Response::default();
@@ -188,6 +606,19 @@ mod tests {
         assert_matches(r#"{"items":[{"title":"Simple Title"}]}"#, response)
     }
 
+    #[test]
+    fn test_write_pretty() -> Result<()> {
+        let mut response = Response::default();
+        response.items(vec![Item::new("Simple Title")]);
+        let mut buffer = Vec::new();
+        response.write_pretty(&mut buffer)?;
+        assert_eq!(
+            String::from_utf8(buffer)?,
+            "{\n  \"items\": [\n    {\n      \"title\": \"Simple Title\"\n    }\n  ]\n}"
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_duration_as_seconds_serialization() {
         let cases = [
@@ -212,6 +643,33 @@ mod tests {
         assert_eq!(result.to_string(), r#"{"duration":null}"#);
     }
 
+    #[test]
+    fn test_deserialize_round_trip() {
+        let mut response = Response::new_with_items(vec![Item::new("Hello").arg("hi")]);
+        response
+            .rerun(Duration::from_millis(2500))
+            .cache(Duration::from_secs(60), true)
+            .skip_knowledge(true)
+            .var("MODE", "search")
+            .raw_field("custom", json!({"a": 1}));
+        let json = serde_json::to_string(&response).unwrap();
+
+        let parsed: Response = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.get_rerun(), Some(Duration::from_millis(2500)));
+        assert_eq!(parsed.get_skip_knowledge(), Some(true));
+        assert_eq!(parsed.get_variables()["MODE"], "search");
+        assert_eq!(parsed.get_raw_field("custom"), Some(&json!({"a": 1})));
+        assert_eq!(
+            parsed.cache_settings().unwrap().seconds,
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(parsed.get_items(), response.get_items());
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+
+        let empty: Response = serde_json::from_str(r#"{"items": []}"#).unwrap();
+        assert_eq!(empty, Response::new());
+    }
+
     fn assert_matches(expected: &str, response: Response) -> Result<()> {
         let mut buffer = Vec::new();
         response.write(&mut buffer)?;
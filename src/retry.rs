@@ -0,0 +1,332 @@
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use log::debug;
+
+use crate::{Item, WorkflowError, ICON_ALERT_STOP};
+
+/// RetryPolicy controls how often, and how patiently, a flaky operation
+/// (a network request, an AppleScript call, etc.) is retried.
+///
+/// alfrusco retries the AppleScript queries in the context module (the
+/// frontmost app, Finder selection and browser tab) with it. alfrusco has
+/// no HTTP client of its own, so workflows wrap their requests in
+/// with_backoff or with_backoff_async.
+///
+/// The delay before retry `n` is `initial_delay * multiplier^(n-1)`, capped
+/// at `max_delay`, and then randomly varied by up to +/- `jitter` (a
+/// fraction of the delay) so concurrent retries don't stay in lockstep.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+    multiplier: f64,
+    jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(2),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The total number of attempts, including the first. At least 1.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// How much the delay grows after each retry. Values below 1.0 are
+    /// treated as 1.0 (a constant delay).
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// The fraction (0.0 to 1.0) by which each delay is randomly varied.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// The delay before the given retry (1 for the first retry), before
+    /// jitter is applied.
+    fn base_delay(&self, retry: u32) -> Duration {
+        let factor = self.multiplier.powi(retry.saturating_sub(1) as i32);
+        let delay = self.initial_delay.as_secs_f64() * factor;
+        Duration::from_secs_f64(delay.min(self.max_delay.as_secs_f64()))
+    }
+
+    fn delay(&self, retry: u32) -> Duration {
+        let delay = self.base_delay(retry).as_secs_f64();
+        let spread = random_spread() * self.jitter;
+        Duration::from_secs_f64((delay * (1.0 + spread)).max(0.0))
+    }
+}
+
+/// Returns a random value between -1.0 and 1.0, for spreading delays out
+/// by a jitter fraction.
+pub(crate) fn random_spread() -> f64 {
+    let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+    random * 2.0 - 1.0
+}
+
+/// RetryError is returned when every attempt failed. It carries the error
+/// from the final attempt.
+#[derive(Debug)]
+pub struct RetryError<E> {
+    pub attempts: u32,
+    pub last_error: E,
+}
+
+impl<E> RetryError<E> {
+    fn attempts(&self) -> String {
+        match self.attempts {
+            1 => "1 attempt".to_string(),
+            n => format!("{} attempts", n),
+        }
+    }
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for RetryError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed after {}: {}", self.attempts(), self.last_error)
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for RetryError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.last_error)
+    }
+}
+
+impl<E: std::error::Error + 'static> WorkflowError for RetryError<E> {
+    fn error_item(&self) -> Item {
+        Item::new(format!("Failed after {}", self.attempts()))
+            .subtitle(self.last_error.to_string())
+            .icon(ICON_ALERT_STOP.into())
+            .valid(false)
+    }
+}
+
+/// Calls `op` until it succeeds or the policy's attempts are exhausted,
+/// sleeping between attempts.
+pub fn with_backoff<T, E, F>(policy: &RetryPolicy, mut op: F) -> Result<T, RetryError<E>>
+where
+    E: std::fmt::Display,
+    F: FnMut() -> Result<T, E>,
+{
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt >= policy.max_attempts => {
+                return Err(RetryError {
+                    attempts: attempt,
+                    last_error: e,
+                })
+            }
+            Err(e) => {
+                let delay = policy.delay(attempt);
+                debug!(
+                    "attempt {} failed ({}), retrying in {:?}",
+                    attempt, e, delay
+                );
+                std::thread::sleep(delay);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// The async equivalent of with_backoff. Between attempts it sleeps on
+/// Tokio's timer inside a Tokio runtime, and on a timer thread otherwise
+/// (e.g. under execute_blocking).
+pub async fn with_backoff_async<T, E, F, Fut>(
+    policy: &RetryPolicy,
+    mut op: F,
+) -> Result<T, RetryError<E>>
+where
+    E: std::fmt::Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 1;
+    loop {
+        match op().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt >= policy.max_attempts => {
+                return Err(RetryError {
+                    attempts: attempt,
+                    last_error: e,
+                })
+            }
+            Err(e) => {
+                let delay = policy.delay(attempt);
+                debug!(
+                    "attempt {} failed ({}), retrying in {:?}",
+                    attempt, e, delay
+                );
+                crate::executor::runtime_sleep(delay).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::Error;
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy::new()
+            .initial_delay(Duration::ZERO)
+            .max_attempts(3)
+    }
+
+    #[test]
+    fn test_base_delay() {
+        let policy = RetryPolicy::new()
+            .initial_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(350))
+            .multiplier(2.0);
+        assert_eq!(policy.base_delay(1), Duration::from_millis(100));
+        assert_eq!(policy.base_delay(2), Duration::from_millis(200));
+        assert_eq!(policy.base_delay(3), Duration::from_millis(350));
+    }
+
+    #[test]
+    fn test_jitter_bounds() {
+        let policy = RetryPolicy::new()
+            .initial_delay(Duration::from_millis(100))
+            .jitter(0.5);
+        for _ in 0..100 {
+            let delay = policy.delay(1);
+            assert!(delay >= Duration::from_millis(50), "{:?}", delay);
+            assert!(delay <= Duration::from_millis(150), "{:?}", delay);
+        }
+        assert_eq!(policy.jitter(0.0).delay(1), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_with_backoff_succeeds_after_failures() {
+        let calls = Cell::new(0);
+        let result = with_backoff(&fast_policy(), || {
+            calls.set(calls.get() + 1);
+            if calls.get() < 3 {
+                Err(Error::Workflow("flaky".to_string()))
+            } else {
+                Ok("done")
+            }
+        });
+        assert_eq!(result.unwrap(), "done");
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn test_with_backoff_exhausted() {
+        let calls = Cell::new(0);
+        let result: Result<(), _> = with_backoff(&fast_policy(), || {
+            calls.set(calls.get() + 1);
+            Err(Error::Workflow(format!("attempt {}", calls.get())))
+        });
+        let error = result.unwrap_err();
+        assert_eq!(calls.get(), 3);
+        assert_eq!(error.attempts, 3);
+        assert_eq!(
+            error.to_string(),
+            "failed after 3 attempts: Workflow Error: attempt 3"
+        );
+
+        let item = error.error_item();
+        assert_eq!(item.title, "Failed after 3 attempts");
+        assert_eq!(item.subtitle.as_deref(), Some("Workflow Error: attempt 3"));
+    }
+
+    #[test]
+    fn test_max_attempts_at_least_one() {
+        let calls = Cell::new(0);
+        let result: Result<(), _> = with_backoff(&fast_policy().max_attempts(0), || {
+            calls.set(calls.get() + 1);
+            Err(Error::Workflow("nope".to_string()))
+        });
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "failed after 1 attempt: Workflow Error: nope"
+        );
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_with_backoff_async_without_runtime() {
+        let policy = RetryPolicy::new()
+            .initial_delay(Duration::from_millis(10))
+            .jitter(0.0);
+        let calls = Cell::new(0);
+        let result = crate::executor::block_on(with_backoff_async(&policy, || {
+            calls.set(calls.get() + 1);
+            let attempt = calls.get();
+            async move {
+                if attempt < 3 {
+                    Err(Error::Workflow("flaky".to_string()))
+                } else {
+                    Ok(attempt)
+                }
+            }
+        }));
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn test_with_backoff_async() {
+        let policy = RetryPolicy::new()
+            .initial_delay(Duration::from_secs(1))
+            .jitter(0.0);
+        let calls = Cell::new(0);
+        let start = tokio::time::Instant::now();
+        let result = with_backoff_async(&policy, || {
+            calls.set(calls.get() + 1);
+            let attempt = calls.get();
+            async move {
+                if attempt < 3 {
+                    Err(Error::Workflow("flaky".to_string()))
+                } else {
+                    Ok(attempt)
+                }
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+        // Slept 1s, then 2s
+        assert_eq!(start.elapsed(), Duration::from_secs(3));
+    }
+}
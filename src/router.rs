@@ -0,0 +1,259 @@
+use crate::workflow::Workflow;
+use crate::{Item, Runnable, RunnableError};
+
+type Handler<E> = Box<dyn FnOnce(&mut Workflow, String) -> Result<(), E>>;
+
+/// Route maps a keyword typed at the start of the query (e.g. "repo") to
+/// the runnable handling the rest of the query.
+pub struct Route<E> {
+    keyword: String,
+    title: String,
+    placeholder: Option<String>,
+    filter: bool,
+    handler: Handler<E>,
+}
+
+impl<E> Route<E> {
+    /// Creates a route for queries starting with `keyword` followed by a
+    /// space. `runnable` is called with the rest of the query to build the
+    /// runnable that handles it.
+    pub fn new<R, F>(keyword: impl Into<String>, runnable: F) -> Self
+    where
+        R: Runnable<Error = E>,
+        F: FnOnce(String) -> R + 'static,
+    {
+        let keyword = keyword.into();
+        Route {
+            title: keyword.clone(),
+            keyword,
+            placeholder: None,
+            filter: false,
+            handler: Box::new(move |workflow, query| runnable(query).run(workflow)),
+        }
+    }
+
+    /// The title of the item suggesting this route. Defaults to the
+    /// keyword.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// The subtitle of the item suggesting this route, describing what to
+    /// type after the keyword (e.g. "Search repositories by name").
+    pub fn placeholder(mut self, placeholder: impl Into<String>) -> Self {
+        self.placeholder = Some(placeholder.into());
+        self
+    }
+
+    /// When true, the route's items are fuzzy filtered by the rest of the
+    /// query, as with Workflow::set_filter_keyword.
+    pub fn filter(mut self, filter: bool) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    fn prefix(&self) -> String {
+        format!("{} ", self.keyword)
+    }
+
+    fn item(&self) -> Item {
+        let item = Item::new(&self.title)
+            .autocomplete_query(self.prefix())
+            .valid(false);
+        match &self.placeholder {
+            Some(placeholder) => item.subtitle(placeholder),
+            None => item,
+        }
+    }
+}
+
+impl<E> std::fmt::Debug for Route<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Route")
+            .field("keyword", &self.keyword)
+            .field("title", &self.title)
+            .field("placeholder", &self.placeholder)
+            .field("filter", &self.filter)
+            .finish_non_exhaustive()
+    }
+}
+
+/// CommandRouter lets one Script Filter offer several subcommands, such
+/// as "repo ", "pr " and "issue ", each handled by its own runnable.
+///
+/// When the query starts with a route's keyword and a space, that route's
+/// runnable handles the rest of the query. Otherwise an item is shown for
+/// each route (filtered by what has been typed) which autocompletes to its
+/// keyword, unless a fallback handles the query instead.
+///
+/// ```
+/// use alfrusco::{CommandRouter, Error, Item, Route, Runnable, Workflow};
+///
+/// struct Repos(String);
+///
+/// impl Runnable for Repos {
+///     type Error = Error;
+///
+///     fn run(self, workflow: &mut Workflow) -> Result<(), Error> {
+///         workflow.append_item(Item::new(format!("Repositories matching {}", self.0)));
+///         Ok(())
+///     }
+/// }
+///
+/// let router = CommandRouter::new("repo alfrusco").route(
+///     Route::new("repo", Repos)
+///         .title("Repositories")
+///         .placeholder("Search repositories by name"),
+/// );
+/// ```
+///
+#[derive(Debug)]
+pub struct CommandRouter<E> {
+    query: String,
+    routes: Vec<Route<E>>,
+    fallback: Option<Route<E>>,
+}
+
+impl<E> CommandRouter<E> {
+    pub fn new(query: impl Into<String>) -> Self {
+        CommandRouter {
+            query: query.into(),
+            routes: Vec::new(),
+            fallback: None,
+        }
+    }
+
+    pub fn route(mut self, route: Route<E>) -> Self {
+        self.routes.push(route);
+        self
+    }
+
+    /// Sets the runnable that handles non-empty queries which don't start
+    /// with any route's keyword. Its keyword is ignored.
+    pub fn fallback(mut self, route: Route<E>) -> Self {
+        self.fallback = Some(route);
+        self
+    }
+}
+
+impl<E: RunnableError> Runnable for CommandRouter<E> {
+    type Error = E;
+
+    fn run(mut self, workflow: &mut Workflow) -> Result<(), E> {
+        let query = self.query.trim_start().to_string();
+
+        let matched = self
+            .routes
+            .iter()
+            .position(|route| query.starts_with(&route.prefix()));
+        let route = match (matched, self.fallback) {
+            (Some(i), _) => {
+                let route = self.routes.swap_remove(i);
+                let rest = query[route.prefix().len()..].trim_start().to_string();
+                Some((route, rest))
+            }
+            (None, Some(fallback)) if !query.trim().is_empty() => Some((fallback, query.clone())),
+            _ => None,
+        };
+
+        match route {
+            Some((route, rest)) => {
+                if route.filter && !rest.is_empty() {
+                    workflow.set_filter_keyword(rest.clone());
+                }
+                (route.handler)(workflow, rest)
+            }
+            None => {
+                workflow.append_items(self.routes.iter().map(Route::item).collect());
+                if !query.trim().is_empty() {
+                    workflow.set_filter_keyword(query.trim().to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::workflow_in;
+    use crate::Error;
+
+    struct Echo(&'static str, String);
+
+    impl Runnable for Echo {
+        type Error = Error;
+
+        fn run(self, workflow: &mut Workflow) -> Result<(), Error> {
+            workflow.append_item(Item::new(format!("{}: {}", self.0, self.1)));
+            workflow.append_item(Item::new("unrelated"));
+            Ok(())
+        }
+    }
+
+    fn router(query: &str) -> CommandRouter<Error> {
+        CommandRouter::new(query)
+            .route(
+                Route::new("repo", |q| Echo("repo", q))
+                    .title("Repositories")
+                    .placeholder("Search repositories"),
+            )
+            .route(Route::new("pr", |q| Echo("pr", q)).filter(true))
+    }
+
+    fn run(router: CommandRouter<Error>) -> Vec<Item> {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        router.run(&mut workflow).unwrap();
+        workflow.filter_and_sort();
+        workflow.response.items
+    }
+
+    fn titles(items: &[Item]) -> Vec<&str> {
+        items.iter().map(|item| item.title.as_str()).collect()
+    }
+
+    #[test]
+    fn test_empty_query_lists_routes() {
+        let items = run(router(""));
+        assert_eq!(titles(&items), vec!["Repositories", "pr"]);
+        assert_eq!(items[0].subtitle.as_deref(), Some("Search repositories"));
+        assert_eq!(items[0].autocomplete.as_deref(), Some("repo "));
+        assert_eq!(items[1].autocomplete.as_deref(), Some("pr "));
+        assert_eq!(items[0].valid, Some(false));
+    }
+
+    #[test]
+    fn test_partial_keyword_filters_routes() {
+        let items = run(router("rep"));
+        assert_eq!(titles(&items), vec!["Repositories"]);
+    }
+
+    #[test]
+    fn test_route_receives_rest_of_query() {
+        let items = run(router("repo  alfrusco"));
+        assert_eq!(titles(&items), vec!["repo: alfrusco", "unrelated"]);
+
+        let items = run(router("repo "));
+        assert_eq!(titles(&items), vec!["repo: ", "unrelated"]);
+    }
+
+    #[test]
+    fn test_route_filtering() {
+        let items = run(router("pr fix bug"));
+        assert_eq!(titles(&items), vec!["pr: fix bug"]);
+    }
+
+    #[test]
+    fn test_fallback() {
+        let fallback = |query: &str| router(query).fallback(Route::new("", |q| Echo("search", q)));
+
+        let items = run(fallback("whatever"));
+        assert_eq!(titles(&items), vec!["search: whatever", "unrelated"]);
+
+        let items = run(fallback(""));
+        assert_eq!(titles(&items), vec!["Repositories", "pr"]);
+    }
+}
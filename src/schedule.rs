@@ -0,0 +1,300 @@
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, Local, TimeZone, Timelike};
+
+use crate::{Error, Result};
+
+/// RunPolicy decides when a background job needs to run again, judged
+/// against when it last ran.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunPolicy {
+    /// Run if the job has never completed a run, and never again after
+    /// that, e.g. for a one-off data migration.
+    Once,
+
+    /// Run when the last run is older than the duration. This is what
+    /// run_in_background's max_age means.
+    Every(Duration),
+
+    /// Run when the schedule has come due since the last run, e.g. daily
+    /// at 6am with `RunPolicy::cron("0 6 * * *")`. A job that has never
+    /// run is due immediately.
+    Cron(CronSchedule),
+}
+
+impl RunPolicy {
+    /// Parses a cron expression into RunPolicy::Cron. See CronSchedule.
+    pub fn cron(expr: &str) -> Result<RunPolicy> {
+        Ok(RunPolicy::Cron(expr.parse()?))
+    }
+
+    /// Returns true if a job whose last run started at `last_run` (None if
+    /// it never ran) should run at `now`.
+    pub fn is_due(&self, last_run: Option<SystemTime>, now: SystemTime) -> bool {
+        let Some(last_run) = last_run else {
+            return true;
+        };
+        match self {
+            RunPolicy::Once => false,
+            RunPolicy::Every(max_age) => {
+                now.duration_since(last_run).unwrap_or_default() >= *max_age
+            }
+            RunPolicy::Cron(schedule) => schedule
+                .next_after(DateTime::<Local>::from(last_run))
+                .is_some_and(|next| SystemTime::from(next) <= now),
+        }
+    }
+}
+
+impl std::fmt::Display for RunPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunPolicy::Once => write!(f, "once"),
+            RunPolicy::Every(max_age) => {
+                write!(f, "every {}", humantime::format_duration(*max_age))
+            }
+            RunPolicy::Cron(schedule) => write!(f, "cron {}", schedule),
+        }
+    }
+}
+
+impl From<Duration> for RunPolicy {
+    fn from(max_age: Duration) -> Self {
+        RunPolicy::Every(max_age)
+    }
+}
+
+/// CronSchedule is a standard five field cron expression: minute, hour,
+/// day of month, month and day of week (0 or 7 is Sunday), evaluated in
+/// local time. Each field is `*`, a number, a range (`1-5`), a step
+/// (`*/15`, `0-30/10`) or a comma separated list of those. Month and
+/// weekday names aren't supported.
+///
+/// As in cron, when both day of month and day of week are restricted, a
+/// day matching either one is scheduled. A field starting with `*` (such
+/// as `*/2`) doesn't count as restricted, so it must match as well.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    expr: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+/// How far ahead next_after looks before giving up on a schedule that
+/// never matches (e.g. February 30th).
+const MAX_SEARCH_DAYS: i64 = 5 * 366;
+
+impl FromStr for CronSchedule {
+    type Err = Error;
+
+    fn from_str(expr: &str) -> Result<CronSchedule> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(Error::Workflow(format!(
+                "cron expression {:?} must have 5 fields",
+                expr
+            )));
+        };
+        let mut weekdays = parse_field(weekday, 0, 7, expr)?;
+        // Both 0 and 7 mean Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(CronSchedule {
+            expr: expr.to_string(),
+            minutes: parse_field(minute, 0, 59, expr)?,
+            hours: parse_field(hour, 0, 23, expr)?,
+            days: parse_field(day, 1, 31, expr)?,
+            months: parse_field(month, 1, 12, expr)?,
+            weekdays,
+            days_restricted: !day.starts_with('*'),
+            weekdays_restricted: !weekday.starts_with('*'),
+        })
+    }
+}
+
+impl std::fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.expr)
+    }
+}
+
+/// Parses one cron field into a bitmask of the values it matches.
+fn parse_field(field: &str, min: u32, max: u32, expr: &str) -> Result<u64> {
+    let invalid = || {
+        Error::Workflow(format!(
+            "invalid field {:?} in cron expression {:?}",
+            field, expr
+        ))
+    };
+    let mut mask = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (
+                    start.parse().map_err(|_| invalid())?,
+                    end.parse().map_err(|_| invalid())?,
+                ),
+                None => {
+                    let value = range.parse().map_err(|_| invalid())?;
+                    // "5/10" means from 5 to the end, every 10
+                    (value, if part.contains('/') { max } else { value })
+                }
+            },
+        };
+        if step == 0 || start < min || end > max || start > end {
+            return Err(invalid());
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+fn matches(mask: u64, value: u32) -> bool {
+    mask & (1 << value) != 0
+}
+
+impl CronSchedule {
+    fn day_matches(&self, time: &DateTime<Local>) -> bool {
+        let day = matches(self.days, time.day());
+        let weekday = matches(self.weekdays, time.weekday().num_days_from_sunday());
+        match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// Returns the first scheduled time strictly after `after`, or None if
+    /// the schedule never comes due within the next five years.
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let start = after
+            .with_second(0)?
+            .with_nanosecond(0)?
+            .checked_add_signed(ChronoDuration::minutes(1))?;
+        let limit = start + ChronoDuration::days(MAX_SEARCH_DAYS);
+        let mut time = start;
+        while time < limit {
+            if !matches(self.months, time.month()) || !self.day_matches(&time) {
+                time = start_of_next_day(&time)?;
+            } else if !matches(self.hours, time.hour()) {
+                time = (time + ChronoDuration::hours(1)).with_minute(0)?;
+            } else if !matches(self.minutes, time.minute()) {
+                time += ChronoDuration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+}
+
+fn start_of_next_day(time: &DateTime<Local>) -> Option<DateTime<Local>> {
+    let midnight = time.date_naive().succ_opt()?.and_hms_opt(0, 0, 0)?;
+    // Midnight can be skipped by a DST change; use the first valid time
+    Local.from_local_datetime(&midnight).earliest().or_else(|| {
+        Local
+            .from_local_datetime(&(midnight + ChronoDuration::hours(1)))
+            .earliest()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(s: &str) -> DateTime<Local> {
+        let naive = chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+        Local.from_local_datetime(&naive).earliest().unwrap()
+    }
+
+    fn next(expr: &str, after: &str) -> String {
+        let schedule: CronSchedule = expr.parse().unwrap();
+        schedule
+            .next_after(local(after))
+            .unwrap()
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    }
+
+    #[test]
+    fn test_next_after() {
+        assert_eq!(next("* * * * *", "2024-03-01 10:15"), "2024-03-01 10:16");
+        assert_eq!(next("*/15 * * * *", "2024-03-01 10:15"), "2024-03-01 10:30");
+        assert_eq!(next("0 6 * * *", "2024-03-01 10:15"), "2024-03-02 06:00");
+        assert_eq!(next("30 9 1 * *", "2024-03-01 10:15"), "2024-04-01 09:30");
+        // Mondays to Fridays; 2024-03-01 is a Friday
+        assert_eq!(next("0 9 * * 1-5", "2024-03-01 10:15"), "2024-03-04 09:00");
+        assert_eq!(next("0 0 * * 7", "2024-03-01 10:15"), "2024-03-03 00:00");
+        // The 15th or any Sunday
+        assert_eq!(next("0 0 15 * 0", "2024-03-01 10:15"), "2024-03-03 00:00");
+        // Odd days that are Mondays: a */2 day of month is unrestricted
+        assert_eq!(next("0 0 */2 * 1", "2024-03-01 10:15"), "2024-03-11 00:00");
+        assert_eq!(next("0 0 29 2 *", "2024-03-01 10:15"), "2028-02-29 00:00");
+    }
+
+    #[test]
+    fn test_never_matching_schedule() {
+        let schedule: CronSchedule = "0 0 30 2 *".parse().unwrap();
+        assert_eq!(schedule.next_after(local("2024-03-01 10:15")), None);
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        for expr in [
+            "",
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(expr.parse::<CronSchedule>().is_err(), "{:?}", expr);
+        }
+    }
+
+    #[test]
+    fn test_is_due() {
+        let now = SystemTime::now();
+        let hour_ago = now - Duration::from_secs(3600);
+
+        assert!(RunPolicy::Once.is_due(None, now));
+        assert!(!RunPolicy::Once.is_due(Some(hour_ago), now));
+
+        let every = RunPolicy::from(Duration::from_secs(1800));
+        assert!(every.is_due(Some(hour_ago), now));
+        assert!(!every.is_due(Some(now), now));
+
+        let minutely = RunPolicy::cron("* * * * *").unwrap();
+        assert!(minutely.is_due(None, now));
+        assert!(minutely.is_due(Some(hour_ago), now));
+        assert!(!minutely.is_due(Some(now), now));
+    }
+
+    #[test]
+    fn test_policy_display() {
+        assert_eq!(RunPolicy::Once.to_string(), "once");
+        assert_eq!(
+            RunPolicy::from(Duration::from_secs(3600)).to_string(),
+            "every 1h"
+        );
+        assert_eq!(
+            RunPolicy::cron("0 6 * * *").unwrap().to_string(),
+            "cron 0 6 * * *"
+        );
+    }
+}
@@ -0,0 +1,375 @@
+use std::fmt::Debug;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use log::{debug, error};
+
+use crate::config::EnvMap;
+use crate::workflow::Workflow;
+use crate::{Error, InternalCommand, Item, Result};
+
+/// The query prefix used for the secret setup step. Items returned by
+/// missing_secret_item autocomplete to `!secret NAME `, after which the
+/// user types the value.
+pub const SETUP_PREFIX: &str = "!secret ";
+
+/// SecretStore abstracts where a workflow's API keys and tokens live, so
+/// workflows can look them up by name without caring whether they come
+/// from the Keychain or the environment.
+pub trait SecretStore: Debug + Send + Sync {
+    /// Returns the secret with the given name, or None if it isn't set.
+    fn get(&self, name: &str) -> Result<Option<String>>;
+
+    /// Stores (or replaces) the secret with the given name.
+    fn set(&self, name: &str, value: &str) -> Result<()>;
+}
+
+/// EnvSecretStore reads secrets from environment variables, such as those
+/// defined in the workflow's User Configuration. It is read-only.
+///
+/// ```
+/// # let dir = tempfile::tempdir().unwrap();
+/// # let workflow = alfrusco::test_support::workflow_in(dir.path());
+/// use alfrusco::secrets::EnvSecretStore;
+///
+/// let store = EnvSecretStore::new(&workflow.config.env);
+/// ```
+///
+#[derive(Clone, Default)]
+pub struct EnvSecretStore {
+    env: EnvMap,
+}
+
+impl EnvSecretStore {
+    /// Reads secrets from `env`, usually the workflow's config.env.
+    pub fn new(env: &EnvMap) -> Self {
+        EnvSecretStore { env: env.clone() }
+    }
+}
+
+// The environment holds the secrets themselves, so it isn't printed
+impl Debug for EnvSecretStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EnvSecretStore").finish_non_exhaustive()
+    }
+}
+
+impl SecretStore for EnvSecretStore {
+    fn get(&self, name: &str) -> Result<Option<String>> {
+        Ok(self
+            .env
+            .get(name)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string))
+    }
+
+    fn set(&self, name: &str, _value: &str) -> Result<()> {
+        Err(Error::Workflow(format!(
+            "cannot set {}: environment secrets are read-only",
+            name
+        )))
+    }
+}
+
+/// KeychainSecretStore keeps secrets as generic passwords in the user's
+/// login Keychain (via the `security` command), under the given service
+/// name. Use the workflow's bundle id as the service so secrets persist
+/// across workflow updates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeychainSecretStore {
+    service: String,
+}
+
+/// The exit code `security` uses when the item could not be found.
+const ERR_SEC_ITEM_NOT_FOUND: i32 = 44;
+
+impl KeychainSecretStore {
+    pub fn new(service: impl Into<String>) -> Self {
+        Self {
+            service: service.into(),
+        }
+    }
+
+    /// Returns a store using the workflow's bundle id as the service.
+    pub fn for_workflow(workflow: &Workflow) -> Self {
+        Self::new(workflow.config.workflow_bundleid.clone())
+    }
+
+    fn find_args(&self, name: &str) -> Vec<String> {
+        vec![
+            "find-generic-password".to_string(),
+            "-s".to_string(),
+            self.service.clone(),
+            "-a".to_string(),
+            name.to_string(),
+            "-w".to_string(),
+        ]
+    }
+
+    /// Returns the command saving the secret, for `security -i` to read
+    /// from stdin. The value isn't passed as an argument, where any local
+    /// user could see it with `ps`.
+    fn add_command(&self, name: &str, value: &str) -> Result<String> {
+        if value.contains(['\n', '\r']) {
+            return Err(Error::Workflow(format!(
+                "cannot save {}: the value spans more than one line",
+                name
+            )));
+        }
+        let quote = |arg: &str| format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""));
+        Ok(format!(
+            "add-generic-password -U -s {} -a {} -w {}\n",
+            quote(&self.service),
+            quote(name),
+            quote(value)
+        ))
+    }
+}
+
+impl SecretStore for KeychainSecretStore {
+    fn get(&self, name: &str) -> Result<Option<String>> {
+        let output = Command::new("security")
+            .args(self.find_args(name))
+            .output()?;
+        if output.status.code() == Some(ERR_SEC_ITEM_NOT_FOUND) {
+            return Ok(None);
+        }
+        if !output.status.success() {
+            return Err(Error::Workflow(format!(
+                "unable to read {} from the Keychain: {}",
+                name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        let value = String::from_utf8(output.stdout)?;
+        Ok(Some(value.trim_end_matches('\n').to_string()))
+    }
+
+    fn set(&self, name: &str, value: &str) -> Result<()> {
+        let command = self.add_command(name, value)?;
+        let mut child = Command::new("security")
+            .arg("-i")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(command.as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(Error::Workflow(format!(
+                "unable to save {} to the Keychain: {}",
+                name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        debug!("saved {} to the Keychain", name);
+        Ok(())
+    }
+}
+
+/// Returns an item telling the user that the named secret is missing.
+/// Actioning it (or pressing Tab) autocompletes to the setup step handled
+/// by Workflow::secret_setup.
+pub fn missing_secret_item(name: &str) -> Item {
+    Item::new(format!("{} is not configured", name))
+        .subtitle(format!("Press Tab or Enter to set {}", name))
+        .autocomplete(format!("{}{} ", SETUP_PREFIX, name))
+        .valid(false)
+        .sticky(true)
+}
+
+/// Splits a setup step query (`!secret NAME value`) into the secret name
+/// and the (possibly empty) value typed so far.
+pub fn parse_setup_query(query: &str) -> Option<(&str, &str)> {
+    let rest = query.strip_prefix(SETUP_PREFIX)?;
+    let (name, value) = rest.split_once(' ').unwrap_or((rest, ""));
+    (!name.is_empty()).then_some((name, value.trim()))
+}
+
+impl Workflow {
+    /// Returns the named secret from the store. When it is missing (or
+    /// can't be read), an item prompting the user to configure it is added
+    /// and None is returned.
+    pub fn require_secret(&mut self, store: &dyn SecretStore, name: &str) -> Option<String> {
+        match store.get(name) {
+            Ok(Some(value)) => Some(value),
+            Ok(None) => {
+                self.prepend_item(missing_secret_item(name));
+                None
+            }
+            Err(e) => {
+                error!("unable to read secret {}: {}", name, e);
+                self.prepend_item(missing_secret_item(name).subtitle(e.to_string()));
+                None
+            }
+        }
+    }
+
+    /// Handles the secret setup step. If `query` is a setup query (see
+    /// missing_secret_item), an item that saves the typed value to the
+    /// Keychain is added and true is returned, in which case the workflow
+    /// should return without producing its normal results.
+    ///
+    /// The item carries a `set_secret` InternalCommand, so the workflow's
+    /// Script Filter must be connected to a Run Script action invoking the
+    /// workflow binary again. The command holds the typed value, which is
+    /// redacted when the command or the environment is debug-printed.
+    ///
+    pub fn secret_setup(&mut self, query: &str) -> bool {
+        let Some((name, value)) = parse_setup_query(query) else {
+            return false;
+        };
+        let item = if value.is_empty() {
+            Item::new(format!("Enter the value for {}", name))
+                .subtitle("Type or paste the value, then press Enter")
+                .valid(false)
+        } else {
+            Item::new(format!("Save {}", name))
+                .subtitle("Stores the value in your Keychain")
+                .arg("")
+                .internal_command(
+                    InternalCommand::new("set_secret")
+                        .arg("secret_name", name)
+                        .arg("secret_value", value),
+                )
+        };
+        self.prepend_item(item.sticky(true));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::workflow_in;
+    use crate::INTERNAL_VAR;
+
+    #[derive(Debug)]
+    struct FailingStore;
+
+    impl SecretStore for FailingStore {
+        fn get(&self, _name: &str) -> Result<Option<String>> {
+            Err(Error::Workflow("locked".to_string()))
+        }
+
+        fn set(&self, _name: &str, _value: &str) -> Result<()> {
+            Err(Error::Workflow("locked".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_env_secret_store() {
+        let env: EnvMap = [("API_TOKEN", "abc123"), ("EMPTY_TOKEN", "")]
+            .into_iter()
+            .collect();
+        let store = EnvSecretStore::new(&env);
+        assert_eq!(store.get("API_TOKEN").unwrap(), Some("abc123".to_string()));
+        assert_eq!(store.get("EMPTY_TOKEN").unwrap(), None);
+        assert_eq!(store.get("MISSING_TOKEN").unwrap(), None);
+        assert!(store.set("API_TOKEN", "new").is_err());
+        assert!(!format!("{:?}", store).contains("abc123"));
+
+        // Only the snapshot is consulted
+        temp_env::with_var("MISSING_TOKEN", Some("xyz"), || {
+            assert_eq!(store.get("MISSING_TOKEN").unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn test_keychain_args() {
+        let store = KeychainSecretStore::new("com.example.workflow");
+        assert_eq!(
+            store.find_args("API_TOKEN"),
+            [
+                "find-generic-password",
+                "-s",
+                "com.example.workflow",
+                "-a",
+                "API_TOKEN",
+                "-w"
+            ]
+        );
+        assert_eq!(
+            store.add_command("API_TOKEN", r#"a "b" \c"#).unwrap(),
+            "add-generic-password -U -s \"com.example.workflow\" -a \"API_TOKEN\" \
+             -w \"a \\\"b\\\" \\\\c\"\n"
+        );
+        assert!(store.add_command("API_TOKEN", "a\nb").is_err());
+    }
+
+    #[test]
+    fn test_parse_setup_query() {
+        assert_eq!(
+            parse_setup_query("!secret API_TOKEN "),
+            Some(("API_TOKEN", ""))
+        );
+        assert_eq!(
+            parse_setup_query("!secret API_TOKEN abc123"),
+            Some(("API_TOKEN", "abc123"))
+        );
+        assert_eq!(
+            parse_setup_query("!secret API_TOKEN"),
+            Some(("API_TOKEN", ""))
+        );
+        assert_eq!(parse_setup_query("!secret "), None);
+        assert_eq!(parse_setup_query("search terms"), None);
+    }
+
+    #[test]
+    fn test_require_secret() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+
+        workflow.config.env.set("API_TOKEN", "abc123");
+        let store = EnvSecretStore::new(&workflow.config.env);
+        assert_eq!(
+            workflow.require_secret(&store, "API_TOKEN"),
+            Some("abc123".to_string())
+        );
+        assert!(workflow.response.items.is_empty());
+
+        workflow.config.env.remove("API_TOKEN");
+        let store = EnvSecretStore::new(&workflow.config.env);
+        assert_eq!(workflow.require_secret(&store, "API_TOKEN"), None);
+        let item = &workflow.response.items[0];
+        assert_eq!(item.title, "API_TOKEN is not configured");
+        assert_eq!(item.autocomplete.as_deref(), Some("!secret API_TOKEN "));
+        assert_eq!(item.valid, Some(false));
+    }
+
+    #[test]
+    fn test_require_secret_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        assert_eq!(workflow.require_secret(&FailingStore, "API_TOKEN"), None);
+        assert_eq!(
+            workflow.response.items[0].subtitle.as_deref(),
+            Some("Workflow Error: locked")
+        );
+    }
+
+    #[test]
+    fn test_secret_setup() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+
+        assert!(!workflow.secret_setup("rust"));
+        assert!(workflow.response.items.is_empty());
+
+        assert!(workflow.secret_setup("!secret API_TOKEN "));
+        assert_eq!(
+            workflow.response.items[0].title,
+            "Enter the value for API_TOKEN"
+        );
+
+        assert!(workflow.secret_setup("!secret API_TOKEN abc123"));
+        let item = &workflow.response.items[0];
+        assert_eq!(item.title, "Save API_TOKEN");
+        assert_eq!(
+            item.get_var(INTERNAL_VAR),
+            Some(r#"{"command":"set_secret","secret_name":"API_TOKEN","secret_value":"abc123"}"#)
+        );
+    }
+}
@@ -0,0 +1,75 @@
+//! Ready-made data sources for common workflows.
+//!
+//! Each source reads data that many workflows rebuild from scratch (such
+//! as browser bookmarks or recently opened files) and returns it as
+//! URLItems or Items, ready to be appended to a Workflow.
+//!
+use std::fs::{copy, remove_file};
+use std::path::Path;
+use std::process::Command;
+
+use serde::de::DeserializeOwned;
+
+use crate::{Error, Result, ResultExt};
+
+pub mod bookmarks;
+#[cfg(feature = "contacts")]
+pub mod contacts;
+mod recent_files;
+
+pub use self::recent_files::{recent_files, FileKind, RECENT_DAYS};
+
+/// Runs a query against a copy of an SQLite database with the `sqlite3`
+/// command, returning its rows. The database is copied (along with its
+/// write-ahead log) because apps keep their databases locked while
+/// running.
+pub(crate) fn query_sqlite<T: DeserializeOwned>(path: &Path, query: &str) -> Result<Vec<T>> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let copy_path = std::env::temp_dir().join(format!("alfrusco-{}-{}", std::process::id(), name));
+    let wal = path.with_file_name(format!("{}-wal", name));
+    let copy_wal =
+        copy_path.with_file_name(format!("alfrusco-{}-{}-wal", std::process::id(), name));
+    copy(path, &copy_path).with_context(|| format!("unable to copy {}", path.display()))?;
+    if wal.is_file() {
+        copy(&wal, &copy_wal).with_context(|| format!("unable to copy {}", wal.display()))?;
+    }
+
+    let output = Command::new("sqlite3")
+        .arg("-readonly")
+        .arg("-json")
+        .arg(&copy_path)
+        .arg(query)
+        .output();
+    remove_file(&copy_path).ok();
+    remove_file(&copy_wal).ok();
+
+    let output = output?;
+    if !output.status.success() {
+        return Err(Error::Workflow(format!(
+            "sqlite3 failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    parse_sqlite_rows(&String::from_utf8_lossy(&output.stdout))
+}
+
+pub(crate) fn parse_sqlite_rows<T: DeserializeOwned>(json: &str) -> Result<Vec<T>> {
+    // sqlite3 prints nothing at all when there are no rows
+    if json.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::from_str(json)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sqlite_rows() {
+        let rows: Vec<serde_json::Value> = parse_sqlite_rows("").unwrap();
+        assert!(rows.is_empty());
+        let rows: Vec<serde_json::Value> = parse_sqlite_rows(r#"[{"id": 1}]"#).unwrap();
+        assert_eq!(rows[0]["id"], 1);
+    }
+}
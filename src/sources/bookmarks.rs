@@ -0,0 +1,685 @@
+//! Browser bookmarks as URLItems.
+//!
+//! Bookmarks reads the bookmark store of Safari, Firefox, Arc, or a
+//! Chromium based browser (Chrome, Chromium, Brave, Edge) for one of the
+//! browser's profiles. Workflow::append_bookmarks caches the result and
+//! refreshes it in the background once it's stale.
+//!
+//! ```no_run
+//! use alfrusco::sources::bookmarks::{Bookmarks, Browser};
+//!
+//! let profiles = Bookmarks::new(Browser::Chrome).profiles()?;
+//! let bookmarks = Bookmarks::new(Browser::Chrome)
+//!     .profile("Profile 1")
+//!     .load()?;
+//! # Ok::<(), alfrusco::Error>(())
+//! ```
+//!
+//! Safari's bookmarks can only be read by apps that have been granted Full
+//! Disk Access, so Alfred needs it for Safari bookmark workflows.
+//!
+
+use std::fmt;
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use log::debug;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use super::query_sqlite;
+use crate::workflow::Workflow;
+use crate::{Error, Result, URLItem};
+
+/// Browser is a browser whose bookmarks can be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Browser {
+    Safari,
+    Chrome,
+    Chromium,
+    Brave,
+    Edge,
+    Arc,
+    Firefox,
+}
+
+impl fmt::Display for Browser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Browser::Safari => "Safari",
+            Browser::Chrome => "Chrome",
+            Browser::Chromium => "Chromium",
+            Browser::Brave => "Brave",
+            Browser::Edge => "Edge",
+            Browser::Arc => "Arc",
+            Browser::Firefox => "Firefox",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Browser {
+    /// The browser's data directory, relative to the home directory.
+    fn data_dir(&self) -> &'static str {
+        match self {
+            Browser::Safari => "Library/Safari",
+            Browser::Chrome => "Library/Application Support/Google/Chrome",
+            Browser::Chromium => "Library/Application Support/Chromium",
+            Browser::Brave => "Library/Application Support/BraveSoftware/Brave-Browser",
+            Browser::Edge => "Library/Application Support/Microsoft Edge",
+            Browser::Arc => "Library/Application Support/Arc",
+            Browser::Firefox => "Library/Application Support/Firefox/Profiles",
+        }
+    }
+}
+
+/// The profile Chromium based browsers create first.
+const CHROMIUM_DEFAULT_PROFILE: &str = "Default";
+
+/// The suffix of the profile Firefox uses by default.
+const FIREFOX_DEFAULT_PROFILE: &str = ".default-release";
+
+/// Lists bookmarks (not folders, separators or smart bookmarks) with their
+/// titles from Firefox's places database.
+const FIREFOX_QUERY: &str = "SELECT b.title AS title, p.url AS url \
+     FROM moz_bookmarks b JOIN moz_places p ON b.fk = p.id \
+     WHERE b.type = 1 AND p.url NOT LIKE 'place:%' \
+     ORDER BY b.parent, b.position";
+
+/// Bookmarks reads one browser profile's bookmarks.
+///
+/// Profiles are the directory names the browser uses for them: "Default"
+/// or "Profile 1" for Chromium based browsers, and e.g.
+/// "x1y2z3.default-release" (or just "default-release") for Firefox.
+/// Without a profile, the browser's default profile is read. Safari and
+/// Arc have a single bookmark store, so their profile is ignored.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bookmarks {
+    browser: Browser,
+    profile: Option<String>,
+    home: Option<PathBuf>,
+}
+
+impl Bookmarks {
+    pub fn new(browser: Browser) -> Self {
+        Bookmarks {
+            browser,
+            profile: None,
+            home: None,
+        }
+    }
+
+    pub fn profile(mut self, profile: impl Into<String>) -> Self {
+        self.profile = Some(profile.into());
+        self
+    }
+
+    /// Looks for the browser's data in `home` instead of the user's home
+    /// directory. Without it, HOME is read from the process environment,
+    /// since Bookmarks is used without a Workflow; pass the HOME in
+    /// `workflow.config.env` to use the workflow's snapshot.
+    pub fn home(mut self, home: impl Into<PathBuf>) -> Self {
+        self.home = Some(home.into());
+        self
+    }
+
+    fn data_dir(&self) -> Result<PathBuf> {
+        let home = match &self.home {
+            Some(home) => home.clone(),
+            None => std::env::var_os("HOME")
+                .map(PathBuf::from)
+                .ok_or_else(|| Error::MissingEnvVar("HOME".to_string()))?,
+        };
+        Ok(home.join(self.browser.data_dir()))
+    }
+
+    /// Returns the names of the browser's profiles that have bookmarks,
+    /// sorted by name. Safari and Arc have none.
+    pub fn profiles(&self) -> Result<Vec<String>> {
+        let store = match self.browser {
+            Browser::Safari | Browser::Arc => return Ok(Vec::new()),
+            Browser::Firefox => "places.sqlite",
+            _ => "Bookmarks",
+        };
+        let dir = self.data_dir()?;
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut profiles: Vec<String> = read_dir(&dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().join(store).is_file())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        profiles.sort();
+        Ok(profiles)
+    }
+
+    /// Returns the path of the file holding the bookmarks.
+    pub fn path(&self) -> Result<PathBuf> {
+        let dir = self.data_dir()?;
+        let path = match self.browser {
+            Browser::Safari => dir.join("Bookmarks.plist"),
+            Browser::Arc => dir.join("StorableSidebar.json"),
+            Browser::Firefox => dir.join(self.firefox_profile()?).join("places.sqlite"),
+            _ => {
+                let profile = self.profile.as_deref().unwrap_or(CHROMIUM_DEFAULT_PROFILE);
+                dir.join(profile).join("Bookmarks")
+            }
+        };
+        if !path.is_file() {
+            return Err(Error::Workflow(format!(
+                "no {} bookmarks found at {}",
+                self.browser,
+                path.display()
+            )));
+        }
+        Ok(path)
+    }
+
+    fn firefox_profile(&self) -> Result<String> {
+        let profiles = self.profiles()?;
+        let found = match &self.profile {
+            Some(name) => profiles.iter().find(|profile| {
+                *profile == name || profile.split_once('.').is_some_and(|(_, n)| n == name)
+            }),
+            None => profiles
+                .iter()
+                .find(|profile| profile.ends_with(FIREFOX_DEFAULT_PROFILE))
+                .or(profiles.first()),
+        };
+        found.cloned().ok_or_else(|| {
+            Error::Workflow(format!(
+                "no Firefox profile {}found",
+                self.profile
+                    .as_ref()
+                    .map(|name| format!("{:?} ", name))
+                    .unwrap_or_default()
+            ))
+        })
+    }
+
+    /// Reads the bookmarks, in the order the browser shows them.
+    pub fn load(&self) -> Result<Vec<URLItem>> {
+        let path = self.path()?;
+        debug!("reading {} bookmarks from {}", self.browser, path.display());
+        let bookmarks = match self.browser {
+            Browser::Safari => parse_safari(&read_safari_plist(&path)?),
+            Browser::Arc => parse_arc(&read_to_string(&path)?)?,
+            Browser::Firefox => firefox_bookmarks(query_sqlite(&path, FIREFOX_QUERY)?),
+            _ => parse_chromium(&read_to_string(&path)?)?,
+        };
+        Ok(bookmarks
+            .into_iter()
+            .map(|(title, url)| URLItem::new(title, url))
+            .collect())
+    }
+
+    /// The key bookmarks are cached under by append_bookmarks.
+    pub fn cache_key(&self) -> String {
+        format!(
+            "bookmarks:{}:{}",
+            self.browser,
+            self.profile.as_deref().unwrap_or_default()
+        )
+    }
+}
+
+impl Workflow {
+    /// Appends the bookmarks as URLItems, reading them in a background
+    /// process and reusing them for `max_age`. While they are read (or
+    /// re-read once stale), the previously read bookmarks are shown and
+    /// Alfred re-runs the Script Filter. See Workflow::stream_items.
+    pub async fn append_bookmarks(
+        &mut self,
+        bookmarks: Bookmarks,
+        max_age: Duration,
+    ) -> Result<()> {
+        let key = bookmarks.cache_key();
+        self.stream_items(
+            &key,
+            max_age,
+            |sink| async move { sink.send(bookmarks.load()?) },
+        )
+        .await
+    }
+}
+
+#[derive(Deserialize)]
+struct ChromiumNode {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    url: Option<String>,
+    #[serde(default)]
+    children: Vec<ChromiumNode>,
+}
+
+fn parse_chromium(json: &str) -> Result<Vec<(String, String)>> {
+    let file: Value = serde_json::from_str(json)?;
+    let mut bookmarks = Vec::new();
+    for root in ["bookmark_bar", "other", "synced"] {
+        if let Some(node) = file.pointer(&format!("/roots/{}", root)) {
+            let node = ChromiumNode::deserialize(node)?;
+            collect_chromium(node, &mut bookmarks);
+        }
+    }
+    Ok(bookmarks)
+}
+
+fn collect_chromium(node: ChromiumNode, bookmarks: &mut Vec<(String, String)>) {
+    match (node.kind.as_str(), node.url) {
+        ("url", Some(url)) => bookmarks.push((title_or_url(node.name, &url), url)),
+        _ => {
+            for child in node.children {
+                collect_chromium(child, bookmarks);
+            }
+        }
+    }
+}
+
+/// Reads the pinned and favorite tabs saved in Arc's sidebar.
+fn parse_arc(json: &str) -> Result<Vec<(String, String)>> {
+    let file: Value = serde_json::from_str(json)?;
+    let containers = file
+        .pointer("/sidebar/containers")
+        .and_then(Value::as_array)
+        .ok_or_else(|| Error::Workflow("Arc sidebar has no containers".to_string()))?;
+    let mut bookmarks = Vec::new();
+    for container in containers {
+        let items = container.get("items").and_then(Value::as_array);
+        // Items alternate between ids and the items themselves
+        for item in items.into_iter().flatten().filter(|item| item.is_object()) {
+            let tab = item.pointer("/data/tab");
+            let Some(url) = tab.and_then(|tab| tab["savedURL"].as_str()) else {
+                continue;
+            };
+            let title = item["title"]
+                .as_str()
+                .or_else(|| tab.and_then(|tab| tab["savedTitle"].as_str()))
+                .unwrap_or_default();
+            bookmarks.push((title_or_url(title.to_string(), url), url.to_string()));
+        }
+    }
+    Ok(bookmarks)
+}
+
+#[derive(Deserialize)]
+struct FirefoxRow {
+    title: Option<String>,
+    url: String,
+}
+
+fn firefox_bookmarks(rows: Vec<FirefoxRow>) -> Vec<(String, String)> {
+    rows.into_iter()
+        .map(|row| {
+            (
+                title_or_url(row.title.unwrap_or_default(), &row.url),
+                row.url,
+            )
+        })
+        .collect()
+}
+
+/// Converts Safari's binary Bookmarks.plist to XML with plutil, which
+/// (unlike its JSON output) handles the dates and data it contains.
+fn read_safari_plist(path: &Path) -> Result<Value> {
+    let output = Command::new("plutil")
+        .args(["-convert", "xml1", "-o", "-"])
+        .arg(path)
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::Workflow(format!(
+            "unable to read Safari bookmarks (does Alfred have Full Disk Access?): {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    parse_plist_xml(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// The list holding Safari's Reading List, which isn't bookmarks.
+const SAFARI_READING_LIST: &str = "com.apple.ReadingList";
+
+fn parse_safari(plist: &Value) -> Vec<(String, String)> {
+    let mut bookmarks = Vec::new();
+    collect_safari(plist, &mut bookmarks);
+    bookmarks
+}
+
+fn collect_safari(node: &Value, bookmarks: &mut Vec<(String, String)>) {
+    match node["WebBookmarkType"].as_str() {
+        Some("WebBookmarkTypeLeaf") => {
+            if let Some(url) = node["URLString"].as_str() {
+                let title = node.pointer("/URIDictionary/title").and_then(Value::as_str);
+                let title = title.unwrap_or_default().to_string();
+                bookmarks.push((title_or_url(title, url), url.to_string()));
+            }
+        }
+        Some("WebBookmarkTypeList") if node["Title"] != SAFARI_READING_LIST => {
+            for child in node["Children"].as_array().into_iter().flatten() {
+                collect_safari(child, bookmarks);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn title_or_url(title: String, url: &str) -> String {
+    if title.trim().is_empty() {
+        url.to_string()
+    } else {
+        title
+    }
+}
+
+/// Parses an XML property list into JSON values: dicts become objects,
+/// integers numbers, booleans bools, and strings, reals, dates and data
+/// become strings.
+pub(crate) fn parse_plist_xml(xml: &str) -> Result<Value> {
+    let start = xml
+        .find("<plist")
+        .and_then(|start| xml[start..].find('>').map(|end| start + end + 1))
+        .ok_or_else(|| Error::Workflow("not an XML property list".to_string()))?;
+    let mut parser = PlistParser {
+        rest: &xml[start..],
+    };
+    let tag = parser.next_tag()?;
+    parser.value(tag)
+}
+
+struct PlistParser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> PlistParser<'a> {
+    fn invalid(&self) -> Error {
+        let context: String = self.rest.chars().take(40).collect();
+        Error::Workflow(format!("invalid property list near {:?}", context))
+    }
+
+    /// Returns the next tag's content, e.g. "dict", "/dict" or "true/".
+    fn next_tag(&mut self) -> Result<&'a str> {
+        let start = self.rest.find('<').ok_or_else(|| self.invalid())?;
+        let end = self.rest[start..].find('>').ok_or_else(|| self.invalid())? + start;
+        let tag = self.rest[start + 1..end].trim();
+        self.rest = &self.rest[end + 1..];
+        Ok(tag)
+    }
+
+    /// Returns the unescaped text up to the closing `name` tag.
+    fn text(&mut self, name: &str) -> Result<String> {
+        let close = format!("</{}>", name);
+        let end = self.rest.find(&close).ok_or_else(|| self.invalid())?;
+        let text = self.rest[..end]
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&");
+        self.rest = &self.rest[end + close.len()..];
+        Ok(text)
+    }
+
+    fn value(&mut self, tag: &str) -> Result<Value> {
+        if let Some(empty) = tag.strip_suffix('/') {
+            return Ok(match empty.trim() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                "dict" => Value::Object(Map::new()),
+                "array" => Value::Array(Vec::new()),
+                _ => Value::String(String::new()),
+            });
+        }
+        let value = match tag {
+            "dict" => {
+                let mut map = Map::new();
+                loop {
+                    match self.next_tag()? {
+                        "/dict" => break,
+                        "key" => {
+                            let key = self.text("key")?;
+                            let tag = self.next_tag()?;
+                            map.insert(key, self.value(tag)?);
+                        }
+                        _ => return Err(self.invalid()),
+                    }
+                }
+                Value::Object(map)
+            }
+            "array" => {
+                let mut values = Vec::new();
+                loop {
+                    match self.next_tag()? {
+                        "/array" => break,
+                        tag => values.push(self.value(tag)?),
+                    }
+                }
+                Value::Array(values)
+            }
+            "integer" => {
+                let text = self.text("integer")?;
+                text.trim()
+                    .parse::<i64>()
+                    .map(Value::from)
+                    .unwrap_or(Value::String(text))
+            }
+            name @ ("string" | "real" | "date" | "data") => Value::String(self.text(name)?),
+            _ => return Err(self.invalid()),
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, write};
+
+    use super::*;
+    use crate::sources::parse_sqlite_rows;
+
+    fn titles_and_urls(bookmarks: &[(String, String)]) -> Vec<(&str, &str)> {
+        bookmarks
+            .iter()
+            .map(|(title, url)| (title.as_str(), url.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_chromium() {
+        let json = r#"{
+            "checksum": "abc",
+            "roots": {
+                "bookmark_bar": {"type": "folder", "name": "Bookmarks bar", "children": [
+                    {"type": "url", "name": "Rust", "url": "https://www.rust-lang.org/"},
+                    {"type": "folder", "name": "Docs", "children": [
+                        {"type": "url", "name": "", "url": "https://docs.rs/"}
+                    ]}
+                ]},
+                "other": {"type": "folder", "name": "Other", "children": [
+                    {"type": "url", "name": "Alfred", "url": "https://www.alfredapp.com/"}
+                ]},
+                "synced": {"type": "folder", "name": "Mobile", "children": []}
+            },
+            "version": 1
+        }"#;
+        assert_eq!(
+            titles_and_urls(&parse_chromium(json).unwrap()),
+            [
+                ("Rust", "https://www.rust-lang.org/"),
+                ("https://docs.rs/", "https://docs.rs/"),
+                ("Alfred", "https://www.alfredapp.com/"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_arc() {
+        let json = r#"{"sidebar": {"containers": [
+            {"global": {}},
+            {"items": [
+                "A",
+                {"id": "A", "title": null, "data": {"tab": {
+                    "savedURL": "https://github.com/", "savedTitle": "GitHub"}}},
+                "B",
+                {"id": "B", "title": "Work", "data": {"list": {}}},
+                "C",
+                {"id": "C", "title": "Renamed", "data": {"tab": {
+                    "savedURL": "https://example.com/", "savedTitle": "Example"}}}
+            ]}
+        ]}}"#;
+        assert_eq!(
+            titles_and_urls(&parse_arc(json).unwrap()),
+            [
+                ("GitHub", "https://github.com/"),
+                ("Renamed", "https://example.com/"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_firefox_bookmarks() {
+        let json = r#"[{"title":"MDN","url":"https://developer.mozilla.org/"},
+            {"title":null,"url":"https://example.com/"}]"#;
+        let rows = parse_sqlite_rows(json).unwrap();
+        assert_eq!(
+            titles_and_urls(&firefox_bookmarks(rows)),
+            [
+                ("MDN", "https://developer.mozilla.org/"),
+                ("https://example.com/", "https://example.com/"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_safari() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>Children</key>
+	<array>
+		<dict>
+			<key>Title</key>
+			<string>BookmarksBar</string>
+			<key>WebBookmarkType</key>
+			<string>WebBookmarkTypeList</string>
+			<key>Children</key>
+			<array>
+				<dict>
+					<key>URIDictionary</key>
+					<dict>
+						<key>title</key>
+						<string>Q&amp;A</string>
+					</dict>
+					<key>URLString</key>
+					<string>https://example.com/?a=1&amp;b=2</string>
+					<key>WebBookmarkType</key>
+					<string>WebBookmarkTypeLeaf</string>
+					<key>Sync</key>
+					<dict>
+						<key>Data</key>
+						<data>
+						YnBsaXN0MDA=
+						</data>
+						<key>Deleted</key>
+						<false/>
+					</dict>
+				</dict>
+			</array>
+		</dict>
+		<dict>
+			<key>Title</key>
+			<string>com.apple.ReadingList</string>
+			<key>WebBookmarkType</key>
+			<string>WebBookmarkTypeList</string>
+			<key>Children</key>
+			<array>
+				<dict>
+					<key>URLString</key>
+					<string>https://example.com/later</string>
+					<key>WebBookmarkType</key>
+					<string>WebBookmarkTypeLeaf</string>
+				</dict>
+			</array>
+		</dict>
+	</array>
+	<key>WebBookmarkFileVersion</key>
+	<integer>1</integer>
+	<key>WebBookmarkType</key>
+	<string>WebBookmarkTypeList</string>
+	<key>Title</key>
+	<string/>
+</dict>
+</plist>"#;
+        let plist = parse_plist_xml(xml).unwrap();
+        assert_eq!(plist["WebBookmarkFileVersion"], 1);
+        assert_eq!(
+            titles_and_urls(&parse_safari(&plist)),
+            [("Q&A", "https://example.com/?a=1&b=2")]
+        );
+    }
+
+    #[test]
+    fn test_invalid_plist() {
+        assert!(parse_plist_xml("<plist><dict><string>x</string></dict></plist>").is_err());
+        assert!(parse_plist_xml("{}").is_err());
+    }
+
+    #[test]
+    fn test_profiles() {
+        let home = tempfile::tempdir().unwrap();
+        let chrome = home.path().join(Browser::Chrome.data_dir());
+        for profile in ["Profile 1", "Default"] {
+            create_dir_all(chrome.join(profile)).unwrap();
+            write(
+                chrome.join(profile).join("Bookmarks"),
+                r#"{"roots": {"other": {"type": "folder", "children": [
+                    {"type": "url", "name": "Rust", "url": "https://www.rust-lang.org/"}
+                ]}}}"#,
+            )
+            .unwrap();
+        }
+        create_dir_all(chrome.join("System Profile")).unwrap();
+
+        let bookmarks = Bookmarks::new(Browser::Chrome).home(home.path());
+        assert_eq!(bookmarks.profiles().unwrap(), ["Default", "Profile 1"]);
+        assert_eq!(
+            bookmarks.path().unwrap(),
+            chrome.join("Default").join("Bookmarks")
+        );
+        assert_eq!(
+            bookmarks.clone().profile("Profile 1").load().unwrap(),
+            [URLItem::new("Rust", "https://www.rust-lang.org/")]
+        );
+        assert!(bookmarks.profile("Profile 2").load().is_err());
+        assert!(Bookmarks::new(Browser::Brave)
+            .home(home.path())
+            .profiles()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_firefox_profiles() {
+        let home = tempfile::tempdir().unwrap();
+        let profiles = home.path().join(Browser::Firefox.data_dir());
+        for profile in ["a1.default", "b2.default-release"] {
+            create_dir_all(profiles.join(profile)).unwrap();
+            write(profiles.join(profile).join("places.sqlite"), "").unwrap();
+        }
+
+        let bookmarks = Bookmarks::new(Browser::Firefox).home(home.path());
+        assert_eq!(
+            bookmarks.path().unwrap(),
+            profiles.join("b2.default-release").join("places.sqlite")
+        );
+        assert_eq!(
+            bookmarks.clone().profile("default").path().unwrap(),
+            profiles.join("a1.default").join("places.sqlite")
+        );
+        assert!(bookmarks.profile("work").path().is_err());
+    }
+}
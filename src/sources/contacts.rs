@@ -0,0 +1,324 @@
+//! Searching the Contacts app's address book.
+//!
+//! Contacts reads the address book databases of every account configured
+//! in Contacts (iCloud, Google, On My Mac, ...) with the `sqlite3`
+//! command. Reading them requires Alfred to have Full Disk Access, which
+//! is why this module is behind the `contacts` feature.
+//!
+//! ```no_run
+//! use alfrusco::sources::contacts::Contacts;
+//!
+//! let items: Vec<_> = Contacts::new()
+//!     .search("crayons")?
+//!     .iter()
+//!     .map(|contact| contact.item())
+//!     .collect();
+//! # Ok::<(), alfrusco::Error>(())
+//! ```
+//!
+
+use std::path::PathBuf;
+
+use log::debug;
+use serde::Deserialize;
+
+use super::query_sqlite;
+use crate::{Error, InternalCommand, Item, Key, Modifier, Result, ICON_USER};
+
+/// The address book directory, relative to the home directory.
+const ADDRESS_BOOK_DIR: &str = "Library/Application Support/AddressBook";
+
+/// The address book database file name.
+const ADDRESS_BOOK_DB: &str = "AddressBook-v22.abcddb";
+
+/// Separates the emails and phone numbers of a contact in query results.
+const LIST_SEPARATOR: char = '\u{1f}';
+
+/// Lists people and companies, with their emails and phone numbers in the
+/// order Contacts shows them.
+const CONTACTS_QUERY: &str = "SELECT r.ZFIRSTNAME AS first_name, r.ZLASTNAME AS last_name, \
+     r.ZORGANIZATION AS organization, \
+     (SELECT group_concat(ZADDRESS, char(31)) FROM \
+       (SELECT ZADDRESS FROM ZABCDEMAILADDRESS WHERE ZOWNER = r.Z_PK ORDER BY ZORDERINGINDEX)) \
+       AS emails, \
+     (SELECT group_concat(ZFULLNUMBER, char(31)) FROM \
+       (SELECT ZFULLNUMBER FROM ZABCDPHONENUMBER WHERE ZOWNER = r.Z_PK ORDER BY ZORDERINGINDEX)) \
+       AS phones \
+     FROM ZABCDRECORD r \
+     WHERE r.ZFIRSTNAME IS NOT NULL OR r.ZLASTNAME IS NOT NULL OR r.ZORGANIZATION IS NOT NULL";
+
+/// Contact is one person or company from the address book.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Contact {
+    /// The full name, or the organization for companies
+    pub name: String,
+    pub organization: Option<String>,
+    pub emails: Vec<String>,
+    pub phones: Vec<String>,
+}
+
+impl Contact {
+    /// Returns true if `query` appears (ignoring case) in the contact's
+    /// name, organization or emails, or, if it has at least three digits,
+    /// in one of its phone numbers ignoring formatting.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return true;
+        }
+        let text_matches = std::iter::once(&self.name)
+            .chain(&self.organization)
+            .chain(&self.emails)
+            .any(|field| field.to_lowercase().contains(&query));
+        let query_digits = digits(&query);
+        text_matches
+            || (query_digits.len() >= 3
+                && self
+                    .phones
+                    .iter()
+                    .any(|phone| digits(phone).contains(&query_digits)))
+    }
+
+    /// Returns an Item for the contact. Choosing it copies the first
+    /// email (or phone number, or name). Cmd copies the email, Alt the
+    /// phone number and Ctrl the name.
+    ///
+    /// Copying uses a `copy` InternalCommand, so the Script Filter must be
+    /// connected to a Run Script action invoking the workflow binary
+    /// again.
+    pub fn item(&self) -> Item {
+        let email = self.emails.first();
+        let phone = self.phones.first();
+        let details: Vec<&str> = self
+            .organization
+            .iter()
+            .filter(|organization| **organization != self.name)
+            .chain(email)
+            .chain(phone)
+            .map(String::as_str)
+            .collect();
+
+        let default = email.or(phone).unwrap_or(&self.name);
+        let mut item = Item::new(&self.name)
+            .icon(ICON_USER.into())
+            .copy_on_action(default.as_str());
+        if !details.is_empty() {
+            item = item.subtitle(details.join(" · "));
+        }
+        let fields = [
+            (Key::Cmd, "Email", email),
+            (Key::Alt, "Phone", phone),
+            (Key::Ctrl, "Name", Some(&self.name)),
+        ];
+        for (key, label, value) in fields {
+            if let Some(value) = value {
+                item = item.modifier(copy_modifier(key, label, value));
+            }
+        }
+        item
+    }
+}
+
+fn copy_modifier(key: Key, label: &str, value: &str) -> Modifier {
+    Modifier::new(key)
+        .subtitle(format!("Copy {} '{}'", label, value))
+        .arg(value)
+        .internal_command(InternalCommand::new("copy").arg("text", value))
+}
+
+fn digits(text: &str) -> String {
+    text.chars().filter(char::is_ascii_digit).collect()
+}
+
+#[derive(Deserialize)]
+struct ContactRow {
+    first_name: Option<String>,
+    last_name: Option<String>,
+    organization: Option<String>,
+    emails: Option<String>,
+    phones: Option<String>,
+}
+
+impl From<ContactRow> for Contact {
+    fn from(row: ContactRow) -> Self {
+        let full_name = [&row.first_name, &row.last_name]
+            .into_iter()
+            .flatten()
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let split = |list: Option<String>| {
+            list.map(|list| list.split(LIST_SEPARATOR).map(str::to_string).collect())
+                .unwrap_or_default()
+        };
+        Contact {
+            name: match full_name.is_empty() {
+                true => row.organization.clone().unwrap_or_default(),
+                false => full_name,
+            },
+            organization: row.organization,
+            emails: split(row.emails),
+            phones: split(row.phones),
+        }
+    }
+}
+
+/// Contacts reads the address book.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Contacts {
+    home: Option<PathBuf>,
+}
+
+impl Contacts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Looks for the address book in `home` instead of the user's home
+    /// directory. Without it, HOME is read from the process environment,
+    /// since Contacts is used without a Workflow; pass the HOME in
+    /// `workflow.config.env` to use the workflow's snapshot.
+    pub fn home(mut self, home: impl Into<PathBuf>) -> Self {
+        self.home = Some(home.into());
+        self
+    }
+
+    /// Returns the address book databases: the local one and one per
+    /// account.
+    pub fn databases(&self) -> Result<Vec<PathBuf>> {
+        let home = match &self.home {
+            Some(home) => home.clone(),
+            None => std::env::var_os("HOME")
+                .map(PathBuf::from)
+                .ok_or_else(|| Error::MissingEnvVar("HOME".to_string()))?,
+        };
+        let dir = home.join(ADDRESS_BOOK_DIR);
+        let mut databases = vec![dir.join(ADDRESS_BOOK_DB)];
+        if let Ok(sources) = std::fs::read_dir(dir.join("Sources")) {
+            let mut accounts: Vec<PathBuf> = sources
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path().join(ADDRESS_BOOK_DB))
+                .collect();
+            accounts.sort();
+            databases.extend(accounts);
+        }
+        databases.retain(|path| path.is_file());
+        if databases.is_empty() {
+            return Err(Error::Workflow(format!(
+                "no address book found in {}",
+                dir.display()
+            )));
+        }
+        Ok(databases)
+    }
+
+    /// Reads every contact, sorted by name.
+    pub fn load(&self) -> Result<Vec<Contact>> {
+        let mut contacts = Vec::new();
+        for database in self.databases()? {
+            debug!("reading contacts from {}", database.display());
+            let rows: Vec<ContactRow> = query_sqlite(&database, CONTACTS_QUERY)?;
+            contacts.extend(rows.into_iter().map(Contact::from));
+        }
+        contacts.sort_by_key(|contact| contact.name.to_lowercase());
+        Ok(contacts)
+    }
+
+    /// Returns the contacts matching `query`. See Contact::matches.
+    pub fn search(&self, query: &str) -> Result<Vec<Contact>> {
+        let mut contacts = self.load()?;
+        contacts.retain(|contact| contact.matches(query));
+        Ok(contacts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::parse_sqlite_rows;
+    use crate::INTERNAL_VAR;
+
+    fn contacts() -> Vec<Contact> {
+        let json = r#"[
+            {"first_name": "Ada", "last_name": "Lovelace", "organization": "Analytical Engines",
+             "emails": "ada@example.com\u001fada@work.example.com", "phones": "+44 (20) 7946-0018"},
+            {"first_name": null, "last_name": null, "organization": "Crayons Inc",
+             "emails": null, "phones": null}
+        ]"#;
+        let rows: Vec<ContactRow> = parse_sqlite_rows(json).unwrap();
+        rows.into_iter().map(Contact::from).collect()
+    }
+
+    #[test]
+    fn test_contact_from_row() {
+        let contacts = contacts();
+        assert_eq!(
+            contacts[0],
+            Contact {
+                name: "Ada Lovelace".to_string(),
+                organization: Some("Analytical Engines".to_string()),
+                emails: vec![
+                    "ada@example.com".to_string(),
+                    "ada@work.example.com".to_string()
+                ],
+                phones: vec!["+44 (20) 7946-0018".to_string()],
+            }
+        );
+        assert_eq!(contacts[1].name, "Crayons Inc");
+    }
+
+    #[test]
+    fn test_matches() {
+        let ada = &contacts()[0];
+        assert!(ada.matches("lovelace"));
+        assert!(ada.matches("ANALYTICAL"));
+        assert!(ada.matches("work.example"));
+        assert!(ada.matches("7946 0018"));
+        assert!(!ada.matches("555 12"));
+        assert!(!ada.matches("babbage"));
+    }
+
+    #[test]
+    fn test_item() {
+        let item = contacts()[0].item();
+        let json = serde_json::to_value(&item).unwrap();
+        assert_eq!(json["title"], "Ada Lovelace");
+        assert_eq!(
+            json["subtitle"],
+            "Analytical Engines · ada@example.com · +44 (20) 7946-0018"
+        );
+        assert_eq!(json["arg"], "ada@example.com");
+        assert_eq!(
+            json["variables"][INTERNAL_VAR],
+            r#"{"command":"copy","text":"ada@example.com"}"#
+        );
+        assert_eq!(
+            json["mods"]["alt"]["variables"][INTERNAL_VAR],
+            r#"{"command":"copy","text":"+44 (20) 7946-0018"}"#
+        );
+        assert_eq!(json["mods"]["ctrl"]["arg"], "Ada Lovelace");
+
+        let company = contacts()[1].item();
+        let json = serde_json::to_value(&company).unwrap();
+        assert!(json.get("subtitle").is_none());
+        assert_eq!(json["arg"], "Crayons Inc");
+        assert!(json["mods"].get("cmd").is_none());
+    }
+
+    #[test]
+    fn test_databases() {
+        let home = tempfile::tempdir().unwrap();
+        assert!(Contacts::new().home(home.path()).databases().is_err());
+
+        let dir = home.path().join(ADDRESS_BOOK_DIR);
+        let account = dir.join("Sources").join("ABC-123");
+        std::fs::create_dir_all(&account).unwrap();
+        std::fs::write(dir.join(ADDRESS_BOOK_DB), "").unwrap();
+        std::fs::write(account.join(ADDRESS_BOOK_DB), "").unwrap();
+        assert_eq!(
+            Contacts::new().home(home.path()).databases().unwrap(),
+            [dir.join(ADDRESS_BOOK_DB), account.join(ADDRESS_BOOK_DB)]
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use std::cmp::Reverse;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use chrono::{DateTime, FixedOffset};
+use log::debug;
+
+use crate::config::EnvMap;
+use crate::mdfind::{escape, file_item, Attribute, Query};
+use crate::{Error, Item, Result};
+
+/// How far back recent_files looks for recently opened files.
+pub const RECENT_DAYS: u32 = 30;
+
+/// The number of paths passed to each mdls invocation.
+const MDLS_BATCH: usize = 256;
+
+/// FileKind restricts recent_files to a kind of file, by UTI.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FileKind {
+    Pdf,
+    Image,
+    /// Plain text, including source code
+    Text,
+    Presentation,
+    Spreadsheet,
+    Folder,
+    Application,
+    /// Any other UTI, e.g. "com.microsoft.word.doc"
+    Other(String),
+}
+
+impl FileKind {
+    fn uti(&self) -> &str {
+        match self {
+            FileKind::Pdf => "com.adobe.pdf",
+            FileKind::Image => "public.image",
+            FileKind::Text => "public.text",
+            FileKind::Presentation => "public.presentation",
+            FileKind::Spreadsheet => "public.spreadsheet",
+            FileKind::Folder => "public.folder",
+            FileKind::Application => "com.apple.application",
+            FileKind::Other(uti) => uti,
+        }
+    }
+}
+
+/// Returns file Items for the `limit` most recently opened files of any
+/// of the given kinds (of any kind if `kinds` is empty), most recent
+/// first. Only files opened in the last RECENT_DAYS days are considered.
+/// `env` (usually the workflow's config.env) supplies HOME for the
+/// items' subtitles.
+///
+/// ```no_run
+/// use alfrusco::config::EnvMap;
+/// use alfrusco::sources::{recent_files, FileKind};
+///
+/// # let env = EnvMap::from_process();
+/// let items = recent_files(20, &[FileKind::Pdf, FileKind::Presentation], &env)?;
+/// # Ok::<(), alfrusco::Error>(())
+/// ```
+///
+pub fn recent_files(limit: usize, kinds: &[FileKind], env: &EnvMap) -> Result<Vec<Item>> {
+    let paths = recent_query(kinds).paths()?;
+    debug!(
+        "{} files opened in the last {} days",
+        paths.len(),
+        RECENT_DAYS
+    );
+    let mut used = Vec::with_capacity(paths.len());
+    for batch in paths.chunks(MDLS_BATCH) {
+        used.extend(last_used_dates(batch)?);
+    }
+    Ok(most_recent(paths, used, limit)
+        .iter()
+        .map(|path| file_item(path, env))
+        .collect())
+}
+
+fn recent_query(kinds: &[FileKind]) -> Query {
+    let query = Query::new().raw(format!(
+        "{} >= $time.today(-{})",
+        Attribute::LastUsedDate,
+        RECENT_DAYS
+    ));
+    if kinds.is_empty() {
+        return query;
+    }
+    let kinds: Vec<String> = kinds
+        .iter()
+        .map(|kind| {
+            format!(
+                "{} == \"{}\"",
+                Attribute::ContentTypeTree,
+                escape(kind.uti())
+            )
+        })
+        .collect();
+    query.raw(format!("({})", kinds.join(" || ")))
+}
+
+/// Returns when each file was last opened, according to Spotlight.
+///
+/// The files are queried with one mdls run. mdls skips files it can't
+/// read (such as ones deleted since the search), without saying which,
+/// so when it returns fewer values than there are paths, each file is
+/// queried on its own instead.
+fn last_used_dates(paths: &[PathBuf]) -> Result<Vec<Option<DateTime<FixedOffset>>>> {
+    let output = mdls(paths)?;
+    let dates = parse_mdls_dates(&output.stdout);
+    if dates.len() == paths.len() {
+        return Ok(dates);
+    }
+    if dates.is_empty() && !output.status.success() {
+        return Err(Error::Workflow(format!(
+            "mdls failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    debug!(
+        "mdls returned {} values for {} files, querying them one at a time",
+        dates.len(),
+        paths.len()
+    );
+    paths
+        .iter()
+        .map(|path| {
+            let output = mdls(std::slice::from_ref(path))?;
+            Ok(parse_mdls_dates(&output.stdout).pop().flatten())
+        })
+        .collect()
+}
+
+fn mdls(paths: &[PathBuf]) -> Result<Output> {
+    Ok(Command::new("mdls")
+        .args(["-raw", "-name", "kMDItemLastUsedDate"])
+        .args(paths)
+        .output()?)
+}
+
+/// Parses `mdls -raw` output: one NUL separated value per file, with
+/// "(null)" for files without the attribute.
+fn parse_mdls_dates(stdout: &[u8]) -> Vec<Option<DateTime<FixedOffset>>> {
+    if stdout.is_empty() {
+        return Vec::new();
+    }
+    String::from_utf8_lossy(stdout)
+        .split('\0')
+        .map(|value| DateTime::parse_from_str(value.trim(), "%Y-%m-%d %H:%M:%S %z").ok())
+        .collect()
+}
+
+fn most_recent(
+    paths: Vec<PathBuf>,
+    used: Vec<Option<DateTime<FixedOffset>>>,
+    limit: usize,
+) -> Vec<PathBuf> {
+    let mut files: Vec<_> = paths
+        .into_iter()
+        .zip(used)
+        .filter_map(|(path, used)| Some((used?, path)))
+        .collect();
+    files.sort_by_key(|(used, _)| Reverse(*used));
+    files
+        .into_iter()
+        .take(limit)
+        .map(|(_, path)| path)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_query() {
+        assert_eq!(
+            recent_query(&[]).query_string(),
+            "kMDItemLastUsedDate >= $time.today(-30)"
+        );
+        assert_eq!(
+            recent_query(&[
+                FileKind::Pdf,
+                FileKind::Other("com.example.\"doc\"".to_string())
+            ])
+            .query_string(),
+            concat!(
+                "kMDItemLastUsedDate >= $time.today(-30) && (",
+                "kMDItemContentTypeTree == \"com.adobe.pdf\"",
+                " || kMDItemContentTypeTree == \"com.example.\\\"doc\\\"\")"
+            )
+        );
+    }
+
+    #[test]
+    fn test_most_recent() {
+        let stdout = b"2024-03-01 10:15:00 +0000\0(null)\x002024-03-02 08:00:00 +0000\x002024-02-01 09:00:00 +0000";
+        let used = parse_mdls_dates(stdout);
+        assert_eq!(used.len(), 4);
+        assert!(parse_mdls_dates(b"").is_empty());
+        assert!(used[1].is_none());
+
+        let paths = ["/a.pdf", "/b.pdf", "/c.pdf", "/d.pdf"].map(PathBuf::from);
+        assert_eq!(
+            most_recent(paths.to_vec(), used, 2),
+            [PathBuf::from("/c.pdf"), PathBuf::from("/a.pdf")]
+        );
+    }
+}
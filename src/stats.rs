@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+
+use crate::item::Item;
+use crate::memoize::write_cached;
+use crate::workflow::Workflow;
+use crate::Result;
+
+/// The internal query that lists the usage counters as items.
+pub(crate) const STATS_QUERY: &str = "workflow:stats";
+
+impl Workflow {
+    /// Adds one to the named usage counter (e.g. "search.performed").
+    ///
+    /// Counters are kept in the workflow's data directory and never leave
+    /// the machine. Typing `workflow:stats` lists them, so workflow authors
+    /// can see which features get used. Failing to save a counter is
+    /// logged, not returned.
+    ///
+    pub fn increment(&self, name: &str) {
+        self.increment_by(name, 1);
+    }
+
+    /// Adds `amount` to the named usage counter.
+    pub fn increment_by(&self, name: &str, amount: u64) {
+        let path = self.counters_path();
+        let mut counters = read_counters(&path);
+        let count = counters.entry(name.to_string()).or_default();
+        *count = count.saturating_add(amount);
+        if let Err(e) = write_cached(&path, &counters) {
+            warn!("unable to save counter '{}': {}", name, e);
+        }
+    }
+
+    /// Returns every usage counter by name.
+    pub fn counters(&self) -> BTreeMap<String, u64> {
+        read_counters(&self.counters_path())
+    }
+
+    /// Clears all usage counters.
+    pub fn reset_counters(&self) -> Result<()> {
+        match std::fs::remove_file(self.counters_path()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Replaces the response items with the usage counters, most used
+    /// first.
+    pub(crate) fn show_counters(&mut self) {
+        debug!("listing usage counters");
+        let mut counters: Vec<(String, u64)> = self.counters().into_iter().collect();
+        counters.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+        let items = if counters.is_empty() {
+            vec![Item::new(self.strings.get("stats.empty")).valid(false)]
+        } else {
+            counters
+                .into_iter()
+                .map(|(name, count)| {
+                    let count = count.to_string();
+                    Item::new(&name)
+                        .subtitle(self.strings.format("stats.count", &[("count", &count)]))
+                        .copy_text(format!("{}: {}", name, count))
+                        .valid(false)
+                })
+                .collect()
+        };
+        self.response.items = items;
+    }
+
+    fn counters_path(&self) -> PathBuf {
+        self.config.workflow_data.join("counters.json")
+    }
+}
+
+fn read_counters(path: &Path) -> BTreeMap<String, u64> {
+    match std::fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+            warn!("ignoring unreadable counters {}: {}", path.display(), e);
+            BTreeMap::new()
+        }),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_increment() {
+        let dir = tempfile::tempdir().unwrap();
+        let workflow = crate::test_support::workflow_in(dir.path());
+        assert!(workflow.counters().is_empty());
+
+        workflow.increment("search.performed");
+        workflow.increment("search.performed");
+        workflow.increment_by("items.copied", 5);
+
+        let counters = workflow.counters();
+        assert_eq!(counters["search.performed"], 2);
+        assert_eq!(counters["items.copied"], 5);
+
+        // Counters persist across executions
+        let workflow = crate::test_support::workflow_in(dir.path());
+        assert_eq!(workflow.counters().len(), 2);
+
+        workflow.reset_counters().unwrap();
+        assert!(workflow.counters().is_empty());
+        workflow.reset_counters().unwrap();
+    }
+
+    #[test]
+    fn test_stats_internal_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = crate::test_support::workflow_in(dir.path());
+        workflow.set_filter_keyword(STATS_QUERY.to_string());
+        workflow.handle_internal_query();
+        assert_eq!(workflow.response.items[0].title, "No usage recorded yet");
+
+        workflow.increment("search.performed");
+        workflow.increment_by("items.copied", 3);
+        workflow.handle_internal_query();
+        workflow.filter_and_sort();
+
+        let items = &workflow.response.items;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].title, "items.copied");
+        assert_eq!(items[0].subtitle, Some("Used 3 times".to_string()));
+        assert_eq!(items[1].title, "search.performed");
+    }
+}
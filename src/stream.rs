@@ -0,0 +1,434 @@
+use std::fs::{create_dir_all, remove_file, write};
+use std::future::Future;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::{debug, error};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::background_job::{is_process_running, own_process_group, terminate_process};
+use crate::memoize::{key_file_stem, read_fresh, write_cached};
+use crate::workflow::Workflow;
+use crate::{Item, Result, ICON_ALERT_CAUTION_BADGE};
+
+/// The environment variable marking the detached process that performs a
+/// stream's fetch. Its value is the stream key.
+pub(crate) const STREAM_VAR: &str = "ALFRUSCO_STREAM";
+
+/// How soon Alfred re-runs the Script Filter while a fetch is in progress.
+const STREAM_RERUN: Duration = Duration::from_millis(300);
+
+/// How long after a failed fetch another one is started, so a failing API
+/// isn't hit again on every rerun.
+const STREAM_FAILURE_BACKOFF: Duration = Duration::from_secs(60);
+
+/// StreamFailure records a failed fetch in the stream's directory, so the
+/// next executions can hold off fetching again and show the error.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct StreamFailure {
+    /// When the fetch failed, in seconds since the Unix epoch
+    failed_at: u64,
+    error: String,
+}
+
+impl StreamFailure {
+    /// True while another fetch shouldn't be started yet.
+    fn is_recent(&self) -> bool {
+        let failed_at = UNIX_EPOCH + Duration::from_secs(self.failed_at);
+        SystemTime::now()
+            .duration_since(failed_at)
+            .unwrap_or_default()
+            < STREAM_FAILURE_BACKOFF
+    }
+}
+
+/// StreamSink receives the results of a streaming fetch as they arrive.
+/// Each send flushes everything received so far to the workflow cache,
+/// where it is shown on the next rerun.
+#[derive(Debug)]
+pub struct StreamSink<T> {
+    path: PathBuf,
+    items: Arc<Mutex<Vec<T>>>,
+}
+
+impl<T> Clone for StreamSink<T> {
+    fn clone(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            items: self.items.clone(),
+        }
+    }
+}
+
+impl<T: Serialize> StreamSink<T> {
+    /// Adds items to the partial results and flushes them to the cache.
+    pub fn send(&self, items: impl IntoIterator<Item = T>) -> Result<()> {
+        let mut all = self.items.lock().unwrap();
+        all.extend(items);
+        write_cached(&self.path, &*all)
+    }
+}
+
+impl Workflow {
+    /// Progressively renders the results of a slow fetch (e.g. a paginated
+    /// API) instead of making the user wait for all of it.
+    ///
+    /// The first time this is called for `key`, the current workflow binary
+    /// is re-run as a detached process to perform `fetch`, and Alfred is
+    /// asked to re-run the Script Filter shortly. Each time `fetch` sends
+    /// items through its StreamSink, they're flushed to the workflow cache
+    /// and shown on the next rerun. Once the fetch completes, its results
+    /// are reused for `max_age`, after which they're shown while a fresh
+    /// fetch runs.
+    ///
+    /// If a fetch fails, the previous results are shown with an item
+    /// carrying the error, and no new fetch is started for a minute.
+    ///
+    /// Results are stored as JSON, so T is any serializable type that can
+    /// be converted into an Item (such as URLItem).
+    ///
+    pub async fn stream_items<T, F, Fut>(
+        &mut self,
+        key: &str,
+        max_age: Duration,
+        fetch: F,
+    ) -> Result<()>
+    where
+        T: Serialize + DeserializeOwned + Into<Item> + Send,
+        F: FnOnce(StreamSink<T>) -> Fut + Send,
+        Fut: Future<Output = Result<()>> + Send,
+    {
+        let stream = StreamFiles::new(self, key);
+
+        if self.config.env.get(STREAM_VAR) == Some(key) {
+            return stream.fetch(fetch).await;
+        }
+
+        if let Some(items) = read_fresh::<Vec<T>>(&stream.complete, max_age) {
+            debug!("stream '{}' is fresh", key);
+            self.append_items(items.into_iter().map(Into::into).collect());
+            return Ok(());
+        }
+
+        let failure = stream.failure().filter(StreamFailure::is_recent);
+        if let Some(failure) = &failure {
+            debug!("stream '{}' failed recently: {}", key, failure.error);
+        } else if !stream.is_running() {
+            stream.spawn(key)?;
+        }
+        // Show what has arrived so far, or the previous results until then
+        let items = read_fresh::<Vec<T>>(&stream.partial, Duration::MAX)
+            .or_else(|| read_fresh(&stream.complete, Duration::MAX))
+            .unwrap_or_default();
+        self.append_items(items.into_iter().map(Into::into).collect());
+        match failure {
+            Some(failure) => {
+                let item = Item::new(self.strings.get("stream.failed"))
+                    .subtitle(failure.error)
+                    .icon(ICON_ALERT_CAUTION_BADGE.into())
+                    .valid(false);
+                self.response.prepend_items(vec![item]);
+            }
+            None => {
+                self.response.rerun(STREAM_RERUN);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the path to the cache subdirectory where streamed results
+    /// are held
+    pub fn streams_dir(&self) -> PathBuf {
+        self.reserved_cache_dir("streams")
+    }
+
+    /// Stops the detached processes fetching streams, so they don't
+    /// recreate the stream files after they are deleted.
+    pub(crate) fn stop_stream_fetches(&self) {
+        let Ok(entries) = std::fs::read_dir(self.streams_dir()) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let pid_file = entry.path().join(PID_FILE);
+            let Ok(started) = pid_file.metadata().and_then(|m| m.modified()) else {
+                continue;
+            };
+            let pid = std::fs::read_to_string(&pid_file)
+                .ok()
+                .and_then(|pid| pid.trim().parse::<u32>().ok());
+            if let Some(pid) = pid {
+                if terminate_process(pid, started) {
+                    debug!("stopped stream fetch pid {}", pid);
+                }
+            }
+        }
+    }
+}
+
+/// The file holding the pid of a stream's fetch process.
+const PID_FILE: &str = "fetch.pid";
+
+/// The cache files backing one stream.
+struct StreamFiles {
+    partial: PathBuf,
+    complete: PathBuf,
+    failed: PathBuf,
+    pid: PathBuf,
+}
+
+impl StreamFiles {
+    fn new(workflow: &Workflow, key: &str) -> Self {
+        let dir = workflow.streams_dir().join(key_file_stem(key));
+        StreamFiles {
+            partial: dir.join("partial.json"),
+            complete: dir.join("complete.json"),
+            failed: dir.join("failed.json"),
+            pid: dir.join(PID_FILE),
+        }
+    }
+
+    /// Performs the fetch in the detached process, promoting the partial
+    /// results to the complete results once it succeeds, or recording the
+    /// failure if it doesn't.
+    async fn fetch<T, F, Fut>(&self, fetch: F) -> Result<()>
+    where
+        T: Serialize,
+        F: FnOnce(StreamSink<T>) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let sink = StreamSink {
+            path: self.partial.clone(),
+            items: Arc::new(Mutex::new(Vec::new())),
+        };
+        let result = fetch(sink.clone()).await;
+        match &result {
+            Ok(()) => {
+                write_cached(&self.complete, &*sink.items.lock().unwrap())?;
+                remove_file(&self.failed).ok();
+            }
+            Err(e) => {
+                error!("streaming fetch failed: {}", e);
+                let failure = StreamFailure {
+                    failed_at: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs(),
+                    error: e.to_string(),
+                };
+                write_cached(&self.failed, &failure)?;
+            }
+        }
+        remove_file(&self.partial).ok();
+        remove_file(&self.pid).ok();
+        result
+    }
+
+    fn failure(&self) -> Option<StreamFailure> {
+        read_fresh(&self.failed, Duration::MAX)
+    }
+
+    /// Returns true if the fetch process recorded in the pid file is still
+    /// running. The pid file's modification time is when the process was
+    /// started, so a pid reused by another process isn't mistaken for it.
+    fn is_running(&self) -> bool {
+        let Ok(started) = self.pid.metadata().and_then(|m| m.modified()) else {
+            return false;
+        };
+        std::fs::read_to_string(&self.pid)
+            .ok()
+            .and_then(|pid| pid.trim().parse::<u32>().ok())
+            .is_some_and(|pid| is_process_running(pid, started))
+    }
+
+    fn spawn(&self, key: &str) -> Result<()> {
+        if let Some(dir) = self.pid.parent() {
+            create_dir_all(dir)?;
+        }
+        remove_file(&self.partial).ok();
+        let mut command = Command::new(std::env::current_exe()?);
+        command
+            .args(std::env::args_os().skip(1))
+            .env(STREAM_VAR, key)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null());
+        // In its own group, so stopping the fetch stops its subprocesses too
+        own_process_group(&mut command);
+        let child = command.spawn()?;
+        debug!("started stream '{}' fetch as pid {}", key, child.id());
+        write(&self.pid, child.id().to_string())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::workflow_in;
+    use crate::{Error, URLItem};
+
+    fn url_items(titles: &[&str]) -> Vec<URLItem> {
+        titles
+            .iter()
+            .map(|title| URLItem::new(*title, format!("https://example.com/{}", title)))
+            .collect()
+    }
+
+    fn titles(workflow: &Workflow) -> Vec<&str> {
+        workflow
+            .response
+            .items
+            .iter()
+            .map(|item| item.title.as_str())
+            .collect()
+    }
+
+    async fn fetch_pages(sink: StreamSink<URLItem>) -> Result<()> {
+        sink.send(url_items(&["one", "two"]))?;
+        sink.send(url_items(&["three"]))?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_completed_fetch_is_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        let stream = StreamFiles::new(&workflow, "pages");
+
+        // What the detached process does
+        stream.fetch(fetch_pages).await.unwrap();
+        assert!(!stream.partial.exists());
+
+        workflow
+            .stream_items("pages", Duration::from_secs(60), fetch_pages)
+            .await
+            .unwrap();
+        assert_eq!(titles(&workflow), vec!["one", "two", "three"]);
+        assert_eq!(workflow.response.rerun, None);
+    }
+
+    #[tokio::test]
+    async fn test_failed_fetch_keeps_previous_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let workflow = workflow_in(dir.path());
+        let stream = StreamFiles::new(&workflow, "pages");
+        write_cached(&stream.complete, &url_items(&["old"])).unwrap();
+
+        let result = stream
+            .fetch(|sink: StreamSink<URLItem>| async move {
+                sink.send(url_items(&["partial"]))?;
+                Err(Error::Workflow("rate limited".to_string()))
+            })
+            .await;
+        assert!(result.is_err());
+        assert!(!stream.partial.exists());
+        let complete: Vec<URLItem> = read_fresh(&stream.complete, Duration::MAX).unwrap();
+        assert_eq!(complete, url_items(&["old"]));
+        assert_eq!(
+            stream.failure().unwrap().error,
+            "Workflow Error: rate limited"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recent_failure_holds_off_fetching() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        let stream = StreamFiles::new(&workflow, "pages");
+        write_cached(&stream.complete, &url_items(&["old"])).unwrap();
+        let failure = StreamFailure {
+            failed_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            error: "rate limited".to_string(),
+        };
+        write_cached(&stream.failed, &failure).unwrap();
+
+        workflow
+            .stream_items("pages", Duration::ZERO, fetch_pages)
+            .await
+            .unwrap();
+        assert!(!stream.pid.exists());
+        assert_eq!(workflow.response.rerun, None);
+        assert_eq!(titles(&workflow), vec!["Unable to load results", "old"]);
+        assert_eq!(
+            workflow.response.items[0].subtitle.as_deref(),
+            Some("rate limited")
+        );
+
+        // Once the backoff has passed, a successful fetch clears it
+        write_cached(
+            &stream.failed,
+            &StreamFailure {
+                failed_at: 0,
+                ..failure
+            },
+        )
+        .unwrap();
+        assert!(!stream.failure().unwrap().is_recent());
+        stream.fetch(fetch_pages).await.unwrap();
+        assert_eq!(stream.failure(), None);
+    }
+
+    #[tokio::test]
+    async fn test_running_fetch_shows_partial_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        let stream = StreamFiles::new(&workflow, "pages");
+
+        // Pretend a child of this test process is the one fetching
+        let mut fetcher = Command::new("/bin/sleep").arg("30").spawn().unwrap();
+        create_dir_all(stream.pid.parent().unwrap()).unwrap();
+        write(&stream.pid, fetcher.id().to_string()).unwrap();
+        write_cached(&stream.complete, &url_items(&["old"])).unwrap();
+        assert!(stream.is_running());
+
+        // Before anything arrives, the previous results are shown
+        workflow
+            .stream_items("pages", Duration::ZERO, fetch_pages)
+            .await
+            .unwrap();
+        assert_eq!(titles(&workflow), vec!["old"]);
+        assert_eq!(workflow.response.rerun, Some(STREAM_RERUN));
+
+        let sink = StreamSink {
+            path: stream.partial.clone(),
+            items: Arc::new(Mutex::new(Vec::new())),
+        };
+        sink.send(url_items(&["one"])).unwrap();
+        workflow.response.items.clear();
+        workflow
+            .stream_items("pages", Duration::ZERO, fetch_pages)
+            .await
+            .unwrap();
+        assert_eq!(titles(&workflow), vec!["one"]);
+        fetcher.kill().unwrap();
+        fetcher.wait().unwrap();
+    }
+
+    #[test]
+    fn test_stale_pid_is_not_running() {
+        let dir = tempfile::tempdir().unwrap();
+        let workflow = workflow_in(dir.path());
+        let stream = StreamFiles::new(&workflow, "pages");
+        assert!(!stream.is_running());
+
+        create_dir_all(stream.pid.parent().unwrap()).unwrap();
+        write(&stream.pid, "not a pid").unwrap();
+        assert!(!stream.is_running());
+
+        // A pid file naming a process that started long before it was
+        // written (the pid was reused) isn't the fetch
+        write(&stream.pid, std::process::id().to_string()).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&stream.pid)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60 * 60))
+            .unwrap();
+        assert!(!stream.is_running());
+    }
+}
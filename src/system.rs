@@ -0,0 +1,225 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+use log::{debug, warn};
+
+use crate::{Error, Result};
+
+/// SystemActions abstracts the handful of operating system interactions
+/// alfrusco performs on behalf of a workflow (opening files and URLs, and
+/// running AppleScript).
+///
+/// The Workflow holds a boxed SystemActions which defaults to
+/// MacSystemActions. Tests can inject a MockSystemActions to verify what
+/// would have been executed without touching the host system.
+///
+pub trait SystemActions: std::fmt::Debug + Send + Sync {
+    /// Opens a file or directory with its default application.
+    fn open_path(&self, path: &Path) -> Result<()>;
+
+    /// Opens a URL in the default handler for its scheme.
+    fn open_url(&self, url: &str) -> Result<()>;
+
+    /// Opens a URL in the application with the given bundle id, passing it
+    /// `args` (e.g. a browser profile). Defaults to open_url for
+    /// implementations that can't choose the application.
+    fn open_url_with(&self, url: &str, bundle_id: &str, args: &[String]) -> Result<()> {
+        warn!("ignoring {} {:?} for {}", bundle_id, args, url);
+        self.open_url(url)
+    }
+
+    /// Runs the provided AppleScript source and returns its stdout.
+    fn run_osascript(&self, script: &str) -> Result<String>;
+}
+
+/// MacSystemActions implements SystemActions by shelling out to the macOS
+/// `open` and `osascript` commands.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MacSystemActions;
+
+impl MacSystemActions {
+    fn run(command: &mut Command) -> Result<String> {
+        debug!("running {:?}", command);
+        let output = command.output()?;
+        if !output.status.success() {
+            return Err(Error::Workflow(format!(
+                "{:?} failed: {}",
+                command,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    }
+}
+
+impl SystemActions for MacSystemActions {
+    fn open_path(&self, path: &Path) -> Result<()> {
+        Self::run(Command::new("open").arg(path))?;
+        Ok(())
+    }
+
+    fn open_url(&self, url: &str) -> Result<()> {
+        Self::run(Command::new("open").arg(url))?;
+        Ok(())
+    }
+
+    /// Runs `open -b`, or `open -n -b ... --args` when there are args, since
+    /// a running application only sees them when a new instance starts.
+    fn open_url_with(&self, url: &str, bundle_id: &str, args: &[String]) -> Result<()> {
+        let mut command = Command::new("open");
+        if args.is_empty() {
+            command.arg("-b").arg(bundle_id).arg(url);
+        } else {
+            command.arg("-n").arg("-b").arg(bundle_id).arg("--args");
+            command.args(args).arg(url);
+        }
+        Self::run(&mut command)?;
+        Ok(())
+    }
+
+    fn run_osascript(&self, script: &str) -> Result<String> {
+        Self::run(Command::new("osascript").arg("-e").arg(script))
+    }
+}
+
+/// NoopSystemActions logs each request and does nothing else. It is the
+/// default on platforms other than macOS, where `open` and `osascript` do
+/// not exist.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopSystemActions;
+
+impl SystemActions for NoopSystemActions {
+    fn open_path(&self, path: &Path) -> Result<()> {
+        warn!("open_path unsupported on this platform: {}", path.display());
+        Ok(())
+    }
+
+    fn open_url(&self, url: &str) -> Result<()> {
+        warn!("open_url unsupported on this platform: {}", url);
+        Ok(())
+    }
+
+    fn open_url_with(&self, url: &str, bundle_id: &str, _args: &[String]) -> Result<()> {
+        warn!(
+            "open_url_with unsupported on this platform: {} in {}",
+            url, bundle_id
+        );
+        Ok(())
+    }
+
+    fn run_osascript(&self, script: &str) -> Result<String> {
+        warn!("osascript unsupported on this platform: {}", script);
+        Ok(String::new())
+    }
+}
+
+/// The SystemActions implementation used by a new Workflow on the target
+/// platform.
+#[cfg(target_os = "macos")]
+pub type DefaultSystemActions = MacSystemActions;
+
+/// The SystemActions implementation used by a new Workflow on the target
+/// platform.
+#[cfg(not(target_os = "macos"))]
+pub type DefaultSystemActions = NoopSystemActions;
+
+/// SystemCall records a single invocation made against a MockSystemActions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SystemCall {
+    OpenPath(PathBuf),
+    OpenUrl(String),
+    /// The URL, the application's bundle id and the args
+    OpenUrlWith(String, String, Vec<String>),
+    RunOsascript(String),
+}
+
+/// MockSystemActions records every call made against it instead of
+/// executing anything. Clones share the same call log, so a test can keep
+/// one handle while injecting another into the Workflow.
+///
+#[derive(Debug, Default, Clone)]
+pub struct MockSystemActions {
+    calls: Arc<Mutex<Vec<SystemCall>>>,
+    osascript_output: String,
+}
+
+impl MockSystemActions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the stdout returned from every run_osascript call.
+    pub fn osascript_output(mut self, output: impl Into<String>) -> Self {
+        self.osascript_output = output.into();
+        self
+    }
+
+    /// Returns a copy of the calls recorded so far, in order.
+    pub fn calls(&self) -> Vec<SystemCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn record(&self, call: SystemCall) {
+        self.calls.lock().unwrap().push(call);
+    }
+}
+
+impl SystemActions for MockSystemActions {
+    fn open_path(&self, path: &Path) -> Result<()> {
+        self.record(SystemCall::OpenPath(path.to_path_buf()));
+        Ok(())
+    }
+
+    fn open_url(&self, url: &str) -> Result<()> {
+        self.record(SystemCall::OpenUrl(url.to_string()));
+        Ok(())
+    }
+
+    fn open_url_with(&self, url: &str, bundle_id: &str, args: &[String]) -> Result<()> {
+        self.record(SystemCall::OpenUrlWith(
+            url.to_string(),
+            bundle_id.to_string(),
+            args.to_vec(),
+        ));
+        Ok(())
+    }
+
+    fn run_osascript(&self, script: &str) -> Result<String> {
+        self.record(SystemCall::RunOsascript(script.to_string()));
+        Ok(self.osascript_output.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noop_does_nothing() {
+        let noop = NoopSystemActions;
+        assert!(noop.open_path(Path::new("/tmp")).is_ok());
+        assert!(noop.open_url("https://crates.io/").is_ok());
+        assert_eq!(noop.run_osascript("beep").unwrap(), "");
+    }
+
+    #[test]
+    fn test_mock_records_calls() {
+        let mock = MockSystemActions::new().osascript_output("Finder");
+        let shared = mock.clone();
+
+        mock.open_path(Path::new("/tmp")).unwrap();
+        mock.open_url("https://www.rust-lang.org/").unwrap();
+        let output = mock.run_osascript("return name of me").unwrap();
+
+        assert_eq!(output, "Finder");
+        assert_eq!(
+            shared.calls(),
+            vec![
+                SystemCall::OpenPath("/tmp".into()),
+                SystemCall::OpenUrl("https://www.rust-lang.org/".to_string()),
+                SystemCall::RunOsascript("return name of me".to_string()),
+            ]
+        );
+    }
+}
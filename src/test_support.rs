@@ -0,0 +1,292 @@
+//! Helpers for unit testing workflows built on alfrusco.
+//!
+//! These are intentionally not gated behind `cfg(test)` so that downstream
+//! crates can use them from their own test modules:
+//!
+//! ```
+//! let dir = tempfile::tempdir().unwrap();
+//! let mut workflow = alfrusco::test_support::workflow_in(dir.path());
+//! workflow.append_item(alfrusco::Item::new("Hello"));
+//! ```
+//!
+//! WorkflowCommand runs a compiled workflow binary end to end, the way
+//! Alfred would, and parses what it prints into a Response.
+//!
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+
+use crate::config::{TestingProvider, WorkflowConfig};
+use crate::{execute, Error, Item, Response, Result, Runnable, Workflow};
+
+/// The PATH Alfred gives scripts, which doesn't include the user's shell
+/// configuration.
+const ALFRED_PATH: &str = "/usr/bin:/bin:/usr/sbin:/sbin";
+
+/// Creates a Workflow whose cache and data directories live inside `dir`,
+/// using WorkflowConfig::for_tests.
+pub fn workflow_in(dir: impl AsRef<Path>) -> Workflow {
+    Workflow::new(WorkflowConfig::for_tests(dir)).expect("failed to create test workflow")
+}
+
+/// Executes the runnable inside `dir` as alfrusco::execute would and
+/// returns the JSON written to Alfred.
+pub fn run_in<R: Runnable>(dir: impl AsRef<Path>, runnable: R) -> String {
+    let mut buffer = Vec::new();
+    execute(
+        &TestingProvider(dir.as_ref().to_path_buf()),
+        runnable,
+        &mut buffer,
+    );
+    String::from_utf8(buffer).expect("workflow wrote invalid UTF-8")
+}
+
+/// WorkflowCommand runs a workflow binary with a synthetic Alfred
+/// environment: only the variables Alfred sets (plus HOME and Alfred's
+/// PATH), the cache and data directories inside `dir`, and `dir/workflow`
+/// as the working directory, as Alfred runs scripts from the workflow's
+/// directory.
+///
+/// ```no_run
+/// use alfrusco::test_support::WorkflowCommand;
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let output = WorkflowCommand::example("static_output", dir.path())
+///     .arg("query")
+///     .env("API_TOKEN", "test")
+///     .run()
+///     .unwrap()
+///     .assert_success();
+/// assert_eq!(output.items()[0].get_title(), "First Option");
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct WorkflowCommand {
+    program: PathBuf,
+    dir: PathBuf,
+    args: Vec<OsString>,
+    env: BTreeMap<String, String>,
+}
+
+impl WorkflowCommand {
+    pub fn new(program: impl Into<PathBuf>, dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref().to_path_buf();
+        let mut env = BTreeMap::new();
+        let vars = [
+            ("alfred_workflow_bundleid", "com.alfrusco.test"),
+            ("alfred_workflow_name", "Test Workflow"),
+            ("alfred_workflow_version", "1.0.0"),
+            ("alfred_workflow_uid", "user.workflow.TEST"),
+            ("alfred_version", "5.5"),
+            ("alfred_version_build", "2257"),
+            ("alfred_preferences", "/tmp/Alfred.alfredpreferences"),
+            ("alfred_theme", "theme.bundled.default"),
+            ("alfred_debug", "0"),
+            ("PATH", ALFRED_PATH),
+        ];
+        for (name, value) in vars {
+            env.insert(name.to_string(), value.to_string());
+        }
+        let dirs = [
+            ("alfred_workflow_cache", "workflow_cache"),
+            ("alfred_workflow_data", "workflow_data"),
+        ];
+        for (name, subdir) in dirs {
+            env.insert(name.to_string(), dir.join(subdir).display().to_string());
+        }
+        if let Some(home) = std::env::var_os("HOME") {
+            env.insert("HOME".to_string(), home.to_string_lossy().into_owned());
+        }
+        WorkflowCommand {
+            program: program.into(),
+            dir,
+            args: Vec::new(),
+            env,
+        }
+    }
+
+    /// Runs one of the crate's examples, which `cargo test` builds next to
+    /// the test binary (unless it's limited to `--lib` or `--tests`).
+    pub fn example(name: &str, dir: impl AsRef<Path>) -> Self {
+        Self::new(example_path(name), dir)
+    }
+
+    /// Adds an argument, such as the query Alfred passes as "$1".
+    pub fn arg(mut self, arg: impl Into<OsString>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args(mut self, args: impl IntoIterator<Item = impl Into<OsString>>) -> Self {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Sets an environment variable, such as a workflow variable or one
+    /// of the Alfred variables, replacing its synthetic value.
+    pub fn env(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.insert(name.into(), value.into());
+        self
+    }
+
+    /// Removes an environment variable, e.g. to test a missing Alfred
+    /// variable.
+    pub fn env_remove(mut self, name: &str) -> Self {
+        self.env.remove(name);
+        self
+    }
+
+    /// Runs the binary to completion.
+    pub fn run(&self) -> Result<WorkflowOutput> {
+        let workflow_dir = self.dir.join("workflow");
+        std::fs::create_dir_all(&workflow_dir)?;
+        let output = Command::new(&self.program)
+            .args(&self.args)
+            .env_clear()
+            .envs(&self.env)
+            .env("PWD", &workflow_dir)
+            .current_dir(&workflow_dir)
+            .output()
+            .map_err(|e| {
+                Error::Workflow(format!("unable to run {}: {}", self.program.display(), e))
+            })?;
+        Ok(WorkflowOutput {
+            status: output.status,
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+}
+
+/// Returns the path of an example built by `cargo test`:
+/// target/<profile>/examples/<name>, next to target/<profile>/deps where
+/// test binaries live.
+fn example_path(name: &str) -> PathBuf {
+    let exe = std::env::current_exe().expect("unable to locate the test binary");
+    let profile_dir = exe
+        .parent()
+        .and_then(|deps| deps.parent())
+        .expect("test binary isn't in target/<profile>/deps");
+    profile_dir
+        .join("examples")
+        .join(name)
+        .with_extension(std::env::consts::EXE_EXTENSION)
+}
+
+/// WorkflowOutput is what a WorkflowCommand printed.
+#[derive(Debug, Clone)]
+pub struct WorkflowOutput {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl WorkflowOutput {
+    /// Panics, showing stderr, unless the binary exited successfully and
+    /// printed a valid Response.
+    pub fn assert_success(self) -> Self {
+        assert!(
+            self.status.success(),
+            "workflow exited with {}\nstderr:\n{}",
+            self.status,
+            self.stderr
+        );
+        if let Err(e) = self.response() {
+            panic!("{}\nstdout:\n{}", e, self.stdout);
+        }
+        self
+    }
+
+    /// Panics unless the binary exited unsuccessfully.
+    pub fn assert_failure(self) -> Self {
+        assert!(
+            !self.status.success(),
+            "workflow succeeded\nstdout:\n{}",
+            self.stdout
+        );
+        self
+    }
+
+    /// Parses stdout as a Script Filter Response.
+    pub fn response(&self) -> Result<Response> {
+        serde_json::from_str(&self.stdout)
+            .map_err(|e| Error::Workflow(format!("workflow didn't print a valid response: {}", e)))
+    }
+
+    /// The items of the Response. Panics if stdout isn't a valid Response.
+    pub fn items(&self) -> Vec<Item> {
+        self.response()
+            .expect("workflow didn't print a valid response")
+            .get_items()
+            .to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Hello;
+
+    impl Runnable for Hello {
+        type Error = Error;
+        fn run(self, workflow: &mut Workflow) -> Result<()> {
+            workflow.append_item(Item::new("Hello"));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_workflow_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let workflow = workflow_in(dir.path());
+        assert_eq!(workflow.cache_dir(), dir.path().join("workflow_cache"));
+        assert!(workflow.data_dir().exists());
+    }
+
+    #[test]
+    fn test_run_in() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            run_in(dir.path(), Hello),
+            r#"{"items":[{"title":"Hello"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_workflow_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = WorkflowCommand::example("static_output", dir.path())
+            .run()
+            .unwrap()
+            .assert_success();
+        let response = output.response().unwrap();
+        assert_eq!(response.get_skip_knowledge(), Some(true));
+        let titles: Vec<_> = output
+            .items()
+            .iter()
+            .map(|i| i.get_title().to_string())
+            .collect();
+        assert_eq!(titles, ["First Option", "Option 2", "Three"]);
+        assert!(dir.path().join("workflow").is_dir());
+        assert!(dir.path().join("workflow_data").is_dir());
+    }
+
+    #[test]
+    fn test_workflow_command_missing_variable() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = WorkflowCommand::example("static_output", dir.path())
+            .env_remove("alfred_workflow_bundleid")
+            .run()
+            .unwrap()
+            .assert_success();
+        assert!(output.stderr.contains("alfred_workflow_bundleid"));
+        let items = output.items();
+        assert_eq!(items.len(), 1);
+        assert!(items[0]
+            .get_subtitle()
+            .unwrap()
+            .contains("alfred_workflow_bundleid"));
+    }
+}
@@ -0,0 +1,88 @@
+//! Text helpers for building item args and AppleScript strings.
+
+/// Words kept lowercase by title_case, unless they start the title.
+const MINOR_WORDS: &[&str] = &[
+    "a", "an", "and", "as", "at", "but", "by", "for", "in", "nor", "of", "on", "or", "the", "to",
+    "via", "vs",
+];
+
+/// Returns `text` as a lowercase, dash-separated slug such as
+/// "rust-1-80-release-notes", for args, uids and file names that should
+/// stay the same however the title is punctuated. Letters and digits are
+/// kept (including non-ASCII ones); runs of anything else become a single
+/// dash.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    if slug.ends_with('-') {
+        slug.pop();
+    }
+    slug
+}
+
+/// Returns `text` in title case: "github-search results" becomes
+/// "Github Search Results". Dashes and underscores separate words like
+/// spaces do. Words that already contain a capital letter (such as
+/// "iPhone" or "URL") are left as they are, and short words like "of"
+/// and "the" stay lowercase unless they come first.
+pub fn title_case(text: &str) -> String {
+    text.split(|c: char| c.is_whitespace() || c == '-' || c == '_')
+        .filter(|word| !word.is_empty())
+        .enumerate()
+        .map(|(i, word)| {
+            let minor = i > 0 && MINOR_WORDS.contains(&word);
+            if minor || word.chars().any(char::is_uppercase) {
+                word.to_string()
+            } else {
+                let mut chars = word.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_uppercase().chain(chars).collect())
+                    .unwrap_or_default()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Escapes `text` for use inside a double-quoted AppleScript string, e.g.
+/// in a script passed to SystemActions::run_osascript.
+pub fn escape_applescript(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(
+            slugify("Rust 1.80 Release Notes"),
+            "rust-1-80-release-notes"
+        );
+        assert_eq!(slugify("  --Hello,  World!--  "), "hello-world");
+        assert_eq!(slugify("Café Crème"), "café-crème");
+        assert_eq!(slugify("!!!"), "");
+    }
+
+    #[test]
+    fn test_title_case() {
+        assert_eq!(title_case("github-search results"), "Github Search Results");
+        assert_eq!(title_case("the state of the art"), "The State of the Art");
+        assert_eq!(title_case("open URL on iPhone"), "Open URL on iPhone");
+        assert_eq!(title_case("élan_vital"), "Élan Vital");
+        assert_eq!(title_case(""), "");
+    }
+
+    #[test]
+    fn test_escape_applescript() {
+        assert_eq!(escape_applescript(r#"a "b" \c"#), r#"a \"b\" \\c"#);
+    }
+}
@@ -0,0 +1,177 @@
+use std::str::FromStr;
+
+use crate::{Error, Result};
+
+/// Color is an RGBA color as reported by Alfred in the
+/// alfred_theme_background family of variables, e.g.
+/// "rgba(255,255,255,0.98)".
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: f32,
+}
+
+impl Color {
+    pub fn new(r: u8, g: u8, b: u8, a: f32) -> Self {
+        Color { r, g, b, a }
+    }
+
+    /// The relative luminance of the color (0.0 for black, 1.0 for white)
+    /// as defined by WCAG 2.0. Alpha is ignored.
+    pub fn luminance(&self) -> f64 {
+        fn channel(value: u8) -> f64 {
+            let c = value as f64 / 255.0;
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        }
+        0.2126 * channel(self.r) + 0.7152 * channel(self.g) + 0.0722 * channel(self.b)
+    }
+
+    /// Returns true when dark text would be hard to read on this color,
+    /// i.e. when light icons should be used.
+    pub fn is_dark(&self) -> bool {
+        // 0.179 is where black and white text have equal contrast
+        self.luminance() < 0.179
+    }
+
+    pub fn appearance(&self) -> Appearance {
+        if self.is_dark() {
+            Appearance::Dark
+        } else {
+            Appearance::Light
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = Error;
+
+    /// Parses "rgba(r,g,b,a)" or "rgb(r,g,b)", as well as "#rrggbb" and
+    /// "#rrggbbaa" hex colors.
+    fn from_str(value: &str) -> Result<Self> {
+        let value = value.trim();
+        let invalid = || Error::Workflow(format!("invalid color {:?}", value));
+
+        if let Some(hex) = value.strip_prefix('#') {
+            let bytes = hex::decode(hex).map_err(|_| invalid())?;
+            return match bytes[..] {
+                [r, g, b] => Ok(Color::new(r, g, b, 1.0)),
+                [r, g, b, a] => Ok(Color::new(r, g, b, a as f32 / 255.0)),
+                _ => Err(invalid()),
+            };
+        }
+
+        let (args, expected) = if let Some(rest) = value.strip_prefix("rgba(") {
+            (rest, 4)
+        } else if let Some(rest) = value.strip_prefix("rgb(") {
+            (rest, 3)
+        } else {
+            return Err(invalid());
+        };
+        let args = args.strip_suffix(')').ok_or_else(invalid)?;
+        let parts: Vec<&str> = args.split(',').map(str::trim).collect();
+        if parts.len() != expected {
+            return Err(invalid());
+        }
+        let channel = |part: &str| part.parse::<u8>().map_err(|_| invalid());
+        let alpha = match parts.get(3) {
+            Some(part) => part
+                .parse::<f32>()
+                .ok()
+                .filter(|a| (0.0..=1.0).contains(a))
+                .ok_or_else(invalid)?,
+            None => 1.0,
+        };
+        Ok(Color::new(
+            channel(parts[0])?,
+            channel(parts[1])?,
+            channel(parts[2])?,
+            alpha,
+        ))
+    }
+}
+
+/// Appearance is whether Alfred's current theme is light or dark. Use it to
+/// pick icon variants that stay legible against the theme background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Appearance {
+    #[default]
+    Light,
+    Dark,
+}
+
+impl Appearance {
+    pub fn is_dark(&self) -> bool {
+        *self == Appearance::Dark
+    }
+
+    /// Returns `light` or `dark` depending on the appearance.
+    pub fn pick<T>(&self, light: T, dark: T) -> T {
+        match self {
+            Appearance::Light => light,
+            Appearance::Dark => dark,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rgba() {
+        let color: Color = "rgba(255,255,255,0.98)".parse().unwrap();
+        assert_eq!(color, Color::new(255, 255, 255, 0.98));
+        let color: Color = "rgba( 28, 30, 33, 1 )".parse().unwrap();
+        assert_eq!(color, Color::new(28, 30, 33, 1.0));
+        let color: Color = "rgb(0,0,0)".parse().unwrap();
+        assert_eq!(color, Color::new(0, 0, 0, 1.0));
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        let color: Color = "#1c1e21".parse().unwrap();
+        assert_eq!(color, Color::new(28, 30, 33, 1.0));
+        let color: Color = "#FFFFFF00".parse().unwrap();
+        assert_eq!(color, Color::new(255, 255, 255, 0.0));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for value in [
+            "",
+            "white",
+            "rgba(255,255,255)",
+            "rgba(256,0,0,1)",
+            "rgba(0,0,0,1.5)",
+            "rgb(0,0,0",
+            "#fff",
+            "#zzzzzz",
+        ] {
+            assert!(value.parse::<Color>().is_err(), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn test_is_dark() {
+        assert!(!Color::new(255, 255, 255, 1.0).is_dark());
+        assert!(!Color::new(236, 236, 236, 1.0).is_dark());
+        assert!(Color::new(0, 0, 0, 1.0).is_dark());
+        assert!(Color::new(28, 30, 33, 1.0).is_dark());
+        assert_eq!(Color::new(28, 30, 33, 1.0).appearance(), Appearance::Dark);
+    }
+
+    #[test]
+    fn test_appearance_pick() {
+        assert_eq!(Appearance::Light.pick("dark.png", "light.png"), "dark.png");
+        assert_eq!(Appearance::Dark.pick("dark.png", "light.png"), "light.png");
+        assert!(Appearance::Dark.is_dark());
+        assert_eq!(Appearance::default(), Appearance::Light);
+    }
+}
@@ -1,6 +1,15 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{Icon, Item, Key, Modifier};
+mod loader;
+
+pub use loader::ColumnMapping;
+
+use crate::text::{slugify, title_case};
+use crate::{Icon, InternalCommand, Item, Key, Modifier, ICON_GENERIC_URL, ICON_INTERNET_LOCATION};
+
+/// The number of path characters kept by URLItem::clean_subtitle before
+/// the path is truncated with an ellipsis.
+const CLEAN_SUBTITLE_PATH_CHARS: usize = 30;
 
 #[non_exhaustive]
 #[derive(Debug, Default, Clone, PartialEq, Hash, Serialize, Deserialize)]
@@ -13,6 +22,13 @@ pub struct URLItem {
     icon: Option<Icon>,
     display_title: Option<String>,
     copy_text: Option<String>,
+    copy_formats: Option<CopyFormats>,
+    #[serde(default)]
+    clean_subtitle: bool,
+    #[serde(default)]
+    auto_icon: bool,
+    #[serde(default)]
+    browsers: Vec<(Vec<Key>, Browser)>,
 }
 
 impl URLItem {
@@ -24,6 +40,15 @@ impl URLItem {
         }
     }
 
+    /// Creates a URLItem titled after the URL: its last path segment in
+    /// title case, without a file extension, or its host when there is no
+    /// path. `https://blog.rust-lang.org/inside-rust/cargo-team.html` is
+    /// titled "Cargo Team".
+    pub fn from_url(url: impl Into<String>) -> Self {
+        let url = url.into();
+        URLItem::new(title_from_url(&url), url)
+    }
+
     pub fn subtitle(mut self, subtitle: impl Into<String>) -> Self {
         self.subtitle = Some(subtitle.into());
         self
@@ -64,95 +89,292 @@ impl URLItem {
         self.copy_text = Some(copy_text.into());
         self
     }
+
+    /// When true and no explicit subtitle is set, the subtitle shows only
+    /// the host and a truncated path (e.g. `github.com/rust-lang/rust`)
+    /// instead of the full URL with scheme, query and fragment.
+    pub fn clean_subtitle(mut self, clean_subtitle: bool) -> Self {
+        self.clean_subtitle = clean_subtitle;
+        self
+    }
+
+    /// When true and no explicit icon is set, an icon is chosen based on
+    /// the URL scheme: the file's own icon for `file:` URLs, a Mail
+    /// message icon for `mailto:` and a web location icon for http(s).
+    pub fn auto_icon(mut self, auto_icon: bool) -> Self {
+        self.auto_icon = auto_icon;
+        self
+    }
+
+    /// Overrides the copy modifiers generated for this item. See
+    /// Workflow::set_copy_formats to change the default for all URLItems.
+    pub fn copy_formats(mut self, copy_formats: CopyFormats) -> Self {
+        self.copy_formats = Some(copy_formats);
+        self
+    }
+
+    /// Adds a modifier opening the URL in `browser` when the item is
+    /// actioned with `keys` held, replacing any earlier browser for the
+    /// same keys. Browser modifiers take precedence over copy modifiers
+    /// bound to the same keys.
+    pub fn open_with(mut self, keys: &[Key], browser: Browser) -> Self {
+        self.browsers.retain(|(k, _)| k.as_slice() != keys);
+        self.browsers.push((keys.to_vec(), browser));
+        self
+    }
+
+    /// Adds a modifier for each `(keys, browser)` pair. See open_with.
+    pub fn open_with_browsers(
+        self,
+        browsers: impl IntoIterator<Item = (Vec<Key>, Browser)>,
+    ) -> Self {
+        browsers
+            .into_iter()
+            .fold(self, |item, (keys, browser)| item.open_with(&keys, browser))
+    }
 }
 
-impl From<URLItem> for Item {
-    fn from(url_item: URLItem) -> Self {
-        let display_title = match url_item.display_title {
-            Some(dt) => dt,
-            None => url_item.title.clone(),
+/// Browser is an application a URLItem modifier can open its URL in,
+/// identified by its bundle id, via alfrusco's internal command handler.
+///
+/// ```
+/// use alfrusco::{Browser, Key, URLItem};
+///
+/// let item = URLItem::new("Rust", "https://www.rust-lang.org/")
+///     .open_with(&[Key::Ctrl], Browser::firefox())
+///     .open_with(&[Key::Cmd, Key::Ctrl], Browser::chrome().profile("Profile 1"));
+/// ```
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Browser {
+    name: String,
+    bundle_id: String,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+impl Browser {
+    /// Creates a Browser shown as `name` in the modifier's subtitle.
+    pub fn new(name: impl Into<String>, bundle_id: impl Into<String>) -> Self {
+        Browser {
+            name: name.into(),
+            bundle_id: bundle_id.into(),
+            args: Vec::new(),
+        }
+    }
+
+    pub fn safari() -> Self {
+        Browser::new("Safari", "com.apple.Safari")
+    }
+
+    pub fn chrome() -> Self {
+        Browser::new("Chrome", "com.google.Chrome")
+    }
+
+    pub fn firefox() -> Self {
+        Browser::new("Firefox", "org.mozilla.firefox")
+    }
+
+    pub fn arc() -> Self {
+        Browser::new("Arc", "company.thebrowser.Browser")
+    }
+
+    pub fn edge() -> Self {
+        Browser::new("Edge", "com.microsoft.edgemac")
+    }
+
+    /// Command line arguments for the browser. A browser that's already
+    /// running is started as a new instance to receive them.
+    pub fn args(mut self, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Opens the URL in a profile: `--profile-directory` for Chromium
+    /// based browsers (e.g. "Profile 1"), `-P` for Firefox (the profile's
+    /// name). The profile is added to the name shown in the subtitle.
+    pub fn profile(mut self, profile: impl Into<String>) -> Self {
+        let profile = profile.into();
+        let args = if self.bundle_id == "org.mozilla.firefox" {
+            vec!["-P".to_string(), profile.clone()]
+        } else {
+            vec![format!("--profile-directory={}", profile)]
         };
-        let title = url_item.title.clone();
-        let short_title = url_item.short_title.clone();
-        let long_title = url_item.long_title.clone();
-        let url = url_item.url.clone();
-        let copy_text = url_item.copy_text.clone();
-
-        let cmd_mod = Modifier::new(Key::Cmd)
-            .subtitle(format!("Copy Markdown Link '{}'", &title))
-            .arg("run")
-            .var("ALFRUSCO_COMMAND", "markdown")
-            .var("TITLE", &title)
-            .var("URL", &url);
-        let alt_mod = Modifier::new(Key::Alt)
-            .subtitle(format!("Copy Rich Text Link '{}'", &title))
+        self.name = format!("{} ({})", self.name, profile);
+        self.args(args)
+    }
+
+    fn modifier(&self, keys: &[Key], url: &str) -> Modifier {
+        let mut command = InternalCommand::new("open_with")
+            .arg("url", url)
+            .arg("open_with", &self.bundle_id);
+        if !self.args.is_empty() {
+            let args = serde_json::to_string(&self.args).unwrap_or_default();
+            command = command.arg("open_with_args", args);
+        }
+        Modifier::new_combo(keys)
+            .subtitle(format!("Open in {}", self.name))
             .arg("run")
-            .var("ALFRUSCO_COMMAND", "richtext")
-            .var("TITLE", &title)
-            .var("URL", &url);
+            .internal_command(command)
+    }
+}
+
+/// CopyFormat is one of the link formats a URLItem modifier can copy to
+/// the clipboard via alfrusco's internal command handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CopyFormat {
+    /// `[title](url)`
+    Markdown,
+    /// An HTML link on the clipboard, pasted as rich text
+    RichText,
+    /// An RTF link on the clipboard, for apps such as Mail and Pages that
+    /// paste RichText's HTML poorly
+    RichTextRtf,
+    /// The HTML source of a link, as plain text
+    Html,
+    /// `[[url][title]]`
+    Org,
+    /// Just the URL
+    PlainUrl,
+    /// `title — url`
+    TitleAndUrl,
+    /// The title as a slug, such as `rust-1-80-release-notes`; see
+    /// text::slugify
+    Slug,
+}
+
+impl CopyFormat {
+    /// The internal command copying the format
+    fn command(&self) -> &'static str {
+        match self {
+            CopyFormat::Markdown => "markdown",
+            CopyFormat::RichText | CopyFormat::RichTextRtf => "richtext",
+            CopyFormat::Html => "html",
+            CopyFormat::Org => "org",
+            CopyFormat::PlainUrl => "url",
+            CopyFormat::TitleAndUrl => "title_url",
+            CopyFormat::Slug => "copy",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            CopyFormat::Markdown => "Copy Markdown Link",
+            CopyFormat::RichText | CopyFormat::RichTextRtf => "Copy Rich Text Link",
+            CopyFormat::Html => "Copy HTML Link",
+            CopyFormat::Org => "Copy Org-mode Link",
+            CopyFormat::PlainUrl => "Copy URL",
+            CopyFormat::TitleAndUrl => "Copy Title and URL",
+            CopyFormat::Slug => "Copy Slug",
+        }
+    }
+}
+
+/// CopyFormats configures which copy modifiers a URLItem generates and
+/// which keys they are bound to.
+///
+/// Each binding applies to the item's title. When a URLItem has a
+/// short_title, each binding is repeated with Shift added to its keys; for
+/// a long_title, with Ctrl added. The default binds Markdown to Cmd and
+/// Rich Text to Alt.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CopyFormats {
+    bindings: Vec<(Vec<Key>, CopyFormat)>,
+}
+
+impl Default for CopyFormats {
+    fn default() -> Self {
+        CopyFormats::none()
+            .bind(&[Key::Cmd], CopyFormat::Markdown)
+            .bind(&[Key::Alt], CopyFormat::RichText)
+    }
+}
+
+impl CopyFormats {
+    /// Creates a CopyFormats with no bindings.
+    pub fn none() -> Self {
+        CopyFormats {
+            bindings: Vec::new(),
+        }
+    }
+
+    /// Binds the key combination to the format, replacing any existing
+    /// binding for the same keys.
+    pub fn bind(mut self, keys: &[Key], format: CopyFormat) -> Self {
+        self.bindings.retain(|(k, _)| k.as_slice() != keys);
+        self.bindings.push((keys.to_vec(), format));
+        self
+    }
+
+    fn modifiers(&self, extra_key: Option<Key>, title: &str, url: &str) -> Vec<Modifier> {
+        self.bindings
+            .iter()
+            .map(|(keys, format)| {
+                let mut keys = keys.clone();
+                keys.extend(extra_key);
+                let modifier = Modifier::new_combo(&keys)
+                    .subtitle(format!("{} '{}'", format.description(), title))
+                    .arg("run");
+                match format {
+                    CopyFormat::Slug => modifier
+                        .internal_command_with(format.command(), &[("text", &slugify(title))]),
+                    CopyFormat::RichTextRtf => modifier.internal_command_with(
+                        format.command(),
+                        &[("richtext_format", "rtf"), ("title", title), ("url", url)],
+                    ),
+                    _ => modifier
+                        .internal_command_with(format.command(), &[("title", title), ("url", url)]),
+                }
+            })
+            .collect()
+    }
+}
+
+impl URLItem {
+    /// Converts this URLItem into an Item, using `default_formats` unless
+    /// the URLItem has its own copy_formats set.
+    pub fn into_item_with(self, default_formats: &CopyFormats) -> Item {
+        let copy_formats = self.copy_formats.as_ref().unwrap_or(default_formats);
+        let display_title = self.display_title.as_ref().unwrap_or(&self.title);
 
         let mut item = Item::new(display_title)
-            .subtitle(&url_item.url)
-            .uid(&url_item.url)
-            .arg(&url_item.url)
-            .copy_text(&url_item.url)
-            .valid(true)
-            .modifier(cmd_mod)
-            .modifier(alt_mod);
-
-        if url_item.subtitle.is_some() {
-            item = item.subtitle(url_item.subtitle.unwrap());
+            .subtitle(&self.url)
+            .uid(&self.url)
+            .arg(&self.url)
+            .copy_text(&self.url)
+            .valid(true);
+
+        let titles = [
+            (None, Some(&self.title)),
+            (Some(Key::Shift), self.short_title.as_ref()),
+            (Some(Key::Ctrl), self.long_title.as_ref()),
+        ];
+        for (extra_key, title) in titles {
+            if let Some(title) = title {
+                for modifier in copy_formats.modifiers(extra_key, title, &self.url) {
+                    item = item.modifier(modifier);
+                }
+            }
         }
 
-        if url_item.icon.is_some() {
-            item = item.icon(url_item.icon.unwrap());
+        for (keys, browser) in &self.browsers {
+            item = item.modifier(browser.modifier(keys, &self.url));
         }
 
-        if let Some(short_title) = &short_title {
-            item = item
-                .modifier(
-                    Modifier::new_combo(&[Key::Cmd, Key::Shift])
-                        .subtitle(format!("Copy Markdown Link '{}'", short_title))
-                        .arg("run")
-                        .var("ALFRUSCO_COMMAND", "markdown")
-                        .var("TITLE", short_title)
-                        .var("URL", &url)
-                        .valid(true),
-                )
-                .modifier(
-                    Modifier::new_combo(&[Key::Alt, Key::Shift])
-                        .subtitle(format!("Copy Rich Text Link '{}'", short_title))
-                        .arg("run")
-                        .var("ALFRUSCO_COMMAND", "richtext")
-                        .var("TITLE", short_title)
-                        .var("URL", &url)
-                        .valid(true),
-                )
+        if let Some(subtitle) = self.subtitle {
+            item = item.subtitle(subtitle);
+        } else if self.clean_subtitle {
+            item = item.subtitle(clean_url(&self.url, CLEAN_SUBTITLE_PATH_CHARS));
         }
 
-        if let Some(long_title) = &long_title {
-            item = item
-                .modifier(
-                    Modifier::new_combo(&[Key::Cmd, Key::Ctrl])
-                        .subtitle(format!("Copy Markdown Link '{}'", long_title))
-                        .arg("run")
-                        .var("ALFRUSCO_COMMAND", "markdown")
-                        .var("TITLE", long_title)
-                        .var("URL", &url)
-                        .valid(true),
-                )
-                .modifier(
-                    Modifier::new_combo(&[Key::Alt, Key::Ctrl])
-                        .subtitle(format!("Copy Rich Text Link '{}'", long_title))
-                        .arg("run")
-                        .var("ALFRUSCO_COMMAND", "richtext")
-                        .var("TITLE", long_title)
-                        .var("URL", &url)
-                        .valid(true),
-                );
+        if let Some(icon) = self.icon {
+            item = item.icon(icon);
+        } else if self.auto_icon {
+            item = item.icon(icon_for_url(&self.url));
         }
 
-        if let Some(copy_text) = copy_text {
+        if let Some(copy_text) = self.copy_text {
             item = item.copy_text(copy_text);
         }
 
@@ -160,11 +382,86 @@ impl From<URLItem> for Item {
     }
 }
 
+/// Returns a display-friendly version of the URL: the host plus a path
+/// truncated to `max_path_chars`. Scheme, credentials, query and fragment
+/// are dropped. URLs without a `scheme://` authority are returned as-is.
+pub fn clean_url(url: &str, max_path_chars: usize) -> String {
+    let Some((_, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    let rest = rest.split(['?', '#']).next().unwrap_or_default();
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, ""),
+    };
+    let host = authority.rsplit('@').next().unwrap_or(authority);
+    let path = path.trim_end_matches('/');
+
+    if path.chars().count() > max_path_chars {
+        let truncated: String = path.chars().take(max_path_chars).collect();
+        format!("{}{}…", host, truncated)
+    } else {
+        format!("{}{}", host, path)
+    }
+}
+
+/// Returns the title URLItem::from_url gives `url`.
+fn title_from_url(url: &str) -> String {
+    let cleaned = clean_url(url, usize::MAX);
+    let (host, path) = cleaned.split_once('/').unwrap_or((&cleaned, ""));
+    let segment = path.rsplit('/').next().unwrap_or_default();
+    let segment = match segment.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && ext.chars().all(|c| c.is_ascii_alphabetic()) => {
+            stem
+        }
+        _ => segment,
+    };
+    let title = title_case(segment);
+    if title.is_empty() {
+        host.to_string()
+    } else {
+        title
+    }
+}
+
+/// Picks a default icon for the URL based on its scheme.
+fn icon_for_url(url: &str) -> Icon {
+    let scheme = url
+        .split_once(':')
+        .map(|(scheme, _)| scheme.to_ascii_lowercase())
+        .unwrap_or_default();
+    match scheme.as_str() {
+        "file" => Icon {
+            type_: Some("fileicon".to_string()),
+            path: url.trim_start_matches("file://").to_string(),
+        },
+        "mailto" => Icon {
+            type_: Some("filetype".to_string()),
+            path: "com.apple.mail.email".to_string(),
+        },
+        "http" | "https" => ICON_INTERNET_LOCATION.into(),
+        _ => ICON_GENERIC_URL.into(),
+    }
+}
+
+impl From<URLItem> for Item {
+    fn from(url_item: URLItem) -> Self {
+        url_item.into_item_with(&CopyFormats::default())
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
-    use crate::Arg;
+    use crate::config::EnvMap;
+    use crate::{Arg, INTERNAL_VAR};
+
+    /// Decodes the internal command the modifier carries.
+    fn internal(modifier: &Modifier) -> InternalCommand {
+        let env: EnvMap = modifier.variables.clone().unwrap().into_iter().collect();
+        InternalCommand::from_env(&env).unwrap()
+    }
 
     #[test]
     fn test_new_url_item() {
@@ -243,6 +540,217 @@ mod tests {
         assert_eq!(icon.path, "com.adobe.pdf");
     }
 
+    #[test]
+    fn test_default_copy_formats() {
+        let item: Item = URLItem::new("Rust", "https://www.rust-lang.org/").into();
+        assert_eq!(item.modifiers.len(), 2);
+        assert_eq!(
+            item.modifiers["cmd"].subtitle,
+            Some("Copy Markdown Link 'Rust'".to_string())
+        );
+        assert_eq!(
+            item.modifiers["alt"].subtitle,
+            Some("Copy Rich Text Link 'Rust'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_custom_copy_formats() {
+        let formats = CopyFormats::none()
+            .bind(&[Key::Cmd], CopyFormat::Org)
+            .bind(&[Key::Fn], CopyFormat::PlainUrl)
+            .bind(&[Key::Cmd], CopyFormat::TitleAndUrl);
+        let item: Item = URLItem::new("Rust", "https://www.rust-lang.org/")
+            .short_title("rs")
+            .copy_formats(formats)
+            .into();
+
+        let mut keys: Vec<&str> = item.modifiers.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(keys, vec!["cmd", "cmd+shift", "fn", "fn+shift"]);
+
+        let cmd = &item.modifiers["cmd"];
+        assert_eq!(cmd.subtitle, Some("Copy Title and URL 'Rust'".to_string()));
+        assert_eq!(internal(cmd).command(), "title_url");
+        let fn_shift = &item.modifiers["fn+shift"];
+        assert_eq!(fn_shift.subtitle, Some("Copy URL 'rs'".to_string()));
+        assert_eq!(internal(fn_shift).get("title"), Some("rs"));
+    }
+
+    #[test]
+    fn test_slug_format() {
+        let formats = CopyFormats::none().bind(&[Key::Ctrl], CopyFormat::Slug);
+        let item: Item = URLItem::new("Rust 1.80: Release Notes", "https://blog.rust-lang.org/")
+            .copy_formats(formats)
+            .into();
+        let ctrl = &item.modifiers["ctrl"];
+        assert_eq!(
+            ctrl.subtitle,
+            Some("Copy Slug 'Rust 1.80: Release Notes'".to_string())
+        );
+        assert_eq!(
+            internal(ctrl),
+            InternalCommand::new("copy").arg("text", "rust-1-80-release-notes")
+        );
+    }
+
+    #[test]
+    fn test_from_url() {
+        let cases = [
+            (
+                "https://blog.rust-lang.org/inside-rust/cargo-team.html",
+                "Cargo Team",
+            ),
+            ("https://github.com/rust-lang/rust/", "Rust"),
+            ("https://blog.rust-lang.org/Rust-1.80.0", "Rust 1.80.0"),
+            ("https://www.rust-lang.org/?q=1", "www.rust-lang.org"),
+            ("mailto:someone@example.com", "mailto:someone@example.com"),
+        ];
+        for (url, title) in cases {
+            let item: Item = URLItem::from_url(url).into();
+            assert_eq!(item.title, title, "{}", url);
+            assert_eq!(item.arg, Some(Arg::One(url.to_string())));
+        }
+    }
+
+    #[test]
+    fn test_rich_text_rtf_format() {
+        let formats = CopyFormats::none().bind(&[Key::Alt], CopyFormat::RichTextRtf);
+        let item: Item = URLItem::new("Rust", "https://www.rust-lang.org/")
+            .copy_formats(formats)
+            .into();
+        let command = internal(&item.modifiers["alt"]);
+        assert_eq!(command.command(), "richtext");
+        assert_eq!(command.get("richtext_format"), Some("rtf"));
+
+        let item: Item = URLItem::new("Rust", "https://www.rust-lang.org/").into();
+        assert_eq!(
+            internal(&item.modifiers["alt"]).get("richtext_format"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_open_with_browsers() {
+        let item: Item = URLItem::new("Rust", "https://www.rust-lang.org/")
+            .long_title("The Rust Programming Language")
+            .open_with(&[Key::Ctrl], Browser::safari())
+            .open_with_browsers([
+                (vec![Key::Ctrl], Browser::firefox().profile("work")),
+                (
+                    vec![Key::Cmd, Key::Ctrl],
+                    Browser::chrome().profile("Profile 1"),
+                ),
+            ])
+            .into();
+
+        let ctrl = &item.modifiers["ctrl"];
+        assert_eq!(ctrl.subtitle.as_deref(), Some("Open in Firefox (work)"));
+        assert_eq!(
+            ctrl.variables.as_ref().unwrap()[INTERNAL_VAR],
+            r#"{"command":"open_with","open_with":"org.mozilla.firefox","open_with_args":"[\"-P\",\"work\"]","url":"https://www.rust-lang.org/"}"#
+        );
+
+        // The browser replaces the long_title's Markdown copy modifier
+        let cmd_ctrl = &item.modifiers["cmd+ctrl"];
+        assert_eq!(
+            cmd_ctrl.subtitle.as_deref(),
+            Some("Open in Chrome (Profile 1)")
+        );
+        let command = internal(cmd_ctrl);
+        assert_eq!(command.get("open_with"), Some("com.google.Chrome"));
+        assert_eq!(
+            command.get("open_with_args"),
+            Some(r#"["--profile-directory=Profile 1"]"#)
+        );
+        assert_eq!(
+            item.modifiers["alt+ctrl"].subtitle.as_deref(),
+            Some("Copy Rich Text Link 'The Rust Programming Language'")
+        );
+
+        let item: Item = URLItem::new("Rust", "https://www.rust-lang.org/")
+            .open_with(&[Key::Shift], Browser::safari())
+            .into();
+        assert_eq!(
+            internal(&item.modifiers["shift"]).get("open_with_args"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_into_item_with_workflow_default() {
+        let defaults = CopyFormats::none().bind(&[Key::Alt], CopyFormat::Html);
+        let item = URLItem::new("Rust", "https://www.rust-lang.org/").into_item_with(&defaults);
+        assert_eq!(item.modifiers.len(), 1);
+        assert_eq!(
+            item.modifiers["alt"].subtitle,
+            Some("Copy HTML Link 'Rust'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_clean_url() {
+        let cases = [
+            ("https://www.rust-lang.org/", "www.rust-lang.org"),
+            (
+                "https://github.com/rust-lang/rust?tab=readme#top",
+                "github.com/rust-lang/rust",
+            ),
+            ("https://user:pw@example.com/a", "example.com/a"),
+            (
+                "https://docs.rs/tokio/latest/tokio/sync/struct.Mutex.html",
+                "docs.rs/tokio/latest/tokio/sync/struc…",
+            ),
+            ("mailto:someone@example.com", "mailto:someone@example.com"),
+        ];
+        for (url, expected) in cases {
+            assert_eq!(clean_url(url, 30), expected, "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_clean_subtitle() {
+        let item: Item = URLItem::new("Rust", "https://github.com/rust-lang/rust?x=1")
+            .clean_subtitle(true)
+            .into();
+        assert_eq!(item.subtitle, Some("github.com/rust-lang/rust".to_string()));
+
+        let item: Item = URLItem::new("Rust", "https://github.com/rust-lang/rust")
+            .subtitle("Explicit")
+            .clean_subtitle(true)
+            .into();
+        assert_eq!(item.subtitle, Some("Explicit".to_string()));
+    }
+
+    #[test]
+    fn test_auto_icon() {
+        let cases = [
+            ("https://www.rust-lang.org/", None, ICON_INTERNET_LOCATION),
+            ("mailto:a@b.com", Some("filetype"), "com.apple.mail.email"),
+            (
+                "file:///Users/crayons/notes.txt",
+                Some("fileicon"),
+                "/Users/crayons/notes.txt",
+            ),
+            ("slack://open", None, ICON_GENERIC_URL),
+        ];
+        for (url, type_, path) in cases {
+            let item: Item = URLItem::new("Item", url).auto_icon(true).into();
+            let icon = item.icon.unwrap();
+            assert_eq!(icon.type_.as_deref(), type_, "{}", url);
+            assert_eq!(icon.path, path, "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_explicit_icon_wins_over_auto_icon() {
+        let item: Item = URLItem::new("Item", "https://www.rust-lang.org/")
+            .icon_from_image("rust.png")
+            .auto_icon(true)
+            .into();
+        assert_eq!(item.icon.unwrap().path, "rust.png");
+    }
+
     #[test]
     fn test_into_item() {
         let item: Item = URLItem::new("Rust", "https://www.rust-lang.org/").into();
@@ -0,0 +1,230 @@
+use std::fs::read_to_string;
+use std::path::Path;
+
+use serde_json::Value;
+
+use super::URLItem;
+use crate::{Error, Result};
+
+/// ColumnMapping names the TSV header columns (or JSON object keys) that
+/// URLItem fields are read from when loading URLItems from a file.
+///
+/// The default mapping reads `title`, `url`, `subtitle`, `short_title`
+/// and `long_title`. Only the title and url columns are required.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnMapping {
+    pub title: String,
+    pub url: String,
+    pub subtitle: Option<String>,
+    pub short_title: Option<String>,
+    pub long_title: Option<String>,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        ColumnMapping {
+            title: "title".to_string(),
+            url: "url".to_string(),
+            subtitle: Some("subtitle".to_string()),
+            short_title: Some("short_title".to_string()),
+            long_title: Some("long_title".to_string()),
+        }
+    }
+}
+
+impl ColumnMapping {
+    pub fn new(title: impl Into<String>, url: impl Into<String>) -> Self {
+        ColumnMapping {
+            title: title.into(),
+            url: url.into(),
+            subtitle: None,
+            short_title: None,
+            long_title: None,
+        }
+    }
+
+    pub fn subtitle(mut self, column: impl Into<String>) -> Self {
+        self.subtitle = Some(column.into());
+        self
+    }
+
+    pub fn short_title(mut self, column: impl Into<String>) -> Self {
+        self.short_title = Some(column.into());
+        self
+    }
+
+    pub fn long_title(mut self, column: impl Into<String>) -> Self {
+        self.long_title = Some(column.into());
+        self
+    }
+
+    /// Builds a URLItem from a record, using `lookup` to fetch a column's
+    /// value by name. Empty optional values are ignored.
+    fn build<'a>(&self, lookup: impl Fn(&str) -> Option<&'a str>) -> Result<URLItem> {
+        let required = |column: &str| {
+            lookup(column)
+                .ok_or_else(|| Error::Workflow(format!("missing required column '{}'", column)))
+        };
+        let optional = |column: &Option<String>| {
+            column
+                .as_deref()
+                .and_then(&lookup)
+                .filter(|value| !value.is_empty())
+        };
+
+        let mut url_item = URLItem::new(required(&self.title)?, required(&self.url)?);
+        if let Some(subtitle) = optional(&self.subtitle) {
+            url_item = url_item.subtitle(subtitle);
+        }
+        if let Some(short_title) = optional(&self.short_title) {
+            url_item = url_item.short_title(short_title);
+        }
+        if let Some(long_title) = optional(&self.long_title) {
+            url_item = url_item.long_title(long_title);
+        }
+        Ok(url_item)
+    }
+}
+
+impl URLItem {
+    /// Loads URLItems from a tab-separated file whose first line is a
+    /// header row, using the default ColumnMapping.
+    pub fn from_tsv(path: impl AsRef<Path>) -> Result<Vec<URLItem>> {
+        Self::from_tsv_with(path, &ColumnMapping::default())
+    }
+
+    /// Loads URLItems from a tab-separated file whose first line is a
+    /// header row naming the columns referenced by `mapping`. Blank lines
+    /// are skipped.
+    pub fn from_tsv_with(path: impl AsRef<Path>, mapping: &ColumnMapping) -> Result<Vec<URLItem>> {
+        let contents = read_to_string(path)?;
+        let mut lines = contents.lines();
+        let headers: Vec<&str> = match lines.next() {
+            Some(header) => header.split('\t').map(str::trim).collect(),
+            None => return Ok(Vec::new()),
+        };
+
+        lines
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+                mapping.build(|column| {
+                    headers
+                        .iter()
+                        .position(|header| *header == column)
+                        .and_then(|index| fields.get(index).copied())
+                })
+            })
+            .collect()
+    }
+
+    /// Loads URLItems from a JSON file containing an array of objects,
+    /// using the default ColumnMapping.
+    pub fn from_json(path: impl AsRef<Path>) -> Result<Vec<URLItem>> {
+        Self::from_json_with(path, &ColumnMapping::default())
+    }
+
+    /// Loads URLItems from a JSON file containing an array of objects whose
+    /// string fields are named by `mapping`.
+    pub fn from_json_with(path: impl AsRef<Path>, mapping: &ColumnMapping) -> Result<Vec<URLItem>> {
+        let contents = read_to_string(path)?;
+        let records: Vec<serde_json::Map<String, Value>> = serde_json::from_str(&contents)?;
+        records
+            .iter()
+            .map(|record| mapping.build(|column| record.get(column).and_then(Value::as_str)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::write;
+
+    use super::*;
+    use crate::Item;
+
+    #[test]
+    fn test_from_tsv() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("links.tsv");
+        write(
+            &path,
+            "title\turl\tsubtitle\n\
+             Rust\thttps://www.rust-lang.org/\tThe language\n\
+             \n\
+             crates.io\thttps://crates.io/\t\n",
+        )?;
+
+        let items = URLItem::from_tsv(&path)?;
+        assert_eq!(items.len(), 2);
+        let first: Item = items[0].clone().into();
+        assert_eq!(first.title, "Rust");
+        assert_eq!(first.subtitle, Some("The language".to_string()));
+        let second: Item = items[1].clone().into();
+        assert_eq!(second.subtitle, Some("https://crates.io/".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_tsv_with_mapping() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("bookmarks.tsv");
+        write(
+            &path,
+            "Link\tName\tShort\nhttps://docs.rs/\tDocs.rs\tdocs\n",
+        )?;
+
+        let mapping = ColumnMapping::new("Name", "Link").short_title("Short");
+        let items = URLItem::from_tsv_with(&path, &mapping)?;
+        let item: Item = items[0].clone().into();
+        assert_eq!(item.title, "Docs.rs");
+        assert!(item.modifiers.contains_key("cmd+shift"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_tsv_missing_column() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("links.tsv");
+        write(&path, "title\nRust\n")?;
+
+        let err = URLItem::from_tsv(&path).unwrap_err();
+        assert!(err.to_string().contains("'url'"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_json() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("links.json");
+        write(
+            &path,
+            r#"[
+                {"title": "Rust", "url": "https://www.rust-lang.org/", "long_title": "The Rust Programming Language"},
+                {"name": "ignored", "title": "Docs", "url": "https://docs.rs/"}
+            ]"#,
+        )?;
+
+        let items = URLItem::from_json(&path)?;
+        assert_eq!(items.len(), 2);
+        let first: Item = items[0].clone().into();
+        assert!(first.modifiers.contains_key("cmd+ctrl"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_json_with_mapping() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("links.json");
+        write(
+            &path,
+            r#"[{"name": "Rust", "href": "https://www.rust-lang.org/"}]"#,
+        )?;
+
+        let items = URLItem::from_json_with(&path, &ColumnMapping::new("name", "href"))?;
+        let item: Item = items[0].clone().into();
+        assert_eq!(item.title, "Rust");
+        Ok(())
+    }
+}
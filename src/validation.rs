@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+
+use log::{error, warn};
+
+use crate::item::Item;
+use crate::response::{MAX_RERUN, MIN_RERUN};
+use crate::workflow::Workflow;
+use crate::{i18n, Error, WorkflowError};
+
+impl Workflow {
+    /// When enabled (and the workflow is running in Alfred's debug mode),
+    /// any validation warnings are also shown as an item at the top of
+    /// the results, in addition to being logged.
+    pub fn show_validation_warnings(&mut self, enabled: bool) {
+        self.show_validation_warnings = enabled;
+    }
+
+    /// Enables strict mode, meant for development: every validation
+    /// warning becomes an error, and the results are replaced by an error
+    /// item listing them, as if the Runnable had failed. Unlike warnings,
+    /// strict checks run outside Alfred's debug mode too.
+    pub fn strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Checks the response for common mistakes when the workflow is
+    /// running in debug mode (or strict mode), logging a warning for each
+    /// one found.
+    pub(crate) fn validate(&mut self) {
+        if !self.config.debug && !self.strict {
+            return;
+        }
+        let mut warnings = validate_items(&self.response.items);
+        if let Some(rerun) = self.response.rerun {
+            if rerun < MIN_RERUN || rerun > MAX_RERUN {
+                warnings.push(format!(
+                    "rerun of {:?} is outside Alfred's 0.1 to 5 second range",
+                    rerun
+                ));
+            }
+        }
+        if self.strict && !warnings.is_empty() {
+            let error = Error::Workflow(format!("strict mode: {}", warnings.join("; ")));
+            error!("{}", error);
+            let item = i18n::with_strings(&self.strings, || error.error_item());
+            self.response.items(vec![self.error_style.apply(item)]);
+            return;
+        }
+        for warning in &warnings {
+            warn!("{}", warning);
+        }
+        if self.show_validation_warnings && !warnings.is_empty() {
+            // Sticky so the warnings survive keyword filtering
+            self.prepend_item(
+                Item::new(format!("{} item warning(s)", warnings.len()))
+                    .subtitle(warnings.join("; "))
+                    .valid(false)
+                    .sticky_with_priority(u8::MAX),
+            );
+        }
+    }
+}
+
+/// Returns a description of each common mistake found in the items:
+/// empty titles, valid items without an arg, bufferable items without a
+/// uid or that aren't valid, duplicate uids, and modifier combinations
+/// that Alfred treats as the same (e.g. "cmd+alt" and "alt+cmd").
+pub(crate) fn validate_items(items: &[Item]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut uids: HashMap<&str, usize> = HashMap::new();
+
+    for (i, item) in items.iter().enumerate() {
+        if item.title.trim().is_empty() {
+            warnings.push(format!("item {} has an empty title", i));
+        }
+
+        if item.valid == Some(true) && item.arg.is_none() {
+            warnings.push(format!(
+                "item {} ({:?}) is valid but has no arg",
+                i, item.title
+            ));
+        }
+
+        if item.bufferable && item.uid.is_none() {
+            warnings.push(format!(
+                "item {} ({:?}) is bufferable but has no uid",
+                i, item.title
+            ));
+        }
+
+        if item.bufferable && item.valid == Some(false) {
+            warnings.push(format!(
+                "item {} ({:?}) is bufferable but not valid",
+                i, item.title
+            ));
+        }
+
+        if let Some(uid) = item.uid.as_deref() {
+            if let Some(first) = uids.insert(uid, i) {
+                warnings.push(format!("items {} and {} share the uid {:?}", first, i, uid));
+            }
+        }
+
+        let mut combos: HashMap<Vec<&str>, Vec<&str>> = HashMap::new();
+        for keys in item.modifiers.keys() {
+            let mut combo: Vec<&str> = keys.split('+').collect();
+            combo.sort_unstable();
+            combos.entry(combo).or_default().push(keys);
+        }
+        let mut duplicates: Vec<Vec<&str>> =
+            combos.into_values().filter(|keys| keys.len() > 1).collect();
+        duplicates.sort_unstable();
+        for mut keys in duplicates {
+            keys.sort_unstable();
+            warnings.push(format!(
+                "item {} ({:?}) has duplicate modifier combos: {}",
+                i,
+                item.title,
+                keys.join(", ")
+            ));
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::test_support::workflow_in;
+    use crate::{Key, Modifier};
+
+    #[test]
+    fn test_valid_items_have_no_warnings() {
+        let items = vec![
+            Item::new("One").uid("1").arg("one").valid(true),
+            Item::new("Two").uid("2"),
+        ];
+        assert!(validate_items(&items).is_empty());
+    }
+
+    #[test]
+    fn test_empty_title() {
+        let warnings = validate_items(&[Item::new(" ")]);
+        assert_eq!(warnings, vec!["item 0 has an empty title"]);
+    }
+
+    #[test]
+    fn test_valid_without_arg() {
+        let warnings = validate_items(&[Item::new("One").valid(true)]);
+        assert_eq!(warnings, vec!["item 0 (\"One\") is valid but has no arg"]);
+    }
+
+    #[test]
+    fn test_bufferable_without_uid() {
+        let items = vec![
+            Item::new("One").arg("one").bufferable(),
+            Item::new("Two").arg("two").bufferable().no_learning(),
+            Item::new("Three").arg("three").bufferable().valid(false),
+        ];
+        let warnings = validate_items(&items);
+        assert_eq!(
+            warnings,
+            vec![
+                "item 1 (\"Two\") is bufferable but has no uid",
+                "item 2 (\"Three\") is bufferable but not valid",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_uid_collision() {
+        let items = vec![
+            Item::new("One").uid("same"),
+            Item::new("Two").uid("other"),
+            Item::new("Three").uid("same"),
+        ];
+        let warnings = validate_items(&items);
+        assert_eq!(warnings, vec!["items 0 and 2 share the uid \"same\""]);
+    }
+
+    #[test]
+    fn test_duplicate_modifier_combos() {
+        let item = Item::new("One")
+            .modifier(Modifier::new_combo(&[Key::Cmd, Key::Alt]))
+            .modifier(Modifier::new_combo(&[Key::Alt, Key::Cmd]))
+            .modifier(Modifier::new(Key::Cmd));
+        let warnings = validate_items(&[item]);
+        assert_eq!(
+            warnings,
+            vec!["item 0 (\"One\") has duplicate modifier combos: alt+cmd, cmd+alt"]
+        );
+    }
+
+    #[test]
+    fn test_validate_adds_debug_item() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        workflow.append_item(Item::new(""));
+
+        workflow.validate();
+        assert_eq!(workflow.response.items.len(), 1);
+
+        workflow.show_validation_warnings(true);
+        workflow.validate();
+        let items = &workflow.response.items;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].title, "1 item warning(s)");
+        assert_eq!(
+            items[0].subtitle.as_deref(),
+            Some("item 0 has an empty title")
+        );
+    }
+
+    #[test]
+    fn test_rerun_out_of_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        workflow.show_validation_warnings(true);
+        workflow.response.rerun(Duration::from_millis(50));
+
+        workflow.validate();
+        assert_eq!(
+            workflow.response.items[0].subtitle.as_deref(),
+            Some("rerun of 50ms is outside Alfred's 0.1 to 5 second range")
+        );
+    }
+
+    #[test]
+    fn test_strict_mode_replaces_items_with_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        workflow.config.debug = false;
+        workflow.strict(true);
+        workflow.append_items(vec![Item::new("One").uid("1"), Item::new("Two").uid("1")]);
+
+        workflow.validate();
+        let items = &workflow.response.items;
+        assert_eq!(items.len(), 1);
+        assert_eq!(
+            items[0].title,
+            "An error occurred: Workflow Error: strict mode: items 0 and 1 share the uid \"1\""
+        );
+        assert_eq!(items[0].sticky, Some(0));
+    }
+
+    #[test]
+    fn test_strict_mode_passes_valid_items() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        workflow.strict(true);
+        workflow.response.rerun(Duration::from_secs(1));
+        workflow.append_item(Item::new("One").uid("1"));
+
+        workflow.validate();
+        assert_eq!(workflow.response.items.len(), 1);
+        assert_eq!(workflow.response.items[0].title, "One");
+    }
+
+    #[test]
+    fn test_validate_skipped_outside_debug_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workflow = workflow_in(dir.path());
+        workflow.config.debug = false;
+        workflow.show_validation_warnings(true);
+        workflow.append_item(Item::new(""));
+
+        workflow.validate();
+        assert_eq!(workflow.response.items.len(), 1);
+    }
+}
@@ -1,9 +1,33 @@
-use std::path::PathBuf;
-
-use crate::config::WorkflowConfig;
-use crate::error::Result;
-use crate::item::Item;
-use crate::response::Response;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::warn;
+use tokio_util::sync::CancellationToken;
+
+use crate::background::JOBS_QUERY;
+use crate::background_job::JobStateSnapshot;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use crate::compression::Compression;
+use crate::config::{parse_duration, WorkflowConfig};
+use crate::degraded::ensure_dirs;
+use crate::dump::DUMP_QUERY;
+use crate::error::{Error, ErrorStyle, Result};
+use crate::filter::{
+    filter_and_sort_items, filter_and_sort_items_by, highlight_matches, FilterKeyFn,
+};
+use crate::history::HISTORY_QUERY;
+use crate::hooks::Hooks;
+use crate::i18n::{locale_from_env, Strings, LOCALES_DIR};
+use crate::item::{Icon, Item};
+use crate::logging;
+use crate::output::Output;
+use crate::reset::{RESET_CONFIRM_QUERY, RESET_QUERY};
+use crate::response::{LearningPolicy, OutputFormat, Response};
+use crate::stats::STATS_QUERY;
+use crate::system::{DefaultSystemActions, SystemActions};
+use crate::theme::Appearance;
+use crate::url_item::{CopyFormats, URLItem};
 
 /// Workflow represents an active execution of an Alfred workflow.
 ///
@@ -20,19 +44,151 @@ pub struct Workflow {
 
     pub keyword: Option<String>,
     pub(crate) sort_and_filter_results: bool,
+
+    /// Produces the text each item is matched against when filtering.
+    pub(crate) filter_key: Option<FilterKey>,
+
+    /// When true, matched characters in filtered titles are underlined.
+    pub(crate) highlight_matches: bool,
+
+    /// When set, this is written instead of the Script Filter response.
+    pub(crate) output: Option<Output>,
+
+    /// How the Script Filter response is written
+    pub(crate) output_format: OutputFormat,
+
+    /// The OS integration used to open files/URLs and run AppleScript.
+    pub(crate) system: Box<dyn SystemActions>,
+
+    /// The copy modifiers generated for URLItems added via append_url_items.
+    pub(crate) copy_formats: CopyFormats,
+
+    /// When this workflow execution started
+    pub(crate) started_at: Instant,
+
+    /// The total time budget for this execution, if any
+    pub(crate) deadline: Option<Duration>,
+
+    /// Cancelled once the deadline passes
+    pub(crate) cancellation_token: CancellationToken,
+
+    /// Lifecycle hooks fired during execution
+    pub(crate) hooks: Hooks,
+
+    /// The maximum number of items to write, if any
+    pub(crate) item_limit: Option<usize>,
+
+    /// The maximum size of the serialized response in bytes, if any
+    pub(crate) byte_limit: Option<usize>,
+
+    /// When true, validation warnings are also shown as an item
+    pub(crate) show_validation_warnings: bool,
+
+    /// When true, validation warnings fail the run
+    pub(crate) strict: bool,
+
+    /// Localized text for built-in and workflow items
+    pub(crate) strings: Arc<Strings>,
+
+    /// How the item for a failed run is presented
+    pub(crate) error_style: ErrorStyle,
+
+    /// Directories that couldn't be created and were replaced by
+    /// temporary fallbacks
+    pub(crate) degraded_dirs: Vec<PathBuf>,
+
+    /// Problems found with the configured data and cache paths
+    pub(crate) dir_diagnostics: Vec<String>,
+
+    /// The number of queries to remember, when query history is enabled
+    pub(crate) query_history: Option<usize>,
+
+    /// Replaces missing icon files, when icon checking is enabled
+    pub(crate) icon_fallback: Option<Icon>,
+
+    /// The workflow's installation directory
+    pub(crate) workflow_dir: PathBuf,
+
+    /// Keys of background jobs that are stale and still running
+    pub(crate) pending_jobs: Vec<String>,
+
+    /// Items describing background jobs, which Cached never saves
+    pub(crate) job_status_items: Vec<Item>,
+
+    /// The state of all background jobs, read on the first job check
+    pub(crate) job_snapshot: Option<JobStateSnapshot>,
+
+    /// Items describing internal state, shown while debugging
+    pub(crate) debug_items: Vec<Item>,
+
+    /// Short random ID identifying this execution in logs
+    pub(crate) run_id: String,
+
+    /// When true, notifications posted during Focus are queued
+    pub(crate) defer_notifications: bool,
+
+    /// How large cached values are compressed, if at all
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    pub(crate) cache_compression: Option<Compression>,
+}
+
+/// FilterKey wraps a user-provided filter key function so Workflow can
+/// remain Debug.
+pub(crate) struct FilterKey(pub(crate) Box<FilterKeyFn>);
+
+impl std::fmt::Debug for FilterKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FilterKey(..)")
+    }
 }
 
 impl Workflow {
-    pub fn new(config: WorkflowConfig) -> Result<Self> {
-        // Ensure workflow data and cache directories exist
-        std::fs::create_dir_all(&config.workflow_data)?;
-        std::fs::create_dir_all(&config.workflow_cache)?;
+    pub fn new(mut config: WorkflowConfig) -> Result<Self> {
+        let run_id = logging::generate_run_id();
+        logging::set_run_id(&run_id);
+        // Check and create the workflow data and cache directories, falling back to
+        // temporary directories rather than failing without Alfred feedback
+        let dirs = ensure_dirs(&mut config, &run_id)?;
+        let workflow_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let strings = Strings::load(
+            workflow_dir.join(LOCALES_DIR),
+            &locale_from_env(&config.env),
+        );
 
         Ok(Workflow {
             config,
             response: Response::default(),
             keyword: None,
             sort_and_filter_results: false,
+            filter_key: None,
+            highlight_matches: false,
+            output: None,
+            output_format: OutputFormat::default(),
+            system: Box::new(DefaultSystemActions::default()),
+            copy_formats: CopyFormats::default(),
+            started_at: Instant::now(),
+            deadline: None,
+            cancellation_token: CancellationToken::new(),
+            hooks: Hooks::default(),
+            item_limit: None,
+            byte_limit: None,
+            show_validation_warnings: false,
+            strict: false,
+            strings: Arc::new(strings),
+            error_style: ErrorStyle::default(),
+            degraded_dirs: dirs.degraded,
+            dir_diagnostics: dirs.diagnostics,
+            query_history: None,
+            icon_fallback: None,
+            workflow_dir,
+            pending_jobs: Vec::new(),
+            job_status_items: Vec::new(),
+            job_snapshot: None,
+            debug_items: Vec::new(),
+            run_id,
+            defer_notifications: false,
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            cache_compression: None,
         })
     }
 
@@ -41,6 +197,23 @@ impl Workflow {
         self.sort_and_filter_results = true;
     }
 
+    /// Overrides the text that items are fuzzy matched against when
+    /// filtering by keyword. By default this is "subtitle : title"; use this
+    /// when an item's visible title is decorative and the meaningful text
+    /// lives elsewhere (e.g. in a variable or the arg).
+    pub fn set_filter_key<F>(&mut self, filter_key: F)
+    where
+        F: Fn(&Item) -> String + Send + Sync + 'static,
+    {
+        self.filter_key = Some(FilterKey(Box::new(filter_key)));
+    }
+
+    /// Opt in to underlining the characters of each title that matched the
+    /// filter keyword. Only applies when a filter keyword is set.
+    pub fn highlight_matches(&mut self, enabled: bool) {
+        self.highlight_matches = enabled;
+    }
+
     pub fn items(&mut self, items: Vec<Item>) {
         self.response.items(items);
     }
@@ -61,10 +234,192 @@ impl Workflow {
         self.response.append_items(vec![item]);
     }
 
+    /// Sets the default copy modifiers for URLItems added with
+    /// append_url_items. URLItems with their own copy_formats keep them.
+    pub fn set_copy_formats(&mut self, copy_formats: CopyFormats) {
+        self.copy_formats = copy_formats;
+    }
+
+    /// Converts the URLItems using the workflow's copy formats and appends
+    /// them to the response.
+    pub fn append_url_items(&mut self, url_items: Vec<URLItem>) {
+        let items = url_items
+            .into_iter()
+            .map(|url_item| url_item.into_item_with(&self.copy_formats))
+            .collect();
+        self.response.append_items(items);
+    }
+
     pub fn skip_knowledge(&mut self, skip: bool) {
         self.response.skip_knowledge(skip);
     }
 
+    /// Controls whether Alfred learns from the items chosen in this
+    /// response. Replaces any earlier skip_knowledge setting.
+    pub fn set_learning_policy(&mut self, policy: LearningPolicy) {
+        self.response.learning_policy(policy);
+    }
+
+    /// Replaces the Script Filter response with an `alfredworkflow` Output
+    /// object. Use this from Runnables invoked by Run Script objects that
+    /// need to pass an arg, variables or config to downstream objects.
+    pub fn output(&mut self, output: Output) {
+        self.output = Some(output);
+    }
+
+    /// Sets the format the Script Filter response is written in. Use
+    /// OutputFormat::Xml for workflows whose objects still expect Alfred
+    /// 2's XML output.
+    pub fn set_output_format(&mut self, format: OutputFormat) {
+        self.output_format = format;
+    }
+
+    /// Sets how the item shown when the Runnable returns an error is
+    /// presented. See ErrorStyle for the defaults.
+    pub fn set_error_style(&mut self, error_style: ErrorStyle) {
+        self.error_style = error_style;
+    }
+
+    /// Replaces the SystemActions used by this workflow. Tests typically
+    /// inject a MockSystemActions here.
+    pub fn set_system_actions(&mut self, system: impl SystemActions + 'static) {
+        self.system = Box::new(system);
+    }
+
+    pub fn system(&self) -> &dyn SystemActions {
+        self.system.as_ref()
+    }
+
+    /// Filters and sorts the response items against the filter keyword, if
+    /// one has been set. Called automatically when the workflow finishes.
+    pub(crate) fn filter_and_sort(&mut self) {
+        if !self.sort_and_filter_results {
+            return;
+        }
+        let Some(keyword) = self.keyword.clone() else {
+            return;
+        };
+        let items = std::mem::take(&mut self.response.items);
+        let mut items = match &self.filter_key {
+            Some(filter_key) => filter_and_sort_items_by(items, keyword.clone(), &filter_key.0),
+            None => filter_and_sort_items(items, keyword.clone()),
+        };
+        if self.highlight_matches {
+            items = highlight_matches(items, &keyword);
+        }
+        self.response.items = items;
+    }
+
+    /// Handles the built-in `workflow:history`, `workflow:stats`,
+    /// `workflow:jobs`, `workflow:reset` and `workflow:dumpjson` queries
+    /// typed as the filter keyword, replacing the response items. Other
+    /// queries are recorded in the query history. Called automatically
+    /// when the workflow finishes.
+    pub(crate) fn handle_internal_query(&mut self) {
+        let query = self.keyword.as_deref().map(str::trim).unwrap_or_default();
+        match query {
+            HISTORY_QUERY => self.show_query_history(),
+            STATS_QUERY => self.show_counters(),
+            DUMP_QUERY => self.show_response_dump(),
+            JOBS_QUERY => self.show_jobs(),
+            RESET_QUERY => self.show_reset(false),
+            RESET_CONFIRM_QUERY => self.show_reset(true),
+            _ => {
+                self.record_filter_keyword();
+                return;
+            }
+        }
+        self.sort_and_filter_results = false;
+    }
+
+    /// Sets the total time budget for this execution, measured from when
+    /// the workflow started. Once it passes, the workflow's
+    /// CancellationToken is cancelled so long-running work can stop early
+    /// and return whatever items it has gathered.
+    ///
+    /// When called inside a Tokio runtime (with the default `tokio`
+    /// feature), a timer task cancels the token at the deadline so
+    /// `cancellation_token().cancelled()` can be awaited (e.g. in
+    /// `tokio::select!`). Without a runtime, the deadline is checked
+    /// whenever is_cancelled() is called.
+    ///
+    pub fn set_deadline(&mut self, deadline: Duration) {
+        self.deadline = Some(deadline);
+        let remaining = deadline.saturating_sub(self.started_at.elapsed());
+        if remaining.is_zero() {
+            self.cancellation_token.cancel();
+        } else {
+            self.spawn_deadline_timer(remaining);
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    fn spawn_deadline_timer(&self, remaining: Duration) {
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let token = self.cancellation_token.clone();
+            handle.spawn(async move {
+                tokio::select! {
+                    _ = tokio::time::sleep(remaining) => {
+                        log::debug!("workflow deadline reached, cancelling");
+                        token.cancel();
+                    }
+                    _ = token.cancelled() => {}
+                }
+            });
+        }
+    }
+
+    #[cfg(not(feature = "tokio"))]
+    fn spawn_deadline_timer(&self, _remaining: Duration) {}
+
+    /// Returns the token that is cancelled when the deadline passes. Clone
+    /// it into spawned tasks or select! on `cancelled()`.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation_token.clone()
+    }
+
+    /// Returns true once the deadline has passed (or the token was
+    /// cancelled manually).
+    pub fn is_cancelled(&self) -> bool {
+        if let Some(deadline) = self.deadline {
+            if self.started_at.elapsed() >= deadline {
+                self.cancellation_token.cancel();
+            }
+        }
+        self.cancellation_token.is_cancelled()
+    }
+
+    /// Returns the time left before the deadline, or None if no deadline
+    /// is set.
+    pub fn time_remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_sub(self.started_at.elapsed()))
+    }
+
+    /// Reads a duration (e.g. "15m" or "1h") from the named workflow
+    /// configuration variable, returning `default` when it is unset or
+    /// empty. Invalid values are logged and also fall back to `default`.
+    ///
+    pub fn config_duration(&self, name: &str, default: Duration) -> Duration {
+        match self.config.env.get(name) {
+            Some(value) if !value.trim().is_empty() => parse_duration(value).unwrap_or_else(|e| {
+                warn!("{}: {}, using {:?}", name, e, default);
+                default
+            }),
+            _ => default,
+        }
+    }
+
+    /// Returns whether Alfred's theme is light or dark, based on the theme
+    /// background color. Falls back to Light when the background is
+    /// unknown, matching Alfred's default theme.
+    pub fn appearance(&self) -> Appearance {
+        self.config
+            .theme_background_color()
+            .map(|color| color.appearance())
+            .unwrap_or_default()
+    }
+
     pub fn data_dir(&self) -> PathBuf {
         self.config.workflow_data.clone()
     }
@@ -72,6 +427,106 @@ impl Workflow {
     pub fn cache_dir(&self) -> PathBuf {
         self.config.workflow_cache.clone()
     }
+
+    /// Returns the named subdirectory of the cache directory, creating it
+    /// if needed. Give each feature its own name so their files can't
+    /// collide. The names alfrusco uses itself (RESERVED_CACHE_SUBDIRS)
+    /// are refused.
+    pub fn cache_subdir(&self, name: &str) -> Result<PathBuf> {
+        if RESERVED_CACHE_SUBDIRS.contains(&name) {
+            return Err(Error::Workflow(format!(
+                "the cache subdirectory {:?} is reserved for alfrusco",
+                name
+            )));
+        }
+        subdir(&self.config.workflow_cache, name)
+    }
+
+    /// Returns one of alfrusco's own cache subdirectories, without
+    /// creating it.
+    pub(crate) fn reserved_cache_dir(&self, name: &str) -> PathBuf {
+        debug_assert!(RESERVED_CACHE_SUBDIRS.contains(&name), "{}", name);
+        self.config.workflow_cache.join(name)
+    }
+
+    /// Returns the named subdirectory of the data directory, creating it
+    /// if needed.
+    pub fn data_subdir(&self, name: &str) -> Result<PathBuf> {
+        subdir(&self.config.workflow_data, name)
+    }
+
+    /// The short random ID of this execution. It prefixes log lines
+    /// written by alfrusco::logging and, when that logger is in use, is
+    /// passed to downstream objects in the ALFRUSCO_RUN_ID variable.
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// The directory the workflow is installed in, which holds its
+    /// info.plist and any bundled scripts and images. Alfred runs
+    /// workflows with this as the working directory, so it is captured
+    /// when the Workflow is created.
+    pub fn workflow_dir(&self) -> PathBuf {
+        self.workflow_dir.clone()
+    }
+
+    /// Overrides the workflow directory, e.g. in tests. Locale files are
+    /// reloaded from the new directory, and relative icon paths and
+    /// LaunchAgents' working directory follow it too.
+    pub fn set_workflow_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.workflow_dir = dir.into();
+        self.strings = Arc::new(Strings::load(
+            self.workflow_dir.join(LOCALES_DIR),
+            self.strings.locale(),
+        ));
+    }
+
+    /// Returns the absolute path of a file bundled with the workflow, given
+    /// its path relative to the workflow directory (e.g. "icons/star.png").
+    pub fn resource(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.workflow_dir.join(path)
+    }
+
+    /// Returns true if the bundled file exists.
+    pub fn resource_exists(&self, path: impl AsRef<Path>) -> bool {
+        self.resource(path).exists()
+    }
+
+    /// Returns the absolute path of a bundled file, or an error naming the
+    /// missing file if it doesn't exist.
+    pub fn require_resource(&self, path: impl AsRef<Path>) -> Result<PathBuf> {
+        let resource = self.resource(path);
+        if resource.exists() {
+            Ok(resource)
+        } else {
+            Err(Error::Workflow(format!(
+                "missing workflow resource {}",
+                resource.display()
+            )))
+        }
+    }
+}
+
+/// Creates and returns `parent/name`, where name must be a single plain
+/// path component.
+/// The cache subdirectories alfrusco uses itself, for the ImageCache,
+/// background jobs, memoized values, Cached runs and streams.
+pub const RESERVED_CACHE_SUBDIRS: &[&str] = &["images", "jobs", "memoize", "runs", "streams"];
+
+fn subdir(parent: &Path, name: &str) -> Result<PathBuf> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(std::path::Component::Normal(_)), None) => {}
+        _ => {
+            return Err(Error::Workflow(format!(
+                "invalid subdirectory name {:?}",
+                name
+            )))
+        }
+    }
+    let dir = parent.join(name);
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
 }
 
 #[cfg(test)]
@@ -79,12 +534,11 @@ mod tests {
     use tempfile::TempDir;
 
     use super::*;
-    use crate::config::{self, ConfigProvider};
+    use crate::system::{MockSystemActions, SystemCall};
 
     fn test_workflow() -> (Workflow, TempDir) {
         let dir = tempfile::tempdir().unwrap();
-        let config = config::TestingProvider(dir.path().into()).config().unwrap();
-        (Workflow::new(config).unwrap(), dir)
+        (crate::test_support::workflow_in(dir.path()), dir)
     }
 
     #[test]
@@ -93,6 +547,95 @@ mod tests {
         assert_eq!(workflow.response.items.len(), 0);
         assert_eq!(workflow.keyword, None);
         assert!(!workflow.sort_and_filter_results);
+        assert!(workflow.output.is_none());
+    }
+
+    #[test]
+    fn test_set_system_actions() {
+        let (mut workflow, _dir) = test_workflow();
+        let mock = MockSystemActions::new();
+        workflow.set_system_actions(mock.clone());
+        workflow.system().open_url("https://crates.io/").unwrap();
+        assert_eq!(
+            mock.calls(),
+            vec![SystemCall::OpenUrl("https://crates.io/".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_filter_and_sort_with_filter_key_and_highlighting() {
+        let (mut workflow, _dir) = test_workflow();
+        workflow.items(vec![
+            Item::new("Bookmarks").var("NAME", "rust"),
+            Item::new("Go").var("NAME", "golang"),
+        ]);
+        workflow.set_filter_keyword("rust".to_string());
+        workflow.set_filter_key(|item| item.get_var("NAME").unwrap_or_default().to_string());
+        workflow.highlight_matches(true);
+        workflow.filter_and_sort();
+
+        assert_eq!(workflow.response.items.len(), 1);
+        // The visible title doesn't match the query, so nothing is underlined
+        assert_eq!(workflow.response.items[0].title, "Bookmarks");
+    }
+
+    #[test]
+    fn test_filter_and_sort_highlighting() {
+        let (mut workflow, _dir) = test_workflow();
+        workflow.items(vec![Item::new("Rust"), Item::new("Go")]);
+        workflow.set_filter_keyword("go".to_string());
+        workflow.highlight_matches(true);
+        workflow.filter_and_sort();
+
+        assert_eq!(workflow.response.items.len(), 1);
+        assert_eq!(workflow.response.items[0].title, "G\u{0332}o\u{0332}");
+    }
+
+    #[test]
+    fn test_append_url_items_uses_copy_formats() {
+        use crate::url_item::CopyFormat;
+        use crate::Key;
+
+        let (mut workflow, _dir) = test_workflow();
+        workflow.set_copy_formats(CopyFormats::none().bind(&[Key::Cmd], CopyFormat::Org));
+        workflow.append_url_items(vec![URLItem::new("Rust", "https://www.rust-lang.org/")]);
+
+        let item = &workflow.response.items[0];
+        assert_eq!(item.modifiers.len(), 1);
+        assert_eq!(
+            item.modifiers["cmd"].subtitle,
+            Some("Copy Org-mode Link 'Rust'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_deadline_without_runtime() {
+        let (mut workflow, _dir) = test_workflow();
+        assert!(!workflow.is_cancelled());
+        assert_eq!(workflow.time_remaining(), None);
+
+        workflow.set_deadline(Duration::from_secs(60));
+        assert!(!workflow.is_cancelled());
+        assert!(workflow.time_remaining().unwrap() > Duration::from_secs(59));
+
+        workflow.set_deadline(Duration::ZERO);
+        assert!(workflow.is_cancelled());
+        assert!(workflow.cancellation_token().is_cancelled());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn test_deadline_cancels_token() {
+        let (mut workflow, _dir) = test_workflow();
+        workflow.set_deadline(Duration::from_millis(500));
+        let token = workflow.cancellation_token();
+        assert!(!token.is_cancelled());
+
+        let bailed = tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(10)) => false,
+            _ = token.cancelled() => true,
+        };
+        assert!(bailed);
     }
 
     #[test]
@@ -169,4 +712,88 @@ mod tests {
         assert_eq!(workflow.response.items[3].title, "Appended Item 1");
         assert_eq!(workflow.response.items[5].title, "Appended Item 3");
     }
+
+    #[test]
+    fn test_appearance() {
+        let (mut workflow, _dir) = test_workflow();
+        assert_eq!(workflow.appearance(), Appearance::Light);
+        workflow.config.theme_background = Some("rgba(28,30,33,0.95)".to_string());
+        assert_eq!(workflow.appearance(), Appearance::Dark);
+        workflow.config.theme_background = None;
+        assert_eq!(workflow.appearance(), Appearance::Light);
+    }
+
+    #[test]
+    fn test_subdirs() {
+        let (workflow, dir) = test_workflow();
+        let thumbnails = workflow.cache_subdir("thumbnails").unwrap();
+        assert_eq!(thumbnails, dir.path().join("workflow_cache/thumbnails"));
+        assert!(thumbnails.is_dir());
+
+        let exports = workflow.data_subdir("exports").unwrap();
+        assert_eq!(exports, dir.path().join("workflow_data/exports"));
+        assert!(exports.is_dir());
+
+        for name in ["", "..", "a/b", "/tmp"] {
+            assert!(workflow.cache_subdir(name).is_err(), "{:?}", name);
+        }
+        for name in RESERVED_CACHE_SUBDIRS {
+            assert!(workflow.cache_subdir(name).is_err(), "{:?}", name);
+        }
+        assert_eq!(
+            workflow.image_cache().dir(),
+            workflow.reserved_cache_dir("images")
+        );
+    }
+
+    #[test]
+    fn test_resources() {
+        let (mut workflow, dir) = test_workflow();
+        assert_eq!(workflow.workflow_dir(), std::env::current_dir().unwrap());
+
+        std::fs::create_dir(dir.path().join("icons")).unwrap();
+        std::fs::write(dir.path().join("icons/star.png"), "").unwrap();
+        workflow.set_workflow_dir(dir.path());
+
+        let star = dir.path().join("icons/star.png");
+        assert_eq!(workflow.resource("icons/star.png"), star);
+        assert!(workflow.resource_exists("icons/star.png"));
+        assert!(!workflow.resource_exists("icons/moon.png"));
+        assert_eq!(workflow.require_resource("icons/star.png").unwrap(), star);
+        let error = workflow.require_resource("icons/moon.png").unwrap_err();
+        assert!(error.to_string().contains("icons/moon.png"), "{}", error);
+
+        std::fs::create_dir(dir.path().join(LOCALES_DIR)).unwrap();
+        std::fs::write(
+            dir.path().join(LOCALES_DIR).join("en.json"),
+            r#"{"greeting": "Hello"}"#,
+        )
+        .unwrap();
+        workflow.set_workflow_dir(dir.path());
+        assert_eq!(workflow.t("greeting"), "Hello");
+    }
+
+    #[test]
+    fn test_config_duration() {
+        let (mut workflow, _dir) = test_workflow();
+        let default = Duration::from_secs(60);
+        workflow.config.env = [
+            ("REFRESH_INTERVAL", "15m"),
+            ("EMPTY_INTERVAL", ""),
+            ("BAD_INTERVAL", "whenever"),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            workflow.config_duration("REFRESH_INTERVAL", default),
+            Duration::from_secs(900)
+        );
+        assert_eq!(workflow.config_duration("EMPTY_INTERVAL", default), default);
+        assert_eq!(workflow.config_duration("BAD_INTERVAL", default), default);
+        assert_eq!(
+            workflow.config_duration("MISSING_INTERVAL", default),
+            default
+        );
+    }
 }
@@ -0,0 +1,187 @@
+use std::io;
+
+use crate::{Arg, Item, ItemType, Response, Result};
+
+/// Writes the response in the XML format understood by Alfred 2 and 3
+/// (and by later versions for backwards compatibility). Only the fields
+/// that format supports are written: title, subtitle, uid, arg, valid,
+/// autocomplete, type, icon, modifier subtitles, text and quicklookurl.
+///
+pub(crate) fn write_response<W: io::Write>(response: &Response, mut writer: W) -> Result<()> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+    writeln!(writer, "<items>")?;
+    for item in &response.items {
+        write_item(item, &mut writer)?;
+    }
+    writeln!(writer, "</items>")?;
+    Ok(())
+}
+
+fn write_item<W: io::Write>(item: &Item, writer: &mut W) -> Result<()> {
+    let mut attributes = String::new();
+    if let Some(uid) = &item.uid {
+        attributes.push_str(&format!(r#" uid="{}""#, escape(uid)));
+    }
+    if let Some(arg) = &item.arg {
+        attributes.push_str(&format!(r#" arg="{}""#, escape(&arg_text(arg))));
+    }
+    if let Some(valid) = item.valid {
+        let valid = if valid { "yes" } else { "no" };
+        attributes.push_str(&format!(r#" valid="{}""#, valid));
+    }
+    if let Some(autocomplete) = &item.autocomplete {
+        attributes.push_str(&format!(r#" autocomplete="{}""#, escape(autocomplete)));
+    }
+    if let Some(item_type) = item.item_type {
+        let item_type = match item_type {
+            ItemType::Default => "default",
+            ItemType::File => "file",
+            ItemType::FileSkipCheck => "file:skipcheck",
+        };
+        attributes.push_str(&format!(r#" type="{}""#, item_type));
+    }
+
+    writeln!(writer, "<item{}>", attributes)?;
+    writeln!(writer, "<title>{}</title>", escape(&item.title))?;
+    if let Some(subtitle) = &item.subtitle {
+        writeln!(writer, "<subtitle>{}</subtitle>", escape(subtitle))?;
+    }
+    let mut modifiers: Vec<_> = item.modifiers.iter().collect();
+    modifiers.sort_by_key(|(keys, _)| keys.as_str());
+    for (keys, modifier) in modifiers {
+        if let Some(subtitle) = &modifier.subtitle {
+            writeln!(
+                writer,
+                r#"<subtitle mod="{}">{}</subtitle>"#,
+                escape(keys),
+                escape(subtitle)
+            )?;
+        }
+    }
+    if let Some(icon) = &item.icon {
+        match &icon.type_ {
+            Some(icon_type) => writeln!(
+                writer,
+                r#"<icon type="{}">{}</icon>"#,
+                escape(icon_type),
+                escape(&icon.path)
+            )?,
+            None => writeln!(writer, "<icon>{}</icon>", escape(&icon.path))?,
+        }
+    }
+    if let Some(text) = &item.text {
+        if let Some(copy) = &text.copy {
+            writeln!(writer, r#"<text type="copy">{}</text>"#, escape(copy))?;
+        }
+        if let Some(large_type) = &text.large_type {
+            writeln!(
+                writer,
+                r#"<text type="largetype">{}</text>"#,
+                escape(large_type)
+            )?;
+        }
+    }
+    if let Some(url) = &item.quicklook_url {
+        writeln!(writer, "<quicklookurl>{}</quicklookurl>", escape(url))?;
+    }
+    writeln!(writer, "</item>")?;
+    Ok(())
+}
+
+fn arg_text(arg: &Arg) -> String {
+    match arg {
+        Arg::One(arg) => arg.clone(),
+        Arg::Many(args) => args.join("\t"),
+    }
+}
+
+/// Escapes text for an attribute value.
+pub(crate) fn escape(text: &str) -> String {
+    escape_text(text)
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+        // Keep tabs and newlines from being normalized to spaces in attributes
+        .replace('\t', "&#9;")
+        .replace('\n', "&#10;")
+}
+
+/// Escapes text for element content. Unlike attribute values, element
+/// text keeps its newlines and tabs (which plist scripts need) and quotes.
+pub(crate) fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Key, Modifier};
+
+    fn xml(response: &Response) -> String {
+        let mut buffer = Vec::new();
+        write_response(response, &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_write_items() {
+        let response = Response::new_with_items(vec![
+            Item::new("Rust & Cargo")
+                .subtitle("<fast>")
+                .uid("rust")
+                .arg("https://www.rust-lang.org/")
+                .valid(true)
+                .icon_for_filetype("public.html")
+                .modifier(Modifier::new(Key::Cmd).subtitle("Open docs"))
+                .copy_text("rust"),
+            Item::new("Second").args(["a", "b"]).valid(false),
+        ]);
+
+        assert_eq!(
+            xml(&response),
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<items>
+<item uid="rust" arg="https://www.rust-lang.org/" valid="yes">
+<title>Rust &amp; Cargo</title>
+<subtitle>&lt;fast&gt;</subtitle>
+<subtitle mod="cmd">Open docs</subtitle>
+<icon type="filetype">public.html</icon>
+<text type="copy">rust</text>
+</item>
+<item arg="a&#9;b" valid="no">
+<title>Second</title>
+</item>
+</items>
+"#
+        );
+    }
+
+    #[test]
+    fn test_output_format_set_by_runnable() {
+        struct Legacy;
+
+        impl crate::Runnable for Legacy {
+            type Error = crate::Error;
+            fn run(self, workflow: &mut crate::Workflow) -> Result<()> {
+                workflow.set_output_format(crate::OutputFormat::Xml);
+                workflow.append_item(Item::new("Hello"));
+                Ok(())
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            crate::test_support::run_in(dir.path(), Legacy),
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<items>\n<item>\n<title>Hello</title>\n</item>\n</items>\n"
+        );
+    }
+
+    #[test]
+    fn test_write_empty() {
+        assert_eq!(
+            xml(&Response::new()),
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<items>\n</items>\n"
+        );
+    }
+}